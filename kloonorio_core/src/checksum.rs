@@ -0,0 +1,202 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt::Debug,
+    hash::{Hash, Hasher},
+};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    inventory::Inventory,
+    player::PlayerId,
+    research::ResearchQueue,
+    save_game::{saved_crafting_queue, SaveParams, SavedStructure},
+    types::CraftingQueue,
+};
+
+/// Everything that goes into the checksum of the simulation, which is everything a save game
+/// holds. Clients in a multiplayer game compare checksums to find out when their simulations went
+/// apart.
+#[derive(SystemParam)]
+pub struct ChecksumParams<'w, 's> {
+    save_params: SaveParams<'w, 's>,
+    player_query: Query<
+        'w,
+        's,
+        (
+            &'static PlayerId,
+            &'static Inventory,
+            &'static CraftingQueue,
+        ),
+    >,
+    research_queue: Res<'w, ResearchQueue>,
+}
+
+impl ChecksumParams<'_, '_> {
+    /// Player positions are left out, as every client moves its own player ahead of the others
+    pub fn checksum(&self) -> u64 {
+        // Entities differ between clients and players have no spawn order, so structures and
+        // players are hashed one by one and their hashes are sorted. Saved ground items are in
+        // the same order on every client already.
+        let structures = self
+            .save_params
+            .saved_structures()
+            .into_iter()
+            .map(|mut structure| {
+                unlink(&mut structure);
+                debug_hash(&structure)
+            });
+        let players = self
+            .player_query
+            .iter()
+            .map(|(player, inventory, crafting_queue)| {
+                debug_hash(&(player, inventory, saved_crafting_queue(crafting_queue)))
+            });
+        let mut hashes: Vec<u64> = structures.chain(players).collect();
+        hashes.sort_unstable();
+        hashes.push(debug_hash(&self.save_params.saved_ground_items()));
+
+        let mut researched: Vec<&String> = self.research_queue.researched.iter().collect();
+        researched.sort();
+        let mut hasher = DefaultHasher::new();
+        hashes.hash(&mut hasher);
+        self.research_queue.queue.hash(&mut hasher);
+        self.research_queue.progress.hash(&mut hasher);
+        researched.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// Saved structures link to each other by the order they were saved in, which is different on
+/// every client
+fn unlink(structure: &mut SavedStructure) {
+    if let Some(belt) = &mut structure.belt {
+        belt.next = None;
+        belt.previous.clear();
+        if let Some(underground) = &mut belt.underground {
+            underground.partner = None;
+        }
+    }
+    if let Some(splitter) = &mut structure.splitter {
+        splitter.outputs.clear();
+        splitter.inputs.clear();
+    }
+}
+
+/// Hashes the debug output, which covers every field without all of them implementing `Hash`
+fn debug_hash(value: &impl Debug) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    format!("{:?}", value).hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::ecs::system::SystemState;
+
+    use crate::{
+        discrete_rotation::{DiscreteRotation, SideCount},
+        ground_item::GroundItem,
+        inventory::{Stack, Storage},
+        item::Item,
+        player::Player,
+        structure_components::{burner::Burner, fluid::FluidBox, lab::Lab},
+        types::Building,
+    };
+
+    use super::*;
+
+    fn checksum(app: &mut App) -> u64 {
+        let mut system_state = SystemState::<ChecksumParams>::new(&mut app.world);
+        system_state.get_mut(&mut app.world).checksum()
+    }
+
+    /// Spawns a chest and two players, in the given order
+    fn spawn_world(app: &mut App, players: [u32; 2]) -> Entity {
+        let mut storage = Inventory::new(4);
+        storage.add_item(&Item::new("Iron plate"), 12);
+        let storage = app.world.spawn((Storage, storage)).id();
+        app.world
+            .spawn((
+                Name::new("Wooden chest"),
+                Building,
+                Transform::from_xyz(2., 3., 1.),
+                DiscreteRotation::new(SideCount::One),
+            ))
+            .add_child(storage);
+        for player in players {
+            let mut inventory = Inventory::new(10);
+            inventory.add_item(&Item::new("Wood"), player + 1);
+            app.world.spawn((
+                Player,
+                PlayerId(player),
+                Transform::from_xyz(player as f32, 0., 1.),
+                inventory,
+                CraftingQueue::default(),
+            ));
+        }
+        storage
+    }
+
+    #[test]
+    fn checksum_ignores_spawn_order() {
+        let mut first = App::new();
+        first.init_resource::<ResearchQueue>();
+        spawn_world(&mut first, [0, 1]);
+        let mut second = App::new();
+        second.init_resource::<ResearchQueue>();
+        second.world.spawn_empty();
+        let storage = spawn_world(&mut second, [1, 0]);
+
+        assert_eq!(checksum(&mut first), checksum(&mut second));
+
+        second
+            .world
+            .get_mut::<Inventory>(storage)
+            .unwrap()
+            .add_item(&Item::new("Iron plate"), 1);
+        assert_ne!(checksum(&mut first), checksum(&mut second));
+    }
+
+    #[test]
+    fn checksum_covers_burners_fluids_labs_and_ground_items() {
+        let mut app = App::new();
+        app.init_resource::<ResearchQueue>();
+        let storage = spawn_world(&mut app, [0, 1]);
+        let chest = app.world.get::<Parent>(storage).unwrap().get();
+        let fluid_box = app.world.spawn(FluidBox::new(100., None, vec![])).id();
+        app.world
+            .entity_mut(chest)
+            .insert((Burner::new(90.), Lab::new(1.)))
+            .add_child(fluid_box);
+        let ground_item = app
+            .world
+            .spawn((
+                GroundItem {
+                    stack: Stack::new(Item::new("Coal"), 1),
+                },
+                Transform::from_xyz(5., 5., 0.5),
+            ))
+            .id();
+
+        let mut checksums = vec![checksum(&mut app)];
+        app.world.get_mut::<Burner>(chest).unwrap().energy = 1.;
+        checksums.push(checksum(&mut app));
+        app.world
+            .get_mut::<FluidBox>(fluid_box)
+            .unwrap()
+            .add("Water", 10.);
+        checksums.push(checksum(&mut app));
+        app.world.get_mut::<Lab>(chest).unwrap().unit_progress = Some(0.5);
+        checksums.push(checksum(&mut app));
+        app.world
+            .get_mut::<GroundItem>(ground_item)
+            .unwrap()
+            .take(1);
+        checksums.push(checksum(&mut app));
+
+        for (i, checksum) in checksums.iter().enumerate() {
+            assert!(!checksums[..i].contains(checksum));
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use bevy::prelude::*;
+
+/// Distance from a player within which they build their ghosts
+pub const CONSTRUCTION_REACH: f32 = 10.0;
+
+pub struct ConstructionQueuePlugin;
+
+impl Plugin for ConstructionQueuePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ConstructionQueue>();
+    }
+}
+
+/// A structure a player planned without building it. The player builds it once they have the
+/// item and are in reach.
+#[derive(Component, Debug)]
+pub struct ConstructionGhost {
+    pub player: Entity,
+    pub structure: String,
+}
+
+/// The ghosts waiting to be built, in the order they were placed
+#[derive(Resource, Default, Debug)]
+pub struct ConstructionQueue(Vec<Entity>);
+
+impl ConstructionQueue {
+    pub fn push(&mut self, ghost: Entity) {
+        self.0.push(ghost);
+    }
+
+    pub fn remove(&mut self, ghost: Entity) {
+        self.0.retain(|queued| *queued != ghost);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.0.iter().copied()
+    }
+}
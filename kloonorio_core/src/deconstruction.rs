@@ -0,0 +1,637 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    construction_queue::{ConstructionGhost, ConstructionQueue},
+    discrete_rotation::DiscreteRotation,
+    inventory::Inventory,
+    item::Item,
+    player_command::{apply_player_commands, InventoryLocations, PlayerCommand, ScheduledCommands},
+    simulation::{SimulationSet, SimulationTick},
+    structure_components::{
+        assembler::Assembler,
+        inserter::Inserter,
+        splitter::{Splitter, SplitterSide},
+        transport_belt::{
+            BeltLane, BeltParams, NextBelt, PreviousBelts, UndergroundBelt, BELT_SLOTS,
+        },
+    },
+    tile_occupants::{EntityOnTiles, TileOccupants},
+    types::{Building, MineStructureCountdown},
+    undo::{ConstructionAction, ConstructionCause, ConstructionEvent, StructurePlacement},
+};
+
+/// Seconds of holding the mouse button on a structure before it is mined
+pub const STRUCTURE_MINING_TIME: f32 = 0.5;
+
+/// The item that marks the structures in an area for deconstruction
+pub const DECONSTRUCTION_PLANNER: &str = "Deconstruction planner";
+
+pub struct DeconstructionPlugin;
+
+impl Plugin for DeconstructionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RemoveStructureEvent>()
+            .add_systems(
+                FixedUpdate,
+                mark_for_deconstruction
+                    .after(apply_player_commands)
+                    .in_set(SimulationSet::Commands),
+            )
+            .add_systems(
+                FixedUpdate,
+                (remove_structures, mine_structures, deconstruct_marked)
+                    .chain()
+                    .in_set(SimulationSet::PlayerMining),
+            );
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect, Serialize, Deserialize)]
+pub enum DeconstructionFilterMode {
+    /// Only mark the structures in the filter
+    Whitelist,
+    /// Mark every structure except those in the filter
+    #[default]
+    Blacklist,
+}
+
+/// The structures a deconstruction planner marks, by name. The default filter marks everything.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Reflect, Serialize, Deserialize)]
+pub struct DeconstructionFilter {
+    pub mode: DeconstructionFilterMode,
+    pub structures: Vec<String>,
+}
+
+impl DeconstructionFilter {
+    pub fn allows(&self, structure: &str) -> bool {
+        let in_filter = self.structures.iter().any(|name| name == structure);
+        match self.mode {
+            DeconstructionFilterMode::Whitelist => in_filter,
+            DeconstructionFilterMode::Blacklist => !in_filter,
+        }
+    }
+}
+
+/// A structure that will be removed and refunded to the player that marked it
+#[derive(Component, Debug)]
+pub struct MarkedForDeconstruction {
+    pub player: Entity,
+    /// Tick the structure was marked on, structures marked first are removed first
+    pub tick: u64,
+}
+
+/// Remove a structure right away, like undoing its placement does
+#[derive(Debug, Event)]
+pub struct RemoveStructureEvent {
+    pub cause: ConstructionCause,
+    pub structure: Entity,
+}
+
+/// Time until a player's next marked structure is removed
+#[derive(Component, Debug)]
+pub struct Deconstructing {
+    pub timer: Timer,
+}
+
+/// The links between belts, splitters and underground belts
+#[derive(SystemParam)]
+pub struct BeltLinks<'w, 's> {
+    commands: Commands<'w, 's>,
+    splitter_query: Query<'w, 's, &'static mut Splitter>,
+    belt_link_query: Query<
+        'w,
+        's,
+        (
+            Option<&'static NextBelt>,
+            Option<&'static mut PreviousBelts>,
+        ),
+    >,
+    underground_belt_query: Query<'w, 's, &'static mut UndergroundBelt>,
+}
+
+impl BeltLinks<'_, '_> {
+    pub fn splitter(&self, structure: Entity) -> Option<&Splitter> {
+        self.splitter_query.get(structure).ok()
+    }
+
+    /// Unlink a belt, splitter or underground belt from the ones around it and the other way
+    /// around. Belts are rebuilt into segments once they are relinked.
+    pub fn unlink(&mut self, structure: Entity) {
+        let Ok((next_belt, previous_belts)) = self.belt_link_query.get(structure) else {
+            return;
+        };
+        let next_belt = next_belt.map(|next_belt| next_belt.0);
+        let previous_belts: Vec<Entity> = previous_belts
+            .map(|previous_belts| previous_belts.belts.iter().copied().collect())
+            .unwrap_or_default();
+
+        // The belts a splitter outputs to have it as previous belt, like the belt in front of a
+        // belt does
+        let outputs = self
+            .splitter_query
+            .get(structure)
+            .map_or(vec![], |splitter| {
+                SplitterSide::ALL
+                    .into_iter()
+                    .filter_map(|side| splitter.output(side))
+                    .collect()
+            });
+        for output in next_belt.into_iter().chain(outputs) {
+            if let Ok((_, Some(mut output_previous_belts))) = self.belt_link_query.get_mut(output) {
+                output_previous_belts.belts.remove(&structure);
+            }
+        }
+
+        for previous_belt in previous_belts {
+            if let Ok(mut splitter) = self.splitter_query.get_mut(previous_belt) {
+                for side in SplitterSide::ALL {
+                    if splitter.output(side) == Some(structure) {
+                        splitter.set_output(side, None);
+                    }
+                }
+            } else {
+                self.commands.entity(previous_belt).remove::<NextBelt>();
+            }
+        }
+
+        let partner = self
+            .underground_belt_query
+            .get(structure)
+            .ok()
+            .and_then(|underground_belt| underground_belt.partner);
+        if let Some(mut partner) =
+            partner.and_then(|partner| self.underground_belt_query.get_mut(partner).ok())
+        {
+            partner.partner = None;
+        }
+
+        self.commands.entity(structure).remove::<NextBelt>();
+        if let Ok((_, Some(mut previous_belts))) = self.belt_link_query.get_mut(structure) {
+            previous_belts.belts = default();
+        }
+    }
+}
+
+/// What is needed to refund a building and to put it back when its removal is undone
+type RemovedBuildingQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Name,
+        &'static Transform,
+        &'static DiscreteRotation,
+        Option<&'static Assembler>,
+        Option<&'static Children>,
+    ),
+    With<Building>,
+>;
+
+/// Removes structures from the world, giving them and everything in them back to a player
+#[derive(SystemParam)]
+pub struct Deconstruction<'w, 's> {
+    commands: Commands<'w, 's>,
+    building_query: RemovedBuildingQuery<'w, 's>,
+    inventory_query: Query<'w, 's, &'static mut Inventory>,
+    inserter_query: Query<'w, 's, &'static Inserter>,
+    belt_params: BeltParams<'w, 's>,
+    belt_links: BeltLinks<'w, 's>,
+    entity_on_tiles_query: Query<'w, 's, &'static EntityOnTiles>,
+    tile_occupants_query: Query<'w, 's, &'static mut TileOccupants>,
+    construction_events: EventWriter<'w, ConstructionEvent>,
+}
+
+impl Deconstruction<'_, '_> {
+    /// The structure's item along with the items in its inventories, in an inserter's hand and
+    /// on a belt or splitter
+    pub fn refund(&self, structure: Entity) -> Vec<(Item, u32)> {
+        let Ok((name, _, _, _, children)) = self.building_query.get(structure) else {
+            return vec![];
+        };
+        let mut refund = vec![(Item::new(name.to_string()), 1)];
+        let inventories = children
+            .into_iter()
+            .flatten()
+            .filter_map(|child| self.inventory_query.get(*child).ok());
+        for inventory in inventories {
+            refund.extend(
+                inventory
+                    .slots
+                    .iter()
+                    .flatten()
+                    .map(|stack| (stack.item.clone(), stack.amount)),
+            );
+        }
+        if let Some(holding) = self
+            .inserter_query
+            .get(structure)
+            .ok()
+            .and_then(Inserter::holding)
+        {
+            refund.push((holding.item.clone(), holding.amount));
+        }
+        for lane in BeltLane::ALL {
+            for slot in 0..BELT_SLOTS {
+                if let Some(item) = self.belt_params.slot(structure, lane, slot) {
+                    refund.push((item.clone(), 1));
+                }
+            }
+            if let Some(splitter) = self.belt_links.splitter(structure) {
+                refund.extend(splitter.items(lane).map(|item| (item.clone(), 1)));
+            }
+        }
+        refund
+    }
+
+    /// Remove a structure and put its refund in the inventory of the player that caused it.
+    /// Nothing is removed if the refund doesn't fit.
+    pub fn remove(&mut self, structure: Entity, cause: ConstructionCause) -> bool {
+        let Ok((name, transform, rotation, assembler, children)) =
+            self.building_query.get(structure)
+        else {
+            return false;
+        };
+        let placement = StructurePlacement {
+            structure: name.to_string(),
+            position: transform.translation.truncate(),
+            rotation: *rotation,
+            recipe: assembler
+                .and_then(|assembler| assembler.recipe.as_ref())
+                .map(|recipe| recipe.name.clone()),
+        };
+        let children: Vec<Entity> = children.into_iter().flatten().copied().collect();
+
+        let refund = self.refund(structure);
+        let Ok(mut inventory) = self.inventory_query.get_mut(cause.player) else {
+            return false;
+        };
+        if !inventory.can_add(&refund) {
+            debug!(?structure, "Refund doesn't fit in the inventory");
+            return false;
+        }
+        inventory.add_items(&refund);
+
+        self.construction_events.send(ConstructionEvent {
+            cause,
+            action: ConstructionAction::Remove(placement),
+        });
+
+        self.belt_links.unlink(structure);
+        for entity in std::iter::once(structure).chain(children) {
+            self.untrack(entity);
+        }
+        self.commands.entity(structure).despawn_recursive();
+        true
+    }
+
+    /// Take an entity out of the occupants of the tiles it is on
+    fn untrack(&mut self, entity: Entity) {
+        let Ok(entity_on_tiles) = self.entity_on_tiles_query.get(entity) else {
+            return;
+        };
+        for tile in entity_on_tiles.tile_entities() {
+            if let Ok(mut tile_occupants) = self.tile_occupants_query.get_mut(*tile) {
+                tile_occupants.remove(&entity);
+            }
+        }
+    }
+}
+
+/// Remove the structures that undoing their placement asked for
+pub fn remove_structures(
+    mut remove_structure_events: EventReader<RemoveStructureEvent>,
+    mut deconstruction: Deconstruction,
+) {
+    for event in remove_structure_events.read() {
+        if !deconstruction.remove(event.structure, event.cause) {
+            debug!(?event, "Could not remove structure");
+        }
+    }
+}
+
+/// Remove the structures players are mining, trying again every time the timer finishes while
+/// the refund doesn't fit
+pub fn mine_structures(
+    time: Res<Time>,
+    tick: Res<SimulationTick>,
+    mut player_query: Query<(Entity, &mut MineStructureCountdown)>,
+    inventory_locations: InventoryLocations,
+    mut deconstruction: Deconstruction,
+) {
+    for (player, mut countdown) in &mut player_query {
+        if !countdown.timer.tick(time.delta()).just_finished() {
+            continue;
+        }
+        if let Some(structure) = inventory_locations.structure(countdown.position) {
+            deconstruction.remove(structure, ConstructionCause::player(player, tick.0));
+        }
+    }
+}
+
+/// Mark or unmark the structures in the areas players dragged their deconstruction planner over.
+/// Ghosts in a marked area are removed right away, there is nothing to refund.
+pub fn mark_for_deconstruction(
+    mut commands: Commands,
+    tick: Res<SimulationTick>,
+    scheduled_commands: Res<ScheduledCommands>,
+    inventory_locations: InventoryLocations,
+    building_query: Query<
+        (Entity, &Name, &Transform, Has<MarkedForDeconstruction>),
+        With<Building>,
+    >,
+    ghost_query: Query<(Entity, &ConstructionGhost, &Transform)>,
+    mut construction_queue: ResMut<ConstructionQueue>,
+) {
+    for (player, command) in scheduled_commands.at(tick.0) {
+        match command {
+            PlayerCommand::MarkForDeconstruction { min, max, filter } => {
+                let Some(player) = inventory_locations.player(*player) else {
+                    continue;
+                };
+                let area = Rect::from_corners(*min, *max);
+                for (structure, _, _, _) in
+                    building_query
+                        .iter()
+                        .filter(|(_, name, transform, marked)| {
+                            !marked
+                                && filter.allows(name)
+                                && area.contains(transform.translation.truncate())
+                        })
+                {
+                    commands.entity(structure).insert(MarkedForDeconstruction {
+                        player,
+                        tick: tick.0,
+                    });
+                }
+                for (ghost, _, _) in ghost_query.iter().filter(|(_, ghost, transform)| {
+                    filter.allows(&ghost.structure)
+                        && area.contains(transform.translation.truncate())
+                }) {
+                    commands.entity(ghost).despawn_recursive();
+                    construction_queue.remove(ghost);
+                }
+                commands.entity(player).insert(Deconstructing {
+                    timer: Timer::from_seconds(STRUCTURE_MINING_TIME, TimerMode::Repeating),
+                });
+            }
+            PlayerCommand::CancelDeconstruction { min, max } => {
+                let area = Rect::from_corners(*min, *max);
+                for (structure, _, _, _) in
+                    building_query.iter().filter(|(_, _, transform, marked)| {
+                        *marked && area.contains(transform.translation.truncate())
+                    })
+                {
+                    commands
+                        .entity(structure)
+                        .remove::<MarkedForDeconstruction>();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Remove the structures players marked one at a time, the ones marked first first and row by row
+/// from the top left within an area. A structure whose refund doesn't fit is tried again.
+pub fn deconstruct_marked(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut player_query: Query<(Entity, &mut Deconstructing)>,
+    marked_query: Query<(Entity, &MarkedForDeconstruction, &Transform)>,
+    mut deconstruction: Deconstruction,
+) {
+    for (player, mut deconstructing) in &mut player_query {
+        if !deconstructing.timer.tick(time.delta()).just_finished() {
+            continue;
+        }
+        let next = marked_query
+            .iter()
+            .filter(|(_, marked, _)| marked.player == player)
+            .min_by(|(_, a, a_transform), (_, b, b_transform)| {
+                let (a_position, b_position) = (a_transform.translation, b_transform.translation);
+                a.tick
+                    .cmp(&b.tick)
+                    .then(b_position.y.total_cmp(&a_position.y))
+                    .then(a_position.x.total_cmp(&b_position.x))
+            });
+        match next {
+            // Everything one drag marked is undone at once
+            Some((structure, marked, _)) => {
+                deconstruction.remove(structure, ConstructionCause::player(player, marked.tick));
+            }
+            None => {
+                commands.entity(player).remove::<Deconstructing>();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use bevy::ecs::system::SystemState;
+
+    use crate::{
+        discrete_rotation::{CompassDirection, DiscreteRotation, SideCount},
+        inventory::Storage,
+        player::PlayerId,
+        structure_components::transport_belt::{rebuild_belt_segments, BeltSegment, TransportBelt},
+    };
+
+    use super::*;
+
+    fn spawn_belt(app: &mut App, x: f32) -> Entity {
+        let mut rotation = DiscreteRotation::new(SideCount::Four);
+        rotation.set(CompassDirection::East);
+        app.world
+            .spawn((
+                Name::new("Transport belt"),
+                Building,
+                Transform::from_xyz(x, 0., 1.),
+                rotation,
+                TransportBelt::default(),
+            ))
+            .id()
+    }
+
+    fn remove(app: &mut App, structure: Entity, player: Entity) -> bool {
+        let mut system_state = SystemState::<Deconstruction>::new(&mut app.world);
+        let removed = system_state
+            .get_mut(&mut app.world)
+            .remove(structure, ConstructionCause::player(player, 0));
+        system_state.apply(&mut app.world);
+        removed
+    }
+
+    #[test]
+    fn removed_belt_is_unlinked_and_refunded_with_its_items() {
+        let mut app = App::new();
+        app.add_event::<ConstructionEvent>()
+            .add_systems(Update, rebuild_belt_segments);
+        let belts = [
+            spawn_belt(&mut app, 0.),
+            spawn_belt(&mut app, 1.),
+            spawn_belt(&mut app, 2.),
+        ];
+        for (index, belt) in belts.iter().enumerate() {
+            let mut belt = app.world.entity_mut(*belt);
+            if let Some(next_belt) = belts.get(index + 1) {
+                belt.insert(NextBelt(*next_belt));
+            }
+            belt.insert(PreviousBelts {
+                belts: belts[..index].iter().rev().take(1).copied().collect(),
+            });
+        }
+        let tile = app.world.spawn(TileOccupants::new([belts[1]].into())).id();
+        app.world
+            .entity_mut(belts[1])
+            .insert(EntityOnTiles::new(vec![tile]));
+        let player = app.world.spawn(Inventory::new(4)).id();
+        app.update();
+
+        let (segment, first_slot) = app
+            .world
+            .get::<TransportBelt>(belts[1])
+            .unwrap()
+            .segment()
+            .unwrap();
+        let mut segment = app.world.get_mut::<BeltSegment>(segment).unwrap();
+        segment.add(BeltLane::Left, first_slot, Item::new("Coal"));
+        segment.add(BeltLane::Right, first_slot + 1, Item::new("Coal"));
+
+        assert!(remove(&mut app, belts[1], player));
+
+        assert!(app.world.get_entity(belts[1]).is_none());
+        assert!(app.world.get::<NextBelt>(belts[0]).is_none());
+        assert!(app
+            .world
+            .get::<PreviousBelts>(belts[2])
+            .unwrap()
+            .belts
+            .is_empty());
+        assert!(!app
+            .world
+            .get::<TileOccupants>(tile)
+            .unwrap()
+            .contains(&belts[1]));
+        let inventory = app.world.get::<Inventory>(player).unwrap();
+        assert_eq!(inventory.num_items(&Item::new("Transport belt")), 1);
+        assert_eq!(inventory.num_items(&Item::new("Coal")), 2);
+    }
+
+    #[test]
+    fn structure_stays_when_refund_does_not_fit() {
+        let mut app = App::new();
+        app.add_event::<ConstructionEvent>();
+        let mut storage = Inventory::new(1);
+        storage.add_item(&Item::new("Iron plate"), 10);
+        let storage = app.world.spawn((Storage, storage)).id();
+        let chest = app
+            .world
+            .spawn((
+                Name::new("Wooden chest"),
+                Building,
+                Transform::default(),
+                DiscreteRotation::new(SideCount::One),
+            ))
+            .add_child(storage)
+            .id();
+        let mut inventory = Inventory::new(1);
+        inventory.add_item(&Item::new("Wood"), 1);
+        let player = app.world.spawn(inventory).id();
+
+        assert!(!remove(&mut app, chest, player));
+        assert!(app.world.get_entity(chest).is_some());
+        // Only buildings are removed, without refunding anything otherwise
+        assert!(!remove(&mut app, storage, player));
+        let inventory = app.world.get::<Inventory>(player).unwrap();
+        assert_eq!(inventory.num_items(&Item::new("Wood")), 1);
+        assert_eq!(inventory.num_items(&Item::new("Iron plate")), 0);
+
+        app.world.get_mut::<Inventory>(player).unwrap().slots = vec![None; 2];
+        assert!(remove(&mut app, chest, player));
+        assert!(app.world.get_entity(chest).is_none());
+        assert!(app.world.get_entity(storage).is_none());
+        let inventory = app.world.get::<Inventory>(player).unwrap();
+        assert_eq!(inventory.num_items(&Item::new("Wooden chest")), 1);
+        assert_eq!(inventory.num_items(&Item::new("Iron plate")), 10);
+    }
+
+    #[test]
+    fn marked_structures_are_removed_one_at_a_time_in_order() {
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .init_resource::<SimulationTick>()
+            .add_event::<ConstructionEvent>()
+            .init_resource::<ScheduledCommands>()
+            .init_resource::<ConstructionQueue>()
+            .add_systems(Update, (mark_for_deconstruction, deconstruct_marked));
+        let mut spawn_structure = |name: &'static str, x: f32, y: f32| {
+            app.world
+                .spawn((
+                    Name::new(name),
+                    Building,
+                    Transform::from_xyz(x, y, 1.),
+                    DiscreteRotation::new(SideCount::One),
+                ))
+                .id()
+        };
+        let second = spawn_structure("Wooden chest", 1., 0.);
+        let first = spawn_structure("Wooden chest", 0., 0.);
+        let filtered = spawn_structure("Stone furnace", 0., 1.);
+        let outside = spawn_structure("Wooden chest", 5., 5.);
+        let player = app.world.spawn((PlayerId(0), Inventory::new(10))).id();
+        let ghost = app
+            .world
+            .spawn((
+                ConstructionGhost {
+                    player,
+                    structure: "Wooden chest".to_string(),
+                },
+                Transform::from_xyz(1., 1., 1.),
+            ))
+            .id();
+        app.world.resource_mut::<ConstructionQueue>().push(ghost);
+        app.world.resource_mut::<ScheduledCommands>().schedule(
+            0,
+            [(
+                PlayerId(0),
+                PlayerCommand::MarkForDeconstruction {
+                    min: Vec2::new(-0.5, -0.5),
+                    max: Vec2::new(1.5, 1.5),
+                    filter: DeconstructionFilter {
+                        mode: DeconstructionFilterMode::Blacklist,
+                        structures: vec!["Stone furnace".to_string()],
+                    },
+                },
+            )],
+        );
+        app.update();
+        assert!(app.world.get::<MarkedForDeconstruction>(first).is_some());
+        assert!(app.world.get::<MarkedForDeconstruction>(filtered).is_none());
+        assert!(app.world.get::<MarkedForDeconstruction>(outside).is_none());
+        // Ghosts have nothing to refund and go right away
+        assert!(app.world.get_entity(ghost).is_none());
+        assert_eq!(app.world.resource::<ConstructionQueue>().iter().count(), 0);
+
+        let advance = |app: &mut App| {
+            app.world.resource_mut::<SimulationTick>().0 += 1;
+            app.world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs_f32(STRUCTURE_MINING_TIME));
+            app.update();
+        };
+        advance(&mut app);
+        assert!(app.world.get_entity(first).is_none());
+        assert!(app.world.get_entity(second).is_some());
+        advance(&mut app);
+        assert!(app.world.get_entity(second).is_none());
+        advance(&mut app);
+        assert!(app.world.get::<Deconstructing>(player).is_none());
+        assert!(app.world.get_entity(filtered).is_some());
+        assert!(app.world.get_entity(outside).is_some());
+        let inventory = app.world.get::<Inventory>(player).unwrap();
+        assert_eq!(inventory.num_items(&Item::new("Wooden chest")), 2);
+    }
+}
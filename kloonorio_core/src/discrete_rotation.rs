@@ -1,4 +1,5 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
 pub struct DiscreteRotationPlugin;
 
@@ -41,7 +42,7 @@ impl TryFrom<u32> for SideCount {
     }
 }
 
-#[derive(Component, Debug, Clone, Copy, PartialEq, Reflect)]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize)]
 pub struct DiscreteRotation {
     current: usize,
     max: usize,
@@ -122,6 +123,11 @@ impl DiscreteRotation {
         }
     }
 
+    /// Unit vector pointing in the direction this rotation is facing, north being +Y.
+    pub fn forward(&self) -> Vec2 {
+        Vec2::from_angle(-self.radians()).rotate(Vec2::Y)
+    }
+
     pub fn radians(&self) -> f32 {
         match self.max {
             1 => 0.0,
@@ -1,12 +1,18 @@
-use bevy::ecs::{
-    entity::Entity,
-    query::Without,
-    system::{Query, SystemParam},
+use bevy::{
+    ecs::{
+        entity::Entity,
+        query::Without,
+        system::{Query, SystemParam},
+    },
+    math::Vec2,
 };
 
 use crate::{
+    ground_item::GroundItemParams,
     inventory::{Inventory, Output, Stack},
-    structure_components::transport_belt::TransportBelt,
+    item::Item,
+    spawn_order::SpawnOrders,
+    structure_components::transport_belt::{far_lane, BeltParams},
     tile_occupants::TileOccupants,
 };
 
@@ -14,46 +20,72 @@ use crate::{
 pub struct DropParams<'w, 's> {
     tile_occupants_query: Query<'w, 's, &'static TileOccupants>,
     inventories_query: Query<'w, 's, &'static mut Inventory, Without<Output>>,
-    belts_query: Query<'w, 's, &'static mut TransportBelt>,
+    belt_params: BeltParams<'w, 's>,
+    ground_item_params: GroundItemParams<'w, 's>,
+    spawn_orders: SpawnOrders<'w, 's>,
 }
 
 impl DropParams<'_, '_> {
-    pub fn can_drop_stack_at_tile(&self, stack: &Stack, tile: Entity) -> bool {
-        self.tile_occupants_query
-            .get(tile)
-            .ok()
-            .map_or(false, |occupants| {
-                occupants.iter().any(|&entity| {
-                    self.inventories_query
-                        .get(entity)
-                        .map_or(false, |inventory| inventory.can_add_stack(stack))
-                        || self
-                            .belts_query
+    pub fn can_drop_item_at_tile(&self, item: &Item, tile: Entity, direction: Vec2) -> bool {
+        let stack = Stack::new(item.clone(), 1);
+        let can_drop_on_occupant =
+            self.tile_occupants_query
+                .get(tile)
+                .ok()
+                .map_or(false, |occupants| {
+                    occupants.iter().any(|&entity| {
+                        self.inventories_query
                             .get(entity)
-                            .map_or(false, |belt| belt.can_add(1))
-                })
-            })
+                            .map_or(false, |inventory| inventory.can_add_stack(&stack))
+                            || self.belt_params.rotation(entity).map_or(false, |rotation| {
+                                self.belt_params
+                                    .can_add(entity, far_lane(rotation, direction), 1)
+                            })
+                    })
+                });
+        can_drop_on_occupant || self.ground_item_params.can_drop_stack_at_tile(&stack, tile)
     }
 
-    pub fn drop_stack_at_tile(&mut self, stack: &Stack, tile: Entity) -> bool {
-        self.tile_occupants_query
+    /// Drop a single item in an inventory or on a belt at the tile, or on the ground of the tile
+    /// centred at `tile_position` if there is nothing else on it. Items dropped on a belt are put
+    /// on the far lane, as seen when moving in `direction`. Belts only take one item per slot, so
+    /// items are dropped one at a time.
+    pub fn drop_item_at_tile(
+        &mut self,
+        item: &Item,
+        tile: Entity,
+        tile_position: Vec2,
+        direction: Vec2,
+    ) -> bool {
+        let stack = Stack::new(item.clone(), 1);
+        // The occupants are tried in the same order on every client
+        let occupants = self
+            .tile_occupants_query
             .get(tile)
-            .ok()
-            .map_or(false, |occupants| {
-                occupants.iter().any(|&entity| {
-                    if let Ok(mut inventory) = self.inventories_query.get_mut(entity) {
-                        if inventory.can_add_stack(stack) {
-                            inventory.add_stack(stack.clone());
-                            return true;
-                        }
-                    }
-                    if let Ok(mut belt) = self.belts_query.get_mut(entity) {
-                        if belt.add(1, stack.item.clone()) {
-                            return true;
-                        }
-                    }
-                    false
-                })
-            })
+            .map_or(Vec::new(), |occupants| {
+                self.spawn_orders.sorted(occupants.iter().copied())
+            });
+        let dropped_on_occupant = occupants.into_iter().any(|entity| {
+            if let Ok(mut inventory) = self.inventories_query.get_mut(entity) {
+                if inventory.can_add_stack(&stack) {
+                    inventory.add_stack(stack.clone());
+                    return true;
+                }
+            }
+            if let Some(lane) = self
+                .belt_params
+                .rotation(entity)
+                .map(|rotation| far_lane(rotation, direction))
+            {
+                if self.belt_params.add(entity, lane, 1, item.clone()) {
+                    return true;
+                }
+            }
+            false
+        });
+        dropped_on_occupant
+            || self
+                .ground_item_params
+                .drop_stack_at_tile(&stack, tile, tile_position)
     }
 }
@@ -0,0 +1,94 @@
+use bevy::{prelude::Entity, reflect::Reflect};
+
+/// A set of entities that iterates in entity order, which a `HashSet` doesn't promise. Entities
+/// are numbered differently on every client though, so systems that act on the first match or
+/// on every entity in turn sort them by `SpawnOrder` first.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Reflect)]
+pub struct EntitySet(Vec<Entity>);
+
+impl EntitySet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns true if the entity wasn't in the set yet
+    pub fn insert(&mut self, entity: Entity) -> bool {
+        match self.0.binary_search(&entity) {
+            Ok(_) => false,
+            Err(index) => {
+                self.0.insert(index, entity);
+                true
+            }
+        }
+    }
+
+    /// Returns true if the entity was in the set
+    pub fn remove(&mut self, entity: &Entity) -> bool {
+        match self.0.binary_search(entity) {
+            Ok(index) => {
+                self.0.remove(index);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    pub fn contains(&self, entity: &Entity) -> bool {
+        self.0.binary_search(entity).is_ok()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Entity> {
+        self.0.iter()
+    }
+}
+
+impl FromIterator<Entity> for EntitySet {
+    fn from_iter<T: IntoIterator<Item = Entity>>(iter: T) -> Self {
+        let mut entities: Vec<Entity> = iter.into_iter().collect();
+        entities.sort();
+        entities.dedup();
+        Self(entities)
+    }
+}
+
+impl<const N: usize> From<[Entity; N]> for EntitySet {
+    fn from(entities: [Entity; N]) -> Self {
+        entities.into_iter().collect()
+    }
+}
+
+impl<'a> IntoIterator for &'a EntitySet {
+    type Item = &'a Entity;
+    type IntoIter = std::slice::Iter<'a, Entity>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn iterates_in_entity_order() {
+        let entities: Vec<Entity> = (0..4).map(Entity::from_raw).collect();
+        let mut set = EntitySet::from([entities[2], entities[0], entities[3]]);
+        assert!(set.insert(entities[1]));
+        assert!(!set.insert(entities[0]));
+        assert!(set.iter().eq(entities.iter()));
+
+        assert!(set.remove(&entities[2]));
+        assert!(!set.remove(&entities[2]));
+        assert!(!set.contains(&entities[2]));
+        assert_eq!(set.len(), 3);
+    }
+}
@@ -0,0 +1,268 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    inventory::Stack,
+    tile_occupants::{EntityOnTiles, TileOccupants, TileTracked},
+};
+
+/// Number of items that fit on the ground of a single tile. Machines dropping items onto the
+/// ground stall until the items are picked up.
+pub const MAX_GROUND_STACK_SIZE: u32 = 1;
+/// Ground items the player walks over are picked up automatically
+pub const WALK_PICKUP_RANGE: f32 = 0.5;
+/// Ground items within this range are picked up while the pickup key is held. Players can't pick
+/// up items from further away.
+pub const KEY_PICKUP_RANGE: f32 = 1.5;
+
+pub struct GroundItemPlugin;
+
+impl Plugin for GroundItemPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<GroundItem>()
+            .add_systems(PostUpdate, despawn_empty_ground_items);
+    }
+}
+
+/// A stack of items lying on a tile. Ground items are tile tracked, so they show up in the
+/// `TileOccupants` of the tile they are lying on.
+#[derive(Component, Debug, Reflect)]
+pub struct GroundItem {
+    pub stack: Stack,
+}
+
+impl GroundItem {
+    pub fn is_empty(&self) -> bool {
+        self.stack.amount == 0
+    }
+
+    pub fn can_add(&self, stack: &Stack) -> bool {
+        !self.is_empty()
+            && self.stack.item == stack.item
+            && self.stack.amount + stack.amount <= MAX_GROUND_STACK_SIZE
+    }
+
+    /// Take up to `amount` items. Empty ground items are despawned at the end of the frame
+    pub fn take(&mut self, amount: u32) -> Option<Stack> {
+        let amount = amount.min(self.stack.amount);
+        if amount == 0 {
+            return None;
+        }
+        self.stack.amount -= amount;
+        Some(Stack::new(self.stack.item.clone(), amount))
+    }
+}
+
+pub fn spawn_ground_item(commands: &mut Commands, stack: Stack, position: Vec2) -> Entity {
+    let transform = Transform::from_translation(position.extend(0.5));
+    commands
+        .spawn((
+            Name::new(format!("{} (ground)", stack.item)),
+            GroundItem { stack },
+            TileTracked,
+            SpatialBundle {
+                transform,
+                // Set right away so the item is tracked on the right tile before the transforms
+                // are propagated
+                global_transform: GlobalTransform::from(transform),
+                ..default()
+            },
+        ))
+        .id()
+}
+
+#[derive(SystemParam)]
+pub struct GroundItemParams<'w, 's> {
+    commands: Commands<'w, 's>,
+    tile_occupants_query: Query<'w, 's, &'static TileOccupants>,
+    ground_items_query: Query<'w, 's, &'static mut GroundItem>,
+}
+
+impl GroundItemParams<'_, '_> {
+    /// The ground item lying on a tile, if there is one that isn't empty
+    pub fn ground_item_at_tile(&self, tile: Entity) -> Option<Entity> {
+        self.tile_occupants_query
+            .get(tile)
+            .ok()?
+            .iter()
+            .find(|occupant| {
+                self.ground_items_query
+                    .get(**occupant)
+                    .map_or(false, |ground_item| !ground_item.is_empty())
+            })
+            .copied()
+    }
+
+    pub fn stack(&self, ground_item: Entity) -> Option<&Stack> {
+        self.ground_items_query
+            .get(ground_item)
+            .ok()
+            .filter(|ground_item| !ground_item.is_empty())
+            .map(|ground_item| &ground_item.stack)
+    }
+
+    /// Whether anything other than ground items occupies the tile, like a structure or its
+    /// inventories
+    pub fn is_tile_blocked(&self, tile: Entity) -> bool {
+        self.tile_occupants_query
+            .get(tile)
+            .map_or(false, |occupants| {
+                occupants
+                    .iter()
+                    .any(|occupant| !self.ground_items_query.contains(*occupant))
+            })
+    }
+
+    /// Items can be dropped on tiles that aren't blocked, as long as they fit on the items that
+    /// are already lying there
+    pub fn can_drop_stack_at_tile(&self, stack: &Stack, tile: Entity) -> bool {
+        !self.is_tile_blocked(tile)
+            && stack.amount <= MAX_GROUND_STACK_SIZE
+            && self.ground_item_at_tile(tile).map_or(true, |ground_item| {
+                self.ground_items_query
+                    .get(ground_item)
+                    .map_or(false, |ground_item| ground_item.can_add(stack))
+            })
+    }
+
+    /// Drop a stack on the ground of a tile centred at `tile_position`, adding it to the items
+    /// already lying there. Returns true if the stack was dropped
+    pub fn drop_stack_at_tile(&mut self, stack: &Stack, tile: Entity, tile_position: Vec2) -> bool {
+        if !self.can_drop_stack_at_tile(stack, tile) {
+            return false;
+        }
+        if let Some(ground_item) = self.ground_item_at_tile(tile) {
+            self.ground_items_query
+                .get_mut(ground_item)
+                .unwrap()
+                .stack
+                .amount += stack.amount;
+        } else {
+            spawn_ground_item(&mut self.commands, stack.clone(), tile_position);
+        }
+        true
+    }
+
+    pub fn take(&mut self, ground_item: Entity, amount: u32) -> Option<Stack> {
+        self.ground_items_query
+            .get_mut(ground_item)
+            .ok()?
+            .take(amount)
+    }
+}
+
+/// Tile tracking can't tell which tiles a despawned entity was on, so empty ground items are
+/// removed from their tiles here before despawning them.
+fn despawn_empty_ground_items(
+    mut commands: Commands,
+    ground_items_query: Query<(Entity, &GroundItem, Option<&EntityOnTiles>)>,
+    mut tile_occupants_query: Query<&mut TileOccupants>,
+) {
+    for (ground_item_entity, ground_item, entity_on_tiles) in &ground_items_query {
+        if !ground_item.is_empty() {
+            continue;
+        }
+        for tile_entity in entity_on_tiles
+            .into_iter()
+            .flat_map(|entity_on_tiles| entity_on_tiles.tile_entities())
+        {
+            if let Ok(mut tile_occupants) = tile_occupants_query.get_mut(*tile_entity) {
+                tile_occupants.remove(&ground_item_entity);
+            }
+        }
+        commands.entity(ground_item_entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::ecs::system::SystemState;
+
+    use crate::item::Item;
+
+    use super::*;
+
+    fn drop_coal(app: &mut App, tile: Entity) -> bool {
+        let mut system_state: SystemState<GroundItemParams> = SystemState::new(&mut app.world);
+        let mut ground_item_params = system_state.get_mut(&mut app.world);
+        let dropped = ground_item_params.drop_stack_at_tile(
+            &Stack::new(Item::new("Coal"), 1),
+            tile,
+            Vec2::ZERO,
+        );
+        system_state.apply(&mut app.world);
+        dropped
+    }
+
+    /// Ground items are added to the tile by the tile tracking, do that by hand here
+    fn track_ground_items(app: &mut App, tile: Entity) {
+        let mut ground_items_query = app
+            .world
+            .query_filtered::<Entity, (With<GroundItem>, Without<EntityOnTiles>)>();
+        let ground_items: Vec<Entity> = ground_items_query.iter(&app.world).collect();
+        for ground_item in ground_items {
+            app.world
+                .get_mut::<TileOccupants>(tile)
+                .unwrap()
+                .add(ground_item);
+            app.world
+                .entity_mut(ground_item)
+                .insert(EntityOnTiles::new(vec![tile]));
+        }
+    }
+
+    #[test]
+    fn drop_on_empty_tile() {
+        let mut app = App::new();
+        let tile = app.world.spawn(TileOccupants::default()).id();
+
+        assert!(drop_coal(&mut app, tile));
+        track_ground_items(&mut app, tile);
+
+        let mut ground_items_query = app.world.query::<&GroundItem>();
+        let ground_item = ground_items_query.single(&app.world);
+        assert_eq!(ground_item.stack, Stack::new(Item::new("Coal"), 1));
+
+        // The tile is full now
+        assert!(!drop_coal(&mut app, tile));
+        assert_eq!(ground_items_query.iter(&app.world).count(), 1);
+    }
+
+    #[test]
+    fn no_drop_on_structures() {
+        let mut app = App::new();
+        let building = app.world.spawn(Name::new("Wooden chest")).id();
+        let tile = app.world.spawn(TileOccupants::new([building].into())).id();
+
+        assert!(!drop_coal(&mut app, tile));
+    }
+
+    #[test]
+    fn empty_ground_items_are_removed() {
+        let mut app = App::new();
+        app.add_systems(PostUpdate, despawn_empty_ground_items);
+        let tile = app.world.spawn(TileOccupants::default()).id();
+        drop_coal(&mut app, tile);
+        track_ground_items(&mut app, tile);
+
+        let mut system_state: SystemState<GroundItemParams> = SystemState::new(&mut app.world);
+        let mut ground_item_params = system_state.get_mut(&mut app.world);
+        let ground_item = ground_item_params.ground_item_at_tile(tile).unwrap();
+        assert_eq!(
+            ground_item_params.take(ground_item, 10),
+            Some(Stack::new(Item::new("Coal"), 1))
+        );
+        assert_eq!(ground_item_params.ground_item_at_tile(tile), None);
+
+        app.update();
+
+        assert!(app.world.get_entity(ground_item).is_none());
+        assert!(!app
+            .world
+            .get::<TileOccupants>(tile)
+            .unwrap()
+            .contains(&ground_item));
+
+        // Something can be dropped on the tile again
+        assert!(drop_coal(&mut app, tile));
+    }
+}
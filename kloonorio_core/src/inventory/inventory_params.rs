@@ -1,11 +1,12 @@
 use bevy::{
     ecs::{
         entity::Entity,
-        query::{ReadOnlyWorldQuery, With, Without, WorldQuery},
+        query::{Has, ReadOnlyWorldQuery, With, Without, WorldQuery},
         system::{Query, SystemParam},
     },
     hierarchy::Children,
 };
+use serde::{Deserialize, Serialize};
 
 use super::{Fuel, Inventory, Output, Source, Storage};
 
@@ -56,7 +57,7 @@ pub struct InventoryParams<'w, 's> {
     children: Query<'w, 's, &'static Children>,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum InventoryType {
     Fuel,
     Source,
@@ -64,6 +65,31 @@ pub enum InventoryType {
     Storage,
 }
 
+/// Which of the inventories of a structure an inventory entity is
+#[derive(WorldQuery)]
+pub struct InventoryKind {
+    fuel: Has<Fuel>,
+    source: Has<Source>,
+    output: Has<Output>,
+    storage: Has<Storage>,
+}
+
+impl InventoryKindItem<'_> {
+    pub fn inventory_type(&self) -> Option<InventoryType> {
+        if self.fuel {
+            Some(InventoryType::Fuel)
+        } else if self.source {
+            Some(InventoryType::Source)
+        } else if self.output {
+            Some(InventoryType::Output)
+        } else if self.storage {
+            Some(InventoryType::Storage)
+        } else {
+            None
+        }
+    }
+}
+
 impl InventoryParams<'_, '_> {
     pub fn get_inventory_component(
         &self,
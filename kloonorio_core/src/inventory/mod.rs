@@ -5,11 +5,12 @@ use bevy::{
     prelude::*,
     utils::{HashMap, HashSet},
 };
+use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
 use crate::item::Item;
 
-pub use self::inventory_params::{InventoryParams, InventoryType};
+pub use self::inventory_params::{InventoryKind, InventoryParams, InventoryType};
 pub use self::stack::{Stack, MAX_STACK_SIZE};
 
 #[derive(Component)]
@@ -26,13 +27,13 @@ pub struct Storage;
 
 pub type Slot = Option<Stack>;
 
-#[derive(Component, Debug, Clone)]
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
 pub struct Inventory {
     pub slots: Vec<Slot>,
     pub allowed_items: ItemFilter,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Reflect)]
+#[derive(Debug, Clone, PartialEq, Eq, Reflect, Serialize, Deserialize)]
 pub enum ItemFilter {
     All,
     Only(HashSet<Item>),
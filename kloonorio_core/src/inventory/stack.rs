@@ -1,10 +1,11 @@
 use bevy::{ecs::component::Component, reflect::Reflect};
+use serde::{Deserialize, Serialize};
 
 use crate::item::Item;
 
 pub const MAX_STACK_SIZE: u32 = 1000;
 
-#[derive(Component, Debug, Clone, PartialEq, Eq, Hash, Reflect)]
+#[derive(Component, Debug, Clone, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
 pub struct Stack {
     pub item: Item,
     pub amount: u32,
@@ -10,7 +10,7 @@ use bevy::{
     reflect::{Reflect, TypeUuid},
     utils::HashMap,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 pub struct ItemPlugin;
 
@@ -21,8 +21,8 @@ impl Plugin for ItemPlugin {
 }
 
 /// An item is a "thing" that can be stored in an inventory, used in or produced by a recipe, etc.
-#[derive(Hash, Eq, PartialEq, Debug, Clone, TypeUuid, Reflect, Deserialize)]
-#[serde(from = "String")]
+#[derive(Hash, Eq, PartialEq, Debug, Clone, TypeUuid, Reflect, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
 #[uuid = "28a860c7-96ee-44e5-ae3b-8a25d9a863d5"]
 pub struct Item(Name);
 
@@ -44,6 +44,12 @@ impl From<String> for Item {
     }
 }
 
+impl From<Item> for String {
+    fn from(item: Item) -> Self {
+        item.0.to_string()
+    }
+}
+
 impl AsRef<str> for Item {
     fn as_ref(&self) -> &str {
         self.0.as_str()
@@ -58,11 +64,42 @@ impl Deref for Item {
     }
 }
 
+/// The properties of an item, as loaded from the item definitions
+#[derive(Clone, Debug, Deserialize, Reflect)]
+pub struct ItemDefinition {
+    pub name: Item,
+    /// Energy released by burning one of the item in MJ. Items without a fuel value can't be
+    /// used as fuel.
+    #[serde(default)]
+    pub fuel_value: f32,
+}
+
+impl ItemDefinition {
+    pub fn is_fuel(&self) -> bool {
+        self.fuel_value > 0.
+    }
+}
+
 #[derive(Resource, Default, Reflect)]
-pub struct Items(HashMap<String, Item>);
+pub struct Items(HashMap<String, ItemDefinition>);
+
+impl Items {
+    /// The fuel value of an item in MJ, if it can be used as fuel
+    pub fn fuel_value(&self, item: &Item) -> Option<f32> {
+        self.get(item.as_ref())
+            .filter(|definition| definition.is_fuel())
+            .map(|definition| definition.fuel_value)
+    }
+
+    pub fn fuels(&self) -> impl Iterator<Item = &Item> {
+        self.values()
+            .filter(|definition| definition.is_fuel())
+            .map(|definition| &definition.name)
+    }
+}
 
 impl Deref for Items {
-    type Target = HashMap<String, Item>;
+    type Target = HashMap<String, ItemDefinition>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
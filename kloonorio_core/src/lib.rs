@@ -1,17 +1,28 @@
 use bevy::app::{PluginGroup, PluginGroupBuilder};
 
+pub mod checksum;
+pub mod construction_queue;
+pub mod deconstruction;
 pub mod discrete_rotation;
 pub mod drop;
+pub mod entity_set;
+pub mod ground_item;
 pub mod health;
 pub mod inventory;
 pub mod item;
 pub mod mineable;
 pub mod player;
+pub mod player_command;
 pub mod recipe;
+pub mod research;
+pub mod save_game;
+pub mod simulation;
+pub mod spawn_order;
 pub mod structure;
 pub mod structure_components;
 pub mod tile_occupants;
 pub mod types;
+pub mod undo;
 
 pub struct KloonorioCorePlugins;
 
@@ -19,9 +30,18 @@ impl PluginGroup for KloonorioCorePlugins {
     fn build(self) -> bevy::app::PluginGroupBuilder {
         PluginGroupBuilder::start::<Self>()
             .add(item::ItemPlugin)
+            .add(simulation::SimulationPlugin)
+            .add(player_command::PlayerCommandPlugin)
             .add(discrete_rotation::DiscreteRotationPlugin)
             .add(structure_components::StructureComponentsPlugin)
             .add(tile_occupants::TileOccupantsPlugin)
+            .add(spawn_order::SpawnOrderPlugin)
             .add(health::HealthPlugin)
+            .add(ground_item::GroundItemPlugin)
+            .add(research::ResearchPlugin)
+            .add(save_game::SaveGamePlugin)
+            .add(deconstruction::DeconstructionPlugin)
+            .add(undo::UndoPlugin)
+            .add(construction_queue::ConstructionQueuePlugin)
     }
 }
@@ -1,4 +1,35 @@
-use bevy::ecs::component::Component;
+use bevy::{ecs::component::Component, math::Vec2, reflect::Reflect};
+use serde::{Deserialize, Serialize};
+
+/// Radius of the circle a player takes up
+pub const PLAYER_RADIUS: f32 = 0.3;
 
 #[derive(Component)]
 pub struct Player;
+
+/// Where a player is in the simulation, the same on every client. The local player's transform
+/// runs ahead, it moves before its position is sent.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq)]
+pub struct SimulatedPosition(pub Vec2);
+
+/// Identifies a player in the same way for every client in a multiplayer game, unlike its entity
+#[derive(
+    Component,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Reflect,
+    Serialize,
+    Deserialize,
+)]
+pub struct PlayerId(pub u32);
+
+/// The player controlled on this client
+#[derive(Component)]
+pub struct LocalPlayer;
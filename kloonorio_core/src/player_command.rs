@@ -0,0 +1,914 @@
+use std::collections::BTreeMap;
+
+use bevy::{
+    ecs::{query::Has, system::SystemParam},
+    prelude::*,
+    utils::FloatOrd,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    deconstruction::{DeconstructionFilter, RemoveStructureEvent, STRUCTURE_MINING_TIME},
+    discrete_rotation::DiscreteRotation,
+    ground_item::{GroundItem, KEY_PICKUP_RANGE},
+    inventory::{
+        drop_within_inventory, transfer_between_slots, Inventory, InventoryKind, InventoryType,
+    },
+    item::Item,
+    player::{LocalPlayer, Player, PlayerId, SimulatedPosition},
+    recipe::{Recipes, CRAFTING_CATEGORY},
+    research::{QueueResearchEvent, ResearchQueue, Technologies},
+    simulation::{SimulationSet, SimulationTick},
+    structure::{PlaceStructureEvent, RotateStructureEvent},
+    structure_components::{
+        assembler::ChangeAssemblerRecipeEvent,
+        inserter::{ChangeInserterFilterEvent, InserterFilter},
+        splitter::{ChangeSplitterSettingsEvent, SplitterSide},
+    },
+    types::{ActiveCraft, Building, CraftingQueue, MineCountdown, MineStructureCountdown},
+    undo::{
+        ActionOrigin, ConstructionAction, ConstructionCause, ConstructionHistory,
+        StructurePlacement,
+    },
+};
+
+pub struct PlayerCommandPlugin;
+
+impl Plugin for PlayerCommandPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CommandSource>()
+            .init_resource::<LocalCommands>()
+            .init_resource::<ScheduledCommands>()
+            .add_event::<PlaceStructureEvent>()
+            .add_event::<RotateStructureEvent>()
+            .add_systems(
+                FixedUpdate,
+                (schedule_local_commands, apply_player_commands)
+                    .chain()
+                    .in_set(SimulationSet::Commands),
+            );
+    }
+}
+
+/// A change to the world made by a player. Input and UI systems don't change the world
+/// themselves, they send commands that every client in a multiplayer game applies on the same
+/// tick.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PlayerCommand {
+    /// The player walked to this position
+    MoveTo(Vec2),
+    /// Place a structure from the player's inventory
+    PlaceStructure {
+        structure: String,
+        position: Vec2,
+        rotation: DiscreteRotation,
+    },
+    /// Plan a structure at a position, the player builds it once they have the item
+    PlaceGhost {
+        structure: String,
+        position: Vec2,
+        rotation: DiscreteRotation,
+    },
+    /// Turn the structure at a position clockwise
+    RotateStructure {
+        position: Vec2,
+    },
+    /// Mine a resource by hand until mining stops
+    StartMining {
+        product: Item,
+    },
+    /// Mine the structure at a position by hand until mining stops, giving it and its contents
+    /// back to the player
+    StartMiningStructure {
+        position: Vec2,
+    },
+    StopMining,
+    /// Mark the structures in an area that pass the filter, to be removed one after the other
+    MarkForDeconstruction {
+        min: Vec2,
+        max: Vec2,
+        filter: DeconstructionFilter,
+    },
+    /// Keep the marked structures in an area
+    CancelDeconstruction {
+        min: Vec2,
+        max: Vec2,
+    },
+    /// Take the ingredients of a recipe from the player's inventory and queue it
+    Craft {
+        recipe: String,
+    },
+    /// Remove a craft from the player's crafting queue
+    CancelCraft {
+        index: usize,
+    },
+    /// Move as much of a stack as fits from one slot to another
+    MoveStack {
+        from: InventorySlot,
+        to: InventorySlot,
+    },
+    /// Pick up the ground items in range of the player
+    PickUp {
+        range: f32,
+    },
+    /// Set the recipe of the assembler at a position
+    ChangeAssemblerRecipe {
+        position: Vec2,
+        recipe: String,
+    },
+    ChangeSplitterSettings {
+        position: Vec2,
+        priority: Option<SplitterSide>,
+        filter: Option<Item>,
+    },
+    ChangeInserterFilter {
+        position: Vec2,
+        filter: InserterFilter,
+    },
+    /// Add a technology to the end of the research queue
+    QueueResearch {
+        technology: String,
+    },
+    /// Revert the player's last construction actions
+    Undo,
+    /// Carry out the player's last undone construction actions again
+    Redo,
+}
+
+/// An inventory that is the same on every client, unlike its entity
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum InventoryLocation {
+    Player(PlayerId),
+    Structure {
+        position: Vec2,
+        inventory: InventoryType,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InventorySlot {
+    pub inventory: InventoryLocation,
+    pub slot: usize,
+}
+
+/// Where the local player's commands come from. Replayed movement is applied to the local
+/// player like to any other, as there is no input moving it.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CommandSource {
+    #[default]
+    Input,
+    Replay,
+}
+
+/// Commands of the local player that haven't been scheduled yet
+#[derive(Resource, Default, Debug)]
+pub struct LocalCommands(pub Vec<PlayerCommand>);
+
+impl LocalCommands {
+    pub fn push(&mut self, command: PlayerCommand) {
+        self.0.push(command);
+    }
+}
+
+/// Commands of every player by the tick they are applied on
+#[derive(Resource, Default, Debug)]
+pub struct ScheduledCommands(BTreeMap<u64, Vec<(PlayerId, PlayerCommand)>>);
+
+impl ScheduledCommands {
+    pub fn schedule(
+        &mut self,
+        tick: u64,
+        commands: impl IntoIterator<Item = (PlayerId, PlayerCommand)>,
+    ) {
+        self.0.entry(tick).or_default().extend(commands);
+    }
+
+    pub fn at(&self, tick: u64) -> &[(PlayerId, PlayerCommand)] {
+        self.0.get(&tick).map_or(&[], Vec::as_slice)
+    }
+
+    /// Forget the commands of ticks that have been simulated
+    pub fn discard_before(&mut self, tick: u64) {
+        self.0 = self.0.split_off(&tick);
+    }
+}
+
+/// Finds the entities of players, structures and inventories by their location and the other way
+/// around
+#[derive(SystemParam)]
+pub struct InventoryLocations<'w, 's> {
+    player_query: Query<'w, 's, (Entity, &'static PlayerId)>,
+    building_query:
+        Query<'w, 's, (Entity, &'static Transform, Option<&'static Children>), With<Building>>,
+    parent_query: Query<'w, 's, &'static Parent>,
+    kind_query: Query<'w, 's, InventoryKind>,
+}
+
+impl InventoryLocations<'_, '_> {
+    pub fn player(&self, player: PlayerId) -> Option<Entity> {
+        self.player_query
+            .iter()
+            .find_map(|(entity, id)| (*id == player).then_some(entity))
+    }
+
+    pub fn structure(&self, position: Vec2) -> Option<Entity> {
+        self.building_query
+            .iter()
+            .find(|(_, transform, _)| transform.translation.truncate() == position)
+            .map(|(entity, _, _)| entity)
+    }
+
+    pub fn location(&self, entity: Entity) -> Option<InventoryLocation> {
+        if let Ok((_, player)) = self.player_query.get(entity) {
+            return Some(InventoryLocation::Player(*player));
+        }
+        let inventory = self.kind_query.get(entity).ok()?.inventory_type()?;
+        let parent = self.parent_query.get(entity).ok()?.get();
+        let (_, transform, _) = self.building_query.get(parent).ok()?;
+        Some(InventoryLocation::Structure {
+            position: transform.translation.truncate(),
+            inventory,
+        })
+    }
+
+    pub fn entity(&self, location: InventoryLocation) -> Option<Entity> {
+        match location {
+            InventoryLocation::Player(player) => self.player(player),
+            InventoryLocation::Structure {
+                position,
+                inventory,
+            } => self
+                .building_query
+                .iter()
+                .find(|(_, transform, _)| transform.translation.truncate() == position)
+                .and_then(|(_, _, children)| children)
+                .and_then(|children| {
+                    children.iter().copied().find(|child| {
+                        self.kind_query
+                            .get(*child)
+                            .is_ok_and(|kind| kind.inventory_type() == Some(inventory))
+                    })
+                }),
+        }
+    }
+}
+
+/// Move the local player's commands to the current tick. In a multiplayer game the session takes
+/// them first, to schedule them on every client at once.
+pub fn schedule_local_commands(
+    tick: Res<SimulationTick>,
+    mut local_commands: ResMut<LocalCommands>,
+    mut scheduled_commands: ResMut<ScheduledCommands>,
+    local_player_query: Query<&PlayerId, With<LocalPlayer>>,
+) {
+    scheduled_commands.discard_before(tick.0);
+    let commands = std::mem::take(&mut local_commands.0);
+    // Without a player there is nobody to apply them to
+    if let Ok(player) = local_player_query.get_single() {
+        scheduled_commands.schedule(
+            tick.0,
+            commands.into_iter().map(|command| (*player, command)),
+        );
+    }
+}
+
+/// Player transforms, kept apart from the building transforms `InventoryLocations` reads
+type PlayerTransformQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut Transform,
+        Option<&'static mut SimulatedPosition>,
+        Has<LocalPlayer>,
+    ),
+    (With<Player>, Without<Building>),
+>;
+
+#[derive(SystemParam)]
+pub struct PlayerCommandParams<'w, 's> {
+    commands: Commands<'w, 's>,
+    locations: InventoryLocations<'w, 's>,
+    inventory_query: Query<'w, 's, &'static mut Inventory>,
+    crafting_queue_query: Query<'w, 's, &'static mut CraftingQueue, With<Player>>,
+    player_transform_query: PlayerTransformQuery<'w, 's>,
+    ground_item_query: Query<'w, 's, (Entity, &'static GlobalTransform, &'static mut GroundItem)>,
+    recipes: Res<'w, Recipes>,
+    research_queue: Res<'w, ResearchQueue>,
+    technologies: Res<'w, Technologies>,
+    command_source: Res<'w, CommandSource>,
+    assembler_recipe_events: EventWriter<'w, ChangeAssemblerRecipeEvent>,
+    splitter_settings_events: EventWriter<'w, ChangeSplitterSettingsEvent>,
+    inserter_filter_events: EventWriter<'w, ChangeInserterFilterEvent>,
+    queue_research_events: EventWriter<'w, QueueResearchEvent>,
+    place_structure_events: EventWriter<'w, PlaceStructureEvent>,
+    rotate_structure_events: EventWriter<'w, RotateStructureEvent>,
+    remove_structure_events: EventWriter<'w, RemoveStructureEvent>,
+    rotation_query: Query<'w, 's, &'static DiscreteRotation, With<Building>>,
+    history_query: Query<'w, 's, &'static mut ConstructionHistory>,
+}
+
+impl PlayerCommandParams<'_, '_> {
+    fn move_to(&mut self, player: Entity, position: Vec2) {
+        if let Ok((mut transform, simulated_position, is_local)) =
+            self.player_transform_query.get_mut(player)
+        {
+            if let Some(mut simulated_position) = simulated_position {
+                simulated_position.0 = position;
+            }
+            // The local player is already further along
+            if !is_local || *self.command_source == CommandSource::Replay {
+                transform.translation = position.extend(transform.translation.z);
+            }
+        }
+    }
+
+    fn craft(&mut self, player: Entity, recipe: &str) {
+        let Some(recipe) = self.recipes.get(recipe) else {
+            warn!(recipe, "Unknown recipe");
+            return;
+        };
+        // Commands from other players and replays aren't checked by the crafting UI
+        if recipe.category != CRAFTING_CATEGORY
+            || !self
+                .research_queue
+                .is_recipe_unlocked(&recipe.name, &self.technologies)
+        {
+            warn!(recipe = recipe.name, "Recipe can't be crafted by hand");
+            return;
+        }
+        let (Ok(mut inventory), Ok(mut crafting_queue)) = (
+            self.inventory_query.get_mut(player),
+            self.crafting_queue_query.get_mut(player),
+        ) else {
+            return;
+        };
+        if inventory.remove_items(&recipe.ingredients) {
+            crafting_queue.0.push_back(ActiveCraft {
+                recipe: recipe.clone(),
+                timer: Timer::from_seconds(recipe.crafting_time, TimerMode::Repeating),
+            });
+        }
+    }
+
+    fn cancel_craft(&mut self, player: Entity, index: usize) {
+        if let Ok(mut crafting_queue) = self.crafting_queue_query.get_mut(player) {
+            crafting_queue.0.remove(index);
+        }
+    }
+
+    fn move_stack(&mut self, from: InventorySlot, to: InventorySlot) {
+        let (Some(source), Some(target)) = (
+            self.locations.entity(from.inventory),
+            self.locations.entity(to.inventory),
+        ) else {
+            warn!(?from, ?to, "Could not find inventories");
+            return;
+        };
+        if source == target {
+            let Ok(mut inventory) = self.inventory_query.get_mut(source) else {
+                return;
+            };
+            if from.slot != to.slot && from.slot.max(to.slot) < inventory.slots.len() {
+                drop_within_inventory(&mut inventory, from.slot, to.slot);
+            }
+        } else if let Ok([mut source_inventory, mut target_inventory]) =
+            self.inventory_query.get_many_mut([source, target])
+        {
+            if let (Some(source_slot), Some(target_slot)) = (
+                source_inventory.slots.get_mut(from.slot),
+                target_inventory.slots.get_mut(to.slot),
+            ) {
+                transfer_between_slots(source_slot, target_slot);
+            }
+        }
+    }
+
+    fn change_assembler_recipe(&mut self, position: Vec2, recipe: &str, cause: ConstructionCause) {
+        let (Some(entity), Some(recipe)) =
+            (self.locations.structure(position), self.recipes.get(recipe))
+        else {
+            warn!(?position, recipe, "Could not find assembler or recipe");
+            return;
+        };
+        self.assembler_recipe_events
+            .send(ChangeAssemblerRecipeEvent {
+                entity,
+                recipe: Some(recipe.clone()),
+                cause: Some(cause),
+            });
+    }
+
+    /// Revert the player's last construction actions by their inverses, last to first. Redoing
+    /// reverts the actions that undid them.
+    fn undo(&mut self, player: Entity, tick: u64, origin: ActionOrigin) {
+        let Ok(mut history) = self.history_query.get_mut(player) else {
+            return;
+        };
+        let Some(entry) = history.take(origin) else {
+            return;
+        };
+        let cause = ConstructionCause {
+            player,
+            tick,
+            origin,
+        };
+        for action in entry.actions.iter().rev() {
+            self.construct(action.inverse(), cause);
+        }
+    }
+
+    fn construct(&mut self, action: ConstructionAction, cause: ConstructionCause) {
+        match action {
+            ConstructionAction::Place(placement) => self
+                .place_structure_events
+                .send(PlaceStructureEvent { cause, placement }),
+            ConstructionAction::Remove(placement) => {
+                let Some(structure) = self.locations.structure(placement.position) else {
+                    debug!(?placement, "Structure to remove is gone");
+                    return;
+                };
+                self.remove_structure_events
+                    .send(RemoveStructureEvent { cause, structure });
+            }
+            ConstructionAction::Rotate { position, to, .. } => {
+                let Some(structure) = self.locations.structure(position) else {
+                    debug!(?position, "Structure to rotate is gone");
+                    return;
+                };
+                self.rotate_structure_events.send(RotateStructureEvent {
+                    cause,
+                    structure,
+                    rotation: to,
+                });
+            }
+            ConstructionAction::ChangeRecipe { position, to, .. } => {
+                let Some(entity) = self.locations.structure(position) else {
+                    debug!(?position, "Assembler to change is gone");
+                    return;
+                };
+                self.assembler_recipe_events
+                    .send(ChangeAssemblerRecipeEvent {
+                        entity,
+                        recipe: to.and_then(|recipe| self.recipes.get(&recipe).cloned()),
+                        cause: Some(cause),
+                    });
+            }
+        }
+    }
+
+    fn rotate(&mut self, player: Entity, tick: u64, position: Vec2) {
+        let Some(rotation) = self
+            .locations
+            .structure(position)
+            .and_then(|structure| self.rotation_query.get(structure).ok())
+        else {
+            return;
+        };
+        let from = *rotation;
+        let mut to = from;
+        to.rotate();
+        self.construct(
+            ConstructionAction::Rotate { position, from, to },
+            ConstructionCause::player(player, tick),
+        );
+    }
+
+    fn pick_up(&mut self, player: Entity, range: f32) {
+        let (Ok((transform, simulated_position, _)), Ok(mut inventory)) = (
+            self.player_transform_query.get(player),
+            self.inventory_query.get_mut(player),
+        ) else {
+            return;
+        };
+        // The local player's transform runs ahead of where the other clients see it
+        let position =
+            simulated_position.map_or(transform.translation.truncate(), |simulated| simulated.0);
+        let range = range.min(KEY_PICKUP_RANGE);
+        let mut ground_items: Vec<(Entity, Vec2)> = self
+            .ground_item_query
+            .iter()
+            .map(|(entity, transform, _)| (entity, transform.translation().truncate()))
+            .filter(|(_, item_position)| position.distance(*item_position) <= range)
+            .collect();
+        // Entities are numbered differently on every client, so the items are picked up by
+        // position and name. Which ones stay on the ground with a full inventory is the same
+        // for everyone.
+        ground_items.sort_by_cached_key(|(entity, item_position)| {
+            let (_, _, ground_item) = self.ground_item_query.get(*entity).unwrap();
+            (
+                FloatOrd(item_position.y),
+                FloatOrd(item_position.x),
+                ground_item.stack.item.to_string(),
+            )
+        });
+        for (entity, _) in ground_items {
+            let (_, _, mut ground_item) = self.ground_item_query.get_mut(entity).unwrap();
+            if ground_item.is_empty() {
+                continue;
+            }
+            // Whatever doesn't fit in the inventory stays on the ground
+            ground_item.stack.amount = inventory
+                .add_stack(ground_item.stack.clone())
+                .map_or(0, |remainder| remainder.amount);
+        }
+    }
+}
+
+/// Apply every player's commands for the current tick, in the order they were scheduled. Settings
+/// of structures and research are changed by their events, which are read right after.
+pub fn apply_player_commands(
+    tick: Res<SimulationTick>,
+    scheduled_commands: Res<ScheduledCommands>,
+    mut params: PlayerCommandParams,
+) {
+    for (player, command) in scheduled_commands.at(tick.0) {
+        let Some(player_entity) = params.locations.player(*player) else {
+            warn!(
+                ?player,
+                ?command,
+                "Command of a player that isn't in the game"
+            );
+            continue;
+        };
+        match command {
+            PlayerCommand::MoveTo(position) => params.move_to(player_entity, *position),
+            PlayerCommand::StartMining { product } => {
+                params
+                    .commands
+                    .entity(player_entity)
+                    .insert(MineCountdown {
+                        timer: Timer::from_seconds(1.0, TimerMode::Repeating),
+                        product: product.clone(),
+                    })
+                    .remove::<MineStructureCountdown>();
+            }
+            PlayerCommand::StartMiningStructure { position } => {
+                params
+                    .commands
+                    .entity(player_entity)
+                    .insert(MineStructureCountdown {
+                        timer: Timer::from_seconds(STRUCTURE_MINING_TIME, TimerMode::Repeating),
+                        position: *position,
+                    })
+                    .remove::<MineCountdown>();
+            }
+            PlayerCommand::StopMining => {
+                params
+                    .commands
+                    .entity(player_entity)
+                    .remove::<(MineCountdown, MineStructureCountdown)>();
+            }
+            PlayerCommand::Craft { recipe } => params.craft(player_entity, recipe),
+            PlayerCommand::CancelCraft { index } => params.cancel_craft(player_entity, *index),
+            PlayerCommand::MoveStack { from, to } => params.move_stack(*from, *to),
+            PlayerCommand::PickUp { range } => params.pick_up(player_entity, *range),
+            PlayerCommand::ChangeAssemblerRecipe { position, recipe } => params
+                .change_assembler_recipe(
+                    *position,
+                    recipe,
+                    ConstructionCause::player(player_entity, tick.0),
+                ),
+            PlayerCommand::ChangeSplitterSettings {
+                position,
+                priority,
+                filter,
+            } => {
+                if let Some(entity) = params.locations.structure(*position) {
+                    params
+                        .splitter_settings_events
+                        .send(ChangeSplitterSettingsEvent {
+                            entity,
+                            priority: *priority,
+                            filter: filter.clone(),
+                        });
+                }
+            }
+            PlayerCommand::ChangeInserterFilter { position, filter } => {
+                if let Some(entity) = params.locations.structure(*position) {
+                    params
+                        .inserter_filter_events
+                        .send(ChangeInserterFilterEvent {
+                            entity,
+                            filter: filter.clone(),
+                        });
+                }
+            }
+            PlayerCommand::QueueResearch { technology } => {
+                params.queue_research_events.send(QueueResearchEvent {
+                    technology: technology.clone(),
+                })
+            }
+            PlayerCommand::PlaceStructure {
+                structure,
+                position,
+                rotation,
+            } => params.place_structure_events.send(PlaceStructureEvent {
+                cause: ConstructionCause::player(player_entity, tick.0),
+                placement: StructurePlacement {
+                    structure: structure.clone(),
+                    position: *position,
+                    rotation: *rotation,
+                    recipe: None,
+                },
+            }),
+            PlayerCommand::Undo => params.undo(player_entity, tick.0, ActionOrigin::Undo),
+            PlayerCommand::Redo => params.undo(player_entity, tick.0, ActionOrigin::Redo),
+            PlayerCommand::RotateStructure { position } => {
+                params.rotate(player_entity, tick.0, *position)
+            }
+            // Ghosts are placed by the game, like structures
+            PlayerCommand::PlaceGhost { .. } => {}
+            // Marks are applied by the deconstruction systems
+            PlayerCommand::MarkForDeconstruction { .. }
+            | PlayerCommand::CancelDeconstruction { .. } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        discrete_rotation::{CompassDirection, SideCount},
+        inventory::{Stack, Storage},
+        recipe::{Recipe, SMELTING_CATEGORY},
+        research::{Technology, TechnologyEffect},
+    };
+
+    use super::*;
+
+    fn player_command_app() -> App {
+        let mut app = App::new();
+        app.init_resource::<SimulationTick>()
+            .init_resource::<CommandSource>()
+            .init_resource::<LocalCommands>()
+            .init_resource::<ScheduledCommands>()
+            .init_resource::<Recipes>()
+            .init_resource::<ResearchQueue>()
+            .init_resource::<Technologies>()
+            .add_event::<ChangeAssemblerRecipeEvent>()
+            .add_event::<ChangeSplitterSettingsEvent>()
+            .add_event::<ChangeInserterFilterEvent>()
+            .add_event::<QueueResearchEvent>()
+            .add_event::<PlaceStructureEvent>()
+            .add_event::<RotateStructureEvent>()
+            .add_event::<RemoveStructureEvent>()
+            .add_systems(
+                Update,
+                (schedule_local_commands, apply_player_commands).chain(),
+            );
+        app
+    }
+
+    fn spawn_player(app: &mut App, id: u32, inventory: Inventory) -> Entity {
+        app.world
+            .spawn((
+                Player,
+                PlayerId(id),
+                Transform::default(),
+                SimulatedPosition::default(),
+                inventory,
+                CraftingQueue::default(),
+            ))
+            .id()
+    }
+
+    #[test]
+    fn rotate_structure_turns_it_clockwise() {
+        let mut app = player_command_app();
+        let player = spawn_player(&mut app, 0, Inventory::new(1));
+        app.world.entity_mut(player).insert(LocalPlayer);
+        let structure = app
+            .world
+            .spawn((
+                Building,
+                Transform::from_xyz(2., 3., 1.),
+                DiscreteRotation::new(SideCount::Four),
+            ))
+            .id();
+
+        app.world
+            .resource_mut::<LocalCommands>()
+            .push(PlayerCommand::RotateStructure {
+                position: Vec2::new(2., 3.),
+            });
+        app.update();
+
+        let events = app.world.resource::<Events<RotateStructureEvent>>();
+        let rotations: Vec<_> = events
+            .get_reader()
+            .read(events)
+            .map(|event| (event.structure, event.rotation.compass_direction()))
+            .collect();
+        assert_eq!(rotations, vec![(structure, CompassDirection::East)]);
+    }
+
+    #[test]
+    fn move_stack_from_player_to_structure() {
+        let mut app = player_command_app();
+        let mut player_inventory = Inventory::new(10);
+        player_inventory.add_item(&Item::new("Wood"), 5);
+        let player = spawn_player(&mut app, 0, player_inventory);
+        app.world.entity_mut(player).insert(LocalPlayer);
+        let chest_inventory = app.world.spawn((Storage, Inventory::new(4))).id();
+        app.world
+            .spawn((Building, Transform::from_xyz(2., 3., 1.)))
+            .add_child(chest_inventory);
+
+        app.world
+            .resource_mut::<LocalCommands>()
+            .push(PlayerCommand::MoveStack {
+                from: InventorySlot {
+                    inventory: InventoryLocation::Player(PlayerId(0)),
+                    slot: 0,
+                },
+                to: InventorySlot {
+                    inventory: InventoryLocation::Structure {
+                        position: Vec2::new(2., 3.),
+                        inventory: InventoryType::Storage,
+                    },
+                    slot: 2,
+                },
+            });
+        app.update();
+
+        assert_eq!(app.world.get::<Inventory>(player).unwrap().slots[0], None);
+        assert_eq!(
+            app.world.get::<Inventory>(chest_inventory).unwrap().slots[2],
+            Some(Stack::new(Item::new("Wood"), 5))
+        );
+    }
+
+    #[test]
+    fn scheduled_commands_apply_to_their_player() {
+        let mut app = player_command_app();
+        app.world.resource_mut::<Recipes>().insert(
+            "Wooden chest".to_string(),
+            Recipe {
+                name: "Wooden chest".to_string(),
+                ingredients: vec![(Item::new("Wood"), 2)],
+                products: vec![(Item::new("Wooden chest"), 1)],
+                crafting_time: 0.5,
+                category: CRAFTING_CATEGORY.to_string(),
+            },
+        );
+        let mut inventory = Inventory::new(10);
+        inventory.add_item(&Item::new("Wood"), 3);
+        let first = spawn_player(&mut app, 0, inventory.clone());
+        let second = spawn_player(&mut app, 1, inventory);
+        app.world.resource_mut::<ScheduledCommands>().schedule(
+            0,
+            [
+                (
+                    PlayerId(1),
+                    PlayerCommand::Craft {
+                        recipe: "Wooden chest".to_string(),
+                    },
+                ),
+                (PlayerId(1), PlayerCommand::MoveTo(Vec2::new(4., 5.))),
+            ],
+        );
+        app.update();
+
+        assert_eq!(
+            app.world
+                .get::<Inventory>(first)
+                .unwrap()
+                .num_items(&Item::new("Wood")),
+            3
+        );
+        assert_eq!(app.world.get::<CraftingQueue>(first).unwrap().0.len(), 0);
+        assert_eq!(
+            app.world
+                .get::<Inventory>(second)
+                .unwrap()
+                .num_items(&Item::new("Wood")),
+            1
+        );
+        assert_eq!(app.world.get::<CraftingQueue>(second).unwrap().0.len(), 1);
+        assert_eq!(
+            app.world.get::<Transform>(second).unwrap().translation,
+            Vec3::new(4., 5., 0.)
+        );
+        assert_eq!(
+            app.world.get::<SimulatedPosition>(second).unwrap().0,
+            Vec2::new(4., 5.)
+        );
+    }
+
+    #[test]
+    fn pick_up_near_the_simulated_position_within_the_key_range() {
+        let mut app = player_command_app();
+        let player = spawn_player(&mut app, 0, Inventory::new(10));
+        // The local player has walked ahead of where the simulation has it
+        app.world
+            .entity_mut(player)
+            .insert((LocalPlayer, Transform::from_xyz(10., 0., 0.)));
+        let ground_items: Vec<_> = [1., 3., 10.]
+            .into_iter()
+            .map(|x| {
+                app.world
+                    .spawn((
+                        GroundItem {
+                            stack: Stack::new(Item::new("Wood"), 1),
+                        },
+                        GlobalTransform::from_xyz(x, 0., 0.5),
+                    ))
+                    .id()
+            })
+            .collect();
+
+        app.world
+            .resource_mut::<LocalCommands>()
+            .push(PlayerCommand::PickUp { range: 100. });
+        app.update();
+
+        assert_eq!(
+            app.world
+                .get::<Inventory>(player)
+                .unwrap()
+                .num_items(&Item::new("Wood")),
+            1
+        );
+        let amounts: Vec<_> = ground_items
+            .iter()
+            .map(|entity| app.world.get::<GroundItem>(*entity).unwrap().stack.amount)
+            .collect();
+        assert_eq!(amounts, vec![0, 1, 1]);
+    }
+
+    fn add_recipe(app: &mut App, name: &str, category: &str) {
+        app.world.resource_mut::<Recipes>().insert(
+            name.to_string(),
+            Recipe {
+                name: name.to_string(),
+                ingredients: vec![(Item::new("Iron plate"), 1)],
+                products: vec![(Item::new(name.to_string()), 1)],
+                crafting_time: 0.5,
+                category: category.to_string(),
+            },
+        );
+    }
+
+    #[test]
+    fn craft_only_unlocked_hand_crafting_recipes() {
+        let mut app = player_command_app();
+        add_recipe(&mut app, "Iron gear wheel", CRAFTING_CATEGORY);
+        add_recipe(&mut app, "Steel plate", SMELTING_CATEGORY);
+        add_recipe(&mut app, "Splitter", CRAFTING_CATEGORY);
+        app.world.resource_mut::<Technologies>().insert(
+            "Logistics".to_string(),
+            Technology {
+                name: "Logistics".to_string(),
+                prerequisites: vec![],
+                cost: vec![],
+                units: 1,
+                time: 1.,
+                effects: vec![TechnologyEffect::UnlockRecipe("Splitter".to_string())],
+            },
+        );
+        let mut inventory = Inventory::new(10);
+        inventory.add_item(&Item::new("Iron plate"), 10);
+        let player = spawn_player(&mut app, 0, inventory);
+        let craft = |recipe: &str| {
+            (
+                PlayerId(0),
+                PlayerCommand::Craft {
+                    recipe: recipe.to_string(),
+                },
+            )
+        };
+        app.world.resource_mut::<ScheduledCommands>().schedule(
+            0,
+            [
+                craft("Steel plate"),
+                craft("Splitter"),
+                craft("Iron gear wheel"),
+            ],
+        );
+        app.update();
+
+        let queue = &app.world.get::<CraftingQueue>(player).unwrap().0;
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue[0].recipe.name, "Iron gear wheel");
+
+        app.world
+            .resource_mut::<ResearchQueue>()
+            .researched
+            .insert("Logistics".to_string());
+        app.world.resource_mut::<SimulationTick>().0 = 1;
+        app.world
+            .resource_mut::<ScheduledCommands>()
+            .schedule(1, [craft("Splitter")]);
+        app.update();
+
+        let queue = &app.world.get::<CraftingQueue>(player).unwrap().0;
+        assert_eq!(queue.len(), 2);
+        assert_eq!(
+            app.world
+                .get::<Inventory>(player)
+                .unwrap()
+                .num_items(&Item::new("Iron plate")),
+            8
+        );
+    }
+}
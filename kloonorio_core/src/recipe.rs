@@ -3,19 +3,37 @@ use std::ops::{Deref, DerefMut};
 use bevy::{
     ecs::system::Resource,
     reflect::{Reflect, TypeUuid},
-    utils::HashMap,
+    utils::{HashMap, HashSet},
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::item::Item;
 
-#[derive(Clone, Debug, Deserialize, TypeUuid, Reflect)]
+/// Category of recipes that don't specify one. These are the only recipes the character can
+/// craft by hand.
+pub const CRAFTING_CATEGORY: &str = "crafting";
+/// Category of smelting recipes, which furnaces pick from by themselves
+pub const SMELTING_CATEGORY: &str = "smelting";
+
+fn default_category() -> String {
+    CRAFTING_CATEGORY.to_string()
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, TypeUuid, Reflect)]
 #[uuid = "1ca725c1-5a0d-484f-8d04-a5a42960e208"]
 pub struct Recipe {
     pub ingredients: Vec<(Item, u32)>,
     pub products: Vec<(Item, u32)>,
     pub crafting_time: f32,
     pub name: String,
+    #[serde(default = "default_category")]
+    pub category: String,
+}
+
+impl Recipe {
+    pub fn in_any_category(&self, categories: &HashSet<String>) -> bool {
+        categories.contains(&self.category)
+    }
 }
 
 #[derive(Resource, Default, Reflect)]
@@ -34,3 +52,13 @@ impl DerefMut for Recipes {
         &mut self.0
     }
 }
+
+impl Recipes {
+    pub fn in_any_category<'a>(
+        &'a self,
+        categories: &'a HashSet<String>,
+    ) -> impl Iterator<Item = &'a Recipe> {
+        self.values()
+            .filter(move |recipe| recipe.in_any_category(categories))
+    }
+}
@@ -0,0 +1,245 @@
+use std::{
+    collections::VecDeque,
+    ops::{Deref, DerefMut},
+};
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{item::Item, player_command::apply_player_commands, simulation::SimulationSet};
+
+pub struct ResearchPlugin;
+
+impl Plugin for ResearchPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<ResearchQueue>()
+            .init_resource::<ResearchQueue>()
+            .add_event::<QueueResearchEvent>()
+            .add_systems(
+                FixedUpdate,
+                queue_research
+                    .after(apply_player_commands)
+                    .in_set(SimulationSet::Commands),
+            );
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Reflect)]
+pub enum TechnologyEffect {
+    UnlockRecipe(String),
+}
+
+#[derive(Clone, Debug, Deserialize, Reflect)]
+pub struct Technology {
+    pub name: String,
+    /// Technologies that need to be researched before this one
+    #[serde(default)]
+    pub prerequisites: Vec<String>,
+    /// Science packs used up by a lab for each unit of research
+    pub cost: Vec<(Item, u32)>,
+    pub units: u32,
+    /// Seconds it takes a lab to research a single unit
+    pub time: f32,
+    pub effects: Vec<TechnologyEffect>,
+}
+
+impl Technology {
+    pub fn unlocks_recipe(&self, recipe: &str) -> bool {
+        self.effects.iter().any(|effect| match effect {
+            TechnologyEffect::UnlockRecipe(unlocked) => unlocked == recipe,
+        })
+    }
+}
+
+#[derive(Resource, Default, Reflect)]
+pub struct Technologies(HashMap<String, Technology>);
+
+impl Deref for Technologies {
+    type Target = HashMap<String, Technology>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for Technologies {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Technologies {
+    /// Science packs used by any technology, the only items labs accept
+    pub fn science_packs(&self) -> HashSet<Item> {
+        self.values()
+            .flat_map(|technology| technology.cost.iter().map(|(item, _)| item.clone()))
+            .collect()
+    }
+}
+
+/// Technologies that are researched by the labs one after the other, and the ones that are done
+#[derive(Resource, Clone, Default, Debug, Reflect, Serialize, Deserialize)]
+pub struct ResearchQueue {
+    pub queue: VecDeque<String>,
+    /// Units of the first technology in the queue that are done
+    pub progress: u32,
+    pub researched: HashSet<String>,
+}
+
+impl ResearchQueue {
+    pub fn current(&self) -> Option<&String> {
+        self.queue.front()
+    }
+
+    pub fn is_researched(&self, technology: &str) -> bool {
+        self.researched.contains(technology)
+    }
+
+    /// A technology can be queued once all its prerequisites are researched or queued
+    pub fn can_queue(&self, technology: &Technology) -> bool {
+        !self.is_researched(&technology.name)
+            && !self.queue.contains(&technology.name)
+            && technology.prerequisites.iter().all(|prerequisite| {
+                self.is_researched(prerequisite) || self.queue.contains(prerequisite)
+            })
+    }
+
+    /// Recipes that no technology unlocks are available from the start
+    pub fn is_recipe_unlocked(&self, recipe: &str, technologies: &Technologies) -> bool {
+        let mut unlocking_technologies = technologies
+            .values()
+            .filter(|technology| technology.unlocks_recipe(recipe))
+            .peekable();
+        unlocking_technologies.peek().is_none()
+            || unlocking_technologies.any(|technology| self.is_researched(&technology.name))
+    }
+
+    /// Add a finished unit of research to the current technology. Returns the technology if it
+    /// is done now.
+    pub fn add_progress(&mut self, technologies: &Technologies) -> Option<String> {
+        let technology = technologies.get(self.current()?)?;
+        self.progress += 1;
+        if self.progress < technology.units {
+            return None;
+        }
+        self.progress = 0;
+        let researched = self.queue.pop_front()?;
+        self.researched.insert(researched.clone());
+        Some(researched)
+    }
+}
+
+#[derive(Debug, Event)]
+pub struct QueueResearchEvent {
+    pub technology: String,
+}
+
+pub fn queue_research(
+    mut queue_research_events: EventReader<QueueResearchEvent>,
+    mut research_queue: ResMut<ResearchQueue>,
+    technologies: Res<Technologies>,
+) {
+    for event in queue_research_events.read() {
+        let Some(technology) = technologies.get(&event.technology) else {
+            warn!("Unknown technology {}", event.technology);
+            continue;
+        };
+        if !research_queue.can_queue(technology) {
+            warn!("Can't queue {} for research", technology.name);
+            continue;
+        }
+        research_queue.queue.push_back(technology.name.clone());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn technology(name: &str, prerequisites: &[&str], recipes: &[&str]) -> Technology {
+        Technology {
+            name: name.to_string(),
+            prerequisites: prerequisites.iter().map(|p| p.to_string()).collect(),
+            cost: vec![(Item::new("Automation science pack"), 1)],
+            units: 2,
+            time: 1.,
+            effects: recipes
+                .iter()
+                .map(|recipe| TechnologyEffect::UnlockRecipe(recipe.to_string()))
+                .collect(),
+        }
+    }
+
+    fn technologies() -> Technologies {
+        let mut technologies = Technologies::default();
+        for technology in [
+            technology("Automation", &[], &["Assembling machine"]),
+            technology("Logistics", &[], &["Splitter"]),
+            technology("Logistics 2", &["Logistics"], &["Splitter"]),
+        ] {
+            technologies.insert(technology.name.clone(), technology);
+        }
+        technologies
+    }
+
+    #[test]
+    fn recipes_are_locked_until_researched() {
+        let technologies = technologies();
+        let mut research_queue = ResearchQueue::default();
+
+        assert!(research_queue.is_recipe_unlocked("Iron gear wheel", &technologies));
+        assert!(!research_queue.is_recipe_unlocked("Assembling machine", &technologies));
+        assert!(!research_queue.is_recipe_unlocked("Splitter", &technologies));
+
+        research_queue.researched.insert("Automation".to_string());
+        assert!(research_queue.is_recipe_unlocked("Assembling machine", &technologies));
+
+        // Any of the technologies unlocking a recipe will do
+        research_queue.researched.insert("Logistics".to_string());
+        assert!(research_queue.is_recipe_unlocked("Splitter", &technologies));
+    }
+
+    #[test]
+    fn prerequisites_are_researched_first() {
+        let mut app = App::new();
+        app.insert_resource(technologies())
+            .add_plugins(ResearchPlugin);
+
+        app.world.send_event(QueueResearchEvent {
+            technology: "Logistics 2".to_string(),
+        });
+        app.world.run_schedule(FixedUpdate);
+        assert!(app.world.resource::<ResearchQueue>().queue.is_empty());
+
+        app.world.send_event(QueueResearchEvent {
+            technology: "Logistics".to_string(),
+        });
+        app.world.send_event(QueueResearchEvent {
+            technology: "Logistics 2".to_string(),
+        });
+        app.world.run_schedule(FixedUpdate);
+        assert_eq!(
+            app.world.resource::<ResearchQueue>().queue,
+            ["Logistics", "Logistics 2"]
+        );
+    }
+
+    #[test]
+    fn research_is_done_after_all_units() {
+        let technologies = technologies();
+        let mut research_queue = ResearchQueue::default();
+        research_queue.queue.push_back("Automation".to_string());
+
+        assert_eq!(research_queue.add_progress(&technologies), None);
+        assert_eq!(
+            research_queue.add_progress(&technologies),
+            Some("Automation".to_string())
+        );
+        assert!(research_queue.is_researched("Automation"));
+        assert!(research_queue.queue.is_empty());
+        assert_eq!(research_queue.add_progress(&technologies), None);
+    }
+}
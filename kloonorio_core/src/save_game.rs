@@ -0,0 +1,934 @@
+use std::time::Duration;
+
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    discrete_rotation::DiscreteRotation,
+    entity_set::EntitySet,
+    ground_item::{spawn_ground_item, GroundItem},
+    inventory::{Inventory, InventoryKind, InventoryType, Stack},
+    item::Item,
+    player::LocalPlayer,
+    recipe::Recipe,
+    research::ResearchQueue,
+    spawn_order::SpawnOrders,
+    structure_components::{
+        assembler::Assembler,
+        burner::Burner,
+        fluid::FluidBox,
+        inserter::{Inserter, InserterFilter},
+        lab::Lab,
+        miner::Miner,
+        splitter::{Splitter, SplitterSide},
+        transport_belt::{
+            BeltLane, BeltParams, BeltSegment, NextBelt, PreviousBelts, TransportBelt,
+            UndergroundBelt, UndergroundBeltKind, BELT_SLOTS,
+        },
+    },
+    types::{ActiveCraft, AppState, Building, CraftingQueue, Powered},
+};
+
+pub struct SaveGamePlugin;
+
+impl Plugin for SaveGamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SaveGameEvent>()
+            .add_event::<LoadGameEvent>()
+            .add_systems(
+                Update,
+                restore_loaded_structures.run_if(in_state(AppState::Running)),
+            );
+    }
+}
+
+/// Write the current game to the save file
+#[derive(Debug, Event)]
+pub struct SaveGameEvent;
+
+/// Replace the current game with the one in the save file
+#[derive(Debug, Event)]
+pub struct LoadGameEvent;
+
+/// Index of a structure in `SaveGame::structures`. Saved structures refer to each other by index,
+/// as entities are different every time the game is loaded.
+pub type StructureId = usize;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SaveGame {
+    pub terrain: SavedTerrain,
+    pub player: SavedPlayer,
+    pub structures: Vec<SavedStructure>,
+    #[serde(default)]
+    pub research: ResearchQueue,
+    #[serde(default)]
+    pub ground_items: Vec<SavedGroundItem>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SavedTerrain {
+    pub seed: u32,
+    /// Tiles that no longer match the generated terrain, by tile position
+    #[serde(default)]
+    pub modified_tiles: Vec<(IVec2, u32)>,
+}
+
+/// Items lying on the ground, with the centre of their tile
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedGroundItem {
+    pub position: Vec2,
+    pub stack: Stack,
+}
+
+/// Spawn the ground items of a save game
+pub fn spawn_saved_ground_items(commands: &mut Commands, ground_items: &[SavedGroundItem]) {
+    for saved in ground_items {
+        spawn_ground_item(commands, saved.stack.clone(), saved.position);
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedPlayer {
+    pub position: Vec2,
+    pub inventory: Inventory,
+    #[serde(default)]
+    pub crafting_queue: Vec<SavedCraft>,
+}
+
+/// A craft in a crafting queue, along with the seconds spent on it so far
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedCraft {
+    pub recipe: Recipe,
+    pub elapsed: f32,
+}
+
+pub fn saved_crafting_queue(crafting_queue: &CraftingQueue) -> Vec<SavedCraft> {
+    crafting_queue
+        .0
+        .iter()
+        .map(|active_craft| SavedCraft {
+            recipe: active_craft.recipe.clone(),
+            elapsed: active_craft.timer.elapsed_secs(),
+        })
+        .collect()
+}
+
+pub fn restored_crafting_queue(saved_crafts: &[SavedCraft]) -> CraftingQueue {
+    CraftingQueue(
+        saved_crafts
+            .iter()
+            .map(|saved_craft| {
+                let mut timer =
+                    Timer::from_seconds(saved_craft.recipe.crafting_time, TimerMode::Repeating);
+                timer.set_elapsed(Duration::from_secs_f32(saved_craft.elapsed));
+                ActiveCraft {
+                    recipe: saved_craft.recipe.clone(),
+                    timer,
+                }
+            })
+            .collect(),
+    )
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedStructure {
+    pub name: String,
+    pub position: Vec2,
+    pub rotation: DiscreteRotation,
+    #[serde(default)]
+    pub inventories: Vec<(InventoryType, Inventory)>,
+    #[serde(default)]
+    pub crafting_queue: Vec<SavedCraft>,
+    #[serde(default)]
+    pub assembler_recipe: Option<Recipe>,
+    #[serde(default)]
+    pub inserter: Option<SavedInserter>,
+    #[serde(default)]
+    pub belt: Option<SavedBelt>,
+    #[serde(default)]
+    pub splitter: Option<SavedSplitter>,
+    #[serde(default)]
+    pub burner: Option<SavedBurner>,
+    /// The contents of the fluid boxes, in the order the structure has them
+    #[serde(default)]
+    pub fluid_boxes: Vec<SavedFluidBox>,
+    /// Progress of the unit of research the lab is working on
+    #[serde(default)]
+    pub lab_progress: Option<f32>,
+    /// Seconds the miner spent mining its next item
+    #[serde(default)]
+    pub miner_progress: Option<f32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedBurner {
+    pub energy: f32,
+    pub fuel_value: f32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedFluidBox {
+    pub fluid: Option<String>,
+    pub amount: f32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedInserter {
+    pub holding: Option<Stack>,
+    pub arm_position: f32,
+    pub filter: Option<InserterFilter>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedBelt {
+    pub next: Option<StructureId>,
+    pub previous: Vec<StructureId>,
+    pub underground: Option<SavedUndergroundBelt>,
+    /// The items on the belt by lane and slot
+    pub items: Vec<(BeltLane, usize, Item)>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedUndergroundBelt {
+    pub kind: UndergroundBeltKind,
+    pub max_distance: u32,
+    pub partner: Option<StructureId>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedSplitter {
+    pub priority: Option<SplitterSide>,
+    pub filter: Option<Item>,
+    pub outputs: Vec<(SplitterSide, StructureId)>,
+    pub inputs: Vec<StructureId>,
+    /// Items waiting in the splitter by lane
+    pub items: Vec<(BeltLane, Item)>,
+}
+
+/// Everything needed to write the structures, the player and the research to a save game
+#[derive(SystemParam)]
+pub struct SaveParams<'w, 's> {
+    structure_query: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Name,
+            &'static Transform,
+            &'static DiscreteRotation,
+        ),
+        With<Building>,
+    >,
+    children_query: Query<'w, 's, &'static Children>,
+    inventory_query: Query<'w, 's, (&'static Inventory, InventoryKind)>,
+    crafting_queue_query: Query<'w, 's, &'static CraftingQueue>,
+    assembler_query: Query<'w, 's, &'static Assembler>,
+    inserter_query: Query<'w, 's, &'static Inserter>,
+    belt_query: Query<
+        'w,
+        's,
+        (Option<&'static NextBelt>, Option<&'static PreviousBelts>),
+        With<TransportBelt>,
+    >,
+    underground_belt_query: Query<'w, 's, &'static UndergroundBelt>,
+    splitter_query: Query<'w, 's, (&'static Splitter, Option<&'static PreviousBelts>)>,
+    belt_params: BeltParams<'w, 's>,
+    burner_query: Query<'w, 's, &'static Burner>,
+    fluid_box_query: Query<'w, 's, &'static FluidBox>,
+    lab_query: Query<'w, 's, &'static Lab>,
+    miner_query: Query<'w, 's, &'static Miner>,
+    ground_item_query: Query<'w, 's, (&'static Transform, &'static GroundItem)>,
+    player_query: Query<
+        'w,
+        's,
+        (
+            &'static Transform,
+            &'static Inventory,
+            &'static CraftingQueue,
+        ),
+        With<LocalPlayer>,
+    >,
+    research_queue: Res<'w, ResearchQueue>,
+    spawn_orders: SpawnOrders<'w, 's>,
+}
+
+impl SaveParams<'_, '_> {
+    pub fn save_game(&self, terrain: SavedTerrain) -> SaveGame {
+        let (player_transform, player_inventory, player_crafting_queue) =
+            self.player_query.single();
+        SaveGame {
+            terrain,
+            player: SavedPlayer {
+                position: player_transform.translation.truncate(),
+                inventory: player_inventory.clone(),
+                crafting_queue: saved_crafting_queue(player_crafting_queue),
+            },
+            structures: self.saved_structures(),
+            research: ResearchQueue {
+                queue: self.research_queue.queue.clone(),
+                progress: self.research_queue.progress,
+                researched: self.research_queue.researched.clone(),
+            },
+            ground_items: self.saved_ground_items(),
+        }
+    }
+
+    /// Ground items by position and item, which is the same on every client
+    pub(crate) fn saved_ground_items(&self) -> Vec<SavedGroundItem> {
+        let mut ground_items: Vec<SavedGroundItem> = self
+            .ground_item_query
+            .iter()
+            .filter(|(_, ground_item)| !ground_item.is_empty())
+            .map(|(transform, ground_item)| SavedGroundItem {
+                position: transform.translation.truncate(),
+                stack: ground_item.stack.clone(),
+            })
+            .collect();
+        ground_items.sort_by(|a, b| {
+            a.position
+                .y
+                .total_cmp(&b.position.y)
+                .then(a.position.x.total_cmp(&b.position.x))
+                .then_with(|| a.stack.item.as_ref().cmp(b.stack.item.as_ref()))
+        });
+        ground_items
+    }
+
+    pub(crate) fn saved_structures(&self) -> Vec<SavedStructure> {
+        // Structures are saved in spawn order, which they get back when they are loaded
+        let entities = self
+            .spawn_orders
+            .sorted(self.structure_query.iter().map(|(entity, ..)| entity));
+        let ids: HashMap<Entity, StructureId> = entities
+            .iter()
+            .enumerate()
+            .map(|(id, entity)| (*entity, id))
+            .collect();
+        // Links to entities that aren't saved structures are dropped
+        let linked_ids = |entities: &mut dyn Iterator<Item = &Entity>| -> Vec<StructureId> {
+            entities
+                .filter_map(|entity| ids.get(entity).copied())
+                .collect()
+        };
+
+        self.structure_query
+            .iter_many(&entities)
+            .map(|(entity, name, transform, rotation)| {
+                let inventories = self
+                    .children_query
+                    .get(entity)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|child| self.inventory_query.get(*child).ok())
+                    .filter_map(|(inventory, kind)| {
+                        kind.inventory_type()
+                            .map(|inventory_type| (inventory_type, inventory.clone()))
+                    })
+                    .collect();
+
+                let belt = self
+                    .belt_query
+                    .get(entity)
+                    .ok()
+                    .map(|(next_belt, previous_belts)| SavedBelt {
+                        next: next_belt.and_then(|next_belt| ids.get(&next_belt.0).copied()),
+                        previous: previous_belts.map_or_else(Vec::new, |previous_belts| {
+                            linked_ids(&mut previous_belts.belts.iter())
+                        }),
+                        underground: self.underground_belt_query.get(entity).ok().map(
+                            |underground_belt| SavedUndergroundBelt {
+                                kind: underground_belt.kind,
+                                max_distance: underground_belt.max_distance,
+                                partner: underground_belt
+                                    .partner
+                                    .and_then(|partner| ids.get(&partner).copied()),
+                            },
+                        ),
+                        items: BeltLane::ALL
+                            .into_iter()
+                            .flat_map(|lane| {
+                                (0..BELT_SLOTS).filter_map(move |slot| {
+                                    self.belt_params
+                                        .slot(entity, lane, slot)
+                                        .map(|item| (lane, slot, item.clone()))
+                                })
+                            })
+                            .collect(),
+                    });
+
+                let splitter =
+                    self.splitter_query
+                        .get(entity)
+                        .ok()
+                        .map(|(splitter, previous_belts)| SavedSplitter {
+                            priority: splitter.priority,
+                            filter: splitter.filter.clone(),
+                            outputs: SplitterSide::ALL
+                                .into_iter()
+                                .filter_map(|side| {
+                                    splitter
+                                        .output(side)
+                                        .and_then(|output| ids.get(&output).copied())
+                                        .map(|output| (side, output))
+                                })
+                                .collect(),
+                            inputs: previous_belts.map_or_else(Vec::new, |previous_belts| {
+                                linked_ids(&mut previous_belts.belts.iter())
+                            }),
+                            items: BeltLane::ALL
+                                .into_iter()
+                                .flat_map(|lane| {
+                                    splitter.items(lane).map(move |item| (lane, item.clone()))
+                                })
+                                .collect(),
+                        });
+
+                SavedStructure {
+                    name: name.to_string(),
+                    position: transform.translation.truncate(),
+                    rotation: *rotation,
+                    inventories,
+                    crafting_queue: self
+                        .crafting_queue_query
+                        .get(entity)
+                        .map_or_else(|_| Vec::new(), saved_crafting_queue),
+                    assembler_recipe: self
+                        .assembler_query
+                        .get(entity)
+                        .ok()
+                        .and_then(|assembler| assembler.recipe.clone()),
+                    inserter: self
+                        .inserter_query
+                        .get(entity)
+                        .ok()
+                        .map(|inserter| SavedInserter {
+                            holding: inserter.holding().cloned(),
+                            arm_position: inserter.arm_position(),
+                            filter: inserter.filter().cloned(),
+                        }),
+                    belt,
+                    splitter,
+                    burner: self
+                        .burner_query
+                        .get(entity)
+                        .ok()
+                        .map(|burner| SavedBurner {
+                            energy: burner.energy,
+                            fuel_value: burner.fuel_value,
+                        }),
+                    fluid_boxes: self
+                        .children_query
+                        .get(entity)
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|child| self.fluid_box_query.get(*child).ok())
+                        .map(|fluid_box| SavedFluidBox {
+                            fluid: fluid_box.fluid.clone(),
+                            amount: fluid_box.amount,
+                        })
+                        .collect(),
+                    lab_progress: self
+                        .lab_query
+                        .get(entity)
+                        .ok()
+                        .and_then(|lab| lab.unit_progress),
+                    miner_progress: self.miner_query.get(entity).ok().map(Miner::progress),
+                }
+            })
+            .collect()
+    }
+}
+
+/// A structure spawned from a save game. Its state is restored once the structure has been built.
+#[derive(Component, Debug)]
+pub struct LoadedStructure(pub SavedStructure);
+
+/// Link a loaded structure to the other loaded structures. `entities` holds the new entity of
+/// every saved structure, in the order they were saved in.
+pub fn link_loaded_structure(
+    commands: &mut Commands,
+    entity: Entity,
+    saved: &SavedStructure,
+    entities: &[Entity],
+) {
+    let linked = |ids: &[StructureId]| -> EntitySet {
+        ids.iter()
+            .filter_map(|id| entities.get(*id).copied())
+            .collect()
+    };
+
+    if let Some(saved_belt) = &saved.belt {
+        // The belt starts out as a segment of its own holding its items, the segments are
+        // merged again once all belts are linked
+        let mut segment = BeltSegment::new(vec![entity]);
+        for (lane, slot, item) in &saved_belt.items {
+            segment.add(*lane, *slot, item.clone());
+        }
+        let segment_entity = commands.spawn(segment).id();
+
+        let mut entity_commands = commands.entity(entity);
+        entity_commands.insert((
+            TransportBelt::in_segment(segment_entity),
+            PreviousBelts {
+                belts: linked(&saved_belt.previous),
+            },
+        ));
+        if let Some(next) = saved_belt.next.and_then(|id| entities.get(id)) {
+            entity_commands.insert(NextBelt(*next));
+        }
+        if let Some(underground) = &saved_belt.underground {
+            entity_commands.insert(UndergroundBelt {
+                kind: underground.kind,
+                max_distance: underground.max_distance,
+                partner: underground.partner.and_then(|id| entities.get(id)).copied(),
+            });
+        }
+    }
+
+    if let Some(saved_splitter) = &saved.splitter {
+        let mut splitter = Splitter::default();
+        splitter.priority = saved_splitter.priority;
+        splitter.filter = saved_splitter.filter.clone();
+        for (side, output) in &saved_splitter.outputs {
+            splitter.set_output(*side, entities.get(*output).copied());
+        }
+        for (lane, item) in &saved_splitter.items {
+            splitter.push_item(*lane, item.clone());
+        }
+        commands.entity(entity).insert((
+            splitter,
+            PreviousBelts {
+                belts: linked(&saved_splitter.inputs),
+            },
+        ));
+    }
+
+    commands
+        .entity(entity)
+        .insert(LoadedStructure(saved.clone()));
+}
+
+/// A loaded structure along with the parts of it that are restored
+type LoadedStructureQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static LoadedStructure,
+        Option<&'static Children>,
+        Option<&'static mut Burner>,
+        Option<&'static mut Lab>,
+        Option<&'static mut Miner>,
+    ),
+>;
+
+/// Put the inventories, crafting queues, inserters, burners, fluid boxes, labs and miners of
+/// loaded structures back in the state they were saved in
+fn restore_loaded_structures(
+    mut commands: Commands,
+    mut loaded_query: LoadedStructureQuery,
+    mut inventory_query: Query<(&mut Inventory, InventoryKind)>,
+    mut crafting_queue_query: Query<&mut CraftingQueue>,
+    mut assembler_query: Query<&mut Assembler>,
+    mut inserter_query: Query<&mut Inserter>,
+    mut fluid_box_query: Query<&mut FluidBox>,
+) {
+    for (entity, LoadedStructure(saved), children, burner, lab, miner) in &mut loaded_query {
+        if saved.inserter.is_some() && !inserter_query.contains(entity) {
+            // Wait for the inserter to be built
+            continue;
+        }
+        if saved.miner_progress.is_some() && miner.is_none() {
+            // Wait for the miner to be built
+            continue;
+        }
+
+        for child in children.into_iter().flatten() {
+            let Ok((mut inventory, kind)) = inventory_query.get_mut(*child) else {
+                continue;
+            };
+            let saved_inventory = saved
+                .inventories
+                .iter()
+                .find(|(inventory_type, _)| Some(*inventory_type) == kind.inventory_type());
+            if let Some((_, saved_inventory)) = saved_inventory {
+                *inventory = saved_inventory.clone();
+            }
+        }
+        let mut fluid_boxes = fluid_box_query.iter_many_mut(children.into_iter().flatten());
+        for saved_fluid_box in &saved.fluid_boxes {
+            let Some(mut fluid_box) = fluid_boxes.fetch_next() else {
+                break;
+            };
+            fluid_box.fluid = saved_fluid_box.fluid.clone();
+            fluid_box.amount = saved_fluid_box.amount;
+        }
+        if let Ok(mut crafting_queue) = crafting_queue_query.get_mut(entity) {
+            *crafting_queue = restored_crafting_queue(&saved.crafting_queue);
+        }
+        if let Ok(mut assembler) = assembler_query.get_mut(entity) {
+            assembler.recipe = saved.assembler_recipe.clone();
+        }
+        if let (Ok(mut inserter), Some(saved_inserter)) =
+            (inserter_query.get_mut(entity), &saved.inserter)
+        {
+            inserter.restore(
+                saved_inserter.holding.clone(),
+                saved_inserter.arm_position,
+                saved_inserter.filter.clone(),
+            );
+        }
+        if let (Some(mut burner), Some(saved_burner)) = (burner, &saved.burner) {
+            burner.energy = saved_burner.energy;
+            burner.fuel_value = saved_burner.fuel_value;
+            // A burner is powered while there is energy left from its fuel
+            if burner.energy > 0. {
+                commands.entity(entity).insert(Powered);
+            }
+        }
+        if let Some(mut lab) = lab {
+            lab.unit_progress = saved.lab_progress;
+        }
+        if let (Some(mut miner), Some(progress)) = (miner, saved.miner_progress) {
+            miner.restore_progress(progress);
+        }
+
+        commands.entity(entity).remove::<LoadedStructure>();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::ecs::system::SystemState;
+
+    use crate::{
+        discrete_rotation::SideCount,
+        inventory::Storage,
+        player::Player,
+        structure_components::fluid::{STEAM, WATER},
+        structure_components::transport_belt::{rebuild_belt_segments, test_belt_slot},
+    };
+
+    use super::*;
+
+    fn save_game_app() -> App {
+        let mut app = App::new();
+        app.init_resource::<ResearchQueue>().add_systems(
+            Update,
+            (
+                rebuild_belt_segments,
+                apply_deferred,
+                restore_loaded_structures,
+            )
+                .chain(),
+        );
+        app.world.spawn((
+            Player,
+            LocalPlayer,
+            Transform::from_xyz(3., 4., 1.),
+            Inventory::new(10),
+            CraftingQueue::default(),
+        ));
+        app
+    }
+
+    fn spawn_structure(app: &mut App, name: &'static str, position: Vec2) -> Entity {
+        app.world
+            .spawn((
+                Name::new(name),
+                Building,
+                Transform::from_translation(position.extend(1.)),
+                DiscreteRotation::new(SideCount::Four),
+            ))
+            .id()
+    }
+
+    /// Save the game, write it to RON and read it back
+    fn save_and_reload(app: &mut App) -> SaveGame {
+        let mut system_state = SystemState::<SaveParams>::new(&mut app.world);
+        let save_game = system_state
+            .get_mut(&mut app.world)
+            .save_game(SavedTerrain::default());
+        let ron = ron::to_string(&save_game).unwrap();
+        ron::from_str(&ron).unwrap()
+    }
+
+    /// Despawn the structures and spawn new ones from the save game, like loading does. The
+    /// structure components besides a storage inventory are added by `components`.
+    fn load_structures(
+        app: &mut App,
+        save_game: &SaveGame,
+        components: impl Fn(&mut EntityWorldMut),
+    ) -> Vec<Entity> {
+        let old_structures: Vec<Entity> = app
+            .world
+            .query_filtered::<Entity, With<Building>>()
+            .iter(&app.world)
+            .collect();
+        for entity in old_structures {
+            app.world.entity_mut(entity).despawn_recursive();
+        }
+
+        let entities: Vec<Entity> = save_game
+            .structures
+            .iter()
+            .map(|saved| {
+                let mut structure = app.world.spawn((
+                    Building,
+                    Transform::from_translation(saved.position.extend(1.)),
+                    saved.rotation,
+                    CraftingQueue::default(),
+                ));
+                structure.with_children(|parent| {
+                    parent.spawn((Storage, Inventory::new(4)));
+                });
+                components(&mut structure);
+                structure.id()
+            })
+            .collect();
+        let mut system_state = SystemState::<Commands>::new(&mut app.world);
+        let mut commands = system_state.get_mut(&mut app.world);
+        for (entity, saved) in entities.iter().zip(&save_game.structures) {
+            link_loaded_structure(&mut commands, *entity, saved, &entities);
+        }
+        system_state.apply(&mut app.world);
+        app.update();
+        entities
+    }
+
+    #[test]
+    fn belts_are_relinked_with_their_items() {
+        let mut app = save_game_app();
+        let first = spawn_structure(&mut app, "Transport belt", Vec2::new(0., 0.));
+        let second = spawn_structure(&mut app, "Transport belt", Vec2::new(0., 1.));
+        app.world.entity_mut(first).insert((
+            TransportBelt::default(),
+            NextBelt(second),
+            PreviousBelts { belts: default() },
+        ));
+        app.world.entity_mut(second).insert((
+            TransportBelt::default(),
+            PreviousBelts {
+                belts: [first].into_iter().collect(),
+            },
+        ));
+        app.update();
+        let mut system_state = SystemState::<BeltParams>::new(&mut app.world);
+        let mut belt_params = system_state.get_mut(&mut app.world);
+        belt_params.add(first, BeltLane::Left, 1, Item::new("Iron plate"));
+        belt_params.add(second, BeltLane::Right, 2, Item::new("Coal"));
+
+        let save_game = save_and_reload(&mut app);
+        let entities = load_structures(&mut app, &save_game, |_| {});
+
+        let first_id = save_game
+            .structures
+            .iter()
+            .position(|saved| saved.position == Vec2::new(0., 0.))
+            .unwrap();
+        let (first, second) = (entities[first_id], entities[1 - first_id]);
+        assert_eq!(app.world.get::<NextBelt>(first).unwrap().0, second);
+        assert!(app
+            .world
+            .get::<PreviousBelts>(second)
+            .unwrap()
+            .belts
+            .contains(&first));
+        assert_eq!(
+            test_belt_slot(&app.world, first, BeltLane::Left, 1),
+            Some(Item::new("Iron plate"))
+        );
+        assert_eq!(
+            test_belt_slot(&app.world, second, BeltLane::Right, 2),
+            Some(Item::new("Coal"))
+        );
+        // Both belts are merged into one segment again
+        assert_eq!(
+            app.world
+                .get::<TransportBelt>(first)
+                .unwrap()
+                .segment()
+                .unwrap()
+                .0,
+            app.world
+                .get::<TransportBelt>(second)
+                .unwrap()
+                .segment()
+                .unwrap()
+                .0
+        );
+    }
+
+    #[test]
+    fn inventories_and_crafting_progress_are_restored() {
+        let mut app = save_game_app();
+        let chest = spawn_structure(&mut app, "Burner assembling machine", Vec2::ZERO);
+        let mut storage = Inventory::new(4);
+        storage.add_item(&Item::new("Iron plate"), 12);
+        let storage = app.world.spawn((Storage, storage)).id();
+        let recipe = Recipe {
+            ingredients: vec![(Item::new("Iron plate"), 2)],
+            products: vec![(Item::new("Iron gear wheel"), 1)],
+            crafting_time: 1.,
+            name: "Iron gear wheel".to_string(),
+            category: "crafting".to_string(),
+        };
+        let mut crafting_queue = CraftingQueue::default();
+        crafting_queue.0.push_back(ActiveCraft {
+            recipe: recipe.clone(),
+            timer: Timer::from_seconds(recipe.crafting_time, TimerMode::Repeating),
+        });
+        crafting_queue.0[0]
+            .timer
+            .tick(Duration::from_secs_f32(0.25));
+        app.world
+            .entity_mut(chest)
+            .insert(crafting_queue)
+            .push_children(&[storage]);
+
+        let save_game = save_and_reload(&mut app);
+        assert_eq!(save_game.player.position, Vec2::new(3., 4.));
+        let entities = load_structures(&mut app, &save_game, |_| {});
+
+        let children = app.world.get::<Children>(entities[0]).unwrap();
+        let storage = app.world.get::<Inventory>(children[0]).unwrap();
+        assert_eq!(storage.num_items(&Item::new("Iron plate")), 12);
+        let crafting_queue = app.world.get::<CraftingQueue>(entities[0]).unwrap();
+        assert_eq!(crafting_queue.0[0].recipe.name, "Iron gear wheel");
+        assert_eq!(crafting_queue.0[0].timer.elapsed_secs(), 0.25);
+        assert!(app.world.get::<LoadedStructure>(entities[0]).is_none());
+    }
+
+    #[test]
+    fn ground_items_are_restored() {
+        let mut app = save_game_app();
+        app.world.spawn((
+            GroundItem {
+                stack: Stack::new(Item::new("Coal"), 1),
+            },
+            Transform::from_xyz(2., 5., 0.5),
+        ));
+
+        let save_game = save_and_reload(&mut app);
+        let old_ground_items: Vec<Entity> = app
+            .world
+            .query_filtered::<Entity, With<GroundItem>>()
+            .iter(&app.world)
+            .collect();
+        for entity in old_ground_items {
+            app.world.despawn(entity);
+        }
+        let mut system_state = SystemState::<Commands>::new(&mut app.world);
+        let mut commands = system_state.get_mut(&mut app.world);
+        spawn_saved_ground_items(&mut commands, &save_game.ground_items);
+        system_state.apply(&mut app.world);
+
+        let mut ground_item_query = app.world.query::<(&Transform, &GroundItem)>();
+        let (transform, ground_item) = ground_item_query.single(&app.world);
+        assert_eq!(transform.translation.truncate(), Vec2::new(2., 5.));
+        assert_eq!(ground_item.stack, Stack::new(Item::new("Coal"), 1));
+    }
+
+    #[test]
+    fn burner_energy_is_restored() {
+        let mut app = save_game_app();
+        let furnace = spawn_structure(&mut app, "Stone furnace", Vec2::ZERO);
+        let mut burner = Burner::new(90.);
+        burner.energy = 1.5;
+        burner.fuel_value = 4.;
+        app.world.entity_mut(furnace).insert((burner, Powered));
+
+        let save_game = save_and_reload(&mut app);
+        let entities = load_structures(&mut app, &save_game, |structure| {
+            structure.insert(Burner::new(90.));
+        });
+
+        let burner = app.world.get::<Burner>(entities[0]).unwrap();
+        assert_eq!(burner.energy, 1.5);
+        assert_eq!(burner.fuel_value, 4.);
+        assert!(app.world.get::<Powered>(entities[0]).is_some());
+    }
+
+    #[test]
+    fn fluid_box_contents_are_restored() {
+        let mut app = save_game_app();
+        let boiler = spawn_structure(&mut app, "Boiler", Vec2::ZERO);
+        let mut water_box = FluidBox::new(100., Some(WATER.to_string()), vec![]);
+        water_box.add(WATER, 30.);
+        let mut steam_box = FluidBox::new(100., Some(STEAM.to_string()), vec![]);
+        steam_box.add(STEAM, 12.5);
+        let fluid_boxes = [
+            app.world.spawn(water_box).id(),
+            app.world.spawn(steam_box).id(),
+        ];
+        app.world.entity_mut(boiler).push_children(&fluid_boxes);
+
+        let save_game = save_and_reload(&mut app);
+        let entities = load_structures(&mut app, &save_game, |structure| {
+            structure.with_children(|parent| {
+                for fluid in [WATER, STEAM] {
+                    parent.spawn(FluidBox::new(100., Some(fluid.to_string()), vec![]));
+                }
+            });
+        });
+
+        let children = app.world.get::<Children>(entities[0]).unwrap();
+        let fluid_boxes: Vec<(Option<String>, f32)> = children
+            .iter()
+            .filter_map(|child| app.world.get::<FluidBox>(*child))
+            .map(|fluid_box| (fluid_box.fluid.clone(), fluid_box.amount))
+            .collect();
+        assert_eq!(
+            fluid_boxes,
+            vec![
+                (Some(WATER.to_string()), 30.),
+                (Some(STEAM.to_string()), 12.5)
+            ]
+        );
+    }
+
+    #[test]
+    fn lab_progress_is_restored() {
+        let mut app = save_game_app();
+        let lab_entity = spawn_structure(&mut app, "Lab", Vec2::ZERO);
+        let mut lab = Lab::new(1.);
+        lab.unit_progress = Some(0.4);
+        app.world.entity_mut(lab_entity).insert(lab);
+
+        let save_game = save_and_reload(&mut app);
+        let entities = load_structures(&mut app, &save_game, |structure| {
+            structure.insert(Lab::new(1.));
+        });
+
+        let lab = app.world.get::<Lab>(entities[0]).unwrap();
+        assert_eq!(lab.unit_progress, Some(0.4));
+    }
+
+    #[test]
+    fn miner_progress_is_restored_once_it_is_built() {
+        let mut app = save_game_app();
+        let miner_entity = spawn_structure(&mut app, "Burner mining drill", Vec2::ZERO);
+        let mut miner = Miner::new(2., vec![], Entity::PLACEHOLDER, Vec2::X);
+        miner.restore_progress(0.75);
+        app.world.entity_mut(miner_entity).insert(miner);
+
+        let save_game = save_and_reload(&mut app);
+        let entities = load_structures(&mut app, &save_game, |_| {});
+        assert!(app.world.get::<LoadedStructure>(entities[0]).is_some());
+
+        // The builder adds the miner later
+        app.world.entity_mut(entities[0]).insert(Miner::new(
+            2.,
+            vec![],
+            Entity::PLACEHOLDER,
+            Vec2::X,
+        ));
+        app.update();
+
+        assert_eq!(
+            app.world.get::<Miner>(entities[0]).unwrap().progress(),
+            0.75
+        );
+        assert!(app.world.get::<LoadedStructure>(entities[0]).is_none());
+    }
+}
@@ -0,0 +1,99 @@
+use bevy::prelude::*;
+
+use crate::types::AppState;
+
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<SimulationTick>()
+            .init_resource::<SimulationTick>()
+            .configure_sets(
+                FixedUpdate,
+                (
+                    SimulationSet::Commands,
+                    SimulationSet::Burners,
+                    SimulationSet::Fluids,
+                    SimulationSet::Power,
+                    SimulationSet::Smelters,
+                    SimulationSet::Assemblers,
+                    SimulationSet::Miners,
+                    SimulationSet::Labs,
+                    SimulationSet::PlayerCrafting,
+                    SimulationSet::PlayerMining,
+                    SimulationSet::Belts,
+                    SimulationSet::Inserters,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Running)),
+            )
+            .add_systems(
+                FixedUpdate,
+                advance_simulation_tick
+                    .after(SimulationSet::Inserters)
+                    .run_if(in_state(AppState::Running)),
+            );
+    }
+}
+
+/// The number of ticks simulated since the game started
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Reflect)]
+pub struct SimulationTick(pub u64);
+
+pub fn advance_simulation_tick(mut tick: ResMut<SimulationTick>) {
+    tick.0 += 1;
+}
+
+/// The factory is simulated on the fixed tick, one set after the other. Every system that
+/// changes the world is in one of these sets, so the same world and inputs always give the same
+/// world on the next tick.
+#[derive(SystemSet, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SimulationSet {
+    /// The players' commands for this tick are applied
+    Commands,
+    /// Burners load and burn fuel
+    Burners,
+    /// Pumps, boilers and steam engines move fluids between fluid boxes
+    Fluids,
+    /// Power poles connect and electric networks spread their production over their consumers
+    Power,
+    Smelters,
+    Assemblers,
+    Miners,
+    Labs,
+    /// Crafting from the player's crafting queue
+    PlayerCrafting,
+    /// Mining resources by hand
+    PlayerMining,
+    /// Belts and splitters move items
+    Belts,
+    /// Inserters move items between belts, inventories and the ground
+    Inserters,
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::ecs::schedule::{LogLevel, ScheduleBuildSettings};
+
+    use super::*;
+    use crate::KloonorioCorePlugins;
+
+    /// Building the schedule fails if two systems that touch the same data can run in either
+    /// order
+    #[test]
+    fn simulation_order_is_unambiguous() {
+        let mut app = App::new();
+        app.add_plugins((TaskPoolPlugin::default(), AssetPlugin::default()))
+            .add_state::<AppState>()
+            .add_plugins(KloonorioCorePlugins);
+        app.world.schedule_scope(FixedUpdate, |world, schedule| {
+            schedule.set_build_settings(ScheduleBuildSettings {
+                ambiguity_detection: LogLevel::Error,
+                ..default()
+            });
+            if let Err(err) = schedule.initialize(world) {
+                panic!("{}", err);
+            }
+        });
+    }
+}
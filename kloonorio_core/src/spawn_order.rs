@@ -0,0 +1,107 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+pub struct SpawnOrderPlugin;
+
+impl Plugin for SpawnOrderPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<SpawnOrder>()
+            .init_resource::<SpawnCounter>();
+    }
+}
+
+/// The order a structure was spawned in by the simulation, which is the same on every client.
+/// Entities themselves are numbered differently on every client, as each spawns its own sprites
+/// and interface in between, so the simulation visits structures in this order instead.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Reflect)]
+pub struct SpawnOrder(pub u64);
+
+/// Hands out the spawn order of the next structure
+#[derive(Resource, Default, Debug)]
+pub struct SpawnCounter(u64);
+
+impl SpawnCounter {
+    pub fn next_order(&mut self) -> SpawnOrder {
+        let spawn_order = SpawnOrder(self.0);
+        self.0 += 1;
+        spawn_order
+    }
+}
+
+#[derive(SystemParam)]
+pub struct SpawnOrders<'w, 's> {
+    spawn_order_query: Query<'w, 's, &'static SpawnOrder>,
+    parent_query: Query<'w, 's, &'static Parent>,
+    children_query: Query<'w, 's, &'static Children>,
+}
+
+impl SpawnOrders<'_, '_> {
+    pub fn get(&self, entity: Entity) -> Option<SpawnOrder> {
+        self.spawn_order_query.get(entity).ok().copied()
+    }
+
+    /// Where an entity goes in spawn order. The parts of a structure, like its inventories, come
+    /// right after it in the order they were added to it.
+    fn key(&self, entity: Entity) -> Option<(SpawnOrder, usize)> {
+        if let Some(spawn_order) = self.get(entity) {
+            return Some((spawn_order, 0));
+        }
+        let parent = self.parent_query.get(entity).ok()?.get();
+        let index = self
+            .children_query
+            .get(parent)
+            .ok()?
+            .iter()
+            .position(|child| *child == entity)?;
+        Some((self.get(parent)?, index + 1))
+    }
+
+    /// Entities in spawn order. Entities the simulation doesn't spawn, like players, come first in
+    /// entity order.
+    pub fn sorted(&self, entities: impl IntoIterator<Item = Entity>) -> Vec<Entity> {
+        let mut entities: Vec<Entity> = entities.into_iter().collect();
+        self.sort_by_entity(&mut entities, |entity| *entity);
+        entities
+    }
+
+    /// Sort things by the spawn order of the entity they belong to
+    pub fn sort_by_entity<T>(&self, items: &mut [T], entity: impl Fn(&T) -> Entity) {
+        self.sort_by_entities(items, |item| [entity(item)]);
+    }
+
+    /// Sort things that belong to several entities, like connections, by the spawn order of the
+    /// first entity, then the second and so on
+    pub fn sort_by_entities<T, const N: usize>(
+        &self,
+        items: &mut [T],
+        entities: impl Fn(&T) -> [Entity; N],
+    ) {
+        items.sort_by_cached_key(|item| entities(item).map(|entity| (self.key(entity), entity)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::ecs::system::SystemState;
+
+    use super::*;
+
+    #[test]
+    fn entities_are_sorted_by_spawn_order() {
+        let mut world = World::new();
+        let mut spawn_counter = SpawnCounter::default();
+        let player = world.spawn_empty().id();
+        let inventory = world.spawn_empty().id();
+        let second = world.spawn_empty().id();
+        let first = world.spawn(spawn_counter.next_order()).id();
+        world.entity_mut(second).insert(spawn_counter.next_order());
+        let fuel = world.spawn_empty().set_parent(first).id();
+        world.entity_mut(inventory).set_parent(first);
+
+        let mut system_state = SystemState::<SpawnOrders>::new(&mut world);
+        let spawn_orders = system_state.get(&world);
+        assert_eq!(
+            spawn_orders.sorted([second, inventory, first, fuel, player]),
+            vec![player, first, fuel, inventory, second]
+        );
+    }
+}
@@ -1,14 +1,18 @@
 use std::ops::{Deref, DerefMut};
 
 use bevy::{
-    ecs::system::Resource,
+    ecs::{entity::Entity, event::Event, system::Resource},
     math::{IVec2, Vec2},
     reflect::{Reflect, TypeUuid},
     utils::HashMap,
 };
 use serde::Deserialize;
 
-use crate::structure_components::StructureComponent;
+use crate::{
+    discrete_rotation::DiscreteRotation,
+    structure_components::StructureComponent,
+    undo::{ConstructionCause, StructurePlacement},
+};
 
 #[derive(Clone, Debug, Deserialize, TypeUuid, Reflect)]
 #[uuid = "540f864d-3e80-4e5d-8be5-1846d7be2484"]
@@ -21,6 +25,15 @@ pub struct Structure {
     pub animated: bool,
 }
 
+impl Structure {
+    /// Offshore pumps can only be placed on water
+    pub fn is_placed_on_water(&self) -> bool {
+        self.components
+            .iter()
+            .any(|component| matches!(component, StructureComponent::OffshorePump(_)))
+    }
+}
+
 #[derive(Resource, Default, Reflect)]
 pub struct Structures(HashMap<String, Structure>);
 
@@ -37,3 +50,20 @@ impl DerefMut for Structures {
         &mut self.0
     }
 }
+
+/// Place a structure from the inventory of the player that caused it. Structures are built by the
+/// game, which knows how to spawn them.
+#[derive(Debug, Event)]
+pub struct PlaceStructureEvent {
+    pub cause: ConstructionCause,
+    pub placement: StructurePlacement,
+}
+
+/// Turn a placed structure to face another way. Structures are rotated by the game, which knows
+/// what depends on the way they face.
+#[derive(Debug, Event)]
+pub struct RotateStructureEvent {
+    pub cause: ConstructionCause,
+    pub structure: Entity,
+    pub rotation: DiscreteRotation,
+}
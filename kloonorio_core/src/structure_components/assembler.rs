@@ -1,53 +1,147 @@
 use bevy::{
-    app::{App, Plugin, Update},
+    app::{App, FixedUpdate, Plugin},
     ecs::{
         component::Component,
         entity::Entity,
-        event::{Event, EventReader},
+        event::{Event, EventReader, EventWriter},
         query::{With, Without},
+        schedule::IntoSystemConfigs,
         system::{Commands, Query, Res},
     },
     hierarchy::Children,
+    log::warn,
     reflect::Reflect,
     time::{Time, Timer, TimerMode},
+    transform::components::Transform,
+    utils::HashSet,
 };
 
 use crate::{
     inventory::{Inventory, ItemFilter, Output, Source},
     item::Item,
-    recipe::Recipe,
+    player_command::apply_player_commands,
+    recipe::{Recipe, Recipes},
+    research::{queue_research, ResearchQueue, Technologies},
+    simulation::SimulationSet,
     types::{ActiveCraft, CraftingQueue, Powered, Working},
+    undo::{ConstructionAction, ConstructionCause, ConstructionEvent},
 };
 
+use super::electricity::{power_speed, ElectricConsumer};
+
 pub struct AssemblerPlugin;
 
 impl Plugin for AssemblerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, (assembler_change_recipe, assembler_tick))
-            .add_event::<ChangeAssemblerRecipeEvent>();
+        app.add_systems(
+            FixedUpdate,
+            (apply_pending_recipes, assembler_change_recipe)
+                .chain()
+                .after(apply_player_commands)
+                // Recipes are checked against the research queue
+                .after(queue_research)
+                .in_set(SimulationSet::Commands),
+        )
+        .add_systems(
+            FixedUpdate,
+            assembler_tick.in_set(SimulationSet::Assemblers),
+        )
+        .add_event::<ChangeAssemblerRecipeEvent>();
     }
 }
 #[derive(Component, Default, Debug, Reflect)]
 pub struct Assembler {
     pub recipe: Option<Recipe>,
+    /// Categories of the recipes this assembler can craft
+    pub categories: HashSet<String>,
+}
+
+impl Assembler {
+    pub fn new(categories: HashSet<String>) -> Self {
+        Assembler {
+            recipe: None,
+            categories,
+        }
+    }
+
+    pub fn can_craft(&self, recipe: &Recipe) -> bool {
+        recipe.in_any_category(&self.categories)
+    }
 }
 
 #[derive(Debug, Event)]
 pub struct ChangeAssemblerRecipeEvent {
     pub entity: Entity,
-    pub recipe: Recipe,
+    /// Without a recipe the assembler stops
+    pub recipe: Option<Recipe>,
+    /// Recipes changed by players can be changed back by undoing it
+    pub cause: Option<ConstructionCause>,
+}
+
+/// Recipe of an assembler that is set once it is built, like that of an assembler that is put
+/// back by undoing its removal
+#[derive(Component, Debug)]
+pub struct PendingRecipe(pub String);
+
+fn apply_pending_recipes(
+    mut commands: Commands,
+    pending_query: Query<(Entity, &PendingRecipe), With<Assembler>>,
+    recipes: Res<Recipes>,
+    mut change_recipe_events: EventWriter<ChangeAssemblerRecipeEvent>,
+) {
+    for (entity, pending_recipe) in &pending_query {
+        commands.entity(entity).remove::<PendingRecipe>();
+        change_recipe_events.send(ChangeAssemblerRecipeEvent {
+            entity,
+            recipe: recipes.get(&pending_recipe.0).cloned(),
+            cause: None,
+        });
+    }
 }
 
 fn assembler_change_recipe(
-    mut assembler_query: Query<(&mut Assembler, &mut CraftingQueue, &Children)>,
+    mut assembler_query: Query<(&Transform, &mut Assembler, &mut CraftingQueue, &Children)>,
     mut change_recipe_events: EventReader<ChangeAssemblerRecipeEvent>,
     mut source_query: Query<&mut Inventory, (With<Source>, Without<Output>)>,
+    mut construction_events: EventWriter<ConstructionEvent>,
+    research_queue: Res<ResearchQueue>,
+    technologies: Res<Technologies>,
 ) {
     for event in change_recipe_events.read() {
-        if let Ok((mut assembler, mut crafting_queue, children)) =
+        if let Ok((transform, mut assembler, mut crafting_queue, children)) =
             assembler_query.get_mut(event.entity)
         {
-            assembler.recipe = Some(event.recipe.clone());
+            if let Some(recipe) = event
+                .recipe
+                .as_ref()
+                .filter(|recipe| !assembler.can_craft(recipe))
+            {
+                warn!(
+                    "Assembler can't craft {} recipes like {}",
+                    recipe.category, recipe.name
+                );
+                continue;
+            }
+            // Player commands and blueprints aren't checked by the recipe selection UI
+            if let Some(recipe) = event
+                .recipe
+                .as_ref()
+                .filter(|recipe| !research_queue.is_recipe_unlocked(&recipe.name, &technologies))
+            {
+                warn!("Recipe {} isn't unlocked yet", recipe.name);
+                continue;
+            }
+            if let Some(cause) = event.cause {
+                construction_events.send(ConstructionEvent {
+                    cause,
+                    action: ConstructionAction::ChangeRecipe {
+                        position: transform.translation.truncate(),
+                        from: assembler.recipe.as_ref().map(|recipe| recipe.name.clone()),
+                        to: event.recipe.as_ref().map(|recipe| recipe.name.clone()),
+                    },
+                });
+            }
+            assembler.recipe = event.recipe.clone();
             crafting_queue.0.clear();
 
             let source_entity = children.iter().find(|c| source_query.get(**c).is_ok());
@@ -55,8 +149,8 @@ fn assembler_change_recipe(
             source.allowed_items = ItemFilter::Only(
                 event
                     .recipe
-                    .ingredients
                     .iter()
+                    .flat_map(|recipe| &recipe.ingredients)
                     .map(|(p, _)| Item::new(p.to_string()))
                     .collect(),
             );
@@ -67,6 +161,7 @@ fn assembler_change_recipe(
 pub fn assembler_tick(
     mut commands: Commands,
     mut assembler_query: Query<(Entity, &Assembler, &mut CraftingQueue, &Children), With<Powered>>,
+    electric_consumer_query: Query<&ElectricConsumer>,
     mut source_query: Query<&mut Inventory, (With<Source>, Without<Output>)>,
     mut output_query: Query<&mut Inventory, (With<Output>, Without<Source>)>,
     time: Res<Time>,
@@ -78,7 +173,11 @@ pub fn assembler_tick(
         let mut source = source_query.get_mut(*source_entity.unwrap()).unwrap();
         let mut output = output_query.get_mut(*output_entity.unwrap()).unwrap();
 
-        let Some(recipe) = &assembler.recipe else {
+        let Some(recipe) = assembler
+            .recipe
+            .as_ref()
+            .filter(|recipe| assembler.can_craft(recipe))
+        else {
             continue;
         };
 
@@ -95,7 +194,9 @@ pub fn assembler_tick(
         }
 
         if let Some(active_build) = crafting_queue.0.front_mut() {
-            if active_build.timer.tick(time.delta()).just_finished() {
+            let speed = power_speed(electric_consumer_query.get(entity).ok());
+            let delta = time.delta().mul_f32(speed);
+            if active_build.timer.tick(delta).just_finished() {
                 output.add_items(&active_build.recipe.products);
                 crafting_queue.0.pop_front();
                 commands.entity(entity).remove::<Working>();
@@ -103,3 +204,92 @@ pub fn assembler_tick(
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use bevy::{app::Update, hierarchy::BuildWorldChildren};
+
+    use crate::{
+        recipe::CRAFTING_CATEGORY,
+        research::{Technology, TechnologyEffect},
+    };
+
+    use super::*;
+
+    #[test]
+    fn locked_recipes_are_rejected() {
+        let mut app = App::new();
+        let mut technologies = Technologies::default();
+        technologies.insert(
+            "Logistics".to_string(),
+            Technology {
+                name: "Logistics".to_string(),
+                prerequisites: vec![],
+                cost: vec![],
+                units: 1,
+                time: 1.,
+                effects: vec![TechnologyEffect::UnlockRecipe("Splitter".to_string())],
+            },
+        );
+        let mut recipes = Recipes::default();
+        recipes.insert(
+            "Splitter".to_string(),
+            Recipe {
+                name: "Splitter".to_string(),
+                ingredients: vec![(Item::new("Iron plate"), 5)],
+                products: vec![(Item::new("Splitter"), 1)],
+                crafting_time: 1.,
+                category: CRAFTING_CATEGORY.to_string(),
+            },
+        );
+        app.init_resource::<ResearchQueue>()
+            .insert_resource(technologies)
+            .insert_resource(recipes)
+            .add_event::<ChangeAssemblerRecipeEvent>()
+            .add_event::<ConstructionEvent>()
+            .add_systems(
+                Update,
+                (apply_pending_recipes, assembler_change_recipe).chain(),
+            );
+        let source = app.world.spawn((Source, Inventory::new(1))).id();
+        let assembler = app
+            .world
+            .spawn((
+                Transform::default(),
+                Assembler::new(HashSet::from([CRAFTING_CATEGORY.to_string()])),
+                CraftingQueue::default(),
+            ))
+            .push_children(&[source])
+            .id();
+
+        // A blueprint sets the recipe once the assembler is built
+        app.world
+            .entity_mut(assembler)
+            .insert(PendingRecipe("Splitter".to_string()));
+        app.update();
+        assert!(app
+            .world
+            .get::<Assembler>(assembler)
+            .unwrap()
+            .recipe
+            .is_none());
+
+        app.world
+            .resource_mut::<ResearchQueue>()
+            .researched
+            .insert("Logistics".to_string());
+        app.world
+            .entity_mut(assembler)
+            .insert(PendingRecipe("Splitter".to_string()));
+        app.update();
+        assert_eq!(
+            app.world
+                .get::<Assembler>(assembler)
+                .unwrap()
+                .recipe
+                .as_ref()
+                .map(|recipe| recipe.name.as_str()),
+            Some("Splitter")
+        );
+    }
+}
@@ -1,27 +1,72 @@
 use bevy::prelude::*;
 
 use crate::{
-    inventory::{Fuel, Inventory},
-    item::Item,
+    inventory::{Fuel, Inventory, ItemFilter},
+    item::Items,
     types::{Powered, Working},
 };
 
-#[derive(Component, Default)]
+/// Burns fuel items from the fuel inventory. The structure is powered as long as there is energy
+/// left from the last fuel item, which drains while the structure is working.
+#[derive(Component, Debug, Reflect)]
 pub struct Burner {
-    pub fuel_timer: Option<Timer>,
+    /// Energy consumption while working in kW
+    pub consumption: f32,
+    /// Energy left from the burning fuel in MJ
+    pub energy: f32,
+    /// Fuel value of the fuel item that was loaded last in MJ
+    pub fuel_value: f32,
+}
+
+impl Burner {
+    pub fn new(consumption: f32) -> Self {
+        Burner {
+            consumption,
+            energy: 0.,
+            fuel_value: 0.,
+        }
+    }
+
+    /// The part of the burning fuel that is left
+    pub fn remaining_fraction(&self) -> f32 {
+        if self.fuel_value > 0. {
+            (self.energy / self.fuel_value).clamp(0., 1.)
+        } else {
+            0.
+        }
+    }
+}
+
+/// Only fuel items can be put in the fuel inventory of a burner
+pub fn burner_fuel_filter(
+    burner_query: Query<&Children, Added<Burner>>,
+    mut fuel_inventory_query: Query<&mut Inventory, With<Fuel>>,
+    items: Res<Items>,
+) {
+    for children in &burner_query {
+        let allowed_items = ItemFilter::Only(items.fuels().cloned().collect());
+        for child in children {
+            if let Ok(mut fuel_inventory) = fuel_inventory_query.get_mut(*child) {
+                fuel_inventory.allowed_items = allowed_items.clone();
+            }
+        }
+    }
 }
 
 pub fn burner_tick(
     mut commands: Commands,
-    mut burner_query: Query<(Entity, &mut Burner), With<Working>>,
+    mut burner_query: Query<(Entity, &mut Burner, Has<Working>), With<Powered>>,
     time: Res<Time>,
 ) {
-    for (entity, mut burner) in &mut burner_query {
-        if let Some(timer) = &mut burner.fuel_timer {
-            if timer.tick(time.delta()).just_finished() {
-                commands.entity(entity).remove::<Powered>();
-                burner.fuel_timer = None;
-            }
+    for (entity, mut burner, working) in &mut burner_query {
+        if !working {
+            continue;
+        }
+        // kW to MJ
+        burner.energy -= burner.consumption / 1000. * time.delta_seconds();
+        if burner.energy <= 0. {
+            burner.energy = 0.;
+            commands.entity(entity).remove::<Powered>();
         }
     }
 }
@@ -30,12 +75,22 @@ pub fn burner_load(
     mut commands: Commands,
     mut fueled_query: Query<(Entity, &mut Burner, &Children), Without<Powered>>,
     mut fuel_inventory_query: Query<&mut Inventory, With<Fuel>>,
+    items: Res<Items>,
 ) {
     for (entity, mut fueled, children) in &mut fueled_query {
         for child in children {
             if let Ok(mut fuel_inventory) = fuel_inventory_query.get_mut(*child) {
-                if fuel_inventory.remove_items(&[(Item::new("Coal"), 1)]) {
-                    fueled.fuel_timer = Some(Timer::from_seconds(10., TimerMode::Once));
+                let fuel = fuel_inventory.slots.iter().flatten().find_map(|stack| {
+                    items
+                        .fuel_value(&stack.item)
+                        .map(|fuel_value| (stack.item.clone(), fuel_value))
+                });
+                let Some((fuel_item, fuel_value)) = fuel else {
+                    continue;
+                };
+                if fuel_inventory.remove_items(&[(fuel_item, 1)]) {
+                    fueled.energy += fuel_value;
+                    fueled.fuel_value = fuel_value;
                     commands.entity(entity).insert(Powered);
                     break;
                 }
@@ -43,3 +98,101 @@ pub fn burner_load(
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use bevy::utils::HashSet;
+
+    use crate::item::{Item, ItemDefinition};
+
+    use super::*;
+
+    fn burner_app() -> App {
+        let mut app = App::new();
+        let mut items = Items::default();
+        for (name, fuel_value) in [("Wood", 2.), ("Coal", 4.), ("Iron ore", 0.)] {
+            items.insert(
+                name.to_string(),
+                ItemDefinition {
+                    name: Item::new(name),
+                    fuel_value,
+                },
+            );
+        }
+        app.init_resource::<Time>()
+            .insert_resource(items)
+            .add_systems(
+                Update,
+                (burner_tick, (burner_fuel_filter, burner_load).chain()),
+            );
+        app
+    }
+
+    /// Spawn a burner using 1 MW, with a fuel inventory holding the given items
+    fn spawn_burner(app: &mut App, fuel: &[(&'static str, u32)]) -> (Entity, Entity) {
+        let mut fuel_inventory = Inventory::new(2);
+        for (item, amount) in fuel {
+            fuel_inventory.add_item(&Item::new(*item), *amount);
+        }
+        let fuel_inventory = app.world.spawn((Fuel, fuel_inventory)).id();
+        let burner = app
+            .world
+            .spawn((Burner::new(1000.), Working))
+            .push_children(&[fuel_inventory])
+            .id();
+        (burner, fuel_inventory)
+    }
+
+    fn advance_time(app: &mut App, seconds: f32) {
+        app.world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(seconds));
+        app.update();
+    }
+
+    #[test]
+    fn fuel_filter_comes_from_item_definitions() {
+        let mut app = burner_app();
+        let (_, fuel_inventory) = spawn_burner(&mut app, &[]);
+
+        app.update();
+
+        let fuel_inventory = app.world.get::<Inventory>(fuel_inventory).unwrap();
+        assert_eq!(
+            fuel_inventory.allowed_items.into_set(),
+            HashSet::from([Item::new("Wood"), Item::new("Coal")])
+        );
+    }
+
+    #[test]
+    fn burner_burns_any_fuel_for_its_energy() {
+        let mut app = burner_app();
+        let (burner, fuel_inventory) = spawn_burner(&mut app, &[("Wood", 1)]);
+
+        app.update();
+        assert!(app.world.get::<Powered>(burner).is_some());
+        assert_eq!(app.world.get::<Burner>(burner).unwrap().energy, 2.);
+        let fuel_inventory = app.world.get::<Inventory>(fuel_inventory).unwrap();
+        assert_eq!(fuel_inventory.num_items(&Item::new("Wood")), 0);
+
+        // 1 MW uses 1 MJ per second
+        advance_time(&mut app, 1.5);
+        assert!(app.world.get::<Powered>(burner).is_some());
+        assert!((app.world.get::<Burner>(burner).unwrap().energy - 0.5).abs() < 1e-4);
+
+        advance_time(&mut app, 1.);
+        assert!(app.world.get::<Powered>(burner).is_none());
+    }
+
+    #[test]
+    fn burner_needs_fuel_items() {
+        let mut app = burner_app();
+        let (burner, _) = spawn_burner(&mut app, &[("Iron ore", 1)]);
+
+        app.update();
+
+        assert!(app.world.get::<Powered>(burner).is_none());
+    }
+}
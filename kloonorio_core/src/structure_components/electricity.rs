@@ -0,0 +1,422 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+    entity_set::EntitySet,
+    simulation::SimulationSet,
+    spawn_order::SpawnOrders,
+    types::{Powered, Working},
+};
+
+pub struct ElectricityPlugin;
+
+impl Plugin for ElectricityPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<PowerPole>()
+            .register_type::<Generator>()
+            .register_type::<ElectricConsumer>()
+            .init_resource::<ElectricNetworks>()
+            .add_systems(
+                FixedUpdate,
+                (connect_power_poles, electric_network_tick)
+                    .chain()
+                    .in_set(SimulationSet::Power),
+            );
+    }
+}
+
+/// Connects to other poles within its wire reach, and powers the generators and consumers within
+/// its supply area
+#[derive(Component, Debug, Reflect)]
+pub struct PowerPole {
+    /// Maximum length in tiles of the wires to other poles
+    pub wire_reach: f32,
+    /// Distance in tiles from the pole to the edge of the square it supplies
+    pub supply_range: f32,
+    pub connections: EntitySet,
+}
+
+impl PowerPole {
+    pub fn new(wire_reach: f32, supply_range: f32) -> Self {
+        PowerPole {
+            wire_reach,
+            supply_range,
+            connections: EntitySet::new(),
+        }
+    }
+
+    /// Poles are connected if they are within reach of both their wires
+    pub fn can_connect(&self, position: Vec2, other: &PowerPole, other_position: Vec2) -> bool {
+        position.distance(other_position) <= self.wire_reach.min(other.wire_reach)
+    }
+
+    pub fn supplies(&self, position: Vec2, other_position: Vec2) -> bool {
+        let offset = (other_position - position).abs();
+        offset.x <= self.supply_range && offset.y <= self.supply_range
+    }
+}
+
+/// Produces electricity for its network as long as it is powered itself, e.g. by a burner
+#[derive(Component, Debug, Reflect)]
+pub struct Generator {
+    /// Maximum production in kW
+    pub production: f32,
+    /// The part of the production the network is using
+    pub load: f32,
+}
+
+impl Generator {
+    pub fn new(production: f32) -> Self {
+        Generator {
+            production,
+            load: 0.,
+        }
+    }
+}
+
+/// Draws electricity from the network it is connected to. The consumer is powered as long as the
+/// network covers any of its demand, and runs slower if it covers only part of it.
+#[derive(Component, Debug, Reflect)]
+pub struct ElectricConsumer {
+    /// Energy consumption in kW
+    pub consumption: f32,
+    /// The part of the consumption the network covers
+    pub satisfaction: f32,
+}
+
+impl ElectricConsumer {
+    pub fn new(consumption: f32) -> Self {
+        ElectricConsumer {
+            consumption,
+            satisfaction: 0.,
+        }
+    }
+}
+
+/// Speed factor of a machine, electric machines slow down when their network can't cover their
+/// demand
+pub fn power_speed(consumer: Option<&ElectricConsumer>) -> f32 {
+    consumer.map_or(1., |consumer| consumer.satisfaction)
+}
+
+#[derive(Debug, Default)]
+pub struct ElectricNetwork {
+    pub poles: Vec<Entity>,
+    /// Production of the powered generators in kW
+    pub production: f32,
+    /// Consumption of all consumers in kW
+    pub demand: f32,
+}
+
+impl ElectricNetwork {
+    /// The part of the demand that the production covers
+    pub fn satisfaction(&self) -> f32 {
+        if self.demand > 0. {
+            (self.production / self.demand).min(1.)
+        } else {
+            1.
+        }
+    }
+
+    /// The part of the production that is used
+    pub fn load(&self) -> f32 {
+        if self.production > 0. {
+            (self.demand / self.production).min(1.)
+        } else {
+            0.
+        }
+    }
+}
+
+/// The electric networks of the last tick, made up of poles connected by wires
+#[derive(Resource, Debug, Default)]
+pub struct ElectricNetworks {
+    pub networks: Vec<ElectricNetwork>,
+}
+
+fn connect_power_poles(mut pole_query: Query<(Entity, &mut PowerPole, &Transform)>) {
+    let added_poles: Vec<Entity> = pole_query
+        .iter_mut()
+        .filter(|(_, pole, _)| pole.is_added())
+        .map(|(entity, _, _)| entity)
+        .collect();
+    for added_pole in added_poles {
+        let (_, pole, transform) = pole_query.get(added_pole).unwrap();
+        let position = transform.translation.truncate();
+        let connections: Vec<Entity> = pole_query
+            .iter()
+            .filter(|(other, other_pole, other_transform)| {
+                *other != added_pole
+                    && pole.can_connect(
+                        position,
+                        other_pole,
+                        other_transform.translation.truncate(),
+                    )
+            })
+            .map(|(other, _, _)| other)
+            .collect();
+        for other in connections {
+            debug!(pole = ?added_pole, ?other, "Connecting power poles");
+            pole_query
+                .get_mut(added_pole)
+                .unwrap()
+                .1
+                .connections
+                .insert(other);
+            pole_query
+                .get_mut(other)
+                .unwrap()
+                .1
+                .connections
+                .insert(added_pole);
+        }
+    }
+}
+
+/// Builds the networks from the connected poles and shares the production of each network
+/// between its consumers. Poles, generators and consumers are visited in spawn order, so the
+/// networks and their sums come out the same on every client.
+fn electric_network_tick(
+    mut commands: Commands,
+    pole_query: Query<(Entity, &PowerPole, &Transform)>,
+    mut generator_query: Query<(Entity, &mut Generator, &Transform, Has<Powered>)>,
+    mut consumer_query: Query<(Entity, &mut ElectricConsumer, &Transform)>,
+    mut electric_networks: ResMut<ElectricNetworks>,
+    spawn_orders: SpawnOrders,
+) {
+    let poles = spawn_orders.sorted(pole_query.iter().map(|(entity, ..)| entity));
+
+    // Walk the wires to find the poles of each network
+    let mut pole_networks: HashMap<Entity, usize> = HashMap::new();
+    let mut networks: Vec<ElectricNetwork> = Vec::new();
+    for pole_entity in &poles {
+        if pole_networks.contains_key(pole_entity) {
+            continue;
+        }
+        let network_index = networks.len();
+        let mut network = ElectricNetwork::default();
+        let mut open = vec![*pole_entity];
+        pole_networks.insert(*pole_entity, network_index);
+        while let Some(current) = open.pop() {
+            network.poles.push(current);
+            let (_, pole, _) = pole_query.get(current).unwrap();
+            for connection in spawn_orders.sorted(pole.connections.iter().copied()) {
+                // Wires to poles that were removed are skipped
+                if pole_query.contains(connection) && !pole_networks.contains_key(&connection) {
+                    pole_networks.insert(connection, network_index);
+                    open.push(connection);
+                }
+            }
+        }
+        networks.push(network);
+    }
+
+    // Structures in the supply area of several networks are connected to the first pole's
+    let network_at = |position: Vec2| {
+        poles
+            .iter()
+            .find(|pole_entity| {
+                let (_, pole, pole_transform) = pole_query.get(**pole_entity).unwrap();
+                pole.supplies(pole_transform.translation.truncate(), position)
+            })
+            .map(|pole_entity| pole_networks[pole_entity])
+    };
+
+    let generator_networks: Vec<(Entity, Option<usize>)> = spawn_orders
+        .sorted(generator_query.iter().map(|(entity, ..)| entity))
+        .into_iter()
+        .map(|entity| {
+            let (_, _, transform, _) = generator_query.get(entity).unwrap();
+            (entity, network_at(transform.translation.truncate()))
+        })
+        .collect();
+    let consumer_networks: Vec<(Entity, Option<usize>)> = spawn_orders
+        .sorted(consumer_query.iter().map(|(entity, ..)| entity))
+        .into_iter()
+        .map(|entity| {
+            let (_, _, transform) = consumer_query.get(entity).unwrap();
+            (entity, network_at(transform.translation.truncate()))
+        })
+        .collect();
+    for (entity, network) in &generator_networks {
+        let (_, generator, _, powered) = generator_query.get(*entity).unwrap();
+        if let Some(network) = network.filter(|_| powered) {
+            networks[network].production += generator.production;
+        }
+    }
+    for (entity, network) in &consumer_networks {
+        let (_, consumer, _) = consumer_query.get(*entity).unwrap();
+        if let Some(network) = network {
+            networks[*network].demand += consumer.consumption;
+        }
+    }
+
+    for (entity, network) in consumer_networks {
+        let (_, mut consumer, _) = consumer_query.get_mut(entity).unwrap();
+        consumer.satisfaction = network.map_or(0., |network| networks[network].satisfaction());
+        if consumer.satisfaction > 0. {
+            commands.entity(entity).insert(Powered);
+        } else {
+            commands.entity(entity).remove::<Powered>();
+        }
+    }
+
+    // Generators only use up their fuel while the network needs their energy
+    for (entity, network) in generator_networks {
+        let (_, mut generator, _, powered) = generator_query.get_mut(entity).unwrap();
+        let network = network.map(|network| &networks[network]);
+        generator.load = network.map_or(0., |network| network.load());
+        if powered && network.map_or(false, |network| network.demand > 0.) {
+            commands.entity(entity).insert(Working);
+        } else {
+            commands.entity(entity).remove::<Working>();
+        }
+    }
+
+    electric_networks.networks = networks;
+}
+
+#[cfg(test)]
+mod test {
+    use crate::spawn_order::SpawnCounter;
+
+    use super::*;
+
+    fn electricity_app() -> App {
+        let mut app = App::new();
+        app.init_resource::<ElectricNetworks>()
+            .add_systems(Update, (connect_power_poles, electric_network_tick).chain());
+        app
+    }
+
+    fn spawn_pole(app: &mut App, x: f32) -> Entity {
+        app.world
+            .spawn((PowerPole::new(7.5, 2.5), Transform::from_xyz(x, 0., 0.)))
+            .id()
+    }
+
+    fn spawn_generator(app: &mut App, x: f32, production: f32) -> Entity {
+        app.world
+            .spawn((
+                Generator::new(production),
+                Powered,
+                Transform::from_xyz(x, 1., 0.),
+            ))
+            .id()
+    }
+
+    fn spawn_consumer(app: &mut App, x: f32, consumption: f32) -> Entity {
+        app.world
+            .spawn((
+                ElectricConsumer::new(consumption),
+                Transform::from_xyz(x, -1., 0.),
+            ))
+            .id()
+    }
+
+    #[test]
+    fn poles_within_reach_form_a_network() {
+        let mut app = electricity_app();
+        let first = spawn_pole(&mut app, 0.);
+        let second = spawn_pole(&mut app, 7.);
+        let far = spawn_pole(&mut app, 20.);
+
+        app.update();
+
+        let first_pole = app.world.get::<PowerPole>(first).unwrap();
+        assert_eq!(first_pole.connections, EntitySet::from([second]));
+        assert!(app
+            .world
+            .get::<PowerPole>(far)
+            .unwrap()
+            .connections
+            .is_empty());
+        assert_eq!(app.world.resource::<ElectricNetworks>().networks.len(), 2);
+    }
+
+    #[test]
+    fn consumers_are_powered_by_generators_on_the_same_network() {
+        let mut app = electricity_app();
+        spawn_pole(&mut app, 0.);
+        spawn_pole(&mut app, 7.);
+        let generator = spawn_generator(&mut app, 0., 100.);
+        let consumer = spawn_consumer(&mut app, 7., 50.);
+        let unconnected_consumer = spawn_consumer(&mut app, 20., 50.);
+
+        app.update();
+
+        let electric_consumer = app.world.get::<ElectricConsumer>(consumer).unwrap();
+        assert_eq!(electric_consumer.satisfaction, 1.);
+        assert!(app.world.get::<Powered>(consumer).is_some());
+        assert!(app.world.get::<Powered>(unconnected_consumer).is_none());
+        assert_eq!(app.world.get::<Generator>(generator).unwrap().load, 0.5);
+        assert!(app.world.get::<Working>(generator).is_some());
+    }
+
+    #[test]
+    fn consumers_slow_down_in_a_brownout() {
+        let mut app = electricity_app();
+        spawn_pole(&mut app, 0.);
+        spawn_generator(&mut app, 0., 100.);
+        let consumers = [
+            spawn_consumer(&mut app, 0., 100.),
+            spawn_consumer(&mut app, 1., 100.),
+        ];
+
+        app.update();
+
+        for consumer in consumers {
+            let electric_consumer = app.world.get::<ElectricConsumer>(consumer).unwrap();
+            assert_eq!(power_speed(Some(electric_consumer)), 0.5);
+            assert!(app.world.get::<Powered>(consumer).is_some());
+        }
+    }
+
+    #[test]
+    fn unpowered_generators_produce_nothing() {
+        let mut app = electricity_app();
+        spawn_pole(&mut app, 0.);
+        let generator = spawn_generator(&mut app, 0., 100.);
+        app.world.entity_mut(generator).remove::<Powered>();
+        let consumer = spawn_consumer(&mut app, 0., 100.);
+
+        app.update();
+
+        assert_eq!(
+            app.world
+                .get::<ElectricConsumer>(consumer)
+                .unwrap()
+                .satisfaction,
+            0.
+        );
+        assert!(app.world.get::<Powered>(consumer).is_none());
+        assert!(app.world.get::<Working>(generator).is_none());
+    }
+
+    #[test]
+    fn structures_supplied_by_several_networks_use_the_first_spawned_pole() {
+        let mut app = electricity_app();
+        // The poles are too far apart for a wire, but both supply the consumer in between
+        let unpowered_pole = app
+            .world
+            .spawn((PowerPole::new(1., 2.5), Transform::from_xyz(4., 0., 0.)))
+            .id();
+        let powered_pole = app
+            .world
+            .spawn((PowerPole::new(1., 2.5), Transform::from_xyz(0., 0., 0.)))
+            .id();
+        spawn_generator(&mut app, -2., 100.);
+        let consumer = spawn_consumer(&mut app, 2., 50.);
+        // The pole numbered last on this client was spawned first by the simulation
+        let mut spawn_counter = SpawnCounter::default();
+        for pole in [powered_pole, unpowered_pole] {
+            app.world
+                .entity_mut(pole)
+                .insert(spawn_counter.next_order());
+        }
+
+        app.update();
+
+        assert_eq!(app.world.resource::<ElectricNetworks>().networks.len(), 2);
+        assert!(app.world.get::<Powered>(consumer).is_some());
+    }
+}
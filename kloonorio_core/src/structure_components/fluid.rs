@@ -0,0 +1,655 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    entity_set::EntitySet,
+    simulation::SimulationSet,
+    spawn_order::SpawnOrders,
+    types::{Powered, Working},
+};
+
+use super::electricity::Generator;
+
+pub const WATER: &str = "Water";
+pub const STEAM: &str = "Steam";
+
+/// Part of the difference in fill level between two connected fluid boxes that is evened out per
+/// second
+const FLOW_RATE: f32 = 10.;
+
+pub struct FluidPlugin;
+
+impl Plugin for FluidPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<FluidBox>()
+            .register_type::<OffshorePump>()
+            .register_type::<Boiler>()
+            .register_type::<SteamEngine>()
+            .add_systems(
+                FixedUpdate,
+                (
+                    connect_fluid_boxes,
+                    offshore_pump_tick,
+                    boiler_tick,
+                    steam_engine_tick,
+                    fluid_flow,
+                )
+                    .chain()
+                    .in_set(SimulationSet::Fluids),
+            );
+    }
+}
+
+fn default_max_distance() -> u32 {
+    1
+}
+
+/// A place where a fluid box connects to the fluid box of a neighbouring structure. Both fluid
+/// boxes need a connection facing the other one.
+#[derive(Clone, Debug, Deserialize, Reflect)]
+pub struct PipeConnection {
+    /// Centre of the tile the connection leaves the structure from, relative to its centre
+    pub position: Vec2,
+    /// Direction from that tile to the connected tile
+    pub direction: Vec2,
+    /// Underground connections reach the closest underground connection facing them within this
+    /// many tiles, and don't connect to regular ones
+    #[serde(default = "default_max_distance")]
+    pub max_distance: u32,
+}
+
+impl PipeConnection {
+    pub fn is_underground(&self) -> bool {
+        self.max_distance > 1
+    }
+}
+
+/// Holds a single fluid. Fluid flows between connected fluid boxes until they are filled to the
+/// same level.
+#[derive(Component, Debug, Reflect)]
+pub struct FluidBox {
+    pub fluid: Option<String>,
+    pub amount: f32,
+    pub capacity: f32,
+    /// Only this fluid can be put in the fluid box
+    pub filter: Option<String>,
+    pub pipe_connections: Vec<PipeConnection>,
+    pub connections: EntitySet,
+}
+
+impl FluidBox {
+    pub fn new(
+        capacity: f32,
+        filter: Option<String>,
+        pipe_connections: Vec<PipeConnection>,
+    ) -> Self {
+        FluidBox {
+            fluid: None,
+            amount: 0.,
+            capacity,
+            filter,
+            pipe_connections,
+            connections: EntitySet::new(),
+        }
+    }
+
+    pub fn level(&self) -> f32 {
+        self.amount / self.capacity
+    }
+
+    pub fn free_space(&self) -> f32 {
+        self.capacity - self.amount
+    }
+
+    pub fn accepts(&self, fluid: &str) -> bool {
+        self.filter.as_ref().map_or(true, |filter| filter == fluid)
+            && self.fluid.as_ref().map_or(true, |current| current == fluid)
+    }
+
+    /// Amount of `fluid` in the fluid box
+    pub fn amount_of(&self, fluid: &str) -> f32 {
+        if self.fluid.as_deref() == Some(fluid) {
+            self.amount
+        } else {
+            0.
+        }
+    }
+
+    /// Add up to `amount` of `fluid`, returns the amount that was added
+    pub fn add(&mut self, fluid: &str, amount: f32) -> f32 {
+        if !self.accepts(fluid) {
+            return 0.;
+        }
+        let added = amount.min(self.free_space()).max(0.);
+        if added > 0. {
+            self.fluid = Some(fluid.to_string());
+            self.amount += added;
+        }
+        added
+    }
+
+    /// Remove up to `amount`, returns the amount that was removed
+    pub fn remove(&mut self, amount: f32) -> f32 {
+        let removed = amount.min(self.amount).max(0.);
+        self.amount -= removed;
+        if self.amount <= f32::EPSILON {
+            self.amount = 0.;
+            self.fluid = None;
+        }
+        removed
+    }
+}
+
+/// Pumps water into its fluid boxes
+#[derive(Component, Debug, Reflect)]
+pub struct OffshorePump {
+    /// Water pumped per second
+    pub pumping_speed: f32,
+}
+
+/// Turns water into steam while it is powered by its burner
+#[derive(Component, Debug, Reflect)]
+pub struct Boiler {
+    /// Steam produced per second
+    pub steam_per_second: f32,
+}
+
+/// Runs on steam to power the generator of the structure
+#[derive(Component, Debug, Reflect)]
+pub struct SteamEngine {
+    /// Steam used per second at full load
+    pub steam_per_second: f32,
+}
+
+/// Position and direction of a pipe connection in the world
+fn world_pipe_connection(
+    transform: &GlobalTransform,
+    pipe_connection: &PipeConnection,
+) -> (Vec2, Vec2) {
+    (
+        transform
+            .transform_point(pipe_connection.position.extend(0.))
+            .truncate(),
+        transform
+            .affine()
+            .transform_vector3(pipe_connection.direction.extend(0.))
+            .truncate()
+            .normalize(),
+    )
+}
+
+/// Connects fluid boxes that were added or moved, like those of a rotated structure, to the fluid
+/// boxes facing them. Moved fluid boxes lose their old connections first.
+fn connect_fluid_boxes(mut fluid_box_query: Query<(Entity, &mut FluidBox, Ref<GlobalTransform>)>) {
+    let moved_fluid_boxes: Vec<Entity> = fluid_box_query
+        .iter_mut()
+        .filter(|(_, fluid_box, transform)| fluid_box.is_added() || transform.is_changed())
+        .map(|(entity, _, _)| entity)
+        .collect();
+    for moved_fluid_box in moved_fluid_boxes {
+        let old_connections = std::mem::take(
+            &mut fluid_box_query
+                .get_mut(moved_fluid_box)
+                .unwrap()
+                .1
+                .connections,
+        );
+        for other in &old_connections {
+            if let Ok((_, mut other_fluid_box, _)) = fluid_box_query.get_mut(*other) {
+                other_fluid_box.connections.remove(&moved_fluid_box);
+            }
+        }
+
+        let (_, fluid_box, transform) = fluid_box_query.get(moved_fluid_box).unwrap();
+        let mut connections = Vec::new();
+        for pipe_connection in &fluid_box.pipe_connections {
+            connections.extend(find_connected_fluid_box(
+                &fluid_box_query,
+                moved_fluid_box,
+                &transform,
+                pipe_connection,
+            ));
+        }
+        for other in connections {
+            debug!(fluid_box = ?moved_fluid_box, ?other, "Connecting fluid boxes");
+            fluid_box_query
+                .get_mut(moved_fluid_box)
+                .unwrap()
+                .1
+                .connections
+                .insert(other);
+            fluid_box_query
+                .get_mut(other)
+                .unwrap()
+                .1
+                .connections
+                .insert(moved_fluid_box);
+        }
+    }
+}
+
+/// The fluid box with a connection facing `pipe_connection`. Underground connections connect to
+/// the closest underground connection in their direction, if it faces them.
+fn find_connected_fluid_box(
+    fluid_box_query: &Query<(Entity, &mut FluidBox, Ref<GlobalTransform>)>,
+    fluid_box_entity: Entity,
+    transform: &GlobalTransform,
+    pipe_connection: &PipeConnection,
+) -> Option<Entity> {
+    let (position, direction) = world_pipe_connection(transform, pipe_connection);
+    for distance in 1..=pipe_connection.max_distance {
+        let connected_position = position + direction * distance as f32;
+        let mut blocked = false;
+        for (other, other_fluid_box, other_transform) in fluid_box_query.iter() {
+            if other == fluid_box_entity {
+                continue;
+            }
+            for other_connection in &other_fluid_box.pipe_connections {
+                if other_connection.is_underground() != pipe_connection.is_underground() {
+                    continue;
+                }
+                let (other_position, other_direction) =
+                    world_pipe_connection(&other_transform, other_connection);
+                if other_position.distance(connected_position) > 0.1 {
+                    continue;
+                }
+                if other_direction.dot(direction) < -0.9
+                    && distance <= other_connection.max_distance
+                {
+                    return Some(other);
+                }
+                blocked = true;
+            }
+        }
+        if blocked {
+            return None;
+        }
+    }
+    None
+}
+
+/// Evens out the fill levels of connected fluid boxes holding the same fluid
+fn fluid_flow(
+    mut fluid_box_query: Query<(Entity, &mut FluidBox)>,
+    spawn_orders: SpawnOrders,
+    time: Res<Time>,
+) {
+    let mut pairs: Vec<[Entity; 2]> = fluid_box_query
+        .iter()
+        .flat_map(|(entity, fluid_box)| {
+            fluid_box
+                .connections
+                .iter()
+                .map(move |other| [entity, *other])
+        })
+        .collect();
+    // Each connection is in both sets, put both in spawn order so they can be told apart. The
+    // levels that come out depend on the order the connections are evened out in.
+    for pair in &mut pairs {
+        spawn_orders.sort_by_entity(pair, |entity| *entity);
+    }
+    spawn_orders.sort_by_entities(&mut pairs, |pair| *pair);
+    pairs.dedup();
+    let share = (FLOW_RATE * time.delta_seconds()).min(0.5);
+    for [a, b] in pairs {
+        // Boxes of removed structures are skipped
+        let Ok([(_, mut a), (_, mut b)]) = fluid_box_query.get_many_mut([a, b]) else {
+            continue;
+        };
+        let (from, to) = if a.level() > b.level() {
+            (&mut a, &mut b)
+        } else {
+            (&mut b, &mut a)
+        };
+        let Some(fluid) = from.fluid.clone() else {
+            continue;
+        };
+        // The amount that makes both levels equal
+        let even_amount = (from.level() - to.level()) / (1. / from.capacity + 1. / to.capacity);
+        let moved = to.add(&fluid, even_amount * share);
+        from.remove(moved);
+    }
+}
+
+fn offshore_pump_tick(
+    mut commands: Commands,
+    pump_query: Query<(Entity, &OffshorePump, &Children)>,
+    mut fluid_box_query: Query<&mut FluidBox>,
+    time: Res<Time>,
+) {
+    for (entity, pump, children) in &pump_query {
+        let mut pumped = 0.;
+        for child in children {
+            if let Ok(mut fluid_box) = fluid_box_query.get_mut(*child) {
+                pumped += fluid_box.add(WATER, pump.pumping_speed * time.delta_seconds());
+            }
+        }
+        if pumped > 0. {
+            commands.entity(entity).insert(Working);
+        } else {
+            commands.entity(entity).remove::<Working>();
+        }
+    }
+}
+
+fn boiler_tick(
+    mut commands: Commands,
+    boiler_query: Query<(Entity, &Boiler, &Children, Has<Powered>)>,
+    mut fluid_box_query: Query<&mut FluidBox>,
+    time: Res<Time>,
+) {
+    for (entity, boiler, children, powered) in &boiler_query {
+        let water_box = children.iter().copied().find(|child| {
+            fluid_box_query.get(*child).map_or(false, |fluid_box| {
+                fluid_box.filter.as_deref() == Some(WATER)
+            })
+        });
+        let steam_box = children.iter().copied().find(|child| {
+            fluid_box_query.get(*child).map_or(false, |fluid_box| {
+                fluid_box.filter.as_deref() == Some(STEAM)
+            })
+        });
+        let (Some(water_box), Some(steam_box)) = (water_box, steam_box) else {
+            continue;
+        };
+        let [mut water_box, mut steam_box] = fluid_box_query
+            .get_many_mut([water_box, steam_box])
+            .unwrap();
+
+        let amount = if powered {
+            (boiler.steam_per_second * time.delta_seconds())
+                .min(water_box.amount_of(WATER))
+                .min(steam_box.free_space())
+        } else {
+            0.
+        };
+        if amount > 0. {
+            water_box.remove(amount);
+            steam_box.add(STEAM, amount);
+            commands.entity(entity).insert(Working);
+        } else {
+            // Keep the burner from using up fuel while there is nothing to boil
+            commands.entity(entity).remove::<Working>();
+        }
+    }
+}
+
+/// Steam engines are powered while they have steam, and use it up depending on how much of their
+/// electricity is used
+fn steam_engine_tick(
+    mut commands: Commands,
+    steam_engine_query: Query<(Entity, &SteamEngine, &Generator, &Children, Has<Working>)>,
+    mut fluid_box_query: Query<&mut FluidBox>,
+    time: Res<Time>,
+) {
+    for (entity, steam_engine, generator, children, working) in &steam_engine_query {
+        let mut steam = 0.;
+        for child in children {
+            if let Ok(mut fluid_box) = fluid_box_query.get_mut(*child) {
+                if working {
+                    let used =
+                        steam_engine.steam_per_second * generator.load * time.delta_seconds();
+                    let steam = fluid_box.amount_of(STEAM);
+                    fluid_box.remove(used.min(steam));
+                }
+                steam += fluid_box.amount_of(STEAM);
+            }
+        }
+        if steam > 0. {
+            commands.entity(entity).insert(Powered);
+        } else {
+            commands.entity(entity).remove::<Powered>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{f32::consts::FRAC_PI_2, time::Duration};
+
+    use crate::spawn_order::SpawnCounter;
+
+    use super::*;
+
+    fn fluid_app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Time>().add_systems(
+            Update,
+            (
+                connect_fluid_boxes,
+                offshore_pump_tick,
+                boiler_tick,
+                fluid_flow,
+            )
+                .chain(),
+        );
+        app
+    }
+
+    fn advance_time(app: &mut App, seconds: f32) {
+        app.world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(seconds));
+        app.update();
+    }
+
+    fn connection(direction: Vec2, max_distance: u32) -> PipeConnection {
+        PipeConnection {
+            position: Vec2::ZERO,
+            direction,
+            max_distance,
+        }
+    }
+
+    /// A pipe connecting east and west
+    fn spawn_pipe(app: &mut App, x: f32) -> Entity {
+        app.world
+            .spawn((
+                FluidBox::new(
+                    100.,
+                    None,
+                    vec![connection(Vec2::X, 1), connection(Vec2::NEG_X, 1)],
+                ),
+                GlobalTransform::from_xyz(x, 0., 0.),
+            ))
+            .id()
+    }
+
+    fn spawn_underground_pipe(app: &mut App, x: f32, direction: Vec2) -> Entity {
+        app.world
+            .spawn((
+                FluidBox::new(
+                    100.,
+                    None,
+                    vec![connection(-direction, 1), connection(direction, 10)],
+                ),
+                GlobalTransform::from_xyz(x, 0., 0.),
+            ))
+            .id()
+    }
+
+    fn fill(app: &mut App, fluid_box: Entity, fluid: &str, amount: f32) {
+        app.world
+            .get_mut::<FluidBox>(fluid_box)
+            .unwrap()
+            .add(fluid, amount);
+    }
+
+    fn amount(app: &App, fluid_box: Entity) -> f32 {
+        app.world.get::<FluidBox>(fluid_box).unwrap().amount
+    }
+
+    #[test]
+    fn fluid_flows_between_connected_pipes() {
+        let mut app = fluid_app();
+        let pipes: Vec<Entity> = (0..3).map(|x| spawn_pipe(&mut app, x as f32)).collect();
+        let unconnected_pipe = spawn_pipe(&mut app, 5.);
+        fill(&mut app, pipes[0], WATER, 90.);
+
+        for _ in 0..100 {
+            advance_time(&mut app, 0.1);
+        }
+
+        for pipe in &pipes {
+            assert!((amount(&app, *pipe) - 30.).abs() < 0.1);
+            let fluid_box = app.world.get::<FluidBox>(*pipe).unwrap();
+            assert_eq!(fluid_box.fluid.as_deref(), Some(WATER));
+        }
+        assert_eq!(amount(&app, unconnected_pipe), 0.);
+    }
+
+    #[test]
+    fn fluid_flows_in_spawn_order() {
+        // Fluid flowing from the first pipe on, for pipes numbered in and against spawn order
+        let flow_once = |reversed: bool| {
+            let mut app = fluid_app();
+            let mut pipes: Vec<Entity> = (0..3).map(|x| spawn_pipe(&mut app, x as f32)).collect();
+            if reversed {
+                pipes.reverse();
+            }
+            let mut spawn_counter = SpawnCounter::default();
+            for pipe in &pipes {
+                app.world
+                    .entity_mut(*pipe)
+                    .insert(spawn_counter.next_order());
+            }
+            fill(&mut app, pipes[0], WATER, 90.);
+            advance_time(&mut app, 0.1);
+            pipes
+                .iter()
+                .map(|pipe| amount(&app, *pipe))
+                .collect::<Vec<f32>>()
+        };
+
+        assert_eq!(flow_once(false), vec![67.5, 16.875, 5.625]);
+        assert_eq!(flow_once(true), flow_once(false));
+    }
+
+    #[test]
+    fn fluids_dont_mix() {
+        let mut app = fluid_app();
+        let water_pipe = spawn_pipe(&mut app, 0.);
+        let steam_pipe = spawn_pipe(&mut app, 1.);
+        fill(&mut app, water_pipe, WATER, 50.);
+        fill(&mut app, steam_pipe, STEAM, 10.);
+
+        advance_time(&mut app, 1.);
+
+        assert_eq!(amount(&app, water_pipe), 50.);
+        assert_eq!(amount(&app, steam_pipe), 10.);
+    }
+
+    #[test]
+    fn underground_pipes_connect_to_the_closest_underground_pipe() {
+        let mut app = fluid_app();
+        let entrance = spawn_underground_pipe(&mut app, 0., Vec2::X);
+        // Regular pipes in between are skipped
+        let pipe = spawn_pipe(&mut app, 2.);
+        let exit = spawn_underground_pipe(&mut app, 4., Vec2::NEG_X);
+        let far_exit = spawn_underground_pipe(&mut app, 6., Vec2::NEG_X);
+
+        app.update();
+
+        let entrance_box = app.world.get::<FluidBox>(entrance).unwrap();
+        assert_eq!(entrance_box.connections, EntitySet::from([exit]));
+        assert!(app
+            .world
+            .get::<FluidBox>(pipe)
+            .unwrap()
+            .connections
+            .is_empty());
+        assert!(app
+            .world
+            .get::<FluidBox>(far_exit)
+            .unwrap()
+            .connections
+            .is_empty());
+    }
+
+    #[test]
+    fn rotated_pipes_connect_in_their_new_direction() {
+        let quarter_turn = |x: f32, y: f32| {
+            GlobalTransform::from(
+                Transform::from_xyz(x, y, 0.).with_rotation(Quat::from_rotation_z(FRAC_PI_2)),
+            )
+        };
+        let mut app = fluid_app();
+        let west = spawn_pipe(&mut app, 0.);
+        let rotated = spawn_pipe(&mut app, 1.);
+        let north = spawn_pipe(&mut app, 1.);
+        app.world.entity_mut(north).insert(quarter_turn(1., 1.));
+
+        app.update();
+        let connections =
+            |app: &App, pipe: Entity| app.world.get::<FluidBox>(pipe).unwrap().connections.clone();
+        assert_eq!(connections(&app, rotated), EntitySet::from([west]));
+        assert!(connections(&app, north).is_empty());
+
+        app.world.entity_mut(rotated).insert(quarter_turn(1., 0.));
+        app.update();
+        assert_eq!(connections(&app, rotated), EntitySet::from([north]));
+        assert_eq!(connections(&app, north), EntitySet::from([rotated]));
+        assert!(connections(&app, west).is_empty());
+    }
+
+    #[test]
+    fn boiler_turns_water_into_steam_while_powered() {
+        let mut app = fluid_app();
+        let water_box = app
+            .world
+            .spawn((
+                FluidBox::new(100., Some(WATER.to_string()), vec![]),
+                GlobalTransform::default(),
+            ))
+            .id();
+        let steam_box = app
+            .world
+            .spawn((
+                FluidBox::new(100., Some(STEAM.to_string()), vec![]),
+                GlobalTransform::default(),
+            ))
+            .id();
+        let boiler = app
+            .world
+            .spawn(Boiler {
+                steam_per_second: 10.,
+            })
+            .push_children(&[water_box, steam_box])
+            .id();
+        fill(&mut app, water_box, WATER, 50.);
+
+        advance_time(&mut app, 1.);
+        assert_eq!(amount(&app, steam_box), 0.);
+        assert!(app.world.get::<Working>(boiler).is_none());
+
+        app.world.entity_mut(boiler).insert(Powered);
+        advance_time(&mut app, 2.);
+        assert_eq!(amount(&app, water_box), 30.);
+        assert_eq!(amount(&app, steam_box), 20.);
+        assert!(app.world.get::<Working>(boiler).is_some());
+    }
+
+    #[test]
+    fn offshore_pump_fills_connected_pipes() {
+        let mut app = fluid_app();
+        let pump_box = app
+            .world
+            .spawn((
+                FluidBox::new(100., Some(WATER.to_string()), vec![connection(Vec2::X, 1)]),
+                GlobalTransform::default(),
+            ))
+            .id();
+        app.world
+            .spawn(OffshorePump { pumping_speed: 20. })
+            .push_children(&[pump_box]);
+        let pipe = spawn_pipe(&mut app, 1.);
+
+        for _ in 0..50 {
+            advance_time(&mut app, 0.1);
+        }
+
+        assert!(amount(&app, pipe) > 10.);
+        let fluid_box = app.world.get::<FluidBox>(pipe).unwrap();
+        assert_eq!(fluid_box.fluid.as_deref(), Some(WATER));
+    }
+}
@@ -1,13 +1,21 @@
 use bevy::{math::Vec3Swizzles, prelude::*};
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    ground_item::GroundItemParams,
     inventory::{Inventory, InventoryParams, InventoryType, Stack, MAX_STACK_SIZE},
     item::Item,
+    player_command::apply_player_commands,
+    simulation::SimulationSet,
+    spawn_order::SpawnOrders,
     tile_occupants::TileOccupants,
-    types::{AppState, Powered, Working},
+    types::{Powered, Working},
 };
 
-use super::transport_belt::{TransportBelt, TransportBeltSet};
+use super::{
+    electricity::{power_speed, ElectricConsumer},
+    transport_belt::{far_lane, BeltLane, BeltParams, BELT_SLOTS},
+};
 
 #[derive(SystemSet, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct InserterSet;
@@ -24,9 +32,15 @@ impl Plugin for InserterPlugin {
                 inserter_tick,
             )
                 .chain()
-                .in_set(InserterSet)
-                .after(TransportBeltSet)
-                .run_if(in_state(AppState::Running)),
+                .in_set(InserterSet),
+        )
+        .configure_sets(FixedUpdate, InserterSet.in_set(SimulationSet::Inserters))
+        .add_event::<ChangeInserterFilterEvent>()
+        .add_systems(
+            FixedUpdate,
+            inserter_change_filter
+                .after(apply_player_commands)
+                .in_set(SimulationSet::Commands),
         )
         .register_type::<Inserter>();
     }
@@ -43,8 +57,14 @@ pub struct Inserter {
     target_arm_position: f32,
     pickup_tile: Entity,
     dropoff_tile: Entity,
+    /// Direction the arm moves in when going from the pickup to the dropoff tile
+    dropoff_direction: Vec2,
     current_action: Option<InserterAction>,
     speed: f32,
+    /// Number of tiles between the inserter and its pickup and dropoff tiles
+    reach: u32,
+    /// Only filter inserters have a filter, other inserters move any item
+    filter: Option<InserterFilter>,
 }
 
 impl Inserter {
@@ -53,6 +73,7 @@ impl Inserter {
         capacity: u32,
         pickup_location_entity: Entity,
         dropoff_location_entity: Entity,
+        dropoff_direction: Vec2,
     ) -> Self {
         Inserter {
             holding: None,
@@ -61,11 +82,26 @@ impl Inserter {
             target_arm_position: 0.,
             pickup_tile: pickup_location_entity,
             dropoff_tile: dropoff_location_entity,
+            dropoff_direction,
             current_action: None,
             speed,
+            reach: 1,
+            filter: None,
         }
     }
 
+    /// Inserters reach one tile by default. The pickup and dropoff tiles passed to `new` should
+    /// be `reach` tiles away from the inserter, see `inserter_pickup_location`.
+    pub fn with_reach(mut self, reach: u32) -> Self {
+        self.reach = reach;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: InserterFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
     pub fn arm_position(&self) -> f32 {
         self.arm_position
     }
@@ -73,6 +109,93 @@ impl Inserter {
     pub fn holding(&self) -> Option<&Stack> {
         self.holding.as_ref()
     }
+
+    pub fn reach(&self) -> u32 {
+        self.reach
+    }
+
+    pub fn filter(&self) -> Option<&InserterFilter> {
+        self.filter.as_ref()
+    }
+
+    /// Put a loaded inserter back in the state it was saved in. The arm stays where it was and
+    /// the next action is planned from scratch.
+    pub fn restore(
+        &mut self,
+        holding: Option<Stack>,
+        arm_position: f32,
+        filter: Option<InserterFilter>,
+    ) {
+        self.holding = holding;
+        self.arm_position = arm_position;
+        self.target_arm_position = arm_position;
+        self.current_action = None;
+        if self.filter.is_some() {
+            self.filter = filter;
+        }
+    }
+
+    /// Point a rotated inserter at its new pickup and dropoff tiles. The arm keeps what it holds
+    /// and the next action is planned from scratch.
+    pub fn retarget(&mut self, pickup_tile: Entity, dropoff_tile: Entity, dropoff_direction: Vec2) {
+        self.pickup_tile = pickup_tile;
+        self.dropoff_tile = dropoff_tile;
+        self.dropoff_direction = dropoff_direction;
+        self.target_arm_position = self.arm_position;
+        self.current_action = None;
+    }
+
+    fn allows(&self, item: &Item) -> bool {
+        self.filter
+            .as_ref()
+            .map_or(true, |filter| filter.allows(item))
+    }
+}
+
+/// Number of items that can be set in the filter of a filter inserter
+pub const INSERTER_FILTER_SLOTS: usize = 5;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect, Serialize, Deserialize)]
+pub enum InserterFilterMode {
+    /// Only pick up the items in the filter
+    #[default]
+    Whitelist,
+    /// Pick up anything except the items in the filter
+    Blacklist,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Reflect, Serialize, Deserialize)]
+pub struct InserterFilter {
+    pub mode: InserterFilterMode,
+    pub items: Vec<Item>,
+}
+
+impl InserterFilter {
+    pub fn allows(&self, item: &Item) -> bool {
+        match self.mode {
+            InserterFilterMode::Whitelist => self.items.contains(item),
+            InserterFilterMode::Blacklist => !self.items.contains(item),
+        }
+    }
+}
+
+#[derive(Debug, Event)]
+pub struct ChangeInserterFilterEvent {
+    pub entity: Entity,
+    pub filter: InserterFilter,
+}
+
+fn inserter_change_filter(
+    mut inserter_query: Query<&mut Inserter>,
+    mut change_filter_events: EventReader<ChangeInserterFilterEvent>,
+) {
+    for event in change_filter_events.read() {
+        if let Ok(mut inserter) = inserter_query.get_mut(event.entity) {
+            if inserter.filter.is_some() {
+                inserter.filter = Some(event.filter.clone());
+            }
+        }
+    }
 }
 
 #[derive(Component, Debug, Reflect)]
@@ -80,15 +203,16 @@ pub struct InserterHand(pub Entity);
 
 #[derive(Hash, PartialEq, Eq, Clone, Debug, Reflect)]
 enum InserterTargetType {
-    Belt(Entity),
+    Belt(Entity, BeltLane),
     Inventory(Entity),
+    /// The ground item to pick up from, or the tile to drop onto
     ItemOnGround(Entity),
 }
 
 impl InserterTargetType {
     fn entity(&self) -> Entity {
         match self {
-            InserterTargetType::Belt(entity) => *entity,
+            InserterTargetType::Belt(entity, _) => *entity,
             InserterTargetType::Inventory(entity) => *entity,
             InserterTargetType::ItemOnGround(entity) => *entity,
         }
@@ -161,49 +285,63 @@ fn find_inventory_pickups_for_entity<'a>(
 
 fn find_belt_pickups_for_entity<'a>(
     entity: Entity,
-    belts_query: &'a Query<&TransportBelt>,
+    belt_params: &'a BeltParams,
     target_item: &'a PickupTarget,
 ) -> impl Iterator<Item = AvailablePickup> + 'a {
-    belts_query
-        .get(entity)
-        .ok()
-        .into_iter()
-        .flat_map(move |belt| {
-            belt.slot(1).unwrap().as_ref().and_then(|item| {
-                let is_target_item = match target_item {
-                    PickupTarget::Any => true,
-                    PickupTarget::Filter(ref filter) => filter.contains(item),
-                };
+    BeltLane::ALL.into_iter().filter_map(move |lane| {
+        belt_params.slot(entity, lane, 1).and_then(|item| {
+            let is_target_item = match target_item {
+                PickupTarget::Any => true,
+                PickupTarget::Filter(ref filter) => filter.contains(item),
+            };
 
-                if is_target_item {
-                    Some(AvailablePickup {
-                        target_type: InserterTargetType::Belt(entity),
-                        target_item: item.clone(),
-                    })
-                } else {
-                    None
-                }
-            })
+            if is_target_item {
+                Some(AvailablePickup {
+                    target_type: InserterTargetType::Belt(entity, lane),
+                    target_item: item.clone(),
+                })
+            } else {
+                None
+            }
+        })
+    })
+}
+
+fn find_ground_pickups_for_entity(
+    entity: Entity,
+    ground_item_params: &GroundItemParams,
+    target_item: &PickupTarget,
+) -> Option<AvailablePickup> {
+    ground_item_params
+        .stack(entity)
+        .filter(|stack| target_item.contains(&stack.item))
+        .map(|stack| AvailablePickup {
+            target_type: InserterTargetType::ItemOnGround(entity),
+            target_item: stack.item.clone(),
         })
 }
 
 fn find_pickups(
     inserter: &Inserter,
     inventories: &InventoryParams,
-    belts_query: &Query<&TransportBelt>,
+    belt_params: &BeltParams,
+    ground_item_params: &GroundItemParams,
     belt_occupants_query: &Query<&TileOccupants>,
+    spawn_orders: &SpawnOrders,
     target_item: &PickupTarget,
 ) -> Vec<AvailablePickup> {
     belt_occupants_query
         .get(inserter.pickup_tile)
-        .map(|o| o.iter())
+        .map(|o| spawn_orders.sorted(o.iter().copied()))
         .into_iter()
         .flatten()
-        .flat_map(move |&entity| {
+        .flat_map(move |entity| {
             let inventory_pickups =
                 find_inventory_pickups_for_entity(entity, inventories, target_item);
-            let belt_pickups = find_belt_pickups_for_entity(entity, belts_query, target_item);
-            inventory_pickups.chain(belt_pickups)
+            let belt_pickups = find_belt_pickups_for_entity(entity, belt_params, target_item);
+            let ground_pickups =
+                find_ground_pickups_for_entity(entity, ground_item_params, target_item);
+            inventory_pickups.chain(belt_pickups).chain(ground_pickups)
         })
         .collect()
 }
@@ -256,16 +394,17 @@ fn find_inventory_dropoffs_for_entity<'a>(
 
 fn find_belt_dropoffs_for_entity<'a>(
     entity: Entity,
-    belts_query: &'a Query<'_, '_, &TransportBelt>,
+    belt_params: &'a BeltParams<'_, '_>,
+    dropoff_direction: Vec2,
 ) -> impl Iterator<Item = DropoffRequest> + 'a {
-    belts_query
-        .get(entity)
-        .ok()
+    belt_params
+        .rotation(entity)
         .into_iter()
-        .filter_map(move |belt| {
-            if belt.can_add(1) {
+        .filter_map(move |rotation| {
+            let lane = far_lane(rotation, dropoff_direction);
+            if belt_params.can_add(entity, lane, 1) {
                 Some(DropoffRequest {
-                    target_type: InserterTargetType::Belt(entity),
+                    target_type: InserterTargetType::Belt(entity, lane),
                     target_item: PickupTarget::Any,
                 })
             } else {
@@ -274,39 +413,78 @@ fn find_belt_dropoffs_for_entity<'a>(
         })
 }
 
+/// Items are dropped on the ground if there is nothing on the dropoff tile to put them in
+fn find_ground_dropoff(
+    tile: Entity,
+    ground_item_params: &GroundItemParams,
+) -> Option<DropoffRequest> {
+    if ground_item_params.is_tile_blocked(tile) {
+        return None;
+    }
+    let lying_stack = ground_item_params
+        .ground_item_at_tile(tile)
+        .and_then(|ground_item| ground_item_params.stack(ground_item));
+    let target_item = match lying_stack {
+        Some(stack) => {
+            // Only more of the same item can be put on top
+            let one_more = Stack::new(stack.item.clone(), 1);
+            if !ground_item_params.can_drop_stack_at_tile(&one_more, tile) {
+                return None;
+            }
+            PickupTarget::Filter(vec![stack.item.clone()])
+        }
+        None => PickupTarget::Any,
+    };
+    Some(DropoffRequest {
+        target_type: InserterTargetType::ItemOnGround(tile),
+        target_item,
+    })
+}
+
 fn find_dropoffs(
     inserter: &Inserter,
     inventories: &InventoryParams,
-    belts_query: &Query<&TransportBelt>,
+    belt_params: &BeltParams,
+    ground_item_params: &GroundItemParams,
     tile_occupants_query: &Query<&TileOccupants>,
+    spawn_orders: &SpawnOrders,
     target_item: Option<&Item>,
 ) -> Vec<DropoffRequest> {
     tile_occupants_query
         .get(inserter.dropoff_tile)
-        .map(|o| o.iter())
+        .map(|o| spawn_orders.sorted(o.iter().copied()))
         .into_iter()
         .flatten()
-        .flat_map(move |&entity| {
+        .flat_map(move |entity| {
             let inventory_dropoffs =
                 find_inventory_dropoffs_for_entity(entity, inventories, target_item);
-            let belt_dropoffs = find_belt_dropoffs_for_entity(entity, belts_query);
+            let belt_dropoffs =
+                find_belt_dropoffs_for_entity(entity, belt_params, inserter.dropoff_direction);
 
             inventory_dropoffs.chain(belt_dropoffs)
         })
+        .chain(find_ground_dropoff(
+            inserter.dropoff_tile,
+            ground_item_params,
+        ))
         .collect()
 }
 
 fn plan_inserter_action(
     inserter: &Inserter,
     inventories: &InventoryParams,
-    belts_query: &Query<&TransportBelt>,
+    belt_params: &BeltParams,
+    ground_item_params: &GroundItemParams,
     tile_occupants_query: &Query<&TileOccupants>,
+    spawn_orders: &SpawnOrders,
 ) -> Option<InserterAction> {
     let dropoffs = find_dropoffs(
         inserter,
         inventories,
-        belts_query,
+        belt_params,
+        ground_item_params,
         tile_occupants_query,
+        spawn_orders,
         inserter.holding.as_ref().map(|stack| &stack.item),
     );
     if let Some(holding) = inserter.holding.as_ref() {
@@ -327,11 +505,14 @@ fn plan_inserter_action(
             find_pickups(
                 inserter,
                 inventories,
-                belts_query,
+                belt_params,
+                ground_item_params,
                 tile_occupants_query,
+                spawn_orders,
                 &dropoff.target_item,
             )
             .iter()
+            .filter(|pickup| inserter.allows(&pickup.target_item))
             .find(|pickup| match dropoff.target_item {
                 PickupTarget::Any => true,
                 PickupTarget::Filter(ref filter) => filter.contains(&pickup.target_item),
@@ -348,7 +529,8 @@ fn plan_inserter_action(
 fn check_inserter_action_valid<'w, 's, 'a>(
     inserter: &'a Inserter,
     inventories: &'a Query<&Inventory>,
-    belts_query: &'a Query<'w, 's, &TransportBelt>,
+    belt_params: &'a BeltParams<'w, 's>,
+    ground_item_params: &'a GroundItemParams<'w, 's>,
     tile_occupants_query: &'a Query<'w, 's, &TileOccupants>,
     action: &'a InserterAction,
 ) -> bool {
@@ -363,28 +545,35 @@ fn check_inserter_action_valid<'w, 's, 'a>(
         return false;
     }
 
-    let dropoff_tile_occupants = tile_occupants_query.get(inserter.dropoff_tile).unwrap();
+    // The filter may have changed since the action was planned. Items that were already picked
+    // up are still dropped off.
+    if inserter.holding.is_none() && !inserter.allows(&action.item) {
+        debug!("Action item is not allowed by the inserter filter");
+        return false;
+    }
+
+    let dropoff_on_tile = match action.dropoff {
+        InserterTargetType::ItemOnGround(tile) => tile == inserter.dropoff_tile,
+        ref dropoff => tile_occupants_query
+            .get(inserter.dropoff_tile)
+            .map_or(false, |occupants| occupants.contains(&dropoff.entity())),
+    };
 
-    if !dropoff_tile_occupants.contains(&action.dropoff.entity()) {
+    if !dropoff_on_tile {
         debug!("Dropoff entity is not on the dropoff tile");
         return false;
     }
 
     let dropoff_valid = match action.dropoff {
-        InserterTargetType::Belt(entity) => belts_query
-            .get(entity)
-            .ok()
-            .map(|belt| belt.can_add(1))
-            .unwrap_or(false),
+        InserterTargetType::Belt(entity, lane) => belt_params.can_add(entity, lane, 1),
         InserterTargetType::Inventory(entity) => {
             let space_in_inventory = inventories
                 .get(entity)
                 .map_or(false, |inventory| inventory.can_add_item(&action.item));
             space_in_inventory
         }
-        InserterTargetType::ItemOnGround(_entity) => {
-            // TODO: Check if the ground is clear
-            true
+        InserterTargetType::ItemOnGround(tile) => {
+            ground_item_params.can_drop_stack_at_tile(&Stack::new(action.item.clone(), 1), tile)
         }
     };
 
@@ -398,27 +587,25 @@ fn check_inserter_action_valid<'w, 's, 'a>(
     }
 
     if let Some(pickup_target) = action.pickup.as_ref() {
-        let pickup_tile_occupants = tile_occupants_query.get(inserter.pickup_tile).unwrap();
-        if !pickup_tile_occupants.contains(&pickup_target.entity()) {
+        // Tiles lose their occupants once the last structure on them is mined
+        let on_pickup_tile = tile_occupants_query
+            .get(inserter.pickup_tile)
+            .is_ok_and(|occupants| occupants.contains(&pickup_target.entity()));
+        if !on_pickup_tile {
             debug!("Pickup entity is not on the pickup tile");
             return false;
         }
 
-        let pickup_valid = action.pickup.as_ref().map_or(true, |pickup| {
-            match pickup {
-                InserterTargetType::Belt(entity) => belts_query
-                    .get(*entity)
-                    .ok()
-                    .map(|belt| belt.slot(1).unwrap().is_some())
-                    .unwrap_or(false),
-                InserterTargetType::Inventory(entity) => inventories
-                    .get(*entity)
-                    .map_or(false, |inventory| inventory.has_item(&action.item)),
-                InserterTargetType::ItemOnGround(_entity) => {
-                    // TODO: Check if the ground is clear
-                    true
-                }
+        let pickup_valid = action.pickup.as_ref().map_or(true, |pickup| match pickup {
+            InserterTargetType::Belt(entity, lane) => {
+                belt_params.slot(*entity, *lane, 1) == Some(&action.item)
             }
+            InserterTargetType::Inventory(entity) => inventories
+                .get(*entity)
+                .map_or(false, |inventory| inventory.has_item(&action.item)),
+            InserterTargetType::ItemOnGround(entity) => ground_item_params
+                .stack(*entity)
+                .map_or(false, |stack| stack.item == action.item),
         });
         if !pickup_valid {
             debug!("Pickup entity is not valid");
@@ -429,14 +616,19 @@ fn check_inserter_action_valid<'w, 's, 'a>(
     true
 }
 
+/// Inserters plan in spawn order, the first one to plan gets the items they both could take
 fn inserter_planner(
     mut commands: Commands,
     mut inserter_query: Query<(Entity, &mut Inserter), With<Powered>>,
     tile_occupants_query: Query<&TileOccupants>,
     mut inventories_set: ParamSet<(InventoryParams, Query<&Inventory>)>,
-    belts_query: Query<&TransportBelt>,
+    belt_params: BeltParams,
+    ground_item_params: GroundItemParams,
+    spawn_orders: SpawnOrders,
 ) {
-    for (inserter_entity, mut inserter) in &mut inserter_query {
+    let inserters = spawn_orders.sorted(inserter_query.iter().map(|(entity, _)| entity));
+    for inserter_entity in inserters {
+        let (_, mut inserter) = inserter_query.get_mut(inserter_entity).unwrap();
         let span = info_span!("Inserter planner", inserter = ?inserter_entity);
         let _enter = span.enter();
 
@@ -449,7 +641,8 @@ fn inserter_planner(
                         !check_inserter_action_valid(
                             &inserter,
                             &inventories_set.p1(),
-                            &belts_query,
+                            &belt_params,
+                            &ground_item_params,
                             &tile_occupants_query,
                             current_action,
                         )
@@ -460,8 +653,10 @@ fn inserter_planner(
                 let new_action = plan_inserter_action(
                     &inserter,
                     &inventories_set.p0(),
-                    &belts_query,
+                    &belt_params,
+                    &ground_item_params,
                     &tile_occupants_query,
+                    &spawn_orders,
                 );
                 inserter.target_arm_position = if inserter.holding.is_some() {
                     1.0
@@ -484,17 +679,24 @@ fn inserter_planner(
 
 fn inserter_tick(
     mut inserter_query: Query<(Entity, &Transform, &mut Inserter), (With<Powered>, With<Working>)>,
+    electric_consumer_query: Query<&ElectricConsumer>,
     time: Res<Time<Fixed>>,
     mut inventories: Query<&mut Inventory>,
-    mut belts_query: Query<&mut TransportBelt>,
+    mut belt_params: BeltParams,
+    mut ground_item_params: GroundItemParams,
+    spawn_orders: SpawnOrders,
 ) {
-    for (inserter_entity, _inserter_transform, mut inserter) in &mut inserter_query {
+    let inserters = spawn_orders.sorted(inserter_query.iter().map(|(entity, ..)| entity));
+    for inserter_entity in inserters {
+        let (_, inserter_transform, mut inserter) =
+            inserter_query.get_mut(inserter_entity).unwrap();
         let span = info_span!("Inserter tick", inserter = ?inserter_entity);
         let _enter = span.enter();
 
         // Move towards the target location
+        let speed = inserter.speed * power_speed(electric_consumer_query.get(inserter_entity).ok());
         inserter.arm_position +=
-            inserter.speed * time.delta_seconds() * inserter.target_arm_position.signum();
+            speed * time.delta_seconds() * inserter.target_arm_position.signum();
         inserter.arm_position = inserter.arm_position.clamp(-1.0, 1.0);
 
         if (inserter.arm_position - inserter.target_arm_position).abs() < 0.01 {
@@ -502,37 +704,70 @@ fn inserter_tick(
                 if let Some(stack) = inserter.holding.take() {
                     // Dropoff
                     match action.dropoff {
-                        InserterTargetType::Belt(entity) => {
-                            let mut belt = belts_query.get_mut(entity).unwrap();
-                            belt.add(1, stack.item);
+                        InserterTargetType::Belt(entity, lane) => {
+                            // Belts take one item at a time, the rest of the stack is dropped
+                            // off in the next ticks. If the lane filled up in the meantime, the
+                            // inserter keeps holding the whole stack.
+                            let mut stack = stack;
+                            if belt_params.add(entity, lane, 1, stack.item.clone()) {
+                                stack.amount -= 1;
+                            }
+                            if stack.amount > 0 {
+                                inserter.holding = Some(stack);
+                            }
                         }
                         InserterTargetType::Inventory(entity) => {
+                            // Whatever doesn't fit anymore is kept in the hand
                             let mut inventory = inventories.get_mut(entity).unwrap();
-                            inventory.add_stack(stack);
+                            inserter.holding = inventory.add_stack(stack);
                         }
-                        InserterTargetType::ItemOnGround(_entity) => {
-                            // TODO: Implement dropping items on the ground
+                        InserterTargetType::ItemOnGround(tile) => {
+                            // Only a few items fit on the ground, so they are dropped one at a
+                            // time. The dropoff direction points to the centre of the tile.
+                            let dropoff_position = inserter_transform.translation.truncate()
+                                + inserter.dropoff_direction;
+                            let mut stack = stack;
+                            let item = Stack::new(stack.item.clone(), 1);
+                            if ground_item_params.drop_stack_at_tile(&item, tile, dropoff_position)
+                            {
+                                stack.amount -= 1;
+                            }
+                            if stack.amount > 0 {
+                                inserter.holding = Some(stack);
+                            }
                         }
                     }
                     inserter.current_action = None;
                 } else {
                     // Pickup
                     match action.pickup.unwrap() {
-                        InserterTargetType::Belt(entity) => {
-                            let mut belt = belts_query.get_mut(entity).unwrap();
-                            let stack = belt.slot_mut(1).unwrap().take().unwrap();
-                            inserter.holding = Some(Stack {
-                                item: stack,
-                                amount: 1,
-                            });
+                        InserterTargetType::Belt(entity, lane) => {
+                            // An inserter that went before this one may have taken the item
+                            let Some(item) = belt_params.take(entity, lane, 1) else {
+                                inserter.current_action = None;
+                                continue;
+                            };
+                            let mut stack = Stack::new(item, 1);
+                            // Inserters with a larger capacity grab more of the same item from
+                            // the rest of the lane on this belt
+                            for slot in 0..BELT_SLOTS {
+                                if stack.amount >= inserter.capacity {
+                                    break;
+                                }
+                                if belt_params.slot(entity, lane, slot) == Some(&stack.item) {
+                                    belt_params.take(entity, lane, slot);
+                                    stack.amount += 1;
+                                }
+                            }
+                            inserter.holding = Some(stack);
                         }
                         InserterTargetType::Inventory(entity) => {
                             let mut inventory = inventories.get_mut(entity).unwrap();
                             let stack = inventory.try_take_item(&action.item, inserter.capacity);
                             inserter.holding = stack;
                         }
-                        InserterTargetType::ItemOnGround(_entity) => {
-                            // TODO: Implement picking up items from the ground
+                        InserterTargetType::ItemOnGround(entity) => {
+                            inserter.holding = ground_item_params.take(entity, inserter.capacity);
                         }
                     }
                     inserter.target_arm_position = 1.0;
@@ -552,19 +787,22 @@ fn inserter_with_offset(inserter_transform: &GlobalTransform, direction: Vec3) -
 pub const INSERTER_PICKUP_OFFSET: Vec3 = Vec3::new(-1., 0., 0.);
 pub const INSERTER_DROPOFF_OFFSET: Vec3 = Vec3::new(1., 0., 0.);
 
-pub fn inserter_dropoff_location(inserter_transform: &GlobalTransform) -> Vec2 {
-    inserter_with_offset(inserter_transform, INSERTER_DROPOFF_OFFSET)
+pub fn inserter_dropoff_location(inserter_transform: &GlobalTransform, reach: u32) -> Vec2 {
+    inserter_with_offset(inserter_transform, INSERTER_DROPOFF_OFFSET * reach as f32)
 }
 
-pub fn inserter_pickup_location(inserter_transform: &GlobalTransform) -> Vec2 {
-    inserter_with_offset(inserter_transform, INSERTER_PICKUP_OFFSET)
+pub fn inserter_pickup_location(inserter_transform: &GlobalTransform, reach: u32) -> Vec2 {
+    inserter_with_offset(inserter_transform, INSERTER_PICKUP_OFFSET * reach as f32)
 }
 
 #[cfg(test)]
 mod test {
     use bevy::{
         app::{App, Update},
-        ecs::system::Query,
+        ecs::{schedule::IntoSystemConfigs, system::Query},
+        math::Vec2,
+        time::{Fixed, Time},
+        transform::components::Transform,
         utils::HashSet,
     };
     use proptest::{prelude::*, strategy::ValueTree};
@@ -572,18 +810,21 @@ mod test {
     use rand::seq::SliceRandom;
 
     use crate::{
+        discrete_rotation::{DiscreteRotation, SideCount},
+        ground_item::GroundItemParams,
         inventory::{Inventory, InventoryParams, Stack, Storage, MAX_STACK_SIZE},
         item::Item,
+        spawn_order::{SpawnCounter, SpawnOrders},
         structure_components::{
             inserter::{
                 find_belt_pickups_for_entity, find_inventory_dropoffs_for_entity,
-                find_inventory_pickups_for_entity, find_pickups, inserter_planner, Inserter,
-                PickupTarget,
+                find_inventory_pickups_for_entity, find_pickups, inserter_planner, inserter_tick,
+                Inserter, InserterFilter, InserterFilterMode, PickupTarget,
             },
-            transport_belt::TransportBelt,
+            transport_belt::{spawn_test_belt, test_belt_slot, BeltLane, BeltParams},
         },
         tile_occupants::TileOccupants,
-        types::Powered,
+        types::{Powered, Working},
     };
     // Strategy to generate random items
     fn arb_item() -> impl Strategy<Value = Item> {
@@ -750,12 +991,12 @@ mod test {
         ) {
             let mut app = App::new();
 
-            let mut belt = TransportBelt::default();
-            for (i, item) in items_in_slots.iter().enumerate() {
-                *belt.slot_mut(i).unwrap() = item.clone();
-
-            }
-            let belt_entity = app.world.spawn(belt).id();
+            let belt_items = items_in_slots
+                .iter()
+                .enumerate()
+                .filter_map(|(i, item)| item.clone().map(|item| (BeltLane::Right, i, item)))
+                .collect();
+            let belt_entity = spawn_test_belt(&mut app.world, DiscreteRotation::new(SideCount::Four), belt_items);
 
             // Collect all items in the belt slots
             let items = items_in_slots.into_iter().flatten().collect::<HashSet<_>>();
@@ -764,12 +1005,12 @@ mod test {
             let pickup_target = arb_pickup_target(&items).new_tree(&mut test_runner).unwrap().current();
             let pickup_target_1 = pickup_target.clone();
 
-            app.add_systems(Update, move |belts_query: Query<&TransportBelt>| {
-                let pickups = find_belt_pickups_for_entity(belt_entity, &belts_query, &pickup_target_1).collect::<Vec<_>>();
+            app.add_systems(Update, move |belt_params: BeltParams| {
+                let pickups = find_belt_pickups_for_entity(belt_entity, &belt_params, &pickup_target_1).collect::<Vec<_>>();
 
                 match &pickup_target_1 {
                     PickupTarget::Any => {
-                        let expected_pickups = belts_query.get(belt_entity).unwrap().slot(1).unwrap().iter().count();
+                        let expected_pickups = belt_params.slot(belt_entity, BeltLane::Right, 1).into_iter().count();
                         assert_eq!(
                             pickups.len(),
                             expected_pickups,
@@ -779,7 +1020,7 @@ mod test {
                         );
                     }
                     PickupTarget::Filter(filter) => {
-                        let expected_pickups = belts_query.get(belt_entity).unwrap().slot(1).unwrap().iter().filter(|item| filter.contains(item)).count();
+                        let expected_pickups = belt_params.slot(belt_entity, BeltLane::Right, 1).into_iter().filter(|item| filter.contains(item)).count();
                         assert_eq!(
                             pickups.len(),
                             expected_pickups,
@@ -792,19 +1033,19 @@ mod test {
                 }
             });
 
-            let empty_belt_entity = app.world.spawn(TransportBelt::default()).id();
+            let empty_belt_entity = spawn_test_belt(&mut app.world, DiscreteRotation::new(SideCount::Four), vec![]);
 
-            app.add_systems(Update, move |belts_query: Query<&TransportBelt>| {
-                let pickups = find_belt_pickups_for_entity(empty_belt_entity, &belts_query, &pickup_target).collect::<Vec<_>>();
+            app.add_systems(Update, move |belt_params: BeltParams| {
+                let pickups = find_belt_pickups_for_entity(empty_belt_entity, &belt_params, &pickup_target).collect::<Vec<_>>();
 
                 assert_eq!(pickups.len(), 0, "There should be no pickups for an empty belt.");
             });
 
-            app.add_systems(Update, move |belts_query: Query<&TransportBelt>| {
+            app.add_systems(Update, move |belt_params: BeltParams| {
                 // Create a nonexistent target item
                 let nonexistent_item = Item::new("Nonexistent");
 
-                let pickups = find_belt_pickups_for_entity(belt_entity, &belts_query, &PickupTarget::Filter(vec![nonexistent_item])).collect::<Vec<_>>();
+                let pickups = find_belt_pickups_for_entity(belt_entity, &belt_params, &PickupTarget::Filter(vec![nonexistent_item])).collect::<Vec<_>>();
 
                 assert_eq!(pickups.len(), 0, "There should be no pickups for a nonexistent target item.");
             });
@@ -833,7 +1074,7 @@ mod test {
             let inserter_entity =
                 app
                     .world
-                    .spawn(Inserter::new(1.0, 10, pickup_tile, dropoff_tile))
+                    .spawn(Inserter::new(1.0, 10, pickup_tile, dropoff_tile, Vec2::X))
                     .id();
 
             let mut items = HashSet::<Item>::new();
@@ -845,15 +1086,19 @@ mod test {
 
             app.add_systems(Update, move |
                 inventories: InventoryParams,
-                belts_query: Query<&TransportBelt>,
+                belt_params: BeltParams,
+                ground_item_params: GroundItemParams,
                 tile_occupants_query: Query<&TileOccupants>,
+                spawn_orders: SpawnOrders,
                 inserter_query: Query<&Inserter>,
                 | {
                 let pickups = find_pickups(
                     inserter_query.get(inserter_entity).unwrap(),
                     &inventories,
-                    &belts_query,
+                    &belt_params,
+                    &ground_item_params,
                     &tile_occupants_query,
+                    &spawn_orders,
                     &pickup_target_1,
                 );
 
@@ -895,19 +1140,19 @@ mod test {
             // Create a Bevy App with necessary plugins
             let mut app = App::new();
 
-            let mut belt = TransportBelt::default();
-            for (i, item) in items_in_slots.iter().enumerate() {
-                *belt.slot_mut(i).unwrap() = item.clone();
-
-            }
-            let belt_entity = app.world.spawn(belt).id();
+            let belt_items = items_in_slots
+                .iter()
+                .enumerate()
+                .filter_map(|(i, item)| item.clone().map(|item| (BeltLane::Right, i, item)))
+                .collect();
+            let belt_entity = spawn_test_belt(&mut app.world, DiscreteRotation::new(SideCount::Four), belt_items);
 
             let pickup_tile = app.world.spawn(TileOccupants::new([belt_entity].into())).id();
             let dropoff_tile = app.world.spawn(TileOccupants::new([].into())).id();
             let inserter_entity =
                 app
                     .world
-                    .spawn(Inserter::new(1.0, 10, pickup_tile, dropoff_tile))
+                    .spawn(Inserter::new(1.0, 10, pickup_tile, dropoff_tile, Vec2::X))
                     .id();
 
             let items = items_in_slots.into_iter().flatten().collect::<HashSet<_>>();
@@ -918,21 +1163,25 @@ mod test {
 
             app.add_systems(Update, move |
                 inventories: InventoryParams,
-                belts_query: Query<&TransportBelt>,
+                belt_params: BeltParams,
+                ground_item_params: GroundItemParams,
                 tile_occupants_query: Query<&TileOccupants>,
+                spawn_orders: SpawnOrders,
                 inserter_query: Query<&Inserter>,
                 | {
                 let pickups = find_pickups(
                     inserter_query.get(inserter_entity).unwrap(),
                     &inventories,
-                    &belts_query,
+                    &belt_params,
+                    &ground_item_params,
                     &tile_occupants_query,
+                    &spawn_orders,
                     &pickup_target_1,
                 );
 
                 match &pickup_target_1 {
                     PickupTarget::Any => {
-                        let expected_pickups = belts_query.get(belt_entity).unwrap().slot(1).unwrap().iter().count();
+                        let expected_pickups = belt_params.slot(belt_entity, BeltLane::Right, 1).into_iter().count();
                         assert_eq!(
                             pickups.len(),
                             expected_pickups,
@@ -942,7 +1191,7 @@ mod test {
                         );
                     }
                     PickupTarget::Filter(filter) => {
-                        let expected_pickups = belts_query.get(belt_entity).unwrap().slot(1).unwrap().iter().filter(|item| filter.contains(item)).count();
+                        let expected_pickups = belt_params.slot(belt_entity, BeltLane::Right, 1).into_iter().filter(|item| filter.contains(item)).count();
                         assert_eq!(
                             pickups.len(),
                             expected_pickups,
@@ -1110,7 +1359,7 @@ mod test {
             let inserter_entity =
                 app
                     .world
-                    .spawn((Inserter::new(1.0, 10, pickup_tile_entity, dropoff_tile_entity), Powered))
+                    .spawn((Inserter::new(1.0, 10, pickup_tile_entity, dropoff_tile_entity, Vec2::X), Powered))
                     .id();
 
             let any_pickup = pickup_inventory.slots.iter().any(|slot| slot.is_some());
@@ -1153,7 +1402,7 @@ mod test {
             let inserter_entity =
                 app
                     .world
-                    .spawn((Inserter::new(1.0, 10, pickup_tile_entity, dropoff_tile_entity), Powered))
+                    .spawn((Inserter::new(1.0, 10, pickup_tile_entity, dropoff_tile_entity, Vec2::X), Powered))
                     .id();
 
 
@@ -1191,7 +1440,7 @@ mod test {
             let inserter_entity =
                 app
                     .world
-                    .spawn((Inserter::new(1.0, 10, pickup_tile_entity, dropoff_tile_entity), Powered))
+                    .spawn((Inserter::new(1.0, 10, pickup_tile_entity, dropoff_tile_entity, Vec2::X), Powered))
                     .id();
 
             let any_matching_items = {
@@ -1212,4 +1461,194 @@ mod test {
             }
         }
     }
+
+    proptest! {
+        #[test]
+        fn test_inserter_planner_filter(
+            pickup_inventory in arb_partial_inventory(),
+            filter_items in arb_items(),
+            mode in prop_oneof![
+                Just(InserterFilterMode::Whitelist),
+                Just(InserterFilterMode::Blacklist),
+            ],
+        ) {
+            let mut app = App::new();
+
+            let pickup_inventory_entity = app.world.spawn((
+                pickup_inventory.clone(),
+                Storage,
+            )).id();
+
+            let dropoff_inventory_entity = app.world.spawn((
+                Inventory::new(10),
+                Storage,
+            )).id();
+
+            let pickup_tile_entity = app.world.spawn(TileOccupants::new([pickup_inventory_entity].into())).id();
+            let dropoff_tile_entity = app.world.spawn(TileOccupants::new([dropoff_inventory_entity].into())).id();
+
+            let filter = InserterFilter {
+                mode,
+                items: filter_items.into_iter().collect(),
+            };
+            let inserter_entity =
+                app
+                    .world
+                    .spawn((
+                        Inserter::new(1.0, 10, pickup_tile_entity, dropoff_tile_entity, Vec2::X)
+                            .with_filter(filter.clone()),
+                        Powered,
+                    ))
+                    .id();
+
+            let any_allowed_pickup = pickup_inventory
+                .slots
+                .iter()
+                .flatten()
+                .any(|stack| filter.allows(&stack.item));
+
+            app.add_systems(Update, inserter_planner);
+            app.update();
+
+            let inserter = app.world.get::<Inserter>(inserter_entity).unwrap();
+            match &inserter.current_action {
+                Some(action) => {
+                    assert!(filter.allows(&action.item), "Inserter picked up {:?}, which is filtered out", action.item);
+                }
+                None => {
+                    assert!(!any_allowed_pickup, "Inserter should have a current action");
+                }
+            }
+        }
+    }
+
+    /// An app that plans and moves inserters without letting time pass, so arms only move when
+    /// the test puts them in place
+    fn inserter_tick_app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Time<Fixed>>()
+            .add_systems(Update, (inserter_planner, inserter_tick).chain());
+        app
+    }
+
+    #[test]
+    fn stack_inserter_takes_multiple_items_from_belt() {
+        let mut app = inserter_tick_app();
+        let coal = Item::new("Coal");
+        let belt_entity = spawn_test_belt(
+            &mut app.world,
+            DiscreteRotation::new(SideCount::Four),
+            (0..3)
+                .map(|slot| (BeltLane::Right, slot, coal.clone()))
+                .collect(),
+        );
+        let dropoff_inventory_entity = app.world.spawn((Inventory::new(10), Storage)).id();
+        let pickup_tile_entity = app
+            .world
+            .spawn(TileOccupants::new([belt_entity].into()))
+            .id();
+        let dropoff_tile_entity = app
+            .world
+            .spawn(TileOccupants::new([dropoff_inventory_entity].into()))
+            .id();
+        let mut inserter = Inserter::new(1.0, 2, pickup_tile_entity, dropoff_tile_entity, Vec2::X);
+        inserter.arm_position = -1.;
+        let inserter_entity = app
+            .world
+            .spawn((inserter, Transform::default(), Powered, Working))
+            .id();
+
+        app.update();
+
+        let inserter = app.world.get::<Inserter>(inserter_entity).unwrap();
+        assert_eq!(inserter.holding, Some(Stack::new(coal.clone(), 2)));
+        // Only one item is left on the belt
+        let left_on_belt = (0..3)
+            .filter(|slot| {
+                test_belt_slot(&app.world, belt_entity, BeltLane::Right, *slot).is_some()
+            })
+            .count();
+        assert_eq!(left_on_belt, 1);
+    }
+
+    #[test]
+    fn inserter_keeps_what_does_not_fit_in_the_dropoff() {
+        let mut app = inserter_tick_app();
+        let coal = Item::new("Coal");
+        let mut dropoff_inventory = Inventory::new(1);
+        dropoff_inventory.add_item(&coal, MAX_STACK_SIZE - 1);
+        let dropoff_inventory_entity = app.world.spawn((dropoff_inventory, Storage)).id();
+        let pickup_tile_entity = app.world.spawn(TileOccupants::default()).id();
+        let dropoff_tile_entity = app
+            .world
+            .spawn(TileOccupants::new([dropoff_inventory_entity].into()))
+            .id();
+        let mut inserter = Inserter::new(1.0, 5, pickup_tile_entity, dropoff_tile_entity, Vec2::X);
+        inserter.arm_position = 1.;
+        inserter.holding = Some(Stack::new(coal.clone(), 3));
+        let inserter_entity = app
+            .world
+            .spawn((inserter, Transform::default(), Powered, Working))
+            .id();
+
+        app.update();
+
+        let inserter = app.world.get::<Inserter>(inserter_entity).unwrap();
+        assert_eq!(inserter.holding, Some(Stack::new(coal.clone(), 2)));
+        let dropoff_inventory = app
+            .world
+            .get::<Inventory>(dropoff_inventory_entity)
+            .unwrap();
+        assert_eq!(dropoff_inventory.num_items(&coal), MAX_STACK_SIZE);
+    }
+
+    #[test]
+    fn inserters_take_turns_in_spawn_order() {
+        let mut app = inserter_tick_app();
+        let coal = Item::new("Coal");
+        let belt_entity = spawn_test_belt(
+            &mut app.world,
+            DiscreteRotation::new(SideCount::Four),
+            vec![(BeltLane::Right, 1, coal.clone())],
+        );
+        let pickup_tile_entity = app
+            .world
+            .spawn(TileOccupants::new([belt_entity].into()))
+            .id();
+        let mut spawn_counter = SpawnCounter::default();
+        let spawn_inserter = |app: &mut App| {
+            let dropoff_inventory_entity = app.world.spawn((Inventory::new(1), Storage)).id();
+            let dropoff_tile_entity = app
+                .world
+                .spawn(TileOccupants::new([dropoff_inventory_entity].into()))
+                .id();
+            let mut inserter =
+                Inserter::new(1.0, 1, pickup_tile_entity, dropoff_tile_entity, Vec2::X);
+            inserter.arm_position = -1.;
+            app.world
+                .spawn((inserter, Transform::default(), Powered, Working))
+                .id()
+        };
+        let second_inserter_entity = spawn_inserter(&mut app);
+        let first_inserter_entity = spawn_inserter(&mut app);
+        // The inserter spawned later on this client was spawned first by the simulation
+        app.world
+            .entity_mut(first_inserter_entity)
+            .insert(spawn_counter.next_order());
+        app.world
+            .entity_mut(second_inserter_entity)
+            .insert(spawn_counter.next_order());
+
+        app.update();
+
+        let holding = |inserter_entity| {
+            app.world
+                .get::<Inserter>(inserter_entity)
+                .unwrap()
+                .holding
+                .clone()
+        };
+        assert_eq!(holding(first_inserter_entity), Some(Stack::new(coal, 1)));
+        assert_eq!(holding(second_inserter_entity), None);
+    }
 }
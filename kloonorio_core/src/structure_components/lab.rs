@@ -0,0 +1,177 @@
+use bevy::prelude::*;
+
+use crate::{
+    inventory::{Inventory, ItemFilter, Source},
+    research::{ResearchQueue, Technologies},
+    simulation::SimulationSet,
+    types::{Powered, Working},
+};
+
+use super::electricity::{power_speed, ElectricConsumer};
+
+pub struct LabPlugin;
+
+impl Plugin for LabPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Lab>().add_systems(
+            FixedUpdate,
+            (lab_source_filter, lab_tick)
+                .chain()
+                .in_set(SimulationSet::Labs),
+        );
+    }
+}
+
+/// Uses up science packs from its source inventory to research the first technology in the
+/// research queue
+#[derive(Component, Debug, Reflect)]
+pub struct Lab {
+    pub speed: f32,
+    /// Progress of the unit of research the lab is working on, the science packs for it are
+    /// already used up
+    pub unit_progress: Option<f32>,
+}
+
+impl Lab {
+    pub fn new(speed: f32) -> Self {
+        Lab {
+            speed,
+            unit_progress: None,
+        }
+    }
+}
+
+/// Only science packs can be put in a lab
+pub fn lab_source_filter(
+    lab_query: Query<&Children, Added<Lab>>,
+    mut source_query: Query<&mut Inventory, With<Source>>,
+    technologies: Res<Technologies>,
+) {
+    for children in &lab_query {
+        let allowed_items = ItemFilter::Only(technologies.science_packs());
+        for child in children {
+            if let Ok(mut source) = source_query.get_mut(*child) {
+                source.allowed_items = allowed_items.clone();
+            }
+        }
+    }
+}
+
+pub fn lab_tick(
+    mut commands: Commands,
+    mut lab_query: Query<(Entity, &mut Lab, &Children), With<Powered>>,
+    mut source_query: Query<&mut Inventory, With<Source>>,
+    electric_consumer_query: Query<&ElectricConsumer>,
+    mut research_queue: ResMut<ResearchQueue>,
+    technologies: Res<Technologies>,
+    time: Res<Time>,
+) {
+    for (entity, mut lab, children) in &mut lab_query {
+        let Some(technology) = research_queue
+            .current()
+            .and_then(|technology| technologies.get(technology))
+        else {
+            commands.entity(entity).remove::<Working>();
+            continue;
+        };
+
+        if lab.unit_progress.is_none() {
+            let source = children
+                .iter()
+                .find(|child| source_query.contains(**child))
+                .and_then(|child| source_query.get_mut(*child).ok());
+            if let Some(mut source) = source {
+                if source.remove_items(&technology.cost) {
+                    lab.unit_progress = Some(0.);
+                }
+            }
+        }
+
+        let speed = lab.speed * power_speed(electric_consumer_query.get(entity).ok());
+        let Some(unit_progress) = lab.unit_progress.as_mut() else {
+            commands.entity(entity).remove::<Working>();
+            continue;
+        };
+        commands.entity(entity).insert(Working);
+        *unit_progress += speed * time.delta_seconds() / technology.time;
+        if *unit_progress >= 1. {
+            lab.unit_progress = None;
+            if let Some(researched) = research_queue.add_progress(&technologies) {
+                info!("Researched {}", researched);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::{
+        item::Item,
+        research::{Technology, TechnologyEffect},
+    };
+
+    use super::*;
+
+    fn lab_app() -> App {
+        let mut app = App::new();
+        let mut technologies = Technologies::default();
+        technologies.insert(
+            "Automation".to_string(),
+            Technology {
+                name: "Automation".to_string(),
+                prerequisites: vec![],
+                cost: vec![(Item::new("Automation science pack"), 1)],
+                units: 2,
+                time: 1.,
+                effects: vec![TechnologyEffect::UnlockRecipe(
+                    "Assembling machine".to_string(),
+                )],
+            },
+        );
+        app.init_resource::<Time>()
+            .init_resource::<ResearchQueue>()
+            .insert_resource(technologies)
+            .add_systems(Update, (lab_source_filter, lab_tick).chain());
+        app
+    }
+
+    fn advance_time(app: &mut App, seconds: f32) {
+        app.world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(seconds));
+        app.update();
+    }
+
+    #[test]
+    fn lab_uses_science_packs_for_research() {
+        let mut app = lab_app();
+        let mut inventory = Inventory::new(1);
+        inventory.add_item(&Item::new("Automation science pack"), 3);
+        let source = app.world.spawn((Source, inventory)).id();
+        let lab = app
+            .world
+            .spawn((Lab::new(1.), Powered))
+            .push_children(&[source])
+            .id();
+
+        // Nothing to research
+        app.update();
+        assert!(app.world.get::<Working>(lab).is_none());
+
+        app.world
+            .resource_mut::<ResearchQueue>()
+            .queue
+            .push_back("Automation".to_string());
+        for _ in 0..4 {
+            advance_time(&mut app, 0.6);
+        }
+
+        let research_queue = app.world.resource::<ResearchQueue>();
+        assert!(research_queue.is_researched("Automation"));
+        let source = app.world.get::<Inventory>(source).unwrap();
+        assert_eq!(source.num_items(&Item::new("Automation science pack")), 1);
+        assert!(!source.can_add(&[(Item::new("Iron plate"), 1)]));
+    }
+}
@@ -1,18 +1,23 @@
+use std::time::Duration;
+
 use bevy::prelude::*;
 
 use crate::{
     drop::DropParams,
-    inventory::Stack,
     mineable::Mineable,
-    types::{AppState, Powered, Working},
+    simulation::SimulationSet,
+    spawn_order::SpawnOrders,
+    types::{Powered, Working},
 };
 
+use super::electricity::{power_speed, ElectricConsumer};
+
 pub struct MinerPlugin;
 
 impl Plugin for MinerPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<Miner>()
-            .add_systems(FixedUpdate, miner_tick.run_if(in_state(AppState::Running)));
+            .add_systems(FixedUpdate, miner_tick.in_set(SimulationSet::Miners));
     }
 }
 
@@ -21,28 +26,50 @@ pub struct Miner {
     timer: Timer,
     mined_tiles: Vec<Entity>,
     dropoff_tile: Entity,
+    dropoff_direction: Vec2,
     current_mineable: Option<Entity>,
 }
 
 impl Miner {
-    pub fn new(speed: f32, mined_tiles: Vec<Entity>, dropoff_tile: Entity) -> Self {
+    pub fn new(
+        speed: f32,
+        mined_tiles: Vec<Entity>,
+        dropoff_tile: Entity,
+        dropoff_direction: Vec2,
+    ) -> Self {
         Miner {
             timer: Timer::from_seconds(speed, TimerMode::Repeating),
             mined_tiles,
             dropoff_tile,
+            dropoff_direction,
             current_mineable: None,
         }
     }
+
+    /// Seconds spent mining the next item
+    pub fn progress(&self) -> f32 {
+        self.timer.elapsed_secs()
+    }
+
+    /// Put back the progress of a miner that was loaded from a save game
+    pub fn restore_progress(&mut self, progress: f32) {
+        self.timer.set_elapsed(Duration::from_secs_f32(progress));
+    }
 }
 
 pub fn miner_tick(
     mut commands: Commands,
-    mut miner_query: Query<(Entity, &mut Miner), With<Powered>>,
+    mut miner_query: Query<(Entity, &GlobalTransform, &mut Miner), With<Powered>>,
+    electric_consumer_query: Query<&ElectricConsumer>,
     time: Res<Time<Fixed>>,
     mineables_query: Query<&Mineable>,
     mut drop_params: DropParams,
+    spawn_orders: SpawnOrders,
 ) {
-    for (miner_entity, mut miner) in miner_query.iter_mut() {
+    // Miners dropping onto the same tile take turns in the same order on every client
+    let miners = spawn_orders.sorted(miner_query.iter().map(|(entity, ..)| entity));
+    for miner_entity in miners {
+        let (_, miner_transform, mut miner) = miner_query.get_mut(miner_entity).unwrap();
         let span = info_span!("Miner tick", miner = ?miner_entity);
         let _enter = span.enter();
 
@@ -63,12 +90,26 @@ pub fn miner_tick(
             current_mineable_entity.and_then(|e| mineables_query.get(e).ok())
         {
             has_mineable = true;
-            let stack = Stack::new(current_mineable.0.clone(), 1);
-            debug!("Produced {:?}", stack);
-            has_dropoff = drop_params.can_drop_stack_at_tile(&stack, miner.dropoff_tile);
-            if miner.timer.tick(time.delta()).just_finished() && has_dropoff {
-                debug!("Dropping stack");
-                drop_params.drop_stack_at_tile(&stack, miner.dropoff_tile);
+            let item = &current_mineable.0;
+            debug!("Produced {:?}", item);
+            has_dropoff = drop_params.can_drop_item_at_tile(
+                item,
+                miner.dropoff_tile,
+                miner.dropoff_direction,
+            );
+            let speed = power_speed(electric_consumer_query.get(miner_entity).ok());
+            let delta = time.delta().mul_f32(speed);
+            if miner.timer.tick(delta).just_finished() && has_dropoff {
+                debug!("Dropping item");
+                // The dropoff direction points from the miner to the centre of the dropoff tile
+                let dropoff_position =
+                    miner_transform.translation().truncate() + miner.dropoff_direction;
+                drop_params.drop_item_at_tile(
+                    item,
+                    miner.dropoff_tile,
+                    dropoff_position,
+                    miner.dropoff_direction,
+                );
             }
         }
 
@@ -79,3 +120,52 @@ pub fn miner_tick(
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        inventory::{Inventory, Stack, Storage},
+        item::Item,
+        spawn_order::SpawnCounter,
+        tile_occupants::TileOccupants,
+    };
+
+    use super::*;
+
+    #[test]
+    fn miners_take_turns_in_spawn_order() {
+        let mut app = App::new();
+        app.init_resource::<Time<Fixed>>()
+            .add_systems(Update, miner_tick);
+        // A chest with room for a single stack, both miners drop into it
+        let chest = app.world.spawn((Inventory::new(1), Storage)).id();
+        let dropoff_tile = app.world.spawn(TileOccupants::new([chest].into())).id();
+        let mut spawn_miner = |product: &'static str| {
+            let mined_tile = app.world.spawn(Mineable(Item::new(product))).id();
+            app.world
+                .spawn((
+                    Miner::new(1., vec![mined_tile], dropoff_tile, Vec2::X),
+                    GlobalTransform::default(),
+                    Powered,
+                ))
+                .id()
+        };
+        let stone_miner = spawn_miner("Stone");
+        let coal_miner = spawn_miner("Coal");
+        // The miner numbered last on this client was spawned first by the simulation
+        let mut spawn_counter = SpawnCounter::default();
+        for miner in [coal_miner, stone_miner] {
+            app.world
+                .entity_mut(miner)
+                .insert(spawn_counter.next_order());
+        }
+
+        app.world
+            .resource_mut::<Time<Fixed>>()
+            .advance_by(Duration::from_secs(1));
+        app.update();
+
+        let inventory = app.world.get::<Inventory>(chest).unwrap();
+        assert_eq!(inventory.slots[0], Some(Stack::new(Item::new("Coal"), 1)));
+    }
+}
@@ -1,23 +1,34 @@
 pub mod assembler;
 pub mod burner;
+pub mod electricity;
+pub mod fluid;
 pub mod inserter;
+pub mod lab;
 pub mod miner;
 pub mod smelter;
+pub mod splitter;
 pub mod transport_belt;
 
 use bevy::{
     app::{App, FixedUpdate, Plugin},
+    ecs::schedule::IntoSystemConfigs,
     reflect::{Reflect, TypeUuid},
     utils::HashSet,
 };
 use serde::Deserialize;
 
+use crate::simulation::SimulationSet;
+
 use self::{
     assembler::AssemblerPlugin,
-    burner::{burner_load, burner_tick},
+    burner::{burner_fuel_filter, burner_load, burner_tick},
+    electricity::ElectricityPlugin,
+    fluid::{FluidPlugin, PipeConnection},
     inserter::InserterPlugin,
+    lab::LabPlugin,
     miner::MinerPlugin,
-    smelter::smelter_tick,
+    smelter::{smelter_source_filter, smelter_tick},
+    splitter::SplitterPlugin,
     transport_belt::TransportBeltPlugin,
 };
 
@@ -31,23 +42,61 @@ impl Plugin for StructureComponentsPlugin {
                 TransportBeltPlugin,
                 AssemblerPlugin,
                 MinerPlugin,
+                SplitterPlugin,
+                ElectricityPlugin,
+                FluidPlugin,
+                LabPlugin,
             ))
-            .add_systems(FixedUpdate, (smelter_tick, burner_tick, burner_load));
+            .add_systems(
+                FixedUpdate,
+                (
+                    (smelter_source_filter, smelter_tick)
+                        .chain()
+                        .in_set(SimulationSet::Smelters),
+                    (burner_fuel_filter, burner_load, burner_tick)
+                        .chain()
+                        .in_set(SimulationSet::Burners),
+                ),
+            );
     }
 }
 
 #[derive(Clone, Debug, Deserialize, TypeUuid, Reflect)]
 #[uuid = "990c9ea7-3c00-4d6b-b9f0-c62b86bb9973"]
 pub enum StructureComponent {
-    Smelter,
-    Burner,
+    /// Recipe categories the furnace can craft
+    Smelter(HashSet<String>),
+    /// Energy consumption in kW while working
+    Burner(f32),
     CraftingQueue,
     Inventory(u32),
     Source(u32, HashSet<String>),
     Output(u32),
     Fuel(u32),
     Miner(f32),
-    Inserter(f32, u32),
+    /// Speed, capacity and reach in tiles
+    Inserter(f32, u32, u32),
+    /// An inserter with a configurable item filter
+    FilterInserter(f32, u32, u32),
     TransportBelt,
-    Assembler,
+    UndergroundBelt(u32),
+    Splitter,
+    /// Recipe categories the assembler can craft
+    Assembler(HashSet<String>),
+    /// Wire reach and supply range in tiles
+    PowerPole(f32, f32),
+    /// Electricity production in kW
+    Generator(f32),
+    /// Electricity consumption in kW
+    ElectricConsumer(f32),
+    /// Capacity, the only fluid it can hold, and where it connects to other fluid boxes
+    FluidBox(f32, Option<String>, Vec<PipeConnection>),
+    /// Water pumped per second
+    OffshorePump(f32),
+    /// Steam produced per second
+    Boiler(f32),
+    /// Steam used per second at full load
+    SteamEngine(f32),
+    /// Research speed
+    Lab(f32),
 }
@@ -1,51 +1,85 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::HashSet};
 
 use crate::types::{ActiveCraft, CraftingQueue, Powered, Working};
 use crate::{
-    inventory::{Inventory, Output, Source},
-    item::Item,
-    recipe::Recipe,
+    inventory::{Inventory, ItemFilter, Output, Source},
+    recipe::Recipes,
 };
 
-#[derive(Component)]
-pub struct Smelter;
+use super::electricity::{power_speed, ElectricConsumer};
+
+/// A furnace picks a recipe by itself from the categories it can craft, based on the items in
+/// its source inventory
+#[derive(Component, Debug, Reflect)]
+pub struct Smelter {
+    pub categories: HashSet<String>,
+}
+
+impl Smelter {
+    pub fn new(categories: HashSet<String>) -> Self {
+        Smelter { categories }
+    }
+}
+
+/// Only ingredients of the recipes a furnace can craft can be put in it
+pub fn smelter_source_filter(
+    smelter_query: Query<(&Smelter, &Children), Added<Smelter>>,
+    mut source_query: Query<&mut Inventory, With<Source>>,
+    recipes: Res<Recipes>,
+) {
+    for (smelter, children) in &smelter_query {
+        let allowed_items = ItemFilter::Only(
+            recipes
+                .in_any_category(&smelter.categories)
+                .flat_map(|recipe| recipe.ingredients.iter().map(|(item, _)| item.clone()))
+                .collect(),
+        );
+        for child in children {
+            if let Ok(mut source) = source_query.get_mut(*child) {
+                source.allowed_items = allowed_items.clone();
+            }
+        }
+    }
+}
 
 pub fn smelter_tick(
     mut commands: Commands,
-    mut smelter_query: Query<
-        (Entity, &mut CraftingQueue, &Children),
-        (With<Smelter>, With<Powered>),
-    >,
+    mut smelter_query: Query<(Entity, &Smelter, &mut CraftingQueue, &Children), With<Powered>>,
+    electric_consumer_query: Query<&ElectricConsumer>,
     mut source_query: Query<&mut Inventory, (With<Source>, Without<Output>)>,
     mut output_query: Query<&mut Inventory, (With<Output>, Without<Source>)>,
+    recipes: Res<Recipes>,
     time: Res<Time>,
 ) {
-    for (entity, mut crafting_queue, children) in smelter_query.iter_mut() {
+    for (entity, smelter, mut crafting_queue, children) in smelter_query.iter_mut() {
         let source_entity = children.iter().find(|c| source_query.get(**c).is_ok());
         let output_entity = children.iter().find(|c| output_query.get(**c).is_ok());
 
         let mut source = source_query.get_mut(*source_entity.unwrap()).unwrap();
         let mut output = output_query.get_mut(*output_entity.unwrap()).unwrap();
 
-        if source.has_items(&[(Item::new("Iron ore"), 1)])
-            && crafting_queue.0.is_empty()
-            && output.can_add(&[(Item::new("Iron plate"), 1)])
-        {
-            source.remove_items(&[(Item::new("Iron ore"), 1)]);
-            crafting_queue.0.push_back(ActiveCraft {
-                timer: Timer::from_seconds(1., TimerMode::Repeating),
-                recipe: Recipe {
-                    ingredients: vec![(Item::new("Iron ore"), 1u32)],
-                    products: vec![(Item::new("Iron plate"), 1u32)],
-                    crafting_time: 0.5,
-                    name: "Iron Plate".into(),
-                },
-            });
-            commands.entity(entity).insert(Working);
+        if crafting_queue.0.is_empty() {
+            // Sort by name so the same recipe is picked every time if several match
+            let recipe = recipes
+                .in_any_category(&smelter.categories)
+                .filter(|recipe| {
+                    source.has_items(&recipe.ingredients) && output.can_add(&recipe.products)
+                })
+                .min_by(|a, b| a.name.cmp(&b.name));
+            if let Some(recipe) = recipe {
+                source.remove_items(&recipe.ingredients);
+                crafting_queue.0.push_back(ActiveCraft {
+                    timer: Timer::from_seconds(recipe.crafting_time, TimerMode::Repeating),
+                    recipe: recipe.clone(),
+                });
+                commands.entity(entity).insert(Working);
+            }
         }
 
         if let Some(active_build) = crafting_queue.0.front_mut() {
-            if active_build.timer.tick(time.delta()).just_finished() {
+            let speed = power_speed(electric_consumer_query.get(entity).ok());
+            let delta = time.delta().mul_f32(speed);
+            if active_build.timer.tick(delta).just_finished() {
                 output.add_items(&active_build.recipe.products);
                 crafting_queue.0.pop_front();
                 commands.entity(entity).remove::<Working>();
@@ -53,3 +87,128 @@ pub fn smelter_tick(
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        item::Item,
+        recipe::{Recipe, CRAFTING_CATEGORY, SMELTING_CATEGORY},
+    };
+
+    use super::*;
+
+    fn smelting_recipe(ingredient: &'static str, amount: u32, product: &'static str) -> Recipe {
+        Recipe {
+            ingredients: vec![(Item::new(ingredient), amount)],
+            products: vec![(Item::new(product), 1)],
+            crafting_time: 1.,
+            name: product.to_string(),
+            category: SMELTING_CATEGORY.to_string(),
+        }
+    }
+
+    fn smelter_app() -> App {
+        let mut app = App::new();
+        let mut recipes = Recipes::default();
+        for recipe in [
+            smelting_recipe("Iron ore", 1, "Iron plate"),
+            smelting_recipe("Copper ore", 1, "Copper plate"),
+            smelting_recipe("Iron plate", 5, "Steel plate"),
+            Recipe {
+                category: CRAFTING_CATEGORY.to_string(),
+                ..smelting_recipe("Iron plate", 1, "Iron gear wheel")
+            },
+        ] {
+            recipes.insert(recipe.name.clone(), recipe);
+        }
+        app.init_resource::<Time>()
+            .insert_resource(recipes)
+            .add_systems(Update, (smelter_source_filter, smelter_tick).chain());
+        app
+    }
+
+    fn spawn_smelter(app: &mut App) -> (Entity, Entity) {
+        let source = app
+            .world
+            .spawn((Source, Inventory::new_with_filter(1, Default::default())))
+            .id();
+        let output = app.world.spawn((Output, Inventory::new(1))).id();
+        let smelter = app
+            .world
+            .spawn((
+                Smelter::new(HashSet::from([SMELTING_CATEGORY.to_string()])),
+                Powered,
+                CraftingQueue::default(),
+            ))
+            .push_children(&[source, output])
+            .id();
+        (smelter, source)
+    }
+
+    #[test]
+    fn source_filter_comes_from_recipes() {
+        let mut app = smelter_app();
+        let (_, source) = spawn_smelter(&mut app);
+
+        app.update();
+
+        let source = app.world.get::<Inventory>(source).unwrap();
+        assert!(source.can_add_item(&Item::new("Copper ore")));
+        assert!(source.can_add_item(&Item::new("Iron plate")));
+        assert!(!source.can_add_item(&Item::new("Coal")));
+    }
+
+    #[test]
+    fn smelter_only_crafts_its_categories() {
+        let mut app = smelter_app();
+        let (smelter, source) = spawn_smelter(&mut app);
+        app.update();
+        // Enough for an iron gear wheel, but that isn't a smelting recipe
+        app.world
+            .get_mut::<Inventory>(source)
+            .unwrap()
+            .add_item(&Item::new("Iron plate"), 1);
+
+        app.update();
+
+        let crafting_queue = app.world.get::<CraftingQueue>(smelter).unwrap();
+        assert!(crafting_queue.0.is_empty());
+    }
+
+    #[test]
+    fn recipe_is_picked_from_source_items() {
+        let mut app = smelter_app();
+        let (smelter, source) = spawn_smelter(&mut app);
+        app.update();
+        app.world
+            .get_mut::<Inventory>(source)
+            .unwrap()
+            .add_item(&Item::new("Copper ore"), 2);
+
+        app.update();
+
+        let crafting_queue = app.world.get::<CraftingQueue>(smelter).unwrap();
+        assert_eq!(
+            crafting_queue.0.front().unwrap().recipe.name,
+            "Copper plate"
+        );
+        let source = app.world.get::<Inventory>(source).unwrap();
+        assert_eq!(source.num_items(&Item::new("Copper ore")), 1);
+    }
+
+    #[test]
+    fn no_recipe_without_enough_ingredients() {
+        let mut app = smelter_app();
+        let (smelter, source) = spawn_smelter(&mut app);
+        app.update();
+        app.world
+            .get_mut::<Inventory>(source)
+            .unwrap()
+            .add_item(&Item::new("Iron plate"), 4);
+
+        app.update();
+
+        let crafting_queue = app.world.get::<CraftingQueue>(smelter).unwrap();
+        assert!(crafting_queue.0.is_empty());
+    }
+}
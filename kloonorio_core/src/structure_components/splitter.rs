@@ -0,0 +1,409 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    discrete_rotation::DiscreteRotation, item::Item, player_command::apply_player_commands,
+    simulation::SimulationSet, spawn_order::SpawnOrders,
+};
+
+use super::transport_belt::{
+    transport_belt_tick, BeltLane, BeltParams, PreviousBelts, TransportBeltSet, TransportBeltTimer,
+    BELT_SLOTS,
+};
+
+/// Number of items a splitter can hold per belt lane, one for each input belt
+const SPLITTER_LANE_CAPACITY: usize = 2;
+
+pub struct SplitterPlugin;
+
+impl Plugin for SplitterPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Splitter>()
+            .add_event::<ChangeSplitterSettingsEvent>()
+            .add_systems(
+                FixedUpdate,
+                splitter_change_settings
+                    .after(apply_player_commands)
+                    .in_set(SimulationSet::Commands),
+            )
+            .add_systems(
+                FixedUpdate,
+                splitter_tick
+                    .after(transport_belt_tick)
+                    .in_set(TransportBeltSet),
+            );
+    }
+}
+
+/// One of the two halves of a splitter, as seen when looking in the direction the splitter is
+/// facing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
+pub enum SplitterSide {
+    #[default]
+    Left,
+    Right,
+}
+
+impl SplitterSide {
+    pub const ALL: [SplitterSide; 2] = [SplitterSide::Left, SplitterSide::Right];
+
+    pub fn opposite(&self) -> Self {
+        match self {
+            SplitterSide::Left => SplitterSide::Right,
+            SplitterSide::Right => SplitterSide::Left,
+        }
+    }
+}
+
+/// Centre of the tile covered by one half of a splitter centred at `center`.
+pub fn splitter_half_position(
+    center: Vec2,
+    rotation: &DiscreteRotation,
+    side: SplitterSide,
+) -> Vec2 {
+    let left = rotation.forward().perp() * 0.5;
+    match side {
+        SplitterSide::Left => center + left,
+        SplitterSide::Right => center - left,
+    }
+}
+
+#[derive(Debug, Default, Reflect)]
+struct SplitterLane {
+    items: VecDeque<Item>,
+    next_output: SplitterSide,
+}
+
+/// Takes items from the belts behind it (its `PreviousBelts`) and spreads them over the belts in
+/// front of it. Input belts end their segment at the splitter and output belts start a new one,
+/// so the splitter joins segments together without being part of one itself.
+#[derive(Component, Debug, Default, Reflect)]
+pub struct Splitter {
+    /// Side that items are sent to first, if any
+    pub priority: Option<SplitterSide>,
+    /// Item that is only sent to the priority side (left if there is none), all other items are
+    /// only sent to the other side
+    pub filter: Option<Item>,
+    left_output: Option<Entity>,
+    right_output: Option<Entity>,
+    left_lane: SplitterLane,
+    right_lane: SplitterLane,
+}
+
+impl Splitter {
+    pub fn output(&self, side: SplitterSide) -> Option<Entity> {
+        match side {
+            SplitterSide::Left => self.left_output,
+            SplitterSide::Right => self.right_output,
+        }
+    }
+
+    pub fn set_output(&mut self, side: SplitterSide, belt: Option<Entity>) {
+        match side {
+            SplitterSide::Left => self.left_output = belt,
+            SplitterSide::Right => self.right_output = belt,
+        }
+    }
+
+    /// Items waiting on the given belt lane to be sent to one of the outputs
+    pub fn items(&self, lane: BeltLane) -> impl Iterator<Item = &Item> {
+        self.lane(lane).items.iter()
+    }
+
+    /// Put a waiting item back on a lane, used when loading a saved game
+    pub(crate) fn push_item(&mut self, lane: BeltLane, item: Item) {
+        self.lane_mut(lane).items.push_back(item);
+    }
+
+    fn lane(&self, lane: BeltLane) -> &SplitterLane {
+        match lane {
+            BeltLane::Left => &self.left_lane,
+            BeltLane::Right => &self.right_lane,
+        }
+    }
+
+    fn lane_mut(&mut self, lane: BeltLane) -> &mut SplitterLane {
+        match lane {
+            BeltLane::Left => &mut self.left_lane,
+            BeltLane::Right => &mut self.right_lane,
+        }
+    }
+
+    /// The sides an item on the given lane may be sent to, in order of preference
+    fn output_sides(&self, item: &Item, lane: BeltLane) -> Vec<SplitterSide> {
+        if let Some(filter) = &self.filter {
+            let filter_side = self.priority.unwrap_or_default();
+            if item == filter {
+                vec![filter_side]
+            } else {
+                vec![filter_side.opposite()]
+            }
+        } else if let Some(priority) = self.priority {
+            vec![priority, priority.opposite()]
+        } else {
+            let next_output = self.lane(lane).next_output;
+            vec![next_output, next_output.opposite()]
+        }
+    }
+}
+
+#[derive(Debug, Event)]
+pub struct ChangeSplitterSettingsEvent {
+    pub entity: Entity,
+    pub priority: Option<SplitterSide>,
+    pub filter: Option<Item>,
+}
+
+fn splitter_change_settings(
+    mut splitter_query: Query<&mut Splitter>,
+    mut change_settings_events: EventReader<ChangeSplitterSettingsEvent>,
+) {
+    for event in change_settings_events.read() {
+        if let Ok(mut splitter) = splitter_query.get_mut(event.entity) {
+            splitter.priority = event.priority;
+            splitter.filter = event.filter.clone();
+        }
+    }
+}
+
+/// Splitters take turns in spawn order, as one may take items off a belt another one feeds
+pub fn splitter_tick(
+    mut splitter_query: Query<(Entity, &mut Splitter, &PreviousBelts)>,
+    mut belt_params: BeltParams,
+    belt_timer: Res<TransportBeltTimer>,
+    spawn_orders: SpawnOrders,
+) {
+    if !belt_timer.just_finished() {
+        return;
+    }
+
+    let splitters = spawn_orders.sorted(splitter_query.iter().map(|(entity, ..)| entity));
+    for splitter_entity in splitters {
+        let (_, mut splitter, inputs) = splitter_query.get_mut(splitter_entity).unwrap();
+        // Take items from the end of the input belts
+        for input in spawn_orders.sorted(inputs.belts.iter().copied()) {
+            for lane in BeltLane::ALL {
+                if splitter.lane(lane).items.len() < SPLITTER_LANE_CAPACITY {
+                    if let Some(item) = belt_params.take(input, lane, BELT_SLOTS - 1) {
+                        splitter.lane_mut(lane).items.push_back(item);
+                    }
+                }
+            }
+        }
+
+        // Spread the items over the output belts, keeping them on the same lane
+        for lane in BeltLane::ALL {
+            while let Some(item) = splitter.lane(lane).items.front().cloned() {
+                let side = splitter.output_sides(&item, lane).into_iter().find(|side| {
+                    splitter
+                        .output(*side)
+                        .map_or(false, |output| belt_params.can_add(output, lane, 0))
+                });
+                let Some(side) = side else {
+                    break;
+                };
+
+                let output = splitter.output(side).unwrap();
+                belt_params.add(output, lane, 0, item);
+
+                let splitter_lane = splitter.lane_mut(lane);
+                splitter_lane.items.pop_front();
+                splitter_lane.next_output = side.opposite();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::ecs::system::SystemState;
+
+    use crate::{
+        discrete_rotation::SideCount,
+        structure_components::transport_belt::{spawn_test_belt, test_belt_slot},
+    };
+
+    use super::*;
+
+    fn spawn_splitter(app: &mut App, splitter: Splitter, inputs: &[Entity]) -> Entity {
+        app.world
+            .spawn((
+                splitter,
+                PreviousBelts {
+                    belts: inputs.iter().copied().collect(),
+                },
+            ))
+            .id()
+    }
+
+    fn spawn_belt(app: &mut App, items: &[(BeltLane, usize, &'static str)]) -> Entity {
+        spawn_test_belt(
+            &mut app.world,
+            DiscreteRotation::new(SideCount::Four),
+            items
+                .iter()
+                .map(|(lane, slot, item)| (*lane, *slot, Item::new(*item)))
+                .collect(),
+        )
+    }
+
+    fn slot(app: &App, belt: Entity, lane: BeltLane) -> Option<Item> {
+        test_belt_slot(&app.world, belt, lane, 0)
+    }
+
+    fn add_to_last_slot(app: &mut App, belt: Entity, lane: BeltLane, item: &'static str) {
+        let mut system_state: SystemState<BeltParams> = SystemState::new(&mut app.world);
+        assert!(system_state.get_mut(&mut app.world).add(
+            belt,
+            lane,
+            BELT_SLOTS - 1,
+            Item::new(item)
+        ));
+    }
+
+    fn tick(app: &mut App) {
+        app.insert_resource(TransportBeltTimer::new(1));
+        app.update();
+    }
+
+    fn splitter_app() -> App {
+        let mut app = App::new();
+        app.add_systems(Update, splitter_tick);
+        app
+    }
+
+    #[test]
+    fn splitter_alternates_outputs() {
+        let mut app = splitter_app();
+        let input = spawn_belt(&mut app, &[(BeltLane::Right, 2, "Coal")]);
+        let left = spawn_belt(&mut app, &[]);
+        let right = spawn_belt(&mut app, &[]);
+        let mut splitter = Splitter::default();
+        splitter.set_output(SplitterSide::Left, Some(left));
+        splitter.set_output(SplitterSide::Right, Some(right));
+        spawn_splitter(&mut app, splitter, &[input]);
+
+        tick(&mut app);
+
+        assert_eq!(slot(&app, left, BeltLane::Right), Some(Item::new("Coal")));
+
+        add_to_last_slot(&mut app, input, BeltLane::Right, "Iron ore");
+
+        tick(&mut app);
+
+        assert_eq!(
+            slot(&app, right, BeltLane::Right),
+            Some(Item::new("Iron ore"))
+        );
+    }
+
+    #[test]
+    fn splitter_balances_two_inputs() {
+        let mut app = splitter_app();
+        let input_a = spawn_belt(&mut app, &[(BeltLane::Left, 2, "Coal")]);
+        let input_b = spawn_belt(&mut app, &[(BeltLane::Left, 2, "Coal")]);
+        let left = spawn_belt(&mut app, &[]);
+        let right = spawn_belt(&mut app, &[]);
+        let mut splitter = Splitter::default();
+        splitter.set_output(SplitterSide::Left, Some(left));
+        splitter.set_output(SplitterSide::Right, Some(right));
+        spawn_splitter(&mut app, splitter, &[input_a, input_b]);
+
+        tick(&mut app);
+
+        for output in [left, right] {
+            assert_eq!(slot(&app, output, BeltLane::Left), Some(Item::new("Coal")));
+        }
+    }
+
+    #[test]
+    fn splitter_prefers_priority_side() {
+        let mut app = splitter_app();
+        let input = spawn_belt(&mut app, &[(BeltLane::Right, 2, "Coal")]);
+        let left = spawn_belt(&mut app, &[]);
+        let right = spawn_belt(&mut app, &[]);
+        let mut splitter = Splitter {
+            priority: Some(SplitterSide::Right),
+            ..default()
+        };
+        splitter.set_output(SplitterSide::Left, Some(left));
+        splitter.set_output(SplitterSide::Right, Some(right));
+        spawn_splitter(&mut app, splitter, &[input]);
+
+        tick(&mut app);
+        let mut system_state: SystemState<BeltParams> = SystemState::new(&mut app.world);
+        system_state
+            .get_mut(&mut app.world)
+            .take(right, BeltLane::Right, 0);
+        add_to_last_slot(&mut app, input, BeltLane::Right, "Coal");
+        tick(&mut app);
+
+        assert_eq!(slot(&app, right, BeltLane::Right), Some(Item::new("Coal")));
+        assert_eq!(slot(&app, left, BeltLane::Right), None);
+    }
+
+    #[test]
+    fn splitter_overflows_when_priority_side_is_full() {
+        let mut app = splitter_app();
+        let input = spawn_belt(&mut app, &[(BeltLane::Right, 2, "Coal")]);
+        let left = spawn_belt(&mut app, &[]);
+        let right = spawn_belt(&mut app, &[(BeltLane::Right, 0, "Wood")]);
+        let mut splitter = Splitter {
+            priority: Some(SplitterSide::Right),
+            ..default()
+        };
+        splitter.set_output(SplitterSide::Left, Some(left));
+        splitter.set_output(SplitterSide::Right, Some(right));
+        spawn_splitter(&mut app, splitter, &[input]);
+
+        tick(&mut app);
+
+        assert_eq!(slot(&app, left, BeltLane::Right), Some(Item::new("Coal")));
+    }
+
+    #[test]
+    fn splitter_filter_sorts_items() {
+        let mut app = splitter_app();
+        let input = spawn_belt(
+            &mut app,
+            &[
+                (BeltLane::Left, 2, "Coal"),
+                (BeltLane::Right, 2, "Iron ore"),
+            ],
+        );
+        let left = spawn_belt(&mut app, &[]);
+        let right = spawn_belt(&mut app, &[]);
+        let mut splitter = Splitter {
+            priority: Some(SplitterSide::Right),
+            filter: Some(Item::new("Iron ore")),
+            ..default()
+        };
+        splitter.set_output(SplitterSide::Left, Some(left));
+        splitter.set_output(SplitterSide::Right, Some(right));
+        spawn_splitter(&mut app, splitter, &[input]);
+
+        tick(&mut app);
+
+        assert_eq!(slot(&app, left, BeltLane::Left), Some(Item::new("Coal")));
+        assert_eq!(slot(&app, left, BeltLane::Right), None);
+        assert_eq!(
+            slot(&app, right, BeltLane::Right),
+            Some(Item::new("Iron ore"))
+        );
+        assert_eq!(slot(&app, right, BeltLane::Left), None);
+    }
+
+    #[test]
+    fn splitter_half_positions() {
+        let mut rotation = DiscreteRotation::new(SideCount::Four);
+        assert_eq!(
+            splitter_half_position(Vec2::new(0.5, 0.), &rotation, SplitterSide::Left),
+            Vec2::new(0., 0.)
+        );
+        rotation.rotate();
+        let right = splitter_half_position(Vec2::new(0., 0.5), &rotation, SplitterSide::Right);
+        assert!(right.abs_diff_eq(Vec2::new(0., 0.), 1e-5));
+    }
+}
@@ -1,12 +1,24 @@
 use std::collections::VecDeque;
 
-use bevy::{prelude::*, utils::HashSet};
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
 
-use crate::{discrete_rotation::DiscreteRotation, inventory::Stack, item::Item, types::AppState};
+use serde::{Deserialize, Serialize};
 
-// TODO: Right now there's a fixed 3 slots for simplicity, but it might be interesting to merge
-// adjacent belts by pre- or appending the slots from newly constructed belts instead of
-// creating separate entities
+use crate::{
+    discrete_rotation::DiscreteRotation, entity_set::EntitySet, item::Item,
+    simulation::SimulationSet, spawn_order::SpawnOrders,
+};
+
+/// Number of item slots on each lane of a single belt
+pub const BELT_SLOTS: usize = 3;
+
+/// Fixed ticks between belts moving their items one slot forward, a second at the default fixed
+/// timestep
+pub const BELT_MOVE_TICKS: u32 = 64;
 
 #[derive(SystemSet, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct TransportBeltSet;
@@ -16,246 +28,660 @@ pub struct TransportBeltPlugin;
 impl Plugin for TransportBeltPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<TransportBelt>()
-            .insert_resource(TransportBeltTimer(Timer::from_seconds(
-                1.,
-                TimerMode::Repeating,
-            )))
+            .register_type::<BeltSegment>()
+            .register_type::<UndergroundBelt>()
+            .register_type::<TransportBeltTimer>()
+            .init_resource::<TransportBeltTimer>()
+            .configure_sets(FixedUpdate, TransportBeltSet.in_set(SimulationSet::Belts))
             .add_systems(
                 FixedUpdate,
-                (
-                    clear_lanes,
-                    apply_deferred,
-                    construct_lanes,
-                    apply_deferred,
-                    transport_belt_tick,
-                )
+                (rebuild_belt_segments, apply_deferred, transport_belt_tick)
                     .chain()
-                    .in_set(TransportBeltSet)
-                    .run_if(in_state(AppState::Running)),
+                    .in_set(TransportBeltSet),
             );
     }
 }
 
-#[derive(Component, Reflect)]
+/// One of the two lanes of a belt, as seen when looking in the direction the belt is moving.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
+pub enum BeltLane {
+    Left,
+    Right,
+}
+
+impl BeltLane {
+    pub const ALL: [BeltLane; 2] = [BeltLane::Left, BeltLane::Right];
+
+    pub fn opposite(&self) -> Self {
+        match self {
+            BeltLane::Left => BeltLane::Right,
+            BeltLane::Right => BeltLane::Left,
+        }
+    }
+}
+
+/// A single belt tile. The items on it are stored in the `BeltSegment` it belongs to, use
+/// `BeltParams` to access them per belt.
+#[derive(Component, Default, Reflect)]
 pub struct TransportBelt {
-    slots: VecDeque<Option<Item>>,
+    segment: Option<Entity>,
+    index: usize,
 }
 
-impl Default for TransportBelt {
-    fn default() -> Self {
+impl TransportBelt {
+    /// A belt at the start of a segment that isn't merged with the belts around it yet
+    pub fn in_segment(segment: Entity) -> Self {
         Self {
-            slots: VecDeque::from(vec![None, None, None]),
+            segment: Some(segment),
+            index: 0,
         }
     }
+
+    /// The segment this belt is part of and the position of the belt's first slot in it
+    pub fn segment(&self) -> Option<(Entity, usize)> {
+        self.segment
+            .map(|segment| (segment, self.index * BELT_SLOTS))
+    }
 }
 
-impl TransportBelt {
-    pub fn can_add(&self, slot: usize) -> bool {
-        self.slots[slot].is_none()
+/// An item on a segment lane, along with the number of free slots between it and the item in
+/// front of it, or the end of the segment for the front item.
+#[derive(Clone, Debug, Reflect)]
+struct GapItem {
+    item: Item,
+    gap: usize,
+}
+
+/// Items on one lane of a segment, front item first. Storing gaps instead of slots means moving
+/// the lane forward only changes a single gap, no matter how many items are on it.
+#[derive(Clone, Debug, Default, Reflect)]
+struct SegmentLane {
+    items: VecDeque<GapItem>,
+}
+
+impl SegmentLane {
+    /// Positions of the items counted from the back of the segment, front item first
+    fn positions(&self, length: usize) -> impl Iterator<Item = (usize, &Item)> {
+        let mut end = length;
+        self.items.iter().map(move |gap_item| {
+            end -= gap_item.gap + 1;
+            (end, &gap_item.item)
+        })
     }
 
-    /// Add a stack to the belt at the given slot. Returns true if the stack was added
-    pub fn add(&mut self, slot: usize, item: Item) -> bool {
-        if self.can_add(slot) {
-            self.slots[slot] = Some(item);
-            true
-        } else {
-            false
-        }
+    fn get(&self, length: usize, position: usize) -> Option<&Item> {
+        self.positions(length)
+            .take_while(|(item_position, _)| *item_position >= position)
+            .find(|(item_position, _)| *item_position == position)
+            .map(|(_, item)| item)
     }
 
-    pub fn slot(&self, slot: usize) -> Option<&Option<Item>> {
-        self.slots.get(slot)
+    fn insert(&mut self, length: usize, position: usize, item: Item) -> bool {
+        if position >= length {
+            return false;
+        }
+        let mut end = length;
+        for index in 0..self.items.len() {
+            let item_position = end - self.items[index].gap - 1;
+            if item_position == position {
+                return false;
+            }
+            if item_position < position {
+                // Split the gap in front of the item behind the new one
+                self.items[index].gap = position - item_position - 1;
+                self.items.insert(
+                    index,
+                    GapItem {
+                        item,
+                        gap: end - position - 1,
+                    },
+                );
+                return true;
+            }
+            end = item_position;
+        }
+        self.items.push_back(GapItem {
+            item,
+            gap: end - position - 1,
+        });
+        true
     }
 
-    pub fn slot_mut(&mut self, slot: usize) -> Option<&mut Option<Item>> {
-        self.slots.get_mut(slot)
+    fn remove(&mut self, length: usize, position: usize) -> Option<Item> {
+        let index = self
+            .positions(length)
+            .take_while(|(item_position, _)| *item_position >= position)
+            .position(|(item_position, _)| item_position == position)?;
+        self.remove_index(index)
     }
 
-    pub fn slots(&self) -> impl Iterator<Item = &Option<Item>> {
-        self.slots.iter()
+    /// Remove an item, merging the gap in front of it into the gap of the item behind it
+    fn remove_index(&mut self, index: usize) -> Option<Item> {
+        let removed = self.items.remove(index)?;
+        if let Some(behind) = self.items.get_mut(index) {
+            behind.gap += removed.gap + 1;
+        }
+        Some(removed.item)
     }
-}
 
-#[derive(Component, Reflect)]
-pub struct NextBelt(pub Entity);
+    /// Move every item forward one slot, except for items queued up at the end of the lane.
+    fn advance(&mut self) {
+        if let Some(gap_item) = self.items.iter_mut().find(|gap_item| gap_item.gap > 0) {
+            gap_item.gap -= 1;
+        }
+    }
 
-#[derive(Component, Reflect)]
-pub struct PreviousBelts {
-    pub belts: HashSet<Entity>,
+    fn front_at_end(&self) -> bool {
+        self.items
+            .front()
+            .map_or(false, |gap_item| gap_item.gap == 0)
+    }
 }
 
-#[derive(Component, Reflect)]
-pub struct Lane {
+/// A run of belts that items move along without changing lanes, from the back to the front.
+/// Segments are only rebuilt when belts are placed, removed or relinked.
+#[derive(Component, Debug, Default, Reflect)]
+pub struct BeltSegment {
     belts: Vec<Entity>,
+    left: SegmentLane,
+    right: SegmentLane,
 }
 
-fn clear_lanes(mut commands: Commands, lane_query: Query<Entity, With<Lane>>) {
-    for lane_entity in &lane_query {
-        commands.entity(lane_entity).despawn_recursive();
+impl BeltSegment {
+    pub fn new(belts: Vec<Entity>) -> Self {
+        Self { belts, ..default() }
     }
-}
 
-fn construct_lanes(
-    mut commands: Commands,
-    last_belts_query: Query<Entity, (With<TransportBelt>, Without<NextBelt>)>,
-    belts_query: Query<(&TransportBelt, &PreviousBelts)>,
-) {
-    let mut lanes: Vec<Lane> = Vec::new();
-    for belt_entity in &last_belts_query {
-        let span = info_span!("Transport belt tick", entity = ?belt_entity);
-        let _enter = span.enter();
+    pub fn belts(&self) -> &[Entity] {
+        &self.belts
+    }
 
-        // First get a list of all belts in the chain
-        let mut belt_lanes = construct_lanes_from_belt_chain(belt_entity, &belts_query);
-        lanes.append(&mut belt_lanes);
+    /// Number of slots on each lane of the segment
+    pub fn slot_count(&self) -> usize {
+        self.belts.len() * BELT_SLOTS
     }
 
-    // Then create the lane entities
-    for lane in lanes {
-        commands.spawn(lane);
+    fn lane(&self, lane: BeltLane) -> &SegmentLane {
+        match lane {
+            BeltLane::Left => &self.left,
+            BeltLane::Right => &self.right,
+        }
     }
-}
 
-fn construct_lanes_from_belt_chain(
-    belt_entity: Entity,
-    belts_query: &Query<(&TransportBelt, &PreviousBelts)>,
-) -> Vec<Lane> {
-    let mut lanes: Vec<Lane> = vec![];
-    let mut lane = Lane {
-        belts: vec![belt_entity],
-    };
-    let mut current_belt = belt_entity;
-    while let Some((prev_belt, branches)) = get_previous_belt(current_belt, belts_query) {
-        lane.belts.push(prev_belt);
-        current_belt = prev_belt;
-        for branch in branches {
-            let mut branch_lanes = construct_lanes_from_belt_chain(branch, belts_query);
-            lanes.append(&mut branch_lanes);
+    fn lane_mut(&mut self, lane: BeltLane) -> &mut SegmentLane {
+        match lane {
+            BeltLane::Left => &mut self.left,
+            BeltLane::Right => &mut self.right,
         }
     }
-    lanes.push(lane);
-    lanes
-}
 
-/// Return the previous belt in the chain and the set of branches (if any)
-fn get_previous_belt(
-    belt_entity: Entity,
-    belts_query: &Query<(&TransportBelt, &PreviousBelts)>,
-) -> Option<(Entity, HashSet<Entity>)> {
-    let (_belt, previous_belts) = belts_query.get(belt_entity).ok()?;
-    // Find a belt that has an item in the last slot
-    let active_prev_belt = previous_belts.belts.iter().find_map(|prev_belt| {
-        let prev_belt = *prev_belt;
-        let prev_belt_slots = belts_query.get(prev_belt).unwrap().0.slots();
-        if prev_belt_slots.last().unwrap().is_some() {
-            Some(prev_belt)
+    pub fn item_at(&self, lane: BeltLane, position: usize) -> Option<&Item> {
+        self.lane(lane).get(self.slot_count(), position)
+    }
+
+    pub fn can_add(&self, lane: BeltLane, position: usize) -> bool {
+        position < self.slot_count() && self.item_at(lane, position).is_none()
+    }
+
+    /// Add an item to the segment at the given lane and position. Returns true if the item was
+    /// added
+    pub fn add(&mut self, lane: BeltLane, position: usize, item: Item) -> bool {
+        let length = self.slot_count();
+        self.lane_mut(lane).insert(length, position, item)
+    }
+
+    pub fn take(&mut self, lane: BeltLane, position: usize) -> Option<Item> {
+        let length = self.slot_count();
+        self.lane_mut(lane).remove(length, position)
+    }
+
+    /// The items on a lane and their positions, front item first
+    pub fn items(&self, lane: BeltLane) -> impl Iterator<Item = (usize, &Item)> {
+        self.lane(lane).positions(self.slot_count())
+    }
+
+    fn advance(&mut self) {
+        self.left.advance();
+        self.right.advance();
+    }
+
+    /// Remove the front item of a lane if it is in the last slot of the segment
+    fn take_front(&mut self, lane: BeltLane) -> Option<Item> {
+        let lane = self.lane_mut(lane);
+        if lane.front_at_end() {
+            lane.remove_index(0)
         } else {
             None
         }
-    });
+    }
+}
+
+/// Per belt access to items, which are stored in the belt's segment
+#[derive(SystemParam)]
+pub struct BeltParams<'w, 's> {
+    belts_query: Query<'w, 's, (&'static TransportBelt, &'static DiscreteRotation)>,
+    segments_query: Query<'w, 's, &'static mut BeltSegment>,
+}
+
+impl<'w, 's> BeltParams<'w, 's> {
+    pub fn rotation(&self, belt: Entity) -> Option<&DiscreteRotation> {
+        self.belts_query
+            .get(belt)
+            .ok()
+            .map(|(_, rotation)| rotation)
+    }
+
+    fn position(&self, belt: Entity, slot: usize) -> Option<(Entity, usize)> {
+        if slot >= BELT_SLOTS {
+            return None;
+        }
+        let (transport_belt, _) = self.belts_query.get(belt).ok()?;
+        let (segment, first_slot) = transport_belt.segment()?;
+        Some((segment, first_slot + slot))
+    }
+
+    pub fn slot(&self, belt: Entity, lane: BeltLane, slot: usize) -> Option<&Item> {
+        let (segment, position) = self.position(belt, slot)?;
+        self.segments_query
+            .get(segment)
+            .ok()?
+            .item_at(lane, position)
+    }
+
+    pub fn can_add(&self, belt: Entity, lane: BeltLane, slot: usize) -> bool {
+        self.position(belt, slot)
+            .map_or(false, |(segment, position)| {
+                self.segments_query
+                    .get(segment)
+                    .map_or(false, |segment| segment.can_add(lane, position))
+            })
+    }
+
+    /// Add an item to a belt at the given lane and slot. Returns true if the item was added
+    pub fn add(&mut self, belt: Entity, lane: BeltLane, slot: usize, item: Item) -> bool {
+        let Some((segment, position)) = self.position(belt, slot) else {
+            return false;
+        };
+        self.segments_query
+            .get_mut(segment)
+            .map_or(false, |mut segment| segment.add(lane, position, item))
+    }
+
+    pub fn take(&mut self, belt: Entity, lane: BeltLane, slot: usize) -> Option<Item> {
+        let (segment, position) = self.position(belt, slot)?;
+        self.segments_query
+            .get_mut(segment)
+            .ok()?
+            .take(lane, position)
+    }
+}
+
+/// The lane of a belt that is furthest away when approaching it while moving in `direction`.
+/// Inserters and drills put items on the far lane, approaching from behind or ahead of the belt
+/// puts them on the right lane.
+pub fn far_lane(belt_rotation: &DiscreteRotation, direction: Vec2) -> BeltLane {
+    let forward = belt_rotation.forward();
+    let left = forward.perp();
+    let sideways = direction.normalize_or_zero().dot(left);
+    if sideways > 0.5 {
+        BeltLane::Left
+    } else {
+        BeltLane::Right
+    }
+}
+
+/// Where an item leaving the end of a lane ends up on the next belt.
+fn transfer_destination(
+    lane: BeltLane,
+    rotation: &DiscreteRotation,
+    next_rotation: &DiscreteRotation,
+    next_has_straight_input: bool,
+    next_input_count: usize,
+) -> Option<(BeltLane, usize)> {
+    let forward = rotation.forward();
+    let next_forward = next_rotation.forward();
+    if forward.dot(next_forward) > 0.5 {
+        // Straight continuation
+        Some((lane, 0))
+    } else if forward.dot(next_forward) < -0.5 {
+        // Belts facing each other can't transfer items
+        None
+    } else if !next_has_straight_input && next_input_count == 1 {
+        // The next belt is a curve, items stay on their lane
+        Some((lane, 0))
+    } else {
+        // Side-loading, items are put on the lane nearest to this belt in the middle of the
+        // next belt
+        Some((far_lane(next_rotation, forward).opposite(), 1))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Reflect, Serialize, Deserialize)]
+pub enum UndergroundBeltKind {
+    /// Takes items from the belts behind it and sends them to its exit
+    Entrance,
+    /// Receives items from its entrance and puts them on the belt in front of it
+    Exit,
+}
+
+/// A belt that passes items underground to its partner, which is at most `max_distance` tiles
+/// away in the direction both are facing. The entrance links to its exit through `NextBelt`, so
+/// items skip the tiles in between.
+#[derive(Component, Debug, Reflect)]
+pub struct UndergroundBelt {
+    pub kind: UndergroundBeltKind,
+    pub max_distance: u32,
+    pub partner: Option<Entity>,
+}
+
+impl UndergroundBelt {
+    pub fn is_entrance(&self) -> bool {
+        self.kind == UndergroundBeltKind::Entrance
+    }
+
+    pub fn is_exit(&self) -> bool {
+        self.kind == UndergroundBeltKind::Exit
+    }
+}
+
+#[derive(Component, Reflect)]
+pub struct NextBelt(pub Entity);
 
-    // If there is no active previous belt, return the first belt in the chain
-    // If there is no previous belt, return None
-    let prev_belt = active_prev_belt.or_else(|| previous_belts.belts.iter().next().copied())?;
+#[derive(Component, Reflect)]
+pub struct PreviousBelts {
+    pub belts: EntitySet,
+}
+
+/// Belts that were placed or relinked
+type ChangedBeltQuery<'w, 's> = Query<
+    'w,
+    's,
+    Entity,
+    Or<(
+        Added<TransportBelt>,
+        Changed<NextBelt>,
+        Changed<PreviousBelts>,
+    )>,
+>;
+
+/// A belt with what decides the segment it is part of
+type SegmentBeltQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut TransportBelt,
+        &'static DiscreteRotation,
+        Option<&'static NextBelt>,
+        Option<&'static PreviousBelts>,
+    ),
+>;
+
+/// Merge straight runs of belts into segments, keeping the items that were on them. Only the
+/// segments with belts that were placed, removed or relinked are rebuilt, along with the segments
+/// of the belts linked to those.
+pub(crate) fn rebuild_belt_segments(
+    mut commands: Commands,
+    mut belt_queries: ParamSet<(ChangedBeltQuery, SegmentBeltQuery)>,
+    mut removed_belts: RemovedComponents<TransportBelt>,
+    mut removed_next_belts: RemovedComponents<NextBelt>,
+    segments_query: Query<(Entity, &BeltSegment)>,
+    spawn_orders: SpawnOrders,
+    // The segment of every belt, removed belts can't be asked for theirs
+    mut belt_segments: Local<HashMap<Entity, Entity>>,
+) {
+    let changed_belts: Vec<Entity> = belt_queries.p0().iter().collect();
+    let mut belts_query = belt_queries.p1();
+    let removed_belts: HashSet<Entity> = removed_belts.read().collect();
+    let changed_belts: Vec<Entity> = changed_belts
+        .into_iter()
+        .chain(removed_next_belts.read())
+        .filter(|belt| belts_query.contains(*belt))
+        .collect();
+    if changed_belts.is_empty() && removed_belts.is_empty() {
+        return;
+    }
 
-    let other_belts = previous_belts
-        .belts
+    // Whether a belt merges with another depends on the links of both, so the belts linked to a
+    // changed one may be merged differently as well
+    let mut linked_belts: Vec<Entity> = changed_belts.clone();
+    for belt in &changed_belts {
+        let (_, _, next_belt, previous_belts) = belts_query.get(*belt).unwrap();
+        linked_belts.extend(next_belt.map(|next_belt| next_belt.0));
+        linked_belts.extend(
+            previous_belts
+                .into_iter()
+                .flat_map(|previous| previous.belts.iter()),
+        );
+    }
+    let mut old_segments: HashSet<Entity> = linked_belts
         .iter()
-        .filter(|b| **b != prev_belt)
-        .cloned()
+        .filter_map(|belt| belts_query.get(*belt).ok())
+        .filter_map(|(transport_belt, ..)| transport_belt.segment)
+        .collect();
+    old_segments.extend(
+        removed_belts
+            .iter()
+            .filter_map(|belt| belt_segments.remove(belt)),
+    );
+
+    // Take the items off the old segments, remembering which belt and slot they were on
+    let mut items = Vec::new();
+    let mut rebuilt_belts: HashSet<Entity> = linked_belts
+        .into_iter()
+        .filter(|belt| belts_query.contains(*belt))
+        .collect();
+    for segment_entity in old_segments {
+        let Ok((_, segment)) = segments_query.get(segment_entity) else {
+            continue;
+        };
+        for lane in BeltLane::ALL {
+            for (position, item) in segment.items(lane) {
+                let belt = segment.belts[position / BELT_SLOTS];
+                items.push((belt, lane, position % BELT_SLOTS, item.clone()));
+            }
+        }
+        rebuilt_belts.extend(
+            segment
+                .belts
+                .iter()
+                .filter(|belt| belts_query.contains(**belt)),
+        );
+        commands.entity(segment_entity).despawn();
+    }
+
+    // A belt that is the only input of the belt in front of it keeps its items on the same lane
+    // when passing them on, so both can be part of the same segment
+    let merges_into: HashMap<Entity, Entity> = rebuilt_belts
+        .iter()
+        .filter_map(|belt_entity| {
+            let (_, rotation, next_belt, _) = belts_query.get(*belt_entity).ok()?;
+            let next_belt_entity = next_belt?.0;
+            if !rebuilt_belts.contains(&next_belt_entity) {
+                return None;
+            }
+            let (_, next_rotation, _, next_previous_belts) =
+                belts_query.get(next_belt_entity).ok()?;
+            let only_input = next_previous_belts.map_or(false, |previous_belts| {
+                previous_belts.belts.len() == 1 && previous_belts.belts.contains(belt_entity)
+            });
+            let facing_each_other = rotation.forward().dot(next_rotation.forward()) < -0.5;
+            (only_input && !facing_each_other).then_some((*belt_entity, next_belt_entity))
+        })
         .collect();
+    let merged_into: HashSet<Entity> = merges_into.values().copied().collect();
+
+    // Segments start at belts nothing merges into, belts that are left over after that form a
+    // loop and start at the belt that was spawned first
+    let rebuilt_belts = spawn_orders.sorted(rebuilt_belts);
+    let first_belts = rebuilt_belts
+        .iter()
+        .filter(|belt| !merged_into.contains(*belt))
+        .chain(rebuilt_belts.iter())
+        .copied();
+    let mut visited = HashSet::new();
+    let mut segments = HashMap::new();
+    for first_belt in first_belts {
+        let mut belts = Vec::new();
+        let mut current_belt = Some(first_belt);
+        while let Some(belt) = current_belt.filter(|belt| visited.insert(*belt)) {
+            belts.push(belt);
+            current_belt = merges_into.get(&belt).copied();
+        }
+        if belts.is_empty() {
+            continue;
+        }
+
+        let segment_entity = commands.spawn_empty().id();
+        for (index, belt) in belts.iter().enumerate() {
+            let (mut transport_belt, ..) = belts_query.get_mut(*belt).unwrap();
+            transport_belt.segment = Some(segment_entity);
+            transport_belt.index = index;
+            belt_segments.insert(*belt, segment_entity);
+        }
+        segments.insert(segment_entity, BeltSegment::new(belts));
+    }
+
+    // Put the items back where they were, items on removed belts are lost
+    for (belt, lane, slot, item) in items {
+        let Some((segment_entity, first_slot)) = belts_query
+            .get(belt)
+            .ok()
+            .and_then(|(transport_belt, ..)| transport_belt.segment())
+        else {
+            continue;
+        };
+        segments
+            .get_mut(&segment_entity)
+            .expect("Belt should be part of a new segment")
+            .add(lane, first_slot + slot, item);
+    }
+
+    for (segment_entity, segment) in segments {
+        commands.entity(segment_entity).insert(segment);
+    }
+}
+
+/// Counts fixed ticks rather than elapsed time, so belts move on the same ticks no matter how
+/// long each tick took
+#[derive(Resource, Debug, Reflect)]
+pub struct TransportBeltTimer {
+    interval: u32,
+    elapsed: u32,
+}
+
+impl TransportBeltTimer {
+    /// Belts move every `interval` fixed ticks
+    pub fn new(interval: u32) -> Self {
+        Self {
+            interval: interval.max(1),
+            elapsed: 0,
+        }
+    }
+
+    fn tick(&mut self) -> bool {
+        self.elapsed = (self.elapsed + 1) % self.interval;
+        self.just_finished()
+    }
 
-    Some((prev_belt, other_belts))
+    /// Whether belts moved this tick
+    pub fn just_finished(&self) -> bool {
+        self.elapsed == 0
+    }
 }
 
-#[derive(Resource)]
-pub struct TransportBeltTimer(Timer);
+impl Default for TransportBeltTimer {
+    fn default() -> Self {
+        Self::new(BELT_MOVE_TICKS)
+    }
+}
 
+/// Move the items on every segment forward, then pass items at the front of a segment on to the
+/// belt in front of it. Segments pass their items on in the spawn order of their last belt.
 pub fn transport_belt_tick(
-    lane_query: Query<&Lane>,
-    mut belts_query: Query<(&mut TransportBelt, Option<&NextBelt>, &DiscreteRotation)>,
+    mut segments_query: Query<(Entity, &mut BeltSegment)>,
+    belts_query: Query<(
+        &TransportBelt,
+        &DiscreteRotation,
+        Option<&NextBelt>,
+        Option<&PreviousBelts>,
+    )>,
+    rotation_query: Query<&DiscreteRotation>,
     mut belt_timer: ResMut<TransportBeltTimer>,
-    time: Res<Time>,
+    spawn_orders: SpawnOrders,
 ) {
-    if !belt_timer.0.tick(time.delta()).just_finished() {
+    if !belt_timer.tick() {
         return;
     }
 
-    for lane in &lane_query {
-        for entity in lane.belts.iter() {
-            let span = info_span!("Transport belt tick", entity = ?entity);
-            let _enter = span.enter();
+    let mut segment_ends = Vec::new();
+    for (segment_entity, mut segment) in &mut segments_query {
+        segment.advance();
+        if let Some(last_belt) = segment.belts.last() {
+            segment_ends.push((segment_entity, *last_belt));
+        }
+    }
+    spawn_orders.sort_by_entity(&mut segment_ends, |(_, last_belt)| *last_belt);
+
+    for (segment_entity, last_belt) in segment_ends {
+        let span = info_span!("Transport belt tick", entity = ?segment_entity);
+        let _enter = span.enter();
 
-            let (last_slot, next_belt_entity, compass_direction) = belts_query
-                .get(*entity)
-                .map(|b| {
-                    (
-                        b.0.slots.back().expect("Belt should have slots").clone(),
-                        b.1.map(|b| b.0),
-                        b.2.compass_direction(),
-                    )
+        let Ok((_, rotation, Some(next_belt), _)) = belts_query.get(last_belt) else {
+            continue;
+        };
+        // Splitters take items off the belts feeding them themselves
+        let Ok((next_transport_belt, next_rotation, _, next_previous_belts)) =
+            belts_query.get(next_belt.0)
+        else {
+            continue;
+        };
+        let Some((next_segment_entity, next_first_slot)) = next_transport_belt.segment() else {
+            continue;
+        };
+        let next_inputs = next_previous_belts.map_or(0, |p| p.belts.len());
+        let next_has_straight_input = next_previous_belts.map_or(false, |p| {
+            p.belts.iter().any(|prev| {
+                rotation_query.get(*prev).map_or(false, |prev_rotation| {
+                    prev_rotation.forward().dot(next_rotation.forward()) > 0.5
                 })
-                .unwrap();
-
-            // First handle the last slot
-            if let Some(product) = last_slot {
-                // Transfer to other belt if possible
-                let mut transfered = false;
-                if let Some((mut belt, _, belt_rotation)) =
-                    next_belt_entity.and_then(|e| belts_query.get_mut(e).ok())
+            })
+        });
+
+        for belt_lane in BeltLane::ALL {
+            let Some((next_lane, next_slot)) = transfer_destination(
+                belt_lane,
+                rotation,
+                next_rotation,
+                next_has_straight_input,
+                next_inputs,
+            ) else {
+                continue;
+            };
+            let next_position = next_first_slot + next_slot;
+
+            if next_segment_entity == segment_entity {
+                // The segment loops back onto itself
+                let (_, mut segment) = segments_query.get_mut(segment_entity).unwrap();
+                if segment.lane(belt_lane).front_at_end()
+                    && segment.can_add(next_lane, next_position)
                 {
-                    let stack = Stack::new(product.clone(), 1);
-                    let slot = {
-                        if belt_rotation.compass_direction() == compass_direction {
-                            0
-                        } else {
-                            1
-                        }
-                    };
-                    transfered = belt.add(slot, stack.item.clone());
+                    let item = segment.take_front(belt_lane).unwrap();
+                    segment.add(next_lane, next_position, item);
                 }
-
-                if transfered {
-                    debug!("Transfered item to other belt");
-                    // Product was transfered to other belt, remove from current belt
-                    *belts_query
-                        .get_mut(*entity)
-                        .as_mut()
-                        .unwrap()
-                        .0
-                        .slots
-                        .back_mut()
-                        .expect("Belt should have slots") = None;
-                    // Rotate the belt 1 slot to the right
-                    belts_query
-                        .get_mut(*entity)
-                        .as_mut()
-                        .unwrap()
-                        .0
-                        .slots
-                        .rotate_right(1);
+            } else {
+                let Ok([(_, mut segment), (_, mut next_segment)]) =
+                    segments_query.get_many_mut([segment_entity, next_segment_entity])
+                else {
+                    continue;
+                };
+                if segment.lane(belt_lane).front_at_end()
+                    && next_segment.can_add(next_lane, next_position)
+                {
+                    let item = segment.take_front(belt_lane).unwrap();
+                    next_segment.add(next_lane, next_position, item);
                 } else {
                     debug!("Belt full");
-                    // Could not transfer to other belt, shift items if there is space
-                    // instead of rotating the belt
-                    if let Ok((belt, _, _)) = belts_query.get_mut(*entity).as_mut() {
-                        for i in (0..belt.slots.len()).rev().skip(1) {
-                            if belt.slots[i + 1].is_none() && belt.slots[i].is_some() {
-                                belt.slots[i + 1] = belt.slots[i].clone();
-                                belt.slots[i] = None;
-                            }
-                        }
-                    }
                 }
-            } else {
-                belts_query
-                    .get_mut(*entity)
-                    .as_mut()
-                    .unwrap()
-                    .0
-                    .slots
-                    .rotate_right(1);
             }
         }
     }
@@ -264,211 +690,573 @@ pub fn transport_belt_tick(
 #[derive(Component, Reflect)]
 pub struct BeltItem;
 
+/// Spawn a belt that forms a segment on its own, with items at the given lanes and slots
 #[cfg(test)]
-mod test {
+pub(crate) fn spawn_test_belt(
+    world: &mut World,
+    rotation: DiscreteRotation,
+    items: Vec<(BeltLane, usize, Item)>,
+) -> Entity {
+    let belt_entity = world.spawn(rotation).id();
+    let mut segment = BeltSegment::new(vec![belt_entity]);
+    for (lane, slot, item) in items {
+        segment.add(lane, slot, item);
+    }
+    let segment_entity = world.spawn(segment).id();
+    world
+        .entity_mut(belt_entity)
+        .insert(TransportBelt::in_segment(segment_entity));
+    belt_entity
+}
 
-    use bevy::time::TimePlugin;
+/// The item in a slot of a belt spawned in a test
+#[cfg(test)]
+pub(crate) fn test_belt_slot(
+    world: &World,
+    belt: Entity,
+    lane: BeltLane,
+    slot: usize,
+) -> Option<Item> {
+    let (segment_entity, first_slot) = world.get::<TransportBelt>(belt)?.segment()?;
+    world
+        .get::<BeltSegment>(segment_entity)?
+        .item_at(lane, first_slot + slot)
+        .cloned()
+}
 
+#[cfg(test)]
+mod test {
     use crate::discrete_rotation::{CompassDirection, SideCount};
 
     use super::*;
 
-    #[test]
-    fn transport_belt_rotate_right() {
+    /// An app that only builds the segments on the first update, so items can be put on them
+    /// before ticking
+    fn belt_app() -> App {
         let mut app = App::new();
-        app.add_plugins(TimePlugin);
+        app.insert_resource(TransportBeltTimer::new(u32::MAX));
+        app.add_systems(
+            Update,
+            (rebuild_belt_segments, apply_deferred, transport_belt_tick).chain(),
+        );
+        app
+    }
+
+    fn tick(app: &mut App) {
+        app.insert_resource(TransportBeltTimer::new(1));
+        app.update();
+    }
+
+    fn add_item(app: &mut App, belt: Entity, lane: BeltLane, slot: usize, name: &'static str) {
+        let (segment_entity, first_slot) = app
+            .world
+            .get::<TransportBelt>(belt)
+            .unwrap()
+            .segment()
+            .unwrap();
+        assert!(app
+            .world
+            .get_mut::<BeltSegment>(segment_entity)
+            .unwrap()
+            .add(lane, first_slot + slot, Item::new(name)));
+    }
 
-        let timer = TransportBeltTimer(Timer::from_seconds(0., TimerMode::Once));
-        app.insert_resource(timer);
+    fn lane(app: &App, belt: Entity, lane: BeltLane) -> Vec<Option<Item>> {
+        (0..BELT_SLOTS)
+            .map(|slot| test_belt_slot(&app.world, belt, lane, slot))
+            .collect()
+    }
 
-        let mut belt = TransportBelt::default();
-        belt.add(0, Item::new("Coal"));
+    fn rotation(direction: CompassDirection) -> DiscreteRotation {
+        let mut rotation = DiscreteRotation::new(SideCount::Four);
+        rotation.set(direction);
+        rotation
+    }
 
+    #[test]
+    fn transport_belt_rotate_right() {
+        let mut app = belt_app();
         let belt_entity = app
             .world
             .spawn((
-                TransformBundle::default(),
-                belt,
+                TransportBelt::default(),
                 DiscreteRotation::new(SideCount::One),
             ))
             .id();
+        app.update();
+        add_item(&mut app, belt_entity, BeltLane::Right, 0, "Coal");
 
-        app.world.spawn(Lane {
-            belts: vec![belt_entity],
-        });
+        tick(&mut app);
 
-        app.add_systems(Update, transport_belt_tick);
+        assert_eq!(
+            lane(&app, belt_entity, BeltLane::Right),
+            vec![None, Some(Item::new("Coal")), None]
+        );
 
-        app.update();
+        tick(&mut app);
 
         assert_eq!(
-            app.world.get::<TransportBelt>(belt_entity).unwrap().slots,
-            vec![None, Some(Item::new("Coal")), None]
+            lane(&app, belt_entity, BeltLane::Right),
+            vec![None, None, Some(Item::new("Coal"))]
         );
+    }
 
-        let timer = TransportBeltTimer(Timer::from_seconds(0., TimerMode::Once));
-        app.insert_resource(timer);
+    #[test]
+    fn transport_belt_moves_every_interval() {
+        let mut app = belt_app();
+        let belt_entity = app
+            .world
+            .spawn((
+                TransportBelt::default(),
+                DiscreteRotation::new(SideCount::One),
+            ))
+            .id();
+        app.update();
+        add_item(&mut app, belt_entity, BeltLane::Right, 0, "Coal");
+        app.insert_resource(TransportBeltTimer::new(3));
 
         app.update();
+        app.update();
+        assert_eq!(
+            lane(&app, belt_entity, BeltLane::Right),
+            vec![Some(Item::new("Coal")), None, None]
+        );
 
+        app.update();
         assert_eq!(
-            app.world.get::<TransportBelt>(belt_entity).unwrap().slots,
-            vec![None, None, Some(Item::new("Coal"))]
+            lane(&app, belt_entity, BeltLane::Right),
+            vec![None, Some(Item::new("Coal")), None]
         );
     }
 
     #[test]
     fn transport_belt_rotate_right_first_two_slots() {
-        let mut app = App::new();
-        app.add_plugins(TimePlugin);
-
-        let mut belt = TransportBelt::default();
-        belt.add(0, Item::new("Coal"));
-        belt.add(1, Item::new("Iron ore"));
-
+        let mut app = belt_app();
         let belt_entity = app
             .world
             .spawn((
-                TransformBundle::default(),
-                belt,
+                TransportBelt::default(),
                 DiscreteRotation::new(SideCount::One),
             ))
             .id();
+        app.update();
+        add_item(&mut app, belt_entity, BeltLane::Right, 0, "Coal");
+        add_item(&mut app, belt_entity, BeltLane::Right, 1, "Iron ore");
 
-        app.world.spawn(Lane {
-            belts: vec![belt_entity],
-        });
-
-        app.add_systems(Update, transport_belt_tick);
+        tick(&mut app);
 
-        let timer = TransportBeltTimer(Timer::from_seconds(0., TimerMode::Once));
-        app.insert_resource(timer);
+        assert_eq!(
+            lane(&app, belt_entity, BeltLane::Right),
+            vec![None, Some(Item::new("Coal")), Some(Item::new("Iron ore"))]
+        );
+    }
 
+    #[test]
+    fn transport_belt_shift() {
+        let mut app = belt_app();
+        let belt_entity = app
+            .world
+            .spawn((
+                TransportBelt::default(),
+                DiscreteRotation::new(SideCount::One),
+            ))
+            .id();
         app.update();
+        add_item(&mut app, belt_entity, BeltLane::Right, 0, "Coal");
+        add_item(&mut app, belt_entity, BeltLane::Right, 2, "Iron ore");
+
+        tick(&mut app);
 
         assert_eq!(
-            app.world.get::<TransportBelt>(belt_entity).unwrap().slots,
+            lane(&app, belt_entity, BeltLane::Right),
             vec![None, Some(Item::new("Coal")), Some(Item::new("Iron ore"))]
         );
     }
 
     #[test]
-    fn transport_belt_shift() {
-        let mut app = App::new();
-        app.add_plugins(TimePlugin);
-        let mut belt = TransportBelt::default();
-        belt.add(0, Item::new("Coal"));
-        belt.add(2, Item::new("Iron ore"));
+    fn transport_belt_transfer_to_next_belt() {
+        let mut app = belt_app();
 
-        let belt_entity = app
+        let belt_b_entity = app
             .world
             .spawn((
-                TransformBundle::default(),
-                belt,
+                TransportBelt::default(),
                 DiscreteRotation::new(SideCount::One),
             ))
             .id();
-
-        app.world.spawn(Lane {
-            belts: vec![belt_entity],
+        let belt_a_entity = app
+            .world
+            .spawn((
+                TransportBelt::default(),
+                NextBelt(belt_b_entity),
+                DiscreteRotation::new(SideCount::One),
+            ))
+            .id();
+        app.world.entity_mut(belt_b_entity).insert(PreviousBelts {
+            belts: [belt_a_entity].into(),
         });
+        app.update();
+        add_item(&mut app, belt_a_entity, BeltLane::Right, 1, "Coal");
+        add_item(&mut app, belt_a_entity, BeltLane::Right, 2, "Iron ore");
 
-        app.add_systems(Update, transport_belt_tick);
+        tick(&mut app);
+
+        assert_eq!(
+            lane(&app, belt_a_entity, BeltLane::Right),
+            vec![None, None, Some(Item::new("Coal"))]
+        );
+        assert_eq!(
+            lane(&app, belt_b_entity, BeltLane::Right),
+            vec![Some(Item::new("Iron ore")), None, None]
+        );
+    }
 
-        let timer = TransportBeltTimer(Timer::from_seconds(0., TimerMode::Once));
-        app.insert_resource(timer);
+    #[test]
+    fn transport_belt_transfer_to_next_belt_perpendicular() {
+        let mut app = belt_app();
 
+        let belt_b_entity = app
+            .world
+            .spawn((TransportBelt::default(), rotation(CompassDirection::East)))
+            .id();
+        let belt_a_entity = app
+            .world
+            .spawn((
+                TransportBelt::default(),
+                NextBelt(belt_b_entity),
+                rotation(CompassDirection::North),
+            ))
+            .id();
         app.update();
+        add_item(&mut app, belt_a_entity, BeltLane::Right, 2, "Coal");
+
+        tick(&mut app);
 
         assert_eq!(
-            app.world.get::<TransportBelt>(belt_entity).unwrap().slots,
-            vec![None, Some(Item::new("Coal")), Some(Item::new("Iron ore"))]
+            lane(&app, belt_a_entity, BeltLane::Right),
+            vec![None, None, None]
+        );
+        assert_eq!(
+            lane(&app, belt_b_entity, BeltLane::Right),
+            vec![None, Some(Item::new("Coal")), None]
         );
     }
 
     #[test]
-    fn transport_belt_transfer_to_next_belt() {
-        let mut app = App::new();
-        app.add_plugins(TimePlugin);
+    fn transport_belt_side_load_onto_near_lane() {
+        let mut app = belt_app();
 
-        let belt_b = TransportBelt::default();
+        // Belt c feeds belt b straight on, making belt a a side-loader
         let belt_b_entity = app
             .world
-            .spawn((belt_b, DiscreteRotation::new(SideCount::One)))
+            .spawn((TransportBelt::default(), rotation(CompassDirection::North)))
+            .id();
+        let belt_c_entity = app
+            .world
+            .spawn((
+                TransportBelt::default(),
+                NextBelt(belt_b_entity),
+                rotation(CompassDirection::North),
+            ))
             .id();
 
-        let mut belt_a = TransportBelt::default();
-        belt_a.add(1, Item::new("Coal"));
-        belt_a.add(2, Item::new("Iron ore"));
-
+        // Belt a is west of belt b, moving east
         let belt_a_entity = app
             .world
             .spawn((
-                belt_a,
+                TransportBelt::default(),
                 NextBelt(belt_b_entity),
-                DiscreteRotation::new(SideCount::One),
+                rotation(CompassDirection::East),
             ))
             .id();
 
-        app.world.spawn(Lane {
-            belts: vec![belt_b_entity, belt_a_entity],
+        app.world.entity_mut(belt_b_entity).insert(PreviousBelts {
+            belts: [belt_a_entity, belt_c_entity].into(),
         });
+        app.update();
+        add_item(&mut app, belt_a_entity, BeltLane::Left, 2, "Coal");
+        add_item(&mut app, belt_a_entity, BeltLane::Right, 2, "Iron ore");
+
+        tick(&mut app);
 
-        app.add_systems(Update, transport_belt_tick);
+        // Only one item fits in the middle of the near lane, the other has to wait
+        assert_eq!(
+            lane(&app, belt_b_entity, BeltLane::Left),
+            vec![None, Some(Item::new("Coal")), None]
+        );
+        assert_eq!(
+            lane(&app, belt_b_entity, BeltLane::Right),
+            vec![None, None, None]
+        );
+        assert_eq!(
+            lane(&app, belt_a_entity, BeltLane::Left),
+            vec![None, None, None]
+        );
+        assert_eq!(
+            lane(&app, belt_a_entity, BeltLane::Right),
+            vec![None, None, Some(Item::new("Iron ore"))]
+        );
+    }
 
-        let timer = TransportBeltTimer(Timer::from_seconds(0., TimerMode::Once));
-        app.insert_resource(timer);
+    #[test]
+    fn transport_belt_curve_keeps_lanes() {
+        let mut app = belt_app();
 
+        let belt_b_entity = app
+            .world
+            .spawn((TransportBelt::default(), rotation(CompassDirection::North)))
+            .id();
+        let belt_a_entity = app
+            .world
+            .spawn((
+                TransportBelt::default(),
+                NextBelt(belt_b_entity),
+                rotation(CompassDirection::East),
+            ))
+            .id();
+        app.world.entity_mut(belt_b_entity).insert(PreviousBelts {
+            belts: [belt_a_entity].into(),
+        });
         app.update();
+        add_item(&mut app, belt_a_entity, BeltLane::Left, 2, "Coal");
+        add_item(&mut app, belt_a_entity, BeltLane::Right, 2, "Iron ore");
+
+        tick(&mut app);
 
         assert_eq!(
-            app.world.get::<TransportBelt>(belt_a_entity).unwrap().slots,
-            vec![None, None, Some(Item::new("Coal"))]
+            lane(&app, belt_b_entity, BeltLane::Left),
+            vec![Some(Item::new("Coal")), None, None]
         );
-
         assert_eq!(
-            app.world.get::<TransportBelt>(belt_b_entity).unwrap().slots,
+            lane(&app, belt_b_entity, BeltLane::Right),
             vec![Some(Item::new("Iron ore")), None, None]
         );
     }
 
     #[test]
-    fn transport_belt_transfer_to_next_belt_perpendicular() {
-        let mut app = App::new();
-        app.add_plugins(TimePlugin);
+    fn transport_belt_loop() {
+        let mut app = belt_app();
+
+        // Four belts going around in a square
+        let directions = [
+            CompassDirection::North,
+            CompassDirection::East,
+            CompassDirection::South,
+            CompassDirection::West,
+        ];
+        let belts: Vec<Entity> = directions
+            .iter()
+            .map(|direction| {
+                app.world
+                    .spawn((TransportBelt::default(), rotation(*direction)))
+                    .id()
+            })
+            .collect();
+        for (i, belt) in belts.iter().enumerate() {
+            let next_belt = belts[(i + 1) % belts.len()];
+            let previous_belt = belts[(i + belts.len() - 1) % belts.len()];
+            app.world.entity_mut(*belt).insert((
+                NextBelt(next_belt),
+                PreviousBelts {
+                    belts: [previous_belt].into(),
+                },
+            ));
+        }
+        app.update();
+
+        let mut segments_query = app.world.query::<&BeltSegment>();
+        assert_eq!(segments_query.iter(&app.world).count(), 1);
+
+        add_item(&mut app, belts[3], BeltLane::Right, 2, "Coal");
+        tick(&mut app);
+
+        assert_eq!(
+            lane(&app, belts[0], BeltLane::Right),
+            vec![Some(Item::new("Coal")), None, None]
+        );
+    }
+
+    #[test]
+    fn belt_segments_merge_straight_runs() {
+        let mut app = belt_app();
+
+        let belts: Vec<Entity> = (0..3)
+            .map(|_| {
+                app.world
+                    .spawn((TransportBelt::default(), rotation(CompassDirection::North)))
+                    .id()
+            })
+            .collect();
+        for pair in belts.windows(2) {
+            app.world.entity_mut(pair[0]).insert(NextBelt(pair[1]));
+            app.world.entity_mut(pair[1]).insert(PreviousBelts {
+                belts: [pair[0]].into(),
+            });
+        }
+        app.update();
+
+        let mut segments_query = app.world.query::<&BeltSegment>();
+        let segment = segments_query.single(&app.world);
+        assert_eq!(segment.belts(), belts.as_slice());
+        assert_eq!(segment.slot_count(), 9);
 
-        let belt_b = TransportBelt::default();
-        let mut belt_b_rotation = DiscreteRotation::new(SideCount::Four);
-        belt_b_rotation.set(CompassDirection::East);
-        let belt_b_entity = app.world.spawn((belt_b, belt_b_rotation)).id();
+        // Items move along the whole segment
+        add_item(&mut app, belts[0], BeltLane::Left, 2, "Coal");
+        tick(&mut app);
+        assert_eq!(
+            lane(&app, belts[1], BeltLane::Left),
+            vec![Some(Item::new("Coal")), None, None]
+        );
+    }
+
+    #[test]
+    fn belt_segments_only_rebuilt_on_change() {
+        let mut app = belt_app();
 
-        let mut belt_a = TransportBelt::default();
-        belt_a.add(2, Item::new("Coal"));
-        let mut belt_a_rotation = DiscreteRotation::new(SideCount::Four);
-        belt_a_rotation.set(CompassDirection::North);
+        let belt_b_entity = app
+            .world
+            .spawn((TransportBelt::default(), rotation(CompassDirection::North)))
+            .id();
         let belt_a_entity = app
             .world
-            .spawn((belt_a, NextBelt(belt_b_entity), belt_a_rotation))
+            .spawn((
+                TransportBelt::default(),
+                NextBelt(belt_b_entity),
+                rotation(CompassDirection::North),
+            ))
             .id();
-
-        app.world.spawn(Lane {
-            belts: vec![belt_b_entity, belt_a_entity],
+        app.world.entity_mut(belt_b_entity).insert(PreviousBelts {
+            belts: [belt_a_entity].into(),
         });
-
-        app.add_systems(Update, transport_belt_tick);
-        let timer = TransportBeltTimer(Timer::from_seconds(0., TimerMode::Once));
-        app.insert_resource(timer);
-
         app.update();
+        add_item(&mut app, belt_b_entity, BeltLane::Right, 1, "Coal");
 
+        let segment = app
+            .world
+            .get::<TransportBelt>(belt_a_entity)
+            .unwrap()
+            .segment;
+        app.update();
         assert_eq!(
-            app.world.get::<TransportBelt>(belt_a_entity).unwrap().slots,
-            vec![None, None, None]
+            app.world
+                .get::<TransportBelt>(belt_a_entity)
+                .unwrap()
+                .segment,
+            segment
         );
 
+        // Placing a belt in front rebuilds the segments, keeping the items
+        let belt_c_entity = app
+            .world
+            .spawn((
+                TransportBelt::default(),
+                rotation(CompassDirection::North),
+                PreviousBelts {
+                    belts: [belt_b_entity].into(),
+                },
+            ))
+            .id();
+        app.world
+            .entity_mut(belt_b_entity)
+            .insert(NextBelt(belt_c_entity));
+        app.update();
+
+        assert_ne!(
+            app.world
+                .get::<TransportBelt>(belt_a_entity)
+                .unwrap()
+                .segment,
+            segment
+        );
+        let mut segments_query = app.world.query::<&BeltSegment>();
+        assert_eq!(segments_query.iter(&app.world).count(), 1);
         assert_eq!(
-            app.world.get::<TransportBelt>(belt_b_entity).unwrap().slots,
+            lane(&app, belt_b_entity, BeltLane::Right),
             vec![None, Some(Item::new("Coal")), None]
         );
     }
+
+    #[test]
+    fn belt_segments_rebuilt_only_around_changed_belts() {
+        let mut app = belt_app();
+        let lone_belt_entity = app
+            .world
+            .spawn((TransportBelt::default(), rotation(CompassDirection::North)))
+            .id();
+        let belt_a_entity = app
+            .world
+            .spawn((TransportBelt::default(), rotation(CompassDirection::East)))
+            .id();
+        app.update();
+        let segment =
+            |app: &App, belt: Entity| app.world.get::<TransportBelt>(belt).unwrap().segment;
+        let lone_segment = segment(&app, lone_belt_entity);
+
+        // Placing a belt in front of another leaves the segments of unrelated belts alone
+        let belt_b_entity = app
+            .world
+            .spawn((
+                TransportBelt::default(),
+                rotation(CompassDirection::East),
+                PreviousBelts {
+                    belts: [belt_a_entity].into(),
+                },
+            ))
+            .id();
+        app.world
+            .entity_mut(belt_a_entity)
+            .insert(NextBelt(belt_b_entity));
+        app.update();
+        assert_eq!(segment(&app, lone_belt_entity), lone_segment);
+        assert_eq!(segment(&app, belt_a_entity), segment(&app, belt_b_entity));
+
+        // The segment of a removed belt goes with it, the other segments are left alone
+        let belt_a_segment = segment(&app, belt_a_entity);
+        app.world.despawn(lone_belt_entity);
+        app.update();
+        let mut segments_query = app.world.query::<&BeltSegment>();
+        assert_eq!(segments_query.iter(&app.world).count(), 1);
+        assert!(app.world.get_entity(lone_segment.unwrap()).is_none());
+        assert_eq!(segment(&app, belt_a_entity), belt_a_segment);
+    }
+
+    #[test]
+    fn segment_lane_gaps() {
+        let mut lane = SegmentLane::default();
+        assert!(lane.insert(6, 1, Item::new("Coal")));
+        assert!(lane.insert(6, 4, Item::new("Iron ore")));
+        assert!(lane.insert(6, 2, Item::new("Stone")));
+        assert!(!lane.insert(6, 4, Item::new("Stone")));
+        assert!(!lane.insert(6, 6, Item::new("Stone")));
+        let gaps: Vec<usize> = lane.items.iter().map(|gap_item| gap_item.gap).collect();
+        assert_eq!(gaps, vec![1, 1, 0]);
+
+        assert_eq!(lane.remove(6, 2), Some(Item::new("Stone")));
+        assert_eq!(lane.remove(6, 3), None);
+        let gaps: Vec<usize> = lane.items.iter().map(|gap_item| gap_item.gap).collect();
+        assert_eq!(gaps, vec![1, 2]);
+
+        // The front item stops at the end, items behind it close the gap
+        for _ in 0..4 {
+            lane.advance();
+        }
+        let positions: Vec<usize> = lane.positions(6).map(|(position, _)| position).collect();
+        assert_eq!(positions, vec![5, 4]);
+        assert!(lane.front_at_end());
+    }
+
+    #[test]
+    fn far_lane_from_sides() {
+        let mut rotation = DiscreteRotation::new(SideCount::Four);
+        rotation.set(CompassDirection::North);
+
+        // Approaching from the west puts items on the east (right) side
+        assert_eq!(far_lane(&rotation, Vec2::X), BeltLane::Right);
+        assert_eq!(far_lane(&rotation, Vec2::NEG_X), BeltLane::Left);
+        assert_eq!(far_lane(&rotation, Vec2::Y), BeltLane::Right);
+
+        rotation.set(CompassDirection::East);
+        assert_eq!(far_lane(&rotation, Vec2::NEG_Y), BeltLane::Right);
+        assert_eq!(far_lane(&rotation, Vec2::Y), BeltLane::Left);
+    }
 }
@@ -3,9 +3,10 @@ use bevy::{
     ecs::component::Component,
     prelude::Entity,
     reflect::Reflect,
-    utils::HashSet,
 };
 
+use crate::entity_set::EntitySet;
+
 pub struct TileOccupantsPlugin;
 
 impl Plugin for TileOccupantsPlugin {
@@ -15,6 +16,10 @@ impl Plugin for TileOccupantsPlugin {
     }
 }
 
+/// Marks entities whose position is tracked in the `TileOccupants` of the tiles they cover
+#[derive(Component)]
+pub struct TileTracked;
+
 #[derive(Component, Debug, Reflect)]
 pub struct EntityOnTiles(Vec<Entity>);
 
@@ -28,10 +33,10 @@ impl EntityOnTiles {
 }
 
 #[derive(Component, Default, Debug, Reflect)]
-pub struct TileOccupants(HashSet<Entity>);
+pub struct TileOccupants(EntitySet);
 
 impl TileOccupants {
-    pub fn new(occupants: HashSet<Entity>) -> Self {
+    pub fn new(occupants: EntitySet) -> Self {
         Self(occupants)
     }
 
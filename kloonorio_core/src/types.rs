@@ -2,7 +2,7 @@ use std::collections::VecDeque;
 
 use bevy::prelude::*;
 
-use crate::recipe::Recipe;
+use crate::{item::Item, recipe::Recipe};
 
 #[derive(Clone, PartialEq, Eq, Component, Debug, Hash, States, Default, Reflect)]
 pub enum AppState {
@@ -43,8 +43,16 @@ pub struct Pickup;
 #[derive(Component)]
 pub struct Dropoff;
 
+/// Mining a resource by hand, one item of the product every time the timer finishes
 #[derive(Component)]
 pub struct MineCountdown {
     pub timer: Timer,
-    pub target: Entity,
+    pub product: Item,
+}
+
+/// Mining the structure at a position by hand, it is removed when the timer finishes
+#[derive(Component)]
+pub struct MineStructureCountdown {
+    pub timer: Timer,
+    pub position: Vec2,
 }
@@ -0,0 +1,393 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+    deconstruction::deconstruct_marked, discrete_rotation::DiscreteRotation,
+    simulation::SimulationSet,
+};
+
+/// Number of actions a player can undo
+pub const HISTORY_LENGTH: usize = 100;
+
+pub struct UndoPlugin;
+
+impl Plugin for UndoPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ConstructionEvent>().add_systems(
+            FixedUpdate,
+            record_construction
+                .after(deconstruct_marked)
+                .in_set(SimulationSet::PlayerMining),
+        );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActionOrigin {
+    /// The player built something
+    Player,
+    /// Undoing an earlier action
+    Undo,
+    /// Redoing an undone action
+    Redo,
+}
+
+/// The player and command a construction action comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConstructionCause {
+    pub player: Entity,
+    /// Tick the command was applied on, actions of one command are undone together
+    pub tick: u64,
+    pub origin: ActionOrigin,
+}
+
+impl ConstructionCause {
+    pub fn player(player: Entity, tick: u64) -> Self {
+        Self {
+            player,
+            tick,
+            origin: ActionOrigin::Player,
+        }
+    }
+}
+
+/// A structure as it was placed or removed, with what is needed to put it back
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructurePlacement {
+    pub structure: String,
+    pub position: Vec2,
+    pub rotation: DiscreteRotation,
+    /// Recipe of an assembler
+    pub recipe: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConstructionAction {
+    Place(StructurePlacement),
+    Remove(StructurePlacement),
+    Rotate {
+        position: Vec2,
+        from: DiscreteRotation,
+        to: DiscreteRotation,
+    },
+    ChangeRecipe {
+        position: Vec2,
+        from: Option<String>,
+        to: Option<String>,
+    },
+}
+
+impl ConstructionAction {
+    /// The action that reverts this one
+    pub fn inverse(&self) -> Self {
+        match self {
+            ConstructionAction::Place(placement) => ConstructionAction::Remove(placement.clone()),
+            ConstructionAction::Remove(placement) => ConstructionAction::Place(placement.clone()),
+            ConstructionAction::Rotate { position, from, to } => ConstructionAction::Rotate {
+                position: *position,
+                from: *to,
+                to: *from,
+            },
+            ConstructionAction::ChangeRecipe { position, from, to } => {
+                ConstructionAction::ChangeRecipe {
+                    position: *position,
+                    from: to.clone(),
+                    to: from.clone(),
+                }
+            }
+        }
+    }
+}
+
+/// A construction action that was carried out
+#[derive(Debug, Event)]
+pub struct ConstructionEvent {
+    pub cause: ConstructionCause,
+    pub action: ConstructionAction,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub tick: u64,
+    pub actions: Vec<ConstructionAction>,
+}
+
+/// The construction actions of a player that can be undone, and the undone ones that can be redone
+#[derive(Component, Default, Debug)]
+pub struct ConstructionHistory {
+    undo: Vec<HistoryEntry>,
+    redo: Vec<HistoryEntry>,
+    /// Entries taken to be undone or redone on this tick, put back if none of their actions are
+    /// carried out
+    pending: Vec<(ActionOrigin, HistoryEntry)>,
+}
+
+impl ConstructionHistory {
+    pub fn record(&mut self, cause: ConstructionCause, action: ConstructionAction) {
+        self.pending.retain(|(origin, _)| *origin != cause.origin);
+        let stack = match cause.origin {
+            ActionOrigin::Player => {
+                self.redo.clear();
+                &mut self.undo
+            }
+            ActionOrigin::Undo => &mut self.redo,
+            ActionOrigin::Redo => &mut self.undo,
+        };
+        match stack.last_mut() {
+            Some(entry) if entry.tick == cause.tick => entry.actions.push(action),
+            _ => {
+                stack.push(HistoryEntry {
+                    tick: cause.tick,
+                    actions: vec![action],
+                });
+                if stack.len() > HISTORY_LENGTH {
+                    stack.remove(0);
+                }
+            }
+        }
+    }
+
+    /// The last actions, reverted last to first by their inverses
+    pub fn pop_undo(&mut self) -> Option<HistoryEntry> {
+        self.undo.pop()
+    }
+
+    /// The last undone actions, redone by the inverses of the actions that undid them
+    pub fn pop_redo(&mut self) -> Option<HistoryEntry> {
+        self.redo.pop()
+    }
+
+    /// The entry to undo or redo, kept until the end of the tick in case nothing comes of it
+    pub fn take(&mut self, origin: ActionOrigin) -> Option<HistoryEntry> {
+        let entry = match origin {
+            ActionOrigin::Redo => self.pop_redo(),
+            _ => self.pop_undo(),
+        }?;
+        self.pending.push((origin, entry.clone()));
+        Some(entry)
+    }
+
+    /// Put back the taken entries none of whose actions were carried out, so undoing again tries
+    /// them again rather than skipping to older ones
+    fn restore_pending(&mut self) {
+        for (origin, entry) in self.pending.drain(..).rev() {
+            match origin {
+                ActionOrigin::Redo => self.redo.push(entry),
+                _ => self.undo.push(entry),
+            }
+        }
+    }
+}
+
+/// Add the actions carried out this tick to the players' histories, and put back the undone
+/// entries that were rejected
+pub fn record_construction(
+    mut commands: Commands,
+    mut construction_events: EventReader<ConstructionEvent>,
+    mut history_query: Query<&mut ConstructionHistory>,
+) {
+    // Players get a history with their first action
+    let mut new_histories: HashMap<Entity, ConstructionHistory> = HashMap::new();
+    for event in construction_events.read() {
+        let player = event.cause.player;
+        if let Ok(mut history) = history_query.get_mut(player) {
+            history.record(event.cause, event.action.clone());
+        } else {
+            new_histories
+                .entry(player)
+                .or_default()
+                .record(event.cause, event.action.clone());
+        }
+    }
+    for mut history in history_query
+        .iter_mut()
+        .filter(|history| !history.pending.is_empty())
+    {
+        history.restore_pending();
+    }
+    for (player, history) in new_histories {
+        if let Some(mut entity_commands) = commands.get_entity(player) {
+            entity_commands.insert(history);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        deconstruction::{remove_structures, RemoveStructureEvent},
+        discrete_rotation::SideCount,
+        inventory::Inventory,
+        item::Item,
+        player::PlayerId,
+        player_command::{apply_player_commands, CommandSource, PlayerCommand, ScheduledCommands},
+        recipe::Recipes,
+        research::{QueueResearchEvent, ResearchQueue, Technologies},
+        simulation::SimulationTick,
+        structure::{PlaceStructureEvent, RotateStructureEvent},
+        structure_components::{
+            assembler::ChangeAssemblerRecipeEvent, inserter::ChangeInserterFilterEvent,
+            splitter::ChangeSplitterSettingsEvent,
+        },
+        types::Building,
+    };
+
+    use super::*;
+
+    fn place(x: f32) -> ConstructionAction {
+        ConstructionAction::Place(StructurePlacement {
+            structure: "Wooden chest".to_string(),
+            position: Vec2::new(x, 0.),
+            rotation: DiscreteRotation::new(SideCount::One),
+            recipe: None,
+        })
+    }
+
+    fn cause(tick: u64, origin: ActionOrigin) -> ConstructionCause {
+        ConstructionCause {
+            player: Entity::PLACEHOLDER,
+            tick,
+            origin,
+        }
+    }
+
+    #[test]
+    fn actions_of_one_tick_are_undone_together() {
+        let mut history = ConstructionHistory::default();
+        history.record(cause(1, ActionOrigin::Player), place(0.));
+        history.record(cause(2, ActionOrigin::Player), place(1.));
+        history.record(cause(2, ActionOrigin::Player), place(2.));
+
+        let entry = history.pop_undo().unwrap();
+        assert_eq!(entry.actions, vec![place(1.), place(2.)]);
+        assert_eq!(history.pop_undo().unwrap().actions, vec![place(0.)]);
+        assert!(history.pop_undo().is_none());
+    }
+
+    #[test]
+    fn rotation_is_undone_by_turning_back() {
+        let from = DiscreteRotation::new(SideCount::Four);
+        let mut to = from;
+        to.rotate();
+        let rotate = ConstructionAction::Rotate {
+            position: Vec2::ZERO,
+            from,
+            to,
+        };
+        assert_eq!(
+            rotate.inverse(),
+            ConstructionAction::Rotate {
+                position: Vec2::ZERO,
+                from: to,
+                to: from,
+            }
+        );
+        assert_eq!(rotate.inverse().inverse(), rotate);
+    }
+
+    #[test]
+    fn new_action_clears_redo() {
+        let mut history = ConstructionHistory::default();
+        history.record(cause(1, ActionOrigin::Player), place(0.));
+        let entry = history.pop_undo().unwrap();
+        history.record(cause(2, ActionOrigin::Undo), entry.actions[0].inverse());
+        assert_eq!(
+            history.redo,
+            vec![HistoryEntry {
+                tick: 2,
+                actions: vec![place(0.).inverse()]
+            }]
+        );
+
+        // Redoing puts the action back on the undo stack without clearing the redo stack
+        let entry = history.pop_redo().unwrap();
+        history.record(cause(3, ActionOrigin::Redo), entry.actions[0].inverse());
+        assert_eq!(history.undo.len(), 1);
+        history.record(cause(3, ActionOrigin::Undo), place(1.).inverse());
+        history.record(cause(4, ActionOrigin::Player), place(1.));
+        assert!(history.pop_redo().is_none());
+    }
+
+    fn undo_app() -> App {
+        let mut app = App::new();
+        app.init_resource::<SimulationTick>()
+            .init_resource::<ScheduledCommands>()
+            .init_resource::<CommandSource>()
+            .init_resource::<Recipes>()
+            .init_resource::<ResearchQueue>()
+            .init_resource::<Technologies>()
+            .add_event::<ChangeAssemblerRecipeEvent>()
+            .add_event::<ChangeSplitterSettingsEvent>()
+            .add_event::<ChangeInserterFilterEvent>()
+            .add_event::<QueueResearchEvent>()
+            .add_event::<PlaceStructureEvent>()
+            .add_event::<RotateStructureEvent>()
+            .add_event::<RemoveStructureEvent>()
+            .add_event::<ConstructionEvent>()
+            .add_systems(
+                Update,
+                (
+                    apply_player_commands,
+                    remove_structures,
+                    record_construction,
+                )
+                    .chain(),
+            );
+        app
+    }
+
+    #[test]
+    fn undoing_a_placement_removes_and_refunds_the_structure() {
+        let mut app = undo_app();
+        let chest = app
+            .world
+            .spawn((
+                Name::new("Wooden chest"),
+                Building,
+                Transform::from_xyz(0., 0., 1.),
+                DiscreteRotation::new(SideCount::One),
+            ))
+            .id();
+        let mut history = ConstructionHistory::default();
+        history.record(cause(0, ActionOrigin::Player), place(0.));
+        let player = app
+            .world
+            .spawn((PlayerId(0), Inventory::new(1), history))
+            .id();
+        app.world
+            .resource_mut::<ScheduledCommands>()
+            .schedule(1, [(PlayerId(0), PlayerCommand::Undo)]);
+        app.world.resource_mut::<SimulationTick>().0 = 1;
+        app.update();
+
+        assert!(app.world.get_entity(chest).is_none());
+        let inventory = app.world.get::<Inventory>(player).unwrap();
+        assert_eq!(inventory.num_items(&Item::new("Wooden chest")), 1);
+        let mut history = app.world.get_mut::<ConstructionHistory>(player).unwrap();
+        assert!(history.pop_undo().is_none());
+        assert_eq!(
+            history.pop_redo().unwrap().actions,
+            vec![place(0.).inverse()]
+        );
+    }
+
+    #[test]
+    fn rejected_undo_keeps_its_entry() {
+        let mut app = undo_app();
+        let mut history = ConstructionHistory::default();
+        history.record(cause(0, ActionOrigin::Player), place(0.));
+        // The chest was removed some other way, so there is nothing to undo the placement of
+        let player = app
+            .world
+            .spawn((PlayerId(0), Inventory::new(1), history))
+            .id();
+        app.world
+            .resource_mut::<ScheduledCommands>()
+            .schedule(1, [(PlayerId(0), PlayerCommand::Undo)]);
+        app.world.resource_mut::<SimulationTick>().0 = 1;
+        app.update();
+
+        let mut history = app.world.get_mut::<ConstructionHistory>(player).unwrap();
+        assert!(history.pop_redo().is_none());
+        assert_eq!(history.pop_undo().unwrap().actions, vec![place(0.)]);
+    }
+}
@@ -0,0 +1,59 @@
+use bevy::{
+    app::{App, Plugin, Update},
+    ecs::{
+        entity::Entity,
+        query::Added,
+        schedule::{common_conditions::in_state, IntoSystemConfigs},
+        system::{Commands, Query, Res},
+    },
+    hierarchy::BuildChildren,
+    math::Vec2,
+    prelude::default,
+};
+use kloonorio_core::{
+    discrete_rotation::{DiscreteRotation, SideCount},
+    ground_item::GroundItem,
+    types::AppState,
+};
+
+use crate::{
+    isometric_sprite::{IsometricSprite, IsometricSpriteBundle},
+    item_textures::ItemTextures,
+};
+
+pub struct GroundItemRenderPlugin;
+
+impl Plugin for GroundItemRenderPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            create_ground_item_sprites.run_if(in_state(AppState::Running)),
+        );
+    }
+}
+
+/// Ground items never change their item, so the sprite only has to be created once
+fn create_ground_item_sprites(
+    mut commands: Commands,
+    ground_item_query: Query<(Entity, &GroundItem), Added<GroundItem>>,
+    item_textures: Res<ItemTextures>,
+) {
+    for (ground_item_entity, ground_item) in &ground_item_query {
+        let sprite = commands
+            .spawn((
+                DiscreteRotation::new(SideCount::One),
+                IsometricSpriteBundle {
+                    texture_atlas: item_textures.get_texture_atlas_handle(),
+                    sprite: IsometricSprite {
+                        custom_size: Some(Vec2::new(0.4, 0.4)),
+                        custom_texture_index: item_textures
+                            .get_texture_index(&ground_item.stack.item),
+                        ..default()
+                    },
+                    ..default()
+                },
+            ))
+            .id();
+        commands.entity(ground_item_entity).add_child(sprite);
+    }
+}
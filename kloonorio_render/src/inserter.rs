@@ -44,8 +44,9 @@ fn animate_arm_position(
         let arm_position = inserter.arm_position();
         let inserter_location = inserter_transform.translation().xy();
 
-        let pickup_location = inserter_pickup_location(inserter_transform);
-        let dropoff_location = inserter_dropoff_location(inserter_transform);
+        let reach = inserter.reach();
+        let pickup_location = inserter_pickup_location(inserter_transform, reach);
+        let dropoff_location = inserter_dropoff_location(inserter_transform, reach);
 
         let normalized_arm_position = (arm_position + 1.0) / 2.0;
         let arm_position = pickup_location.lerp(dropoff_location, normalized_arm_position);
@@ -55,8 +56,10 @@ fn animate_arm_position(
 
         let (mut hand_transform, mut visibility, mut iso_sprite) =
             arm_query.get_mut(inserter_hand.0).unwrap();
-        hand_transform.translation =
-            INSERTER_PICKUP_OFFSET.lerp(INSERTER_DROPOFF_OFFSET, normalized_arm_position);
+        hand_transform.translation = (INSERTER_PICKUP_OFFSET * reach as f32).lerp(
+            INSERTER_DROPOFF_OFFSET * reach as f32,
+            normalized_arm_position,
+        );
 
         if let Some(item) = inserter.holding() {
             *visibility = Visibility::Visible;
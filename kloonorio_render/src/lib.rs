@@ -1,6 +1,7 @@
 use bevy::app::{PluginGroup, PluginGroupBuilder};
 
 mod building_animation;
+mod ground_item;
 mod inserter;
 pub mod isometric_sprite;
 pub mod item_textures;
@@ -15,5 +16,6 @@ impl PluginGroup for KloonorioRenderPlugins {
             .add(transport_belt::TransportBeltRenderPlugin)
             .add(inserter::InserterRenderPlugin)
             .add(building_animation::BuildingAnimationPlugin)
+            .add(ground_item::GroundItemRenderPlugin)
     }
 }
@@ -13,7 +13,7 @@ use bevy::{
 };
 use kloonorio_core::{
     discrete_rotation::{DiscreteRotation, SideCount},
-    structure_components::transport_belt::{BeltItem, TransportBelt},
+    structure_components::transport_belt::{BeltItem, BeltLane, BeltSegment, BELT_SLOTS},
     types::AppState,
 };
 
@@ -24,7 +24,7 @@ use crate::{
 
 pub fn create_transport_belt_sprites(
     mut commands: Commands,
-    transport_belt_query: Query<(Entity, &TransportBelt)>,
+    segment_query: Query<&BeltSegment>,
     belt_item_query: Query<Entity, With<BeltItem>>,
     item_textures: Res<ItemTextures>,
 ) {
@@ -32,10 +32,21 @@ pub fn create_transport_belt_sprites(
         commands.entity(belt_item).despawn_recursive();
     }
 
-    for (transport_belt_entity, transport_belt) in transport_belt_query.iter() {
-        for (i, slot) in transport_belt.slots().enumerate() {
-            if let Some(product) = slot {
-                let sprite_transform = Transform::from_xyz(0., (i as i32 - 1) as f32 * 0.3, 1.);
+    for segment in segment_query.iter() {
+        for lane in BeltLane::ALL {
+            let lane_offset = match lane {
+                BeltLane::Left => -0.2,
+                BeltLane::Right => 0.2,
+            };
+            for (position, product) in segment.items(lane) {
+                let transport_belt_entity = segment.belts()[position / BELT_SLOTS];
+                if commands.get_entity(transport_belt_entity).is_none() {
+                    // Removed since the segments were last built
+                    continue;
+                }
+                let i = position % BELT_SLOTS;
+                let sprite_transform =
+                    Transform::from_xyz(lane_offset, (i as i32 - 1) as f32 * 0.3, 1.);
                 let slot_sprite = commands
                     .spawn((
                         BeltItem,
@@ -45,7 +56,7 @@ pub fn create_transport_belt_sprites(
                             texture_atlas: item_textures.get_texture_atlas_handle(),
                             sprite: IsometricSprite {
                                 // Pass the custom size
-                                custom_size: Some(Vec2::new(0.4, 0.4)),
+                                custom_size: Some(Vec2::new(0.3, 0.3)),
                                 custom_texture_index: Some(
                                     item_textures.get_texture_index(product).unwrap(),
                                 ),
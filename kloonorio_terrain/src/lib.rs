@@ -16,7 +16,7 @@ use bevy::{
 use bevy_ecs_tilemap::prelude::*;
 use ndarray::prelude::*;
 
-use kloonorio_core::{item::Item, mineable::Mineable, player::Player, types::AppState};
+use kloonorio_core::{item::Item, mineable::Mineable, player::LocalPlayer, types::AppState};
 
 use self::{
     debug::{chunk_gizmos, hovered_tile_gizmo},
@@ -32,8 +32,9 @@ pub struct KloonorioTerrainPlugin;
 
 impl Plugin for KloonorioTerrainPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(TilemapPlugin)
-            .register_type::<TerrainSettings>()
+        // The `TilemapPlugin` is added by the app, it renders the tilemaps and isn't needed to
+        // simulate without a window
+        app.register_type::<TerrainSettings>()
             .register_type::<CursorWorldPos>()
             .register_type::<HoveredTile>()
             .init_resource::<TerrainSettings>()
@@ -74,11 +75,15 @@ pub const _FLOWERS: u32 = 6;
 pub const STONE: u32 = 7;
 pub const COAL: u32 = 8;
 pub const IRON: u32 = 9;
+pub const COPPER: u32 = 10;
 
 #[derive(Component, Default)]
 pub struct Terrain {
     pub terrain_texture: Handle<Image>,
     pub chunks: HashMap<IVec2, Entity>,
+    /// Tiles that no longer match the generated terrain, by tile position. They replace the
+    /// generated tiles when their chunk is spawned.
+    pub modified_tiles: HashMap<IVec2, u32>,
 }
 
 impl Terrain {
@@ -92,7 +97,7 @@ impl Terrain {
 
 #[derive(Resource, Debug, Reflect)]
 pub struct TerrainSettings {
-    seed: u32,
+    pub seed: u32,
     chunk_spawn_radius: i32,
     #[cfg(feature = "async")]
     enable_async: bool,
@@ -126,6 +131,15 @@ pub struct ChunkData {
     ores: HashMap<UVec2, u32>,
 }
 
+impl ChunkData {
+    /// The texture of the tile at a position within the chunk
+    pub fn tile(&self, tile_pos: IVec2) -> Option<u32> {
+        let x = usize::try_from(tile_pos.x).ok()?;
+        let y = usize::try_from(tile_pos.y).ok()?;
+        self.tiles.get((x, y)).copied().flatten()
+    }
+}
+
 #[cfg(feature = "async")]
 #[derive(Component)]
 #[component(storage = "SparseSet")]
@@ -239,12 +253,12 @@ struct SpawnedChunkTilemap;
 fn spawn_generated_chunks(
     mut commands: Commands,
     mut chunk_task: Query<
-        (Entity, &ChunkData, &Parent),
+        (Entity, &Chunk, &ChunkData, &Parent),
         (Added<ChunkData>, Without<SpawnedChunkTilemap>),
     >,
     terrain_query: Query<&Terrain>,
 ) {
-    for (chunk_entity, chunk_data, parent) in &mut chunk_task {
+    for (chunk_entity, chunk, chunk_data, parent) in &mut chunk_task {
         let terrain = terrain_query
             .get(parent.get())
             .expect("Terrain entity not found");
@@ -252,6 +266,8 @@ fn spawn_generated_chunks(
             &mut commands,
             chunk_data,
             chunk_entity,
+            chunk.position,
+            &terrain.modified_tiles,
             terrain.terrain_texture.clone(),
         );
     }
@@ -261,6 +277,8 @@ fn add_chunk_tilemap(
     commands: &mut Commands,
     chunk_data: &ChunkData,
     chunk_entity: Entity,
+    chunk_position: IVec2,
+    modified_tiles: &HashMap<IVec2, u32>,
     texture_handle: Handle<Image>,
 ) {
     let map_type = TilemapType::Square;
@@ -278,6 +296,9 @@ fn add_chunk_tilemap(
             let y = y as u32;
             if let Some(texture_id) = tile {
                 let tile_pos = TilePos { x, y };
+                let modified_texture_id =
+                    modified_tiles.get(&chunk_tile_to_tile_position(chunk_position, tile_pos));
+                let texture_id = modified_texture_id.unwrap_or(texture_id);
 
                 let mut tile_entity_commands = commands.spawn(TileBundle {
                     position: tile_pos,
@@ -286,10 +307,12 @@ fn add_chunk_tilemap(
 
                     ..default()
                 });
-                if chunk_data.ores.contains_key(&UVec2::new(x, y)) {
+                if chunk_data.ores.contains_key(&UVec2::new(x, y)) && modified_texture_id.is_none()
+                {
                     let product = match *texture_id {
                         COAL => Item::new("Coal"),
                         IRON => Item::new("Iron ore"),
+                        COPPER => Item::new("Copper ore"),
                         STONE => Item::new("Stone"),
                         _ => panic!("Invalid ore type"),
                     };
@@ -362,7 +385,11 @@ fn update_cursor_pos(
     camera_query: Query<(&GlobalTransform, &Camera)>,
     mut cursor_pos: ResMut<CursorWorldPos>,
 ) {
-    let Some(cursor_position) = window.single().cursor_position() else {
+    let Some(cursor_position) = window
+        .get_single()
+        .ok()
+        .and_then(|window| window.cursor_position())
+    else {
         return;
     };
     let Ok((camera_transform, camera)) = camera_query.get_single() else {
@@ -387,7 +414,7 @@ pub fn hovered_tile(
     cursor_world_pos: Res<CursorWorldPos>,
     hovered_tile_query: Query<Entity, With<HoveredTile>>,
     terrain_params: TerrainParams,
-    player_query: Query<Entity, With<Player>>,
+    player_query: Query<Entity, With<LocalPlayer>>,
 ) {
     for hovered_tile in &hovered_tile_query {
         commands.entity(hovered_tile).remove::<HoveredTile>();
@@ -407,7 +434,14 @@ pub fn hovered_tile(
     }
 }
 
-fn terrain_pos_to_chunk_id(terrain_pos: Vec2) -> IVec2 {
+/// The world position of a tile in a chunk, tile positions start at the bottom left corner of
+/// the chunk
+pub fn chunk_tile_to_tile_position(chunk_position: IVec2, tile_pos: TilePos) -> IVec2 {
+    chunk_position * CHUNK_SIZE.as_ivec2() + IVec2::new(tile_pos.x as i32, tile_pos.y as i32)
+        - CHUNK_SIZE.as_ivec2() / 2
+}
+
+pub fn terrain_pos_to_chunk_id(terrain_pos: Vec2) -> IVec2 {
     IVec2::new(
         ((terrain_pos.x + CHUNK_SIZE.x as f32 * 0.5) / CHUNK_SIZE.x as f32).floor() as i32,
         ((terrain_pos.y + CHUNK_SIZE.y as f32 * 0.5) / CHUNK_SIZE.y as f32).floor() as i32,
@@ -525,6 +559,24 @@ impl<'w, 's> TerrainParams<'w, 's> {
     pub fn tile_texture_index(&self, tile_entity: Entity) -> Option<TileTextureIndex> {
         self.tiles.get(tile_entity).ok().copied()
     }
+
+    /// The texture of the tile at a world position, whether its chunk is spawned or not. Chunks
+    /// are only spawned around the local player, this is the same for every player.
+    pub fn tile_texture_at(&self, world_position: Vec2) -> Option<u32> {
+        let (terrain_entity, terrain) = self.terrain_query.get_single().ok()?;
+        let tile_position = world_position.round().as_ivec2();
+        if let Some(texture_id) = terrain.modified_tiles.get(&tile_position) {
+            return Some(*texture_id);
+        }
+        let chunk_position = terrain_pos_to_chunk_id(world_position);
+        self.generator_query
+            .get(terrain_entity)
+            .ok()?
+            .generate_chunk(chunk_position)
+            .tile(
+                tile_position - chunk_position * CHUNK_SIZE.as_ivec2() + CHUNK_SIZE.as_ivec2() / 2,
+            )
+    }
 }
 
 pub fn spawn_test_terrain(app: &mut App) -> Option<Entity> {
@@ -617,4 +669,39 @@ pub mod test {
             );
         }
     }
+
+    #[test]
+    fn tile_texture_at_unspawned_chunk() {
+        let mut app = App::new();
+        app.add_plugins(TransformPlugin);
+        app.world.spawn(TerrainBundle {
+            generator: TerrainGenerator::new(Box::new(FlatChunkGenerator::new(WATER))),
+            ..default()
+        });
+        let mut system_state = SystemState::<TerrainParams>::new(&mut app.world);
+        let terrain_params = system_state.get_mut(&mut app.world);
+        assert_eq!(
+            terrain_params.tile_texture_at(Vec2::new(-4., 4.)),
+            Some(WATER)
+        );
+        assert_eq!(
+            terrain_params.tile_texture_at(Vec2::new(30., -12.)),
+            Some(WATER)
+        );
+
+        app.world
+            .query::<&mut Terrain>()
+            .single_mut(&mut app.world)
+            .modified_tiles
+            .insert(IVec2::new(30, -12), GROUND);
+        let terrain_params = system_state.get_mut(&mut app.world);
+        assert_eq!(
+            terrain_params.tile_texture_at(Vec2::new(30., -12.)),
+            Some(GROUND)
+        );
+        assert_eq!(
+            terrain_params.tile_texture_at(Vec2::new(31., -12.)),
+            Some(WATER)
+        );
+    }
 }
@@ -19,7 +19,8 @@ use noise::{NoiseFn, OpenSimplex, ScalePoint, Seedable, SuperSimplex, Turbulence
 use rand_xoshiro::Xoshiro256StarStar;
 
 use super::{
-    ChunkData, CHUNK_SIZE, COAL, DEEP_WATER, GRASS, GROUND, IRON, STONE, TALL_GRASS, TREE, WATER,
+    ChunkData, CHUNK_SIZE, COAL, COPPER, DEEP_WATER, GRASS, GROUND, IRON, STONE, TALL_GRASS, TREE,
+    WATER,
 };
 
 #[derive(Component, Clone)]
@@ -145,7 +146,7 @@ fn generate_region(seed: u32, region_location: IVec2) -> Region {
 
     let mut rng = Xoshiro256StarStar::seed_from_u64(region_seed);
     let ore_types = ore_locations.iter().map(|_| {
-        let ore_types = [(COAL, 2), (IRON, 2), (STONE, 1)];
+        let ore_types = [(COAL, 2), (IRON, 2), (COPPER, 2), (STONE, 1)];
 
         let ore_type = ore_types
             .choose_weighted(&mut rng, |item| item.1)
@@ -3,11 +3,18 @@ use bevy_egui::EguiContexts;
 
 use kloonorio_core::{
     inventory::{Fuel, Inventory, InventoryParams, InventoryType, Output, Source, Storage},
-    player::Player,
-    recipe::Recipes,
+    item::Items,
+    player::LocalPlayer,
+    player_command::{LocalCommands, PlayerCommand},
+    research::ResearchQueue,
     structure_components::{
-        assembler::{Assembler, ChangeAssemblerRecipeEvent},
+        assembler::Assembler,
         burner::Burner,
+        electricity::{ElectricConsumer, Generator},
+        fluid::FluidBox,
+        inserter::{Inserter, InserterFilter, InserterFilterMode, INSERTER_FILTER_SLOTS},
+        lab::Lab,
+        splitter::{Splitter, SplitterSide},
     },
     types::{AppState, Building, CraftingQueue},
 };
@@ -30,8 +37,17 @@ impl Plugin for BuildingUiPlugin {
 struct BuildingParam<'w, 's> {
     crafting_machine_query: Query<'w, 's, &'static CraftingQueue>,
     burner_query: Query<'w, 's, &'static mut Burner>,
+    electric_consumer_query: Query<'w, 's, &'static ElectricConsumer>,
+    generator_query: Query<'w, 's, &'static Generator>,
+    fluid_box_query: Query<'w, 's, &'static FluidBox>,
+    children_query: Query<'w, 's, &'static Children>,
+    building_transform_query: Query<'w, 's, &'static Transform, With<Building>>,
     assembler_query: Query<'w, 's, &'static Assembler>,
-    assembler_recipe_change_events: EventWriter<'w, ChangeAssemblerRecipeEvent>,
+    splitter_query: Query<'w, 's, &'static Splitter>,
+    inserter_query: Query<'w, 's, &'static Inserter>,
+    local_commands: ResMut<'w, LocalCommands>,
+    lab_query: Query<'w, 's, &'static Lab>,
+    research_queue: Res<'w, ResearchQueue>,
     slot_events: EventWriter<'w, SlotEvent>,
 }
 
@@ -41,7 +57,7 @@ fn building_ui(
     player_query: Query<
         (Entity, &SelectedBuilding, &Inventory, &Hand),
         (
-            With<Player>,
+            With<LocalPlayer>,
             Without<Building>,
             Without<Source>,
             Without<Output>,
@@ -60,6 +76,16 @@ fn building_ui(
         let name = name
             .get(*selected_building)
             .map_or("Building", |n| n.as_str());
+        // Commands find the building by its position, which is the same for every player
+        let Ok(building_transform) = building_param
+            .building_transform_query
+            .get(*selected_building)
+        else {
+            // The building was mined
+            commands.entity(player_entity).remove::<SelectedBuilding>();
+            return;
+        };
+        let position = building_transform.translation.truncate();
 
         let mut window_open = true;
         egui::Window::new(name)
@@ -107,9 +133,35 @@ fn building_ui(
                                     assembling_machine_widget(
                                         ui,
                                         assembler,
-                                        *selected_building,
-                                        &mut building_param.assembler_recipe_change_events,
-                                        &definitions.recipes,
+                                        position,
+                                        &mut building_param.local_commands,
+                                        &building_param.research_queue,
+                                        &definitions,
+                                    );
+                                }
+                                if let Ok(splitter) =
+                                    building_param.splitter_query.get(*selected_building)
+                                {
+                                    splitter_widget(
+                                        ui,
+                                        splitter,
+                                        position,
+                                        &mut building_param.local_commands,
+                                        &definitions.items,
+                                    );
+                                }
+                                if let Some(filter) = building_param
+                                    .inserter_query
+                                    .get(*selected_building)
+                                    .ok()
+                                    .and_then(|inserter| inserter.filter())
+                                {
+                                    inserter_filter_widget(
+                                        ui,
+                                        filter,
+                                        position,
+                                        &mut building_param.local_commands,
+                                        &definitions.items,
                                     );
                                 }
                                 if let Ok(crafting_queue) = building_param
@@ -138,6 +190,22 @@ fn building_ui(
                                         &definitions,
                                     );
                                 }
+                                if let Ok(lab) = building_param.lab_query.get(*selected_building) {
+                                    let source = inventory_params
+                                        .get_child_inventory(
+                                            *selected_building,
+                                            InventoryType::Source,
+                                        )
+                                        .unwrap();
+                                    lab_widget(
+                                        ui,
+                                        lab,
+                                        source,
+                                        hand,
+                                        &mut building_param.slot_events,
+                                        &definitions,
+                                    );
+                                }
 
                                 if let Ok(burner) =
                                     building_param.burner_query.get_mut(*selected_building)
@@ -158,6 +226,34 @@ fn building_ui(
                                         &definitions,
                                     );
                                 }
+                                if let Ok(electric_consumer) = building_param
+                                    .electric_consumer_query
+                                    .get(*selected_building)
+                                {
+                                    ui.separator();
+                                    electricity_widget(
+                                        ui,
+                                        "Power:",
+                                        electric_consumer.satisfaction,
+                                    );
+                                }
+                                if let Ok(generator) =
+                                    building_param.generator_query.get(*selected_building)
+                                {
+                                    ui.separator();
+                                    electricity_widget(ui, "Load:", generator.load);
+                                }
+                                let fluid_boxes = building_param
+                                    .children_query
+                                    .get(*selected_building)
+                                    .into_iter()
+                                    .flatten()
+                                    .filter_map(|child| {
+                                        building_param.fluid_box_query.get(*child).ok()
+                                    });
+                                for fluid_box in fluid_boxes {
+                                    fluid_box_widget(ui, fluid_box);
+                                }
                             });
                         });
                 });
@@ -180,11 +276,38 @@ fn burner_widget(
     ui.horizontal(|ui| {
         ui.label("Fuel:");
         inventory_grid(fuel.0, fuel.1, ui, hand, slot_events, definitions);
-        if let Some(timer) = &burner.fuel_timer {
-            ui.add(egui::ProgressBar::new(1. - timer.percent()).desired_width(100.));
-        } else {
-            ui.add(egui::ProgressBar::new(0.).desired_width(100.));
-        }
+        ui.add(
+            egui::ProgressBar::new(burner.remaining_fraction())
+                .desired_width(100.)
+                .text(format!("{:.1} MJ", burner.energy)),
+        );
+    });
+}
+
+fn electricity_widget(ui: &mut egui::Ui, label: &str, fraction: f32) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.add(
+            egui::ProgressBar::new(fraction)
+                .desired_width(100.)
+                .show_percentage(),
+        );
+    });
+}
+
+fn fluid_box_widget(ui: &mut egui::Ui, fluid_box: &FluidBox) {
+    let fluid = fluid_box
+        .fluid
+        .as_deref()
+        .or(fluid_box.filter.as_deref())
+        .unwrap_or("Empty");
+    ui.horizontal(|ui| {
+        ui.label(format!("{}:", fluid));
+        ui.add(
+            egui::ProgressBar::new(fluid_box.level())
+                .desired_width(100.)
+                .text(format!("{:.0}/{}", fluid_box.amount, fluid_box.capacity)),
+        );
     });
 }
 
@@ -216,26 +339,167 @@ fn crafting_machine_widget(
     });
 }
 
+fn lab_widget(
+    ui: &mut egui::Ui,
+    lab: &Lab,
+    source: (Entity, &Inventory),
+    hand: &Hand,
+    slot_events: &mut EventWriter<SlotEvent>,
+    definitions: &Definitions,
+) {
+    ui.horizontal_centered(|ui| {
+        inventory_grid(source.0, source.1, ui, hand, slot_events, definitions);
+        ui.add(
+            egui::ProgressBar::new(lab.unit_progress.unwrap_or(0.))
+                .desired_width(100.)
+                .show_percentage(),
+        );
+    });
+}
+
 fn assembling_machine_widget(
     ui: &mut egui::Ui,
     assembler: &Assembler,
-    assembler_entity: Entity,
-    assembler_recipe_change_events: &mut EventWriter<ChangeAssemblerRecipeEvent>,
-    recipes: &Recipes,
+    position: Vec2,
+    local_commands: &mut LocalCommands,
+    research_queue: &ResearchQueue,
+    definitions: &Definitions,
 ) {
     ui.horizontal_centered(|ui| {
         if let Some(recipe) = &assembler.recipe {
             ui.label(recipe.name.as_str());
         }
         ui.menu_button("Select recipe", |ui| {
-            for recipe in recipes.values() {
+            for recipe in definitions
+                .recipes
+                .in_any_category(&assembler.categories)
+                .filter(|recipe| {
+                    research_queue.is_recipe_unlocked(&recipe.name, &definitions.technologies)
+                })
+            {
                 if ui.button(recipe.name.as_str()).clicked() {
-                    assembler_recipe_change_events.send(ChangeAssemblerRecipeEvent {
-                        entity: assembler_entity,
-                        recipe: recipe.clone(),
+                    local_commands.push(PlayerCommand::ChangeAssemblerRecipe {
+                        position,
+                        recipe: recipe.name.clone(),
                     });
                 }
             }
         })
     });
 }
+
+fn splitter_widget(
+    ui: &mut egui::Ui,
+    splitter: &Splitter,
+    position: Vec2,
+    local_commands: &mut LocalCommands,
+    items: &Items,
+) {
+    ui.horizontal(|ui| {
+        ui.label("Output priority:");
+        for (label, priority) in [
+            ("None", None),
+            ("Left", Some(SplitterSide::Left)),
+            ("Right", Some(SplitterSide::Right)),
+        ] {
+            if ui.radio(splitter.priority == priority, label).clicked() {
+                local_commands.push(PlayerCommand::ChangeSplitterSettings {
+                    position,
+                    priority,
+                    filter: splitter.filter.clone(),
+                });
+            }
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label("Filter:");
+        if let Some(filter) = &splitter.filter {
+            ui.label(filter.to_string());
+        }
+        ui.menu_button("Select item", |ui| {
+            if ui.button("None").clicked() {
+                local_commands.push(PlayerCommand::ChangeSplitterSettings {
+                    position,
+                    priority: splitter.priority,
+                    filter: None,
+                });
+            }
+            for item in items.values().map(|definition| &definition.name) {
+                if ui.button(item.to_string()).clicked() {
+                    local_commands.push(PlayerCommand::ChangeSplitterSettings {
+                        position,
+                        priority: splitter.priority,
+                        filter: Some(item.clone()),
+                    });
+                }
+            }
+        });
+    });
+}
+
+fn inserter_filter_widget(
+    ui: &mut egui::Ui,
+    filter: &InserterFilter,
+    position: Vec2,
+    local_commands: &mut LocalCommands,
+    items: &Items,
+) {
+    ui.horizontal(|ui| {
+        ui.label("Mode:");
+        for (label, mode) in [
+            ("Whitelist", InserterFilterMode::Whitelist),
+            ("Blacklist", InserterFilterMode::Blacklist),
+        ] {
+            if ui.radio(filter.mode == mode, label).clicked() {
+                local_commands.push(PlayerCommand::ChangeInserterFilter {
+                    position,
+                    filter: InserterFilter {
+                        mode,
+                        items: filter.items.clone(),
+                    },
+                });
+            }
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label("Filter:");
+        for (index, item) in filter.items.iter().enumerate() {
+            if ui
+                .button(item.to_string())
+                .on_hover_text("Click to remove")
+                .clicked()
+            {
+                let mut items = filter.items.clone();
+                items.remove(index);
+                local_commands.push(PlayerCommand::ChangeInserterFilter {
+                    position,
+                    filter: InserterFilter {
+                        mode: filter.mode,
+                        items,
+                    },
+                });
+            }
+        }
+        if filter.items.len() < INSERTER_FILTER_SLOTS {
+            ui.menu_button("Add item", |ui| {
+                for item in items
+                    .values()
+                    .map(|definition| &definition.name)
+                    .filter(|item| !filter.items.contains(item))
+                {
+                    if ui.button(item.to_string()).clicked() {
+                        let mut items = filter.items.clone();
+                        items.push(item.clone());
+                        local_commands.push(PlayerCommand::ChangeInserterFilter {
+                            position,
+                            filter: InserterFilter {
+                                mode: filter.mode,
+                                items,
+                            },
+                        });
+                    }
+                }
+            });
+        }
+    });
+}
@@ -9,9 +9,10 @@ use crate::{
 };
 use kloonorio_core::{
     inventory::Inventory,
-    player::Player,
-    recipe::Recipe,
-    types::{ActiveCraft, CraftingQueue},
+    player::LocalPlayer,
+    player_command::{LocalCommands, PlayerCommand},
+    recipe::{Recipe, CRAFTING_CATEGORY},
+    research::ResearchQueue,
 };
 
 use super::{icon::recipe_icon, tooltip::recipe_tooltip, UiSet};
@@ -47,11 +48,16 @@ pub fn recipe_slot(
 
 pub fn craft_ui(
     ui: &mut egui::Ui,
-    inventory: &mut Inventory,
-    build_queue: &mut CraftingQueue,
+    inventory: &Inventory,
+    local_commands: &mut LocalCommands,
+    research_queue: &ResearchQueue,
     definitions: &Definitions,
 ) {
-    let mut recipe_it = definitions.recipes.values();
+    // Everything else needs a machine
+    let mut recipe_it = definitions.recipes.values().filter(|recipe| {
+        recipe.category == CRAFTING_CATEGORY
+            && research_queue.is_recipe_unlocked(&recipe.name, &definitions.technologies)
+    });
     egui::Grid::new("crafting")
         .min_col_width(32.)
         .max_col_width(32.)
@@ -97,13 +103,8 @@ pub fn craft_ui(
                             });
 
                         if response.clicked() {
-                            inventory.remove_items(&recipe.ingredients);
-                            build_queue.0.push_back(ActiveCraft {
-                                recipe: recipe.clone(),
-                                timer: Timer::from_seconds(
-                                    recipe.crafting_time,
-                                    TimerMode::Repeating,
-                                ),
+                            local_commands.push(PlayerCommand::Craft {
+                                recipe: recipe.name.clone(),
                             });
                         }
                         response.on_hover_ui_at_pointer(|ui| {
@@ -140,22 +141,25 @@ fn toggle_character_ui(
 
 fn character_ui(
     mut egui_context: EguiContexts,
-    mut inventory_query: Query<(Entity, &mut Inventory, &Hand, &mut CraftingQueue), With<Player>>,
+    inventory_query: Query<(Entity, &Inventory, &Hand), With<LocalPlayer>>,
     mut slot_events: EventWriter<SlotEvent>,
+    mut local_commands: ResMut<LocalCommands>,
     character_ui_open: Res<CharacterUiOpen>,
+    research_queue: Res<ResearchQueue>,
     definitions: Definitions,
 ) {
     if !character_ui_open.0 {
         return;
     }
+    let Ok((player_entity, inventory, hand)) = inventory_query.get_single() else {
+        return;
+    };
 
     egui::Window::new("Character")
         .resizable(false)
         .collapsible(false)
         .title_bar(false)
         .show(egui_context.ctx_mut(), |ui| {
-            let (player_entity, ref mut inventory, hand, ref mut crafting_queue) =
-                inventory_query.single_mut();
             egui::Grid::new("character_ui_grid")
                 .spacing([10., 10.])
                 .show(ui, |ui| {
@@ -170,7 +174,13 @@ fn character_ui(
                         &mut slot_events,
                         &definitions,
                     );
-                    craft_ui(ui, inventory, crafting_queue, &definitions);
+                    craft_ui(
+                        ui,
+                        inventory,
+                        &mut local_commands,
+                        &research_queue,
+                        &definitions,
+                    );
                 });
         });
 }
@@ -1,9 +1,13 @@
-use bevy::ecs::{query::With, system::Query};
+use bevy::ecs::{
+    query::With,
+    system::{Query, ResMut},
+};
 use bevy_egui::EguiContexts;
 use egui::{Align2, Color32, PointerButton, Response, Sense};
 
 use kloonorio_core::{
-    player::Player,
+    player::LocalPlayer,
+    player_command::{LocalCommands, PlayerCommand},
     types::{ActiveCraft, CraftingQueue},
 };
 
@@ -13,7 +17,8 @@ use super::{icon::recipe_icon, tooltip::recipe_tooltip};
 
 pub fn crafting_queue_ui(
     mut egui_context: EguiContexts,
-    mut crafting_queue_query: Query<&mut CraftingQueue, With<Player>>,
+    crafting_queue_query: Query<&CraftingQueue, With<LocalPlayer>>,
+    mut local_commands: ResMut<LocalCommands>,
     definitions: Definitions,
 ) {
     let mut to_cancel: Vec<usize> = vec![];
@@ -22,9 +27,9 @@ pub fn crafting_queue_ui(
         .anchor(Align2::LEFT_BOTTOM, (5., -5.))
         .interactable(true)
         .show(egui_context.ctx_mut(), |ui| {
-            for mut crafting_queue in &mut crafting_queue_query {
+            for crafting_queue in &crafting_queue_query {
                 ui.horizontal(|ui| {
-                    for (index, build) in crafting_queue.0.iter_mut().enumerate() {
+                    for (index, build) in crafting_queue.0.iter().enumerate() {
                         let response = queue_item_ui(ui, build, &definitions.icons);
                         if response.clicked_by(PointerButton::Secondary) {
                             to_cancel.push(index);
@@ -39,14 +44,13 @@ pub fn crafting_queue_ui(
             }
         });
 
-    for index in to_cancel.iter().rev() {
-        for mut crafting_queue in &mut crafting_queue_query {
-            crafting_queue.0.remove(*index);
-        }
+    // Later crafts first, so the indices of the others stay the same
+    for index in to_cancel.into_iter().rev() {
+        local_commands.push(PlayerCommand::CancelCraft { index });
     }
 }
 
-fn queue_item_ui(ui: &mut egui::Ui, build: &mut ActiveCraft, icons: &Icons) -> Response {
+fn queue_item_ui(ui: &mut egui::Ui, build: &ActiveCraft, icons: &Icons) -> Response {
     let (rect, response) = ui.allocate_exact_size(
         egui::Vec2::new(32., 32.),
         Sense::hover().union(Sense::click()),
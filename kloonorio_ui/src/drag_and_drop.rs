@@ -2,14 +2,17 @@ use bevy::prelude::*;
 
 use crate::inventory_grid::{Hand, InventoryIndex, SlotEvent};
 use kloonorio_core::{
-    inventory::{drop_within_inventory, transfer_between_slots, Inventory, Slot, Stack},
-    player::Player,
+    inventory::Inventory,
+    player::LocalPlayer,
+    player_command::{InventoryLocations, InventorySlot, LocalCommands, PlayerCommand},
 };
 
 pub fn drop_system(
-    mut hand_query: Query<&mut Hand, With<Player>>,
+    mut hand_query: Query<&mut Hand, With<LocalPlayer>>,
     mut slot_events: EventReader<SlotEvent>,
-    mut inventories_query: Query<&mut Inventory>,
+    inventories_query: Query<&Inventory>,
+    inventory_locations: InventoryLocations,
+    mut local_commands: ResMut<LocalCommands>,
 ) {
     for event @ SlotEvent::Clicked(drop) in slot_events.read() {
         let span = info_span!("Handling drop event", ?event);
@@ -17,25 +20,30 @@ pub fn drop_system(
         for mut hand in hand_query.iter_mut() {
             info_span!("hand", hand = ?hand);
             if let Some(item_in_hand) = hand.get_item() {
-                if item_in_hand.entity == drop.entity {
-                    if item_in_hand.slot == drop.slot {
-                        hand.clear();
-                    } else {
-                        let mut inventory = inventories_query.get_mut(item_in_hand.entity).unwrap();
-                        drop_within_inventory(&mut inventory, item_in_hand.slot, drop.slot);
+                if item_in_hand.entity == drop.entity && item_in_hand.slot == drop.slot {
+                    hand.clear();
+                } else if let (Some(source), Some(target)) = (
+                    inventory_locations.location(item_in_hand.entity),
+                    inventory_locations.location(drop.entity),
+                ) {
+                    // The hand is emptied once the stack has moved out of its slot
+                    local_commands.push(PlayerCommand::MoveStack {
+                        from: InventorySlot {
+                            inventory: source,
+                            slot: item_in_hand.slot,
+                        },
+                        to: InventorySlot {
+                            inventory: target,
+                            slot: drop.slot,
+                        },
+                    });
+                    if item_in_hand.entity != drop.entity {
+                        hand.reset_rotation();
                     }
-                } else if let Ok([mut source_inventory, mut target_inventory]) =
-                    inventories_query.get_many_mut([item_in_hand.entity, drop.entity])
-                {
-                    let source_slot: &mut Slot =
-                        source_inventory.slots.get_mut(item_in_hand.slot).unwrap();
-                    let target_slot: &mut Slot = target_inventory.slots.get_mut(drop.slot).unwrap();
-                    transfer_between_slots(source_slot, target_slot);
-                    hand.reset_rotation();
                 } else {
                     error!("Could not get inventories");
                 }
-            } else if let Ok(inventory) = inventories_query.get_mut(drop.entity) {
+            } else if let Ok(inventory) = inventories_query.get(drop.entity) {
                 if inventory.slots[drop.slot].is_some() {
                     // No item it hand, but there is an item in the slot, pick it up
                     let inventory_index = InventoryIndex::new(drop.entity, drop.slot);
@@ -43,23 +51,34 @@ pub fn drop_system(
                     hand.set_item(drop.entity, drop.slot);
                 }
             }
+        }
+    }
+}
 
-            // If the hand contains an InventoryIndex pointing to an empty slot, empty the hand
-            if let Some(item_in_hand) = hand.get_item() {
-                let inventory = inventories_query.get(item_in_hand.entity).unwrap();
-                let slot: &Option<Stack> = &inventory.slots[item_in_hand.slot];
-                if slot.is_none() {
-                    hand.clear();
-                    debug!("Emptied hand");
-                }
-            }
+/// If the hand contains an InventoryIndex pointing to an empty slot, empty the hand
+pub fn clear_empty_hand(mut hand_query: Query<&mut Hand>, inventories_query: Query<&Inventory>) {
+    for mut hand in &mut hand_query {
+        let Some(item_in_hand) = hand.get_item() else {
+            continue;
+        };
+        let slot_is_empty = inventories_query
+            .get(item_in_hand.entity)
+            .map_or(true, |inventory| {
+                inventory
+                    .slots
+                    .get(item_in_hand.slot)
+                    .map_or(true, Option::is_none)
+            });
+        if slot_is_empty {
+            hand.clear();
+            debug!("Emptied hand");
         }
     }
 }
 
 pub fn clear_hand(
     keyboard_input: Res<Input<KeyCode>>,
-    mut hand_query: Query<&mut Hand, With<Player>>,
+    mut hand_query: Query<&mut Hand, With<LocalPlayer>>,
 ) {
     if keyboard_input.just_pressed(KeyCode::Apostrophe) {
         for mut hand in hand_query.iter_mut() {
@@ -71,7 +90,23 @@ pub fn clear_hand(
 #[cfg(test)]
 mod test {
     use crate::inventory_grid::InventoryIndex;
-    use kloonorio_core::{inventory::Stack, item::Item};
+    use kloonorio_core::{
+        deconstruction::RemoveStructureEvent,
+        inventory::Stack,
+        item::Item,
+        player::{Player, PlayerId},
+        player_command::{
+            apply_player_commands, schedule_local_commands, CommandSource, ScheduledCommands,
+        },
+        recipe::Recipes,
+        research::{QueueResearchEvent, ResearchQueue, Technologies},
+        simulation::SimulationTick,
+        structure::{PlaceStructureEvent, RotateStructureEvent},
+        structure_components::{
+            assembler::ChangeAssemblerRecipeEvent, inserter::ChangeInserterFilterEvent,
+            splitter::ChangeSplitterSettingsEvent,
+        },
+    };
 
     use super::*;
     use bevy::utils::HashMap;
@@ -99,6 +134,43 @@ mod test {
     }
     }
 
+    /// Drops go through the local player's commands, like they do in the game
+    fn drop_app() -> App {
+        let mut app = App::new();
+        app.add_event::<SlotEvent>()
+            .init_resource::<SimulationTick>()
+            .init_resource::<CommandSource>()
+            .init_resource::<LocalCommands>()
+            .init_resource::<ScheduledCommands>()
+            .init_resource::<Recipes>()
+            .init_resource::<ResearchQueue>()
+            .init_resource::<Technologies>()
+            .add_event::<ChangeAssemblerRecipeEvent>()
+            .add_event::<ChangeSplitterSettingsEvent>()
+            .add_event::<ChangeInserterFilterEvent>()
+            .add_event::<QueueResearchEvent>()
+            .add_event::<PlaceStructureEvent>()
+            .add_event::<RotateStructureEvent>()
+            .add_event::<RemoveStructureEvent>()
+            .add_systems(
+                Update,
+                (
+                    drop_system,
+                    schedule_local_commands,
+                    apply_player_commands,
+                    clear_empty_hand,
+                )
+                    .chain(),
+            );
+        app
+    }
+
+    fn spawn_player(app: &mut App, inventory: Inventory) -> Entity {
+        app.world
+            .spawn((Player, LocalPlayer, PlayerId(0), inventory))
+            .id()
+    }
+
     proptest! {
     #![proptest_config(ProptestConfig::with_cases(1000))]
         #[test]
@@ -164,25 +236,22 @@ mod test {
 
     #[test]
     fn drop_system_put_in_hand() {
-        let mut app = App::new();
+        let mut app = drop_app();
 
         let mut inventory = Inventory::new(10);
 
         inventory.add_item(&Item::new("Wood"), 1);
 
-        let player_id = app.world.spawn((Player, inventory)).id();
+        let player_id = spawn_player(&mut app, inventory);
 
         let hand = Hand::default();
 
         app.world.get_entity_mut(player_id).unwrap().insert(hand);
 
-        app.add_event::<SlotEvent>();
         app.world
             .resource_mut::<Events<SlotEvent>>()
             .send(SlotEvent::clicked(player_id, 0));
 
-        app.add_systems(Update, drop_system);
-
         app.update();
 
         assert_eq!(
@@ -193,25 +262,22 @@ mod test {
 
     #[test]
     fn drop_system_to_empty_clear_hand() {
-        let mut app = App::new();
+        let mut app = drop_app();
 
         let mut inventory = Inventory::new(10);
 
         inventory.add_item(&Item::new("Wood"), 1);
 
-        let player_id = app.world.spawn((Player, inventory)).id();
+        let player_id = spawn_player(&mut app, inventory);
 
         let hand = Hand::new(player_id, 0);
 
         app.world.get_entity_mut(player_id).unwrap().insert(hand);
 
-        app.add_event::<SlotEvent>();
         app.world
             .resource_mut::<Events<SlotEvent>>()
             .send(SlotEvent::clicked(player_id, 1));
 
-        app.add_systems(Update, drop_system);
-
         app.update();
 
         assert_eq!(app.world.get::<Hand>(player_id).unwrap().get_item(), None);
@@ -219,25 +285,22 @@ mod test {
 
     #[test]
     fn drop_system_same_slot() {
-        let mut app = App::new();
+        let mut app = drop_app();
 
         let mut inventory = Inventory::new(10);
 
         inventory.add_item(&Item::new("Wood"), 1);
 
-        let player_id = app.world.spawn((Player, inventory)).id();
+        let player_id = spawn_player(&mut app, inventory);
 
         let hand = Hand::new(player_id, 0);
 
         app.world.get_entity_mut(player_id).unwrap().insert(hand);
 
-        app.add_event::<SlotEvent>();
         app.world
             .resource_mut::<Events<SlotEvent>>()
             .send(SlotEvent::clicked(player_id, 0));
 
-        app.add_systems(Update, drop_system);
-
         app.update();
 
         assert_eq!(app.world.get::<Hand>(player_id).unwrap().get_item(), None);
@@ -245,26 +308,23 @@ mod test {
 
     #[test]
     fn drop_system_same_product() {
-        let mut app = App::new();
+        let mut app = drop_app();
 
         let mut inventory = Inventory::new(10);
 
         inventory.slots[0] = Some(Stack::new(Item::new("Wood"), 1));
         inventory.slots[1] = Some(Stack::new(Item::new("Wood"), 1));
 
-        let player_id = app.world.spawn((Player, inventory)).id();
+        let player_id = spawn_player(&mut app, inventory);
 
         let hand = Hand::new(player_id, 0);
 
         app.world.get_entity_mut(player_id).unwrap().insert(hand);
 
-        app.add_event::<SlotEvent>();
         app.world
             .resource_mut::<Events<SlotEvent>>()
             .send(SlotEvent::clicked(player_id, 1));
 
-        app.add_systems(Update, drop_system);
-
         app.update();
 
         assert_eq!(app.world.get::<Hand>(player_id).unwrap().get_item(), None);
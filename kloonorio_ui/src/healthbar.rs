@@ -1,11 +1,11 @@
 use bevy::ecs::{query::With, system::Query};
 use bevy_egui::EguiContexts;
 use egui::{epaint::Shadow, Align2, Color32, Frame};
-use kloonorio_core::{health::Health, player::Player};
+use kloonorio_core::{health::Health, player::LocalPlayer};
 
 pub fn healthbar(
     mut egui_context: EguiContexts,
-    player_health_query: Query<&Health, With<Player>>,
+    player_health_query: Query<&Health, With<LocalPlayer>>,
 ) {
     let Ok(player_health) = player_health_query.get_single() else {
         return;
@@ -17,7 +17,7 @@ use egui::{
     Align2, Color32, Frame, Pos2, Response, Sense, Stroke,
 };
 
-use kloonorio_core::{inventory::Inventory, player::Player};
+use kloonorio_core::{inventory::Inventory, player::LocalPlayer};
 
 use crate::{
     icon::{item_icon, Icons},
@@ -56,7 +56,7 @@ impl Hotbar {
 
 fn hotbar_ui(
     mut egui_context: EguiContexts,
-    mut hotbar_query: Query<(Entity, &mut Hotbar, &Inventory, &mut Hand), With<Player>>,
+    mut hotbar_query: Query<(Entity, &mut Hotbar, &Inventory, &mut Hand), With<LocalPlayer>>,
     definitions: Definitions,
 ) {
     egui::Area::new("Hotbar")
@@ -94,6 +94,9 @@ fn hotbar_ui(
                                             if let Some(index) = inventory.find_item(item.as_str())
                                             {
                                                 hand.set_item(player_entity, index)
+                                            } else {
+                                                // Without the item its structure can be planned
+                                                hand.set_ghost(item.to_string())
                                             }
                                         } else if let Some(inventory_idx) = hand.get_item() {
                                             if let Some(item) = &inventory.slots[inventory_idx.slot]
@@ -176,7 +179,7 @@ fn hotbar_item_ui(
 }
 
 fn hotbar_keyboard(
-    mut hotbar_query: Query<(Entity, &Hotbar, &mut Hand, &Inventory), With<Player>>,
+    mut hotbar_query: Query<(Entity, &Hotbar, &mut Hand, &Inventory), With<LocalPlayer>>,
     keyboard_input: Res<Input<KeyCode>>,
 ) {
     for (player_entity, hotbar, mut hand, inventory) in &mut hotbar_query {
@@ -193,14 +196,16 @@ fn hotbar_keyboard(
             (KeyCode::Key0, 9),
         ]);
 
-        if let Some(index) = bindings_map
+        if let Some(item) = bindings_map
             .iter()
             .find(|(&key, _)| keyboard_input.just_pressed(key))
             .and_then(|(_, index)| hotbar.0.get(*index as usize))
             .and_then(|hbi| hbi.item.as_ref())
-            .and_then(|item| inventory.find_item(item.as_str()))
         {
-            hand.set_item(player_entity, index)
+            match inventory.find_item(item.as_str()) {
+                Some(index) => hand.set_item(player_entity, index),
+                None => hand.set_ghost(item.to_string()),
+            }
         }
     }
 }
@@ -1,9 +1,12 @@
-use bevy::ecs::system::Query;
+use bevy::ecs::{query::With, system::Query};
 use bevy_egui::EguiContexts;
 use egui::Align2;
-use kloonorio_core::types::MineCountdown;
+use kloonorio_core::{player::LocalPlayer, types::MineCountdown};
 
-pub fn interaction_ui(mut egui_context: EguiContexts, interact_query: Query<&MineCountdown>) {
+pub fn interaction_ui(
+    mut egui_context: EguiContexts,
+    interact_query: Query<&MineCountdown, With<LocalPlayer>>,
+) {
     if let Ok(interact) = interact_query.get_single() {
         egui::Window::new("Interaction")
             .anchor(Align2::CENTER_BOTTOM, (0., -65.))
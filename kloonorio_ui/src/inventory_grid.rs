@@ -132,6 +132,8 @@ impl InventoryIndex {
 pub struct Hand {
     pub item: Option<InventoryIndex>,
     pub rotation: Option<DiscreteRotation>,
+    /// Structure to plan as ghosts, picked from the hotbar while none are in the inventory
+    pub ghost: Option<String>,
 }
 
 impl Hand {
@@ -140,6 +142,7 @@ impl Hand {
         Self {
             item: Some(InventoryIndex::new(entity, slot)),
             rotation: None,
+            ghost: None,
         }
     }
 
@@ -149,11 +152,21 @@ impl Hand {
 
     pub fn set_item(&mut self, entity: Entity, slot: SlotIndex) {
         self.item = Some(InventoryIndex::new(entity, slot));
+        self.ghost = None;
+    }
+
+    pub fn set_ghost(&mut self, structure: String) {
+        if self.ghost.as_ref() != Some(&structure) {
+            self.rotation = None;
+        }
+        self.item = None;
+        self.ghost = Some(structure);
     }
 
     pub fn clear(&mut self) {
         self.item = None;
         self.rotation = None;
+        self.ghost = None;
     }
 
     pub fn reset_rotation(&mut self) {
@@ -8,7 +8,9 @@ pub mod hotbar;
 pub mod icon;
 mod interact_ui;
 pub mod inventory_grid;
+mod menu_ui;
 pub mod picker;
+mod research_ui;
 mod tooltip;
 mod util;
 
@@ -25,16 +27,18 @@ use bevy::{
 use bevy_egui::{EguiContexts, EguiPlugin};
 use bevy_inspector_egui::DefaultInspectorConfigPlugin;
 use building_ui::BuildingUiPlugin;
+use menu_ui::MenuUiPlugin;
 use picker::PickerPlugin;
+use research_ui::ResearchUiPlugin;
 
 use self::{
     character_ui::CharacterUiPlugin,
     debug::DebugPlugin,
-    drag_and_drop::{clear_hand, drop_system},
+    drag_and_drop::{clear_empty_hand, clear_hand, drop_system},
     hotbar::HotbarPlugin,
     inventory_grid::SlotEvent,
 };
-use kloonorio_core::{player::Player, types::AppState};
+use kloonorio_core::{player::LocalPlayer, types::AppState};
 
 pub struct KloonorioUiPlugin;
 
@@ -50,6 +54,8 @@ impl Plugin for KloonorioUiPlugin {
                 PickerPlugin,
                 BuildingUiPlugin,
                 DebugPlugin,
+                ResearchUiPlugin,
+                MenuUiPlugin,
             ))
             .add_systems(
                 Update,
@@ -65,7 +71,10 @@ impl Plugin for KloonorioUiPlugin {
             .add_event::<SlotEvent>()
             .add_systems(
                 Update,
-                drop_system.after(UiSet).run_if(in_state(AppState::Running)),
+                (drop_system, clear_empty_hand)
+                    .chain()
+                    .after(UiSet)
+                    .run_if(in_state(AppState::Running)),
             );
     }
 }
@@ -77,8 +86,8 @@ pub struct HoveringUI;
 fn hovering_ui(
     mut commands: Commands,
     mut egui_context: EguiContexts,
-    hovering_player_query: Query<Entity, (With<Player>, With<HoveringUI>)>,
-    non_hovering_player_query: Query<Entity, (With<Player>, Without<HoveringUI>)>,
+    hovering_player_query: Query<Entity, (With<LocalPlayer>, With<HoveringUI>)>,
+    non_hovering_player_query: Query<Entity, (With<LocalPlayer>, Without<HoveringUI>)>,
 ) {
     if egui_context.ctx_mut().is_pointer_over_area() {
         for entity in non_hovering_player_query.iter() {
@@ -0,0 +1,57 @@
+use bevy::{input, prelude::*};
+use bevy_egui::EguiContexts;
+
+use kloonorio_core::save_game::{LoadGameEvent, SaveGameEvent};
+
+use super::UiSet;
+
+#[derive(Resource, Default)]
+struct MenuUiOpen(bool);
+
+fn toggle_menu_ui(
+    mut menu_ui_open: ResMut<MenuUiOpen>,
+    input: Res<Input<input::keyboard::KeyCode>>,
+) {
+    if input.just_pressed(KeyCode::Escape) {
+        menu_ui_open.0 = !menu_ui_open.0;
+    }
+}
+
+fn menu_ui(
+    mut egui_context: EguiContexts,
+    mut menu_ui_open: ResMut<MenuUiOpen>,
+    mut save_game_events: EventWriter<SaveGameEvent>,
+    mut load_game_events: EventWriter<LoadGameEvent>,
+) {
+    if !menu_ui_open.0 {
+        return;
+    }
+
+    let mut close = false;
+    egui::Window::new("Menu")
+        .resizable(false)
+        .collapsible(false)
+        .open(&mut menu_ui_open.0)
+        .show(egui_context.ctx_mut(), |ui| {
+            if ui.button("Save game").clicked() {
+                save_game_events.send(SaveGameEvent);
+                close = true;
+            }
+            if ui.button("Load game").clicked() {
+                load_game_events.send(LoadGameEvent);
+                close = true;
+            }
+        });
+    if close {
+        menu_ui_open.0 = false;
+    }
+}
+
+pub struct MenuUiPlugin;
+
+impl Plugin for MenuUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MenuUiOpen>()
+            .add_systems(Update, (toggle_menu_ui, menu_ui).chain().in_set(UiSet));
+    }
+}
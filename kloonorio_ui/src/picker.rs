@@ -14,7 +14,7 @@ use bevy::{
 use bevy_egui::EguiContexts;
 use bevy_rapier2d::{pipeline::QueryFilter, plugin::RapierContext};
 use egui::Align2;
-use kloonorio_core::{player::Player, types::Building};
+use kloonorio_core::{player::LocalPlayer, types::Building};
 use kloonorio_terrain::CursorWorldPos;
 use tracing::{debug, instrument};
 
@@ -40,17 +40,19 @@ fn pick_building(
     rapier_context: Res<RapierContext>,
     mouse_input: Res<Input<MouseButton>>,
     building_query: Query<&Building>,
-    player_query: Query<Entity, With<Player>>,
+    player_query: Query<Entity, With<LocalPlayer>>,
     cursor_pos: Res<CursorWorldPos>,
 ) {
     if !mouse_input.just_pressed(MouseButton::Left) {
         return;
     }
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
 
     let cursor: Vec2 = cursor_pos.0.xy();
     rapier_context.intersections_with_point(cursor, QueryFilter::new(), |entity| {
         if let Ok(_building) = building_query.get(entity) {
-            let player = player_query.single();
             commands.entity(player).insert(SelectedBuilding(entity));
             debug!("Selected building: {:?}", entity);
             return false;
@@ -0,0 +1,117 @@
+use bevy::{input, prelude::*};
+use bevy_egui::EguiContexts;
+
+use kloonorio_core::{
+    player_command::{LocalCommands, PlayerCommand},
+    research::{ResearchQueue, Technology, TechnologyEffect},
+};
+
+use crate::util::Definitions;
+
+use super::UiSet;
+
+#[derive(Resource, Default)]
+struct ResearchUiOpen(bool);
+
+fn toggle_research_ui(
+    mut research_ui_open: ResMut<ResearchUiOpen>,
+    input: Res<Input<input::keyboard::KeyCode>>,
+) {
+    if input.just_pressed(KeyCode::T) {
+        research_ui_open.0 = !research_ui_open.0;
+    }
+}
+
+fn technology_tooltip(ui: &mut egui::Ui, technology: &Technology) {
+    let cost = technology
+        .cost
+        .iter()
+        .map(|(item, amount)| format!("{} x {}", amount, item))
+        .collect::<Vec<_>>()
+        .join(", ");
+    ui.label(format!(
+        "{} units of {} in {}s",
+        technology.units, cost, technology.time
+    ));
+    if !technology.prerequisites.is_empty() {
+        ui.label(format!("Requires: {}", technology.prerequisites.join(", ")));
+    }
+    for effect in &technology.effects {
+        match effect {
+            TechnologyEffect::UnlockRecipe(recipe) => {
+                ui.label(format!("Unlocks recipe: {}", recipe));
+            }
+        }
+    }
+}
+
+fn research_ui(
+    mut egui_context: EguiContexts,
+    mut research_ui_open: ResMut<ResearchUiOpen>,
+    research_queue: Res<ResearchQueue>,
+    mut local_commands: ResMut<LocalCommands>,
+    definitions: Definitions,
+) {
+    if !research_ui_open.0 {
+        return;
+    }
+
+    egui::Window::new("Research")
+        .resizable(false)
+        .collapsible(false)
+        .open(&mut research_ui_open.0)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.heading("Researching");
+            let current = research_queue
+                .current()
+                .and_then(|technology| definitions.technologies.get(technology));
+            if let Some(technology) = current {
+                ui.horizontal(|ui| {
+                    ui.label(technology.name.as_str());
+                    ui.add(
+                        egui::ProgressBar::new(
+                            research_queue.progress as f32 / technology.units as f32,
+                        )
+                        .desired_width(100.)
+                        .text(format!("{}/{}", research_queue.progress, technology.units)),
+                    );
+                });
+                for queued in research_queue.queue.iter().skip(1) {
+                    ui.label(queued.as_str());
+                }
+            } else {
+                ui.label("Nothing");
+            }
+
+            ui.separator();
+            ui.heading("Available");
+            let mut available: Vec<&Technology> = definitions
+                .technologies
+                .values()
+                .filter(|technology| research_queue.can_queue(technology))
+                .collect();
+            available.sort_by(|a, b| a.name.cmp(&b.name));
+            for technology in available {
+                if ui
+                    .button(technology.name.as_str())
+                    .on_hover_ui(|ui| technology_tooltip(ui, technology))
+                    .clicked()
+                {
+                    local_commands.push(PlayerCommand::QueueResearch {
+                        technology: technology.name.clone(),
+                    });
+                }
+            }
+        });
+}
+
+pub struct ResearchUiPlugin;
+
+impl Plugin for ResearchUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ResearchUiOpen>().add_systems(
+            Update,
+            (toggle_research_ui, research_ui).chain().in_set(UiSet),
+        );
+    }
+}
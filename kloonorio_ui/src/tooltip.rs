@@ -1,7 +1,7 @@
 use egui::{Color32, Response, RichText};
 
 use kloonorio_core::{
-    inventory::Stack, item::Item, item::Items, recipe::Recipe, structure::Structure,
+    inventory::Stack, item::ItemDefinition, item::Items, recipe::Recipe, structure::Structure,
     structure::Structures, structure_components::StructureComponent,
 };
 
@@ -46,8 +46,52 @@ pub fn structure_rows(ui: &mut egui::Ui, structure: &Structure) {
                 ui.label(format!("Storage size: {} slots", size));
                 ui.end_row();
             }
-            StructureComponent::Burner => {
-                ui.label("Consumes fuel");
+            StructureComponent::Burner(consumption) => {
+                ui.label(format!("Consumes fuel: {} kW", consumption));
+                ui.end_row();
+            }
+            StructureComponent::Smelter(categories) | StructureComponent::Assembler(categories) => {
+                let mut categories = categories.iter().cloned().collect::<Vec<_>>();
+                categories.sort();
+                ui.label(format!("Crafts: {}", categories.join(", ")));
+                ui.end_row();
+            }
+            StructureComponent::PowerPole(wire_reach, supply_range) => {
+                ui.label(format!("Wire reach: {} tiles", wire_reach));
+                ui.end_row();
+                ui.label(format!("Supply area: {0}x{0} tiles", supply_range * 2.));
+                ui.end_row();
+            }
+            StructureComponent::Generator(production) => {
+                ui.label(format!("Produces electricity: {} kW", production));
+                ui.end_row();
+            }
+            StructureComponent::ElectricConsumer(consumption) => {
+                ui.label(format!("Consumes electricity: {} kW", consumption));
+                ui.end_row();
+            }
+            StructureComponent::FluidBox(capacity, filter, _) => {
+                ui.label(format!(
+                    "Fluid capacity: {} {}",
+                    capacity,
+                    filter.as_deref().unwrap_or("")
+                ));
+                ui.end_row();
+            }
+            StructureComponent::OffshorePump(pumping_speed) => {
+                ui.label(format!("Pumps water: {}/s", pumping_speed));
+                ui.end_row();
+            }
+            StructureComponent::Boiler(steam_per_second) => {
+                ui.label(format!("Produces steam: {}/s", steam_per_second));
+                ui.end_row();
+            }
+            StructureComponent::SteamEngine(steam_per_second) => {
+                ui.label(format!("Consumes steam: {}/s", steam_per_second));
+                ui.end_row();
+            }
+            StructureComponent::Lab(speed) => {
+                ui.label(format!("Research speed: {}", speed));
                 ui.end_row();
             }
             _ => {}
@@ -55,8 +99,10 @@ pub fn structure_rows(ui: &mut egui::Ui, structure: &Structure) {
     }
 }
 
-pub fn item_rows(ui: &mut egui::Ui, item: &Item) {
-    let _ = item;
+pub fn item_rows(ui: &mut egui::Ui, item: &ItemDefinition) {
+    if item.is_fuel() {
+        ui.label(format!("Fuel value: {} MJ", item.fuel_value));
+    }
     ui.end_row();
 }
 
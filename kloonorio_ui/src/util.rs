@@ -1,5 +1,5 @@
 use bevy::ecs::system::{Res, SystemParam};
-use kloonorio_core::{item::Items, recipe::Recipes, structure::Structures};
+use kloonorio_core::{item::Items, recipe::Recipes, research::Technologies, structure::Structures};
 
 use crate::icon::Icons;
 
@@ -9,4 +9,5 @@ pub struct Definitions<'w> {
     pub recipes: Res<'w, Recipes>,
     pub icons: Res<'w, Icons>,
     pub items: Res<'w, Items>,
+    pub technologies: Res<'w, Technologies>,
 }
@@ -0,0 +1,154 @@
+use std::{path::PathBuf, time::SystemTime};
+
+use bevy::{
+    prelude::*,
+    tasks::{IoTaskPool, Task},
+};
+use futures_lite::future;
+
+use kloonorio_core::{save_game::SaveParams, types::AppState};
+use kloonorio_terrain::{Terrain, TerrainSettings};
+
+use crate::save_file::{saved_terrain, write_save_file, PendingLoad, SaveFileError};
+
+pub struct AutosavePlugin;
+
+impl Plugin for AutosavePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<AutosaveSettings>()
+            .init_resource::<AutosaveSettings>()
+            .init_resource::<AutosaveState>()
+            .add_systems(
+                Update,
+                (autosave, finish_autosave)
+                    .run_if(in_state(AppState::Running))
+                    .run_if(not(resource_exists::<PendingLoad>())),
+            );
+    }
+}
+
+#[derive(Resource, Debug, Reflect)]
+pub struct AutosaveSettings {
+    pub enabled: bool,
+    /// Minutes between autosaves
+    pub interval: f32,
+    /// Number of autosave files that are written in turn, each autosave overwrites the oldest
+    pub slots: u32,
+}
+
+impl Default for AutosaveSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval: 5.,
+            slots: 3,
+        }
+    }
+}
+
+pub fn autosave_path(slot: u32) -> PathBuf {
+    PathBuf::from(format!("saves/autosave_{}.ron", slot + 1))
+}
+
+/// The slot to overwrite, an unused slot or else the one written longest ago. Slots are looked up
+/// on every autosave, as a new session doesn't know which one the last session wrote.
+fn oldest_slot(slots: u32, modified: impl Fn(u32) -> Option<SystemTime>) -> u32 {
+    (0..slots.max(1))
+        .min_by_key(|slot| modified(*slot))
+        .unwrap_or_default()
+}
+
+fn autosave_modified(slot: u32) -> Option<SystemTime> {
+    std::fs::metadata(autosave_path(slot))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[derive(Resource)]
+struct AutosaveState {
+    timer: Timer,
+    /// Writing the previous autosave, serializing and writing happen off the main thread so the
+    /// simulation doesn't stall
+    task: Option<Task<Result<PathBuf, SaveFileError>>>,
+}
+
+impl Default for AutosaveState {
+    fn default() -> Self {
+        Self {
+            timer: Timer::from_seconds(
+                AutosaveSettings::default().interval * 60.,
+                TimerMode::Repeating,
+            ),
+            task: None,
+        }
+    }
+}
+
+/// Take a snapshot of the world every `AutosaveSettings::interval` minutes and write it to the
+/// oldest autosave slot in the background
+fn autosave(
+    mut autosave_state: ResMut<AutosaveState>,
+    autosave_settings: Res<AutosaveSettings>,
+    save_params: SaveParams,
+    terrain_query: Query<&Terrain>,
+    terrain_settings: Res<TerrainSettings>,
+    time: Res<Time>,
+) {
+    if autosave_settings.is_changed() {
+        let interval = std::time::Duration::from_secs_f32(autosave_settings.interval * 60.);
+        autosave_state.timer.set_duration(interval);
+    }
+    if !autosave_settings.enabled
+        || !autosave_state.timer.tick(time.delta()).just_finished()
+        || autosave_state.task.is_some()
+    {
+        return;
+    }
+
+    let terrain = saved_terrain(&terrain_settings, terrain_query.single());
+    let save_game = save_params.save_game(terrain);
+    let slot = oldest_slot(autosave_settings.slots, autosave_modified);
+    let path = autosave_path(slot);
+    autosave_state.task = Some(IoTaskPool::get().spawn(async move {
+        write_save_file(&path, &save_game)?;
+        Ok(path)
+    }));
+}
+
+fn finish_autosave(mut autosave_state: ResMut<AutosaveState>) {
+    let Some(task) = autosave_state.task.as_mut() else {
+        return;
+    };
+    let Some(result) = future::block_on(future::poll_once(task)) else {
+        return;
+    };
+    match result {
+        Ok(path) => info!("Autosaved game to {}", path.display()),
+        Err(err) => error!("Could not autosave game: {}", err),
+    }
+    autosave_state.task = None;
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn autosave_overwrites_unused_slot_first() {
+        let modified = |slot| (slot != 1).then_some(SystemTime::UNIX_EPOCH);
+        assert_eq!(oldest_slot(3, modified), 1);
+    }
+
+    #[test]
+    fn autosave_overwrites_oldest_slot() {
+        // An earlier session wrote slot 2, then slot 0 and last slot 1
+        let written = [30, 40, 10];
+        let modified =
+            |slot: u32| Some(SystemTime::UNIX_EPOCH + Duration::from_secs(written[slot as usize]));
+        assert_eq!(oldest_slot(3, modified), 2);
+        assert_eq!(oldest_slot(2, modified), 0);
+        assert_eq!(oldest_slot(0, modified), 0);
+    }
+}
@@ -81,12 +81,17 @@ fn spawn_biter_packs(
 ) {
     if timer.0.tick(time.delta()).just_finished() {
         // Pick a random chunk to spawn in
-        // The chunk must be at least 25 chunks away from the center chunk (0, 0) and the player
+        // The chunk must be at least 25 chunks away from the center chunk (0, 0) and the players
+        let player_positions: Vec<Vec2> = player_query
+            .iter()
+            .map(|transform| transform.translation().xy())
+            .collect();
         let eligible_chunks = chunks_query.iter().filter(|chunk| {
             let chunk_position = chunk.position().as_vec2() * 3.;
-            let player_position = player_query.single().translation().xy();
 
-            chunk_position.distance(player_position) > 25.
+            player_positions
+                .iter()
+                .all(|player_position| chunk_position.distance(*player_position) > 25.)
                 && chunk_position.distance(Vec2::ZERO) > 25.
         });
 
@@ -148,14 +153,29 @@ fn spawn_biter(spawn_param: &mut BiterSpawnParam, position: Vec2) {
         });
 }
 
+/// The player closest to a position, biters go after it
+fn nearest_player<'a>(
+    players: impl Iterator<Item = (Entity, &'a GlobalTransform)>,
+    position: Vec2,
+) -> Option<(Entity, Vec2)> {
+    players
+        .map(|(player, transform)| (player, transform.translation().xy()))
+        .min_by(|(_, a), (_, b)| {
+            a.distance_squared(position)
+                .total_cmp(&b.distance_squared(position))
+        })
+}
+
 fn move_to_player(
-    player_query: Query<&GlobalTransform, With<Player>>,
+    player_query: Query<(Entity, &GlobalTransform), With<Player>>,
     mut biter_query: Query<(&mut KinematicCharacterController, &GlobalTransform), With<Biter>>,
 ) {
-    let player_position = player_query.single().translation().xy();
-
     for (mut biter_controller, biter_transform) in biter_query.iter_mut() {
-        let direction_to_player = player_position - biter_transform.translation().xy();
+        let biter_position = biter_transform.translation().xy();
+        let Some((_, player_position)) = nearest_player(player_query.iter(), biter_position) else {
+            return;
+        };
+        let direction_to_player = player_position - biter_position;
 
         if direction_to_player.length() > 0.1 {
             biter_controller.translation = Some(direction_to_player.normalize() * 0.1);
@@ -165,12 +185,15 @@ fn move_to_player(
 
 fn attack_player(
     mut commands: Commands,
-    player_query: Query<Entity, With<Player>>,
-    biter_query: Query<Entity, (With<Biter>, Without<Target>)>,
+    player_query: Query<(Entity, &GlobalTransform), With<Player>>,
+    biter_query: Query<(Entity, &GlobalTransform), (With<Biter>, Without<Target>)>,
 ) {
-    for biter in biter_query.iter() {
-        let player = player_query.iter().next().unwrap();
-        commands.entity(biter).insert(Target(player));
+    for (biter, biter_transform) in biter_query.iter() {
+        if let Some((player, _)) =
+            nearest_player(player_query.iter(), biter_transform.translation().xy())
+        {
+            commands.entity(biter).insert(Target(player));
+        }
     }
 }
 
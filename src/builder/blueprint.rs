@@ -0,0 +1,780 @@
+use std::path::{Path, PathBuf};
+
+use bevy::{ecs::system::SystemParam, math::Vec3Swizzles, prelude::*, utils::HashMap};
+use bevy_egui::{egui, EguiContexts};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use kloonorio_core::{
+    discrete_rotation::DiscreteRotation,
+    item::Item,
+    player::LocalPlayer,
+    player_command::{LocalCommands, PlayerCommand},
+    simulation::SimulationTick,
+    structure::{Structure, Structures},
+    structure_components::{
+        assembler::Assembler,
+        inserter::{Inserter, InserterFilter},
+        splitter::{Splitter, SplitterSide},
+    },
+    types::{AppState, Building},
+};
+use kloonorio_terrain::CursorWorldPos;
+use kloonorio_ui::{inventory_grid::Hand, HoveringUI};
+
+use crate::builder::placeable::{
+    create_structure_texture_atlas, rotated_structure_size, spawn_structure_ghost, structure_rect,
+};
+
+/// Ticks to wait for a pasted structure to be built before its settings are dropped
+const SETTINGS_TIMEOUT: u64 = 120;
+
+pub struct BlueprintPlugin;
+
+impl Plugin for BlueprintPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BlueprintLibrary>()
+            .init_resource::<BlueprintTool>()
+            .init_resource::<BlueprintUi>()
+            .init_resource::<PendingSettings>()
+            .add_systems(Startup, load_blueprint_library)
+            .add_systems(
+                Update,
+                (
+                    toggle_blueprint_ui,
+                    blueprint_ui,
+                    select_blueprint_area,
+                    blueprint_rotation,
+                    paste_blueprint,
+                    apply_pending_settings,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Running)),
+            );
+    }
+}
+
+/// A layout of structures that can be pasted anywhere. Offsets are in tiles from the bottom left
+/// tile of the blueprint.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Blueprint {
+    pub name: String,
+    /// Size of the blueprint in tiles
+    pub size: IVec2,
+    pub structures: Vec<BlueprintStructure>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlueprintStructure {
+    pub name: String,
+    /// Bottom left tile of the structure
+    pub offset: IVec2,
+    pub rotation: DiscreteRotation,
+    #[serde(default)]
+    pub recipe: Option<String>,
+    #[serde(default)]
+    pub inserter_filter: Option<InserterFilter>,
+    #[serde(default)]
+    pub splitter_priority: Option<SplitterSide>,
+    #[serde(default)]
+    pub splitter_filter: Option<Item>,
+}
+
+impl BlueprintStructure {
+    /// Commands that give a built structure the settings of this one
+    fn settings(&self, position: Vec2) -> Vec<PlayerCommand> {
+        let mut settings = vec![];
+        if let Some(recipe) = &self.recipe {
+            settings.push(PlayerCommand::ChangeAssemblerRecipe {
+                position,
+                recipe: recipe.clone(),
+            });
+        }
+        if let Some(filter) = &self.inserter_filter {
+            settings.push(PlayerCommand::ChangeInserterFilter {
+                position,
+                filter: filter.clone(),
+            });
+        }
+        if self.splitter_priority.is_some() || self.splitter_filter.is_some() {
+            settings.push(PlayerCommand::ChangeSplitterSettings {
+                position,
+                priority: self.splitter_priority,
+                filter: self.splitter_filter.clone(),
+            });
+        }
+        settings
+    }
+}
+
+impl Blueprint {
+    /// Blueprint of structures whose offsets are still world tiles, moved so the blueprint
+    /// starts at tile (0, 0). Structures that aren't known are left out.
+    pub fn new(
+        name: String,
+        structures: Vec<BlueprintStructure>,
+        definitions: &Structures,
+    ) -> Option<Self> {
+        let structures: Vec<BlueprintStructure> = structures
+            .into_iter()
+            .filter(|structure| definitions.contains_key(&structure.name))
+            .collect();
+        let min = structures
+            .iter()
+            .map(|structure| structure.offset)
+            .reduce(IVec2::min)?;
+        let max = structures
+            .iter()
+            .map(|structure| {
+                structure.offset
+                    + rotated_structure_size(&definitions[&structure.name], &structure.rotation)
+            })
+            .reduce(IVec2::max)?;
+        Some(Self {
+            name,
+            size: max - min,
+            structures: structures
+                .into_iter()
+                .map(|structure| BlueprintStructure {
+                    offset: structure.offset - min,
+                    ..structure
+                })
+                .collect(),
+        })
+    }
+
+    /// The blueprint turned a quarter clockwise. Structures with a single side keep their
+    /// footprint, which only matters for the ones that aren't square.
+    pub fn rotated(&self, definitions: &Structures) -> Self {
+        Self {
+            name: self.name.clone(),
+            size: IVec2::new(self.size.y, self.size.x),
+            structures: self
+                .structures
+                .iter()
+                .filter_map(|structure| {
+                    let definition = definitions.get(&structure.name)?;
+                    let size = rotated_structure_size(definition, &structure.rotation);
+                    let mut rotation = structure.rotation;
+                    // Structures with 8 sides turn twice for a quarter, and ones with 2 can't
+                    for _ in 0..definition.sides / 4 {
+                        rotation.rotate();
+                    }
+                    Some(BlueprintStructure {
+                        offset: IVec2::new(
+                            structure.offset.y,
+                            self.size.x - structure.offset.x - size.x,
+                        ),
+                        rotation,
+                        ..structure.clone()
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// The structures with their positions when the bottom left tile of the blueprint is at
+    /// `origin`
+    pub fn placements<'a>(
+        &'a self,
+        origin: IVec2,
+        definitions: &'a Structures,
+    ) -> impl Iterator<Item = (&'a BlueprintStructure, &'a Structure, Vec2)> + 'a {
+        self.structures.iter().filter_map(move |structure| {
+            let definition = definitions.get(&structure.name)?;
+            let size = rotated_structure_size(definition, &structure.rotation);
+            let position =
+                (origin + structure.offset).as_vec2() + (size - IVec2::ONE).as_vec2() / 2.;
+            Some((structure, definition, position))
+        })
+    }
+}
+
+/// Bottom left tile covered by a structure
+fn structure_tile(structure: &Structure, position: Vec2, rotation: &DiscreteRotation) -> IVec2 {
+    (structure_rect(structure, position, rotation).min + 0.5)
+        .round()
+        .as_ivec2()
+}
+
+/// Rect covering the tiles from one corner to the other
+fn selection_rect(start: IVec2, end: IVec2) -> Rect {
+    Rect::from_corners(start.as_vec2(), end.as_vec2()).inset(0.5)
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum BlueprintLibraryError {
+    /// An [IO](std::io) Error.
+    #[error("Could not access blueprint library: {0}")]
+    Io(#[from] std::io::Error),
+    /// A [Ron](ron) Error while writing.
+    #[error("Could not write RON: {0}")]
+    Ron(#[from] ron::Error),
+    /// A [Ron](ron) Error while reading.
+    #[error("Could not parse RON: {0}")]
+    RonSpannedError(#[from] ron::error::SpannedError),
+}
+
+pub fn write_blueprint_library(
+    path: &Path,
+    blueprints: &[Blueprint],
+) -> Result<(), BlueprintLibraryError> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+    std::fs::write(
+        path,
+        ron::ser::to_string_pretty(blueprints, PrettyConfig::default())?,
+    )?;
+    Ok(())
+}
+
+pub fn read_blueprint_library(path: &Path) -> Result<Vec<Blueprint>, BlueprintLibraryError> {
+    let ron = std::fs::read_to_string(path)?;
+    Ok(ron::from_str(&ron)?)
+}
+
+/// The blueprints the player has made, kept in the library file between games
+#[derive(Resource, Debug)]
+pub struct BlueprintLibrary {
+    pub path: PathBuf,
+    pub blueprints: Vec<Blueprint>,
+}
+
+impl Default for BlueprintLibrary {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("blueprints/library.ron"),
+            blueprints: vec![],
+        }
+    }
+}
+
+impl BlueprintLibrary {
+    fn save(&self) {
+        if let Err(err) = write_blueprint_library(&self.path, &self.blueprints) {
+            error!("Could not save blueprints: {}", err);
+        }
+    }
+}
+
+#[derive(Resource, Default, Debug)]
+pub(crate) enum BlueprintTool {
+    #[default]
+    Off,
+    /// Dragging a rectangle over the structures to put in a new blueprint
+    Selecting { start: Option<IVec2> },
+    /// Pasting a blueprint, turned the way it will be placed
+    Pasting(Blueprint),
+}
+
+impl BlueprintTool {
+    pub(crate) fn is_pasting(&self) -> bool {
+        matches!(self, BlueprintTool::Pasting(_))
+    }
+}
+
+#[derive(Resource, Default)]
+struct BlueprintUi {
+    open: bool,
+    /// Name of the next blueprint
+    name: String,
+}
+
+/// Settings of pasted structures, sent once the structure has been built
+#[derive(Resource, Default)]
+struct PendingSettings(Vec<PendingSetting>);
+
+struct PendingSetting {
+    structure: String,
+    position: Vec2,
+    command: PlayerCommand,
+    expires: u64,
+}
+
+type BlueprintBuildingQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Name,
+        &'static Transform,
+        &'static DiscreteRotation,
+        Option<&'static Assembler>,
+        Option<&'static Inserter>,
+        Option<&'static Splitter>,
+    ),
+    With<Building>,
+>;
+
+fn load_blueprint_library(mut library: ResMut<BlueprintLibrary>) {
+    if !library.path.exists() {
+        return;
+    }
+    match read_blueprint_library(&library.path) {
+        Ok(blueprints) => library.blueprints = blueprints,
+        Err(err) => error!("Could not load blueprints: {}", err),
+    }
+}
+
+fn toggle_blueprint_ui(mut blueprint_ui: ResMut<BlueprintUi>, input: Res<Input<KeyCode>>) {
+    if input.just_pressed(KeyCode::B) {
+        blueprint_ui.open = !blueprint_ui.open;
+    }
+}
+
+fn blueprint_ui(
+    mut egui_context: EguiContexts,
+    mut blueprint_ui: ResMut<BlueprintUi>,
+    mut library: ResMut<BlueprintLibrary>,
+    mut tool: ResMut<BlueprintTool>,
+    mut hand_query: Query<&mut Hand, With<LocalPlayer>>,
+) {
+    if !blueprint_ui.open {
+        return;
+    }
+
+    let BlueprintUi { open, name } = &mut *blueprint_ui;
+    let mut removed = None;
+    egui::Window::new("Blueprints")
+        .resizable(false)
+        .collapsible(false)
+        .open(open)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(name);
+                if ui.button("New blueprint").clicked() {
+                    *tool = BlueprintTool::Selecting { start: None };
+                }
+            });
+            if matches!(*tool, BlueprintTool::Selecting { .. }) {
+                ui.label("Drag over the structures to add, right click to cancel");
+            }
+
+            ui.separator();
+            for (index, blueprint) in library.blueprints.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "{} ({} structures)",
+                        blueprint.name,
+                        blueprint.structures.len()
+                    ));
+                    if ui.button("Paste").clicked() {
+                        *tool = BlueprintTool::Pasting(blueprint.clone());
+                        for mut hand in &mut hand_query {
+                            hand.clear();
+                        }
+                    }
+                    if ui.button("Delete").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            }
+        });
+    if let Some(index) = removed {
+        library.blueprints.remove(index);
+        library.save();
+    }
+}
+
+/// The local player's use of the mouse in the world
+#[derive(SystemParam)]
+struct BlueprintCursor<'w, 's> {
+    player_query: Query<'w, 's, (&'static Hand, Has<HoveringUI>), With<LocalPlayer>>,
+    cursor_pos: Res<'w, CursorWorldPos>,
+    mouse_input: Res<'w, Input<MouseButton>>,
+    keys: Res<'w, Input<KeyCode>>,
+}
+
+impl BlueprintCursor<'_, '_> {
+    fn tile(&self) -> IVec2 {
+        self.cursor_pos.0.xy().round().as_ivec2()
+    }
+
+    /// Clicks on the UI don't reach the world
+    fn clicked(&self) -> bool {
+        self.mouse_input.just_pressed(MouseButton::Left)
+            && self
+                .player_query
+                .get_single()
+                .is_ok_and(|(_, hovering_ui)| !hovering_ui)
+    }
+
+    fn cancelled(&self) -> bool {
+        self.mouse_input.just_pressed(MouseButton::Right)
+    }
+
+    fn holding_item(&self) -> bool {
+        self.player_query
+            .get_single()
+            .is_ok_and(|(hand, _)| hand.get_item().is_some() || hand.ghost.is_some())
+    }
+
+    /// Pasting with shift held plans the structures as ghosts
+    fn planning(&self) -> bool {
+        self.keys
+            .any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight])
+    }
+}
+
+#[derive(SystemParam)]
+struct GhostParam<'w, 's> {
+    commands: Commands<'w, 's>,
+    asset_server: Res<'w, AssetServer>,
+    texture_atlases: ResMut<'w, Assets<TextureAtlas>>,
+}
+
+#[derive(SystemParam)]
+struct PasteParam<'w> {
+    local_commands: ResMut<'w, LocalCommands>,
+    pending_settings: ResMut<'w, PendingSettings>,
+    tick: Res<'w, SimulationTick>,
+}
+
+impl PasteParam<'_> {
+    fn place(&mut self, blueprint_structure: &BlueprintStructure, position: Vec2) {
+        self.local_commands.push(PlayerCommand::PlaceStructure {
+            structure: blueprint_structure.name.clone(),
+            position,
+            rotation: blueprint_structure.rotation,
+        });
+        for command in blueprint_structure.settings(position) {
+            self.pending_settings.0.push(PendingSetting {
+                structure: blueprint_structure.name.clone(),
+                position,
+                command,
+                expires: self.tick.0 + SETTINGS_TIMEOUT,
+            });
+        }
+    }
+
+    /// Ghosts can be built long after the settings would expire, so they are built without them
+    fn plan(&mut self, blueprint_structure: &BlueprintStructure, position: Vec2) {
+        self.local_commands.push(PlayerCommand::PlaceGhost {
+            structure: blueprint_structure.name.clone(),
+            position,
+            rotation: blueprint_structure.rotation,
+        });
+    }
+}
+
+/// Drag a rectangle to make a blueprint of the structures fully inside it
+fn select_blueprint_area(
+    mut tool: ResMut<BlueprintTool>,
+    mut library: ResMut<BlueprintLibrary>,
+    mut blueprint_ui: ResMut<BlueprintUi>,
+    mut gizmos: Gizmos,
+    cursor: BlueprintCursor,
+    building_query: BlueprintBuildingQuery,
+    structures: Res<Structures>,
+) {
+    let BlueprintTool::Selecting { start } = &mut *tool else {
+        return;
+    };
+    if cursor.cancelled() {
+        *tool = BlueprintTool::Off;
+        return;
+    }
+    if cursor.clicked() {
+        *start = Some(cursor.tile());
+    }
+    let Some(start) = *start else {
+        return;
+    };
+    let selection = selection_rect(start, cursor.tile());
+    gizmos.rect_2d(
+        selection.center(),
+        0.,
+        selection.size(),
+        Color::rgb(0.3, 0.6, 1.0),
+    );
+    if !cursor.mouse_input.just_released(MouseButton::Left) {
+        return;
+    }
+
+    let selected = building_query
+        .iter()
+        .filter_map(
+            |(name, transform, rotation, assembler, inserter, splitter)| {
+                let structure = structures.get(name.as_str())?;
+                let position = transform.translation.truncate();
+                let rect = structure_rect(structure, position, rotation);
+                if !(selection.contains(rect.min) && selection.contains(rect.max)) {
+                    return None;
+                }
+                Some(BlueprintStructure {
+                    name: structure.name.clone(),
+                    offset: structure_tile(structure, position, rotation),
+                    rotation: *rotation,
+                    recipe: assembler
+                        .and_then(|assembler| assembler.recipe.as_ref())
+                        .map(|recipe| recipe.name.clone()),
+                    inserter_filter: inserter.and_then(|inserter| inserter.filter().cloned()),
+                    splitter_priority: splitter.and_then(|splitter| splitter.priority),
+                    splitter_filter: splitter.and_then(|splitter| splitter.filter.clone()),
+                })
+            },
+        )
+        .collect();
+    let name = match blueprint_ui.name.trim() {
+        "" => format!("Blueprint {}", library.blueprints.len() + 1),
+        name => name.to_string(),
+    };
+    let Some(blueprint) = Blueprint::new(name, selected, &structures) else {
+        info!("No structures selected for the blueprint");
+        *tool = BlueprintTool::Off;
+        return;
+    };
+    info!(
+        "Made blueprint {} of {} structures",
+        blueprint.name,
+        blueprint.structures.len()
+    );
+    library.blueprints.push(blueprint.clone());
+    library.save();
+    blueprint_ui.name.clear();
+    *tool = BlueprintTool::Pasting(blueprint);
+}
+
+fn blueprint_rotation(
+    keys: Res<Input<KeyCode>>,
+    mut tool: ResMut<BlueprintTool>,
+    structures: Res<Structures>,
+) {
+    if keys.just_pressed(KeyCode::R) {
+        if let BlueprintTool::Pasting(blueprint) = &mut *tool {
+            *blueprint = blueprint.rotated(&structures);
+        }
+    }
+}
+
+/// Show the blueprint under the cursor and place the structures that fit on click. The player
+/// needs the structures in their inventory, and the settings are sent once they are built.
+/// Shift-clicking plans ghosts of the structures instead.
+fn paste_blueprint(
+    mut tool: ResMut<BlueprintTool>,
+    cursor: BlueprintCursor,
+    building_query: BlueprintBuildingQuery,
+    structures: Res<Structures>,
+    mut ghost_param: GhostParam,
+    mut paste_param: PasteParam,
+) {
+    let BlueprintTool::Pasting(blueprint) = &*tool else {
+        return;
+    };
+    // Taking an item in hand puts the blueprint away
+    if cursor.cancelled() || cursor.holding_item() {
+        *tool = BlueprintTool::Off;
+        return;
+    }
+
+    let occupied: Vec<Rect> = building_query
+        .iter()
+        .filter_map(|(name, transform, rotation, ..)| {
+            structures.get(name.as_str()).map(|building| {
+                structure_rect(building, transform.translation.truncate(), rotation)
+            })
+        })
+        .collect();
+    let origin = cursor.tile() - blueprint.size / 2;
+    let place = cursor.clicked();
+    let planning = cursor.planning();
+    let GhostParam {
+        commands,
+        asset_server,
+        texture_atlases,
+    } = &mut ghost_param;
+    let mut texture_atlas_handles = HashMap::new();
+    for (blueprint_structure, structure, position) in blueprint.placements(origin, &structures) {
+        let rect = structure_rect(structure, position, &blueprint_structure.rotation);
+        let fits = occupied
+            .iter()
+            .all(|other| rect.intersect(*other).is_empty());
+        if place && fits {
+            if planning {
+                paste_param.plan(blueprint_structure, position);
+            } else {
+                paste_param.place(blueprint_structure, position);
+            }
+            continue;
+        }
+
+        let texture_atlas_handle = texture_atlas_handles
+            .entry(&structure.name)
+            .or_insert_with(|| {
+                create_structure_texture_atlas(asset_server, structure, texture_atlases)
+            })
+            .clone();
+        let color = if fits {
+            Color::rgba(0.5, 0.7, 1.0, 0.5)
+        } else {
+            Color::rgba(1.0, 0.3, 0.3, 0.5)
+        };
+        spawn_structure_ghost(
+            commands,
+            position,
+            blueprint_structure.rotation,
+            texture_atlas_handle,
+            color,
+            structure,
+        );
+    }
+}
+
+/// Send the settings of pasted structures once they are built, the inserters and splitters
+/// only take their settings once their builders are done
+fn apply_pending_settings(
+    mut pending_settings: ResMut<PendingSettings>,
+    mut local_commands: ResMut<LocalCommands>,
+    building_query: BlueprintBuildingQuery,
+    tick: Res<SimulationTick>,
+) {
+    pending_settings.0.retain(|pending| {
+        let built =
+            building_query
+                .iter()
+                .any(|(name, transform, _, assembler, inserter, splitter)| {
+                    name.as_str() == pending.structure
+                        && transform.translation.truncate() == pending.position
+                        && match pending.command {
+                            PlayerCommand::ChangeAssemblerRecipe { .. } => assembler.is_some(),
+                            PlayerCommand::ChangeInserterFilter { .. } => inserter.is_some(),
+                            PlayerCommand::ChangeSplitterSettings { .. } => splitter.is_some(),
+                            _ => true,
+                        }
+                });
+        if built {
+            local_commands.push(pending.command.clone());
+        }
+        !built && tick.0 < pending.expires
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use kloonorio_core::discrete_rotation::SideCount;
+
+    use super::*;
+
+    fn definitions() -> Structures {
+        let mut structures = Structures::default();
+        for (name, size, sides) in [
+            ("Splitter", IVec2::new(2, 1), 4),
+            ("Inserter", IVec2::new(1, 1), 4),
+            ("Stone furnace", IVec2::new(2, 2), 1),
+        ] {
+            structures.insert(
+                name.to_string(),
+                Structure {
+                    name: name.into(),
+                    size,
+                    sides,
+                    collider: size.as_vec2() * 0.9,
+                    components: vec![],
+                    animated: false,
+                },
+            );
+        }
+        structures
+    }
+
+    fn blueprint_structure(
+        name: &str,
+        offset: IVec2,
+        rotation: DiscreteRotation,
+    ) -> BlueprintStructure {
+        BlueprintStructure {
+            name: name.into(),
+            offset,
+            rotation,
+            recipe: None,
+            inserter_filter: None,
+            splitter_priority: None,
+            splitter_filter: None,
+        }
+    }
+
+    /// A furnace with an inserter above it and a splitter right of that
+    fn furnace_blueprint(definitions: &Structures) -> Blueprint {
+        let north = DiscreteRotation::new(SideCount::Four);
+        let furnace = definitions.get("Stone furnace").unwrap();
+        let furnace_rotation = DiscreteRotation::new(SideCount::One);
+        Blueprint::new(
+            "Furnace".into(),
+            vec![
+                blueprint_structure(
+                    "Stone furnace",
+                    structure_tile(furnace, Vec2::new(10.5, -4.5), &furnace_rotation),
+                    furnace_rotation,
+                ),
+                blueprint_structure("Inserter", IVec2::new(10, -3), north),
+                blueprint_structure("Splitter", IVec2::new(11, -3), north),
+            ],
+            definitions,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn blueprint_starts_at_its_bottom_left_tile() {
+        let definitions = definitions();
+        let blueprint = furnace_blueprint(&definitions);
+
+        assert_eq!(blueprint.size, IVec2::new(3, 3));
+        let offsets: Vec<IVec2> = blueprint.structures.iter().map(|s| s.offset).collect();
+        assert_eq!(
+            offsets,
+            vec![IVec2::new(0, 0), IVec2::new(0, 2), IVec2::new(1, 2)]
+        );
+
+        let positions: Vec<Vec2> = blueprint
+            .placements(IVec2::new(10, -5), &definitions)
+            .map(|(_, _, position)| position)
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                Vec2::new(10.5, -4.5),
+                Vec2::new(10., -3.),
+                Vec2::new(11.5, -3.)
+            ]
+        );
+    }
+
+    #[test]
+    fn rotated_blueprint_turns_clockwise() {
+        let definitions = definitions();
+        let blueprint = furnace_blueprint(&definitions);
+
+        let rotated = blueprint.rotated(&definitions);
+        assert_eq!(rotated.size, IVec2::new(3, 3));
+        let offsets: Vec<IVec2> = rotated.structures.iter().map(|s| s.offset).collect();
+        // The furnace ends up on the left and the splitter below the inserter, facing east
+        assert_eq!(
+            offsets,
+            vec![IVec2::new(0, 1), IVec2::new(2, 2), IVec2::new(2, 0)]
+        );
+        assert_eq!(rotated.structures[2].rotation.get(), 1);
+
+        let mut turned = blueprint.clone();
+        for _ in 0..4 {
+            turned = turned.rotated(&definitions);
+        }
+        assert_eq!(turned, blueprint);
+    }
+
+    #[test]
+    fn blueprint_structures_stay_inside_blueprint_when_rotated() {
+        let definitions = definitions();
+        let mut blueprint = furnace_blueprint(&definitions);
+        for _ in 0..4 {
+            for (structure, definition, position) in blueprint.placements(IVec2::ZERO, &definitions)
+            {
+                let rect = structure_rect(definition, position, &structure.rotation);
+                assert!(selection_rect(IVec2::ZERO, blueprint.size - 1).contains(rect.min));
+                assert!(selection_rect(IVec2::ZERO, blueprint.size - 1).contains(rect.max));
+            }
+            blueprint = blueprint.rotated(&definitions);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use bevy::{ecs::system::SystemParam, math::Vec3Swizzles, prelude::*};
+use bevy_egui::{egui, EguiContexts};
+
+use kloonorio_core::{
+    deconstruction::{
+        DeconstructionFilter, DeconstructionFilterMode, MarkedForDeconstruction,
+        DECONSTRUCTION_PLANNER,
+    },
+    discrete_rotation::DiscreteRotation,
+    inventory::Inventory,
+    player::LocalPlayer,
+    player_command::{LocalCommands, PlayerCommand},
+    structure::Structures,
+    types::AppState,
+};
+use kloonorio_terrain::CursorWorldPos;
+use kloonorio_ui::{inventory_grid::Hand, HoveringUI};
+
+use crate::builder::placeable::structure_rect;
+
+const MARK_COLOR: Color = Color::rgb(1.0, 0.2, 0.2);
+const CANCEL_COLOR: Color = Color::rgb(0.3, 0.6, 1.0);
+
+pub struct DeconstructionPlannerPlugin;
+
+impl Plugin for DeconstructionPlannerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DeconstructionPlanner>().add_systems(
+            Update,
+            (
+                deconstruction_planner_ui,
+                select_deconstruction_area,
+                draw_deconstruction_marks,
+            )
+                .chain()
+                .run_if(in_state(AppState::Running)),
+        );
+    }
+}
+
+/// The local player's deconstruction planner
+#[derive(Resource, Default, Debug)]
+struct DeconstructionPlanner {
+    filter: DeconstructionFilter,
+    /// Tile the current drag started on
+    start: Option<IVec2>,
+}
+
+/// The local player's use of the deconstruction planner in the world
+#[derive(SystemParam)]
+struct PlannerCursor<'w, 's> {
+    player_query:
+        Query<'w, 's, (&'static Hand, &'static Inventory, Has<HoveringUI>), With<LocalPlayer>>,
+    cursor_pos: Res<'w, CursorWorldPos>,
+    mouse_input: Res<'w, Input<MouseButton>>,
+    keys: Res<'w, Input<KeyCode>>,
+}
+
+impl PlannerCursor<'_, '_> {
+    fn tile(&self) -> IVec2 {
+        self.cursor_pos.0.xy().round().as_ivec2()
+    }
+
+    fn holding_planner(&self) -> bool {
+        self.player_query
+            .get_single()
+            .is_ok_and(|(hand, inventory, _)| {
+                hand.get_item()
+                    .and_then(|index| inventory.slots.get(index.slot))
+                    .and_then(Option::as_ref)
+                    .is_some_and(|stack| &*stack.item == DECONSTRUCTION_PLANNER)
+            })
+    }
+
+    /// Clicks on the UI don't reach the world
+    fn clicked(&self) -> bool {
+        self.mouse_input.just_pressed(MouseButton::Left)
+            && self
+                .player_query
+                .get_single()
+                .is_ok_and(|(_, _, hovering_ui)| !hovering_ui)
+    }
+
+    /// Dragging with shift held takes the marks off instead
+    fn cancelling(&self) -> bool {
+        self.keys
+            .any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight])
+    }
+}
+
+fn deconstruction_planner_ui(
+    mut egui_context: EguiContexts,
+    mut planner: ResMut<DeconstructionPlanner>,
+    cursor: PlannerCursor,
+    structures: Res<Structures>,
+) {
+    if !cursor.holding_planner() {
+        return;
+    }
+
+    let filter = &mut planner.filter;
+    egui::Window::new("Deconstruction planner")
+        .resizable(false)
+        .collapsible(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label("Drag to mark structures, hold shift to unmark them");
+            ui.horizontal(|ui| {
+                ui.label("Mode:");
+                for (label, mode) in [
+                    ("Whitelist", DeconstructionFilterMode::Whitelist),
+                    ("Blacklist", DeconstructionFilterMode::Blacklist),
+                ] {
+                    ui.radio_value(&mut filter.mode, mode, label);
+                }
+            });
+            ui.horizontal_wrapped(|ui| {
+                ui.label("Filter:");
+                let mut removed = None;
+                for (index, structure) in filter.structures.iter().enumerate() {
+                    if ui
+                        .button(structure.as_str())
+                        .on_hover_text("Click to remove")
+                        .clicked()
+                    {
+                        removed = Some(index);
+                    }
+                }
+                if let Some(index) = removed {
+                    filter.structures.remove(index);
+                }
+                ui.menu_button("Add structure", |ui| {
+                    let mut names: Vec<&String> = structures
+                        .keys()
+                        .filter(|name| !filter.structures.contains(name))
+                        .collect();
+                    names.sort();
+                    for name in names {
+                        if ui.button(name.as_str()).clicked() {
+                            filter.structures.push(name.clone());
+                            ui.close_menu();
+                        }
+                    }
+                });
+            });
+        });
+}
+
+/// Drag a rectangle to mark the structures whose center is inside it for deconstruction
+fn select_deconstruction_area(
+    mut planner: ResMut<DeconstructionPlanner>,
+    mut gizmos: Gizmos,
+    cursor: PlannerCursor,
+    mut local_commands: ResMut<LocalCommands>,
+) {
+    if !cursor.holding_planner() {
+        planner.start = None;
+        return;
+    }
+    if cursor.clicked() {
+        planner.start = Some(cursor.tile());
+    }
+    let Some(start) = planner.start else {
+        return;
+    };
+    let selection = Rect::from_corners(start.as_vec2(), cursor.tile().as_vec2()).inset(0.5);
+    let cancelling = cursor.cancelling();
+    gizmos.rect_2d(
+        selection.center(),
+        0.,
+        selection.size(),
+        if cancelling { CANCEL_COLOR } else { MARK_COLOR },
+    );
+    if !cursor.mouse_input.just_released(MouseButton::Left) {
+        return;
+    }
+
+    local_commands.push(if cancelling {
+        PlayerCommand::CancelDeconstruction {
+            min: selection.min,
+            max: selection.max,
+        }
+    } else {
+        PlayerCommand::MarkForDeconstruction {
+            min: selection.min,
+            max: selection.max,
+            filter: planner.filter.clone(),
+        }
+    });
+    planner.start = None;
+}
+
+/// Cross out the structures that are marked for deconstruction
+fn draw_deconstruction_marks(
+    mut gizmos: Gizmos,
+    marked_query: Query<(&Name, &Transform, &DiscreteRotation), With<MarkedForDeconstruction>>,
+    structures: Res<Structures>,
+) {
+    for (name, transform, rotation) in &marked_query {
+        let Some(structure) = structures.get(name.as_str()) else {
+            continue;
+        };
+        let rect = structure_rect(structure, transform.translation.xy(), rotation).inset(-0.1);
+        gizmos.line_2d(rect.min, rect.max, MARK_COLOR);
+        gizmos.line_2d(
+            Vec2::new(rect.min.x, rect.max.y),
+            Vec2::new(rect.max.x, rect.min.y),
+            MARK_COLOR,
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use bevy::{ecs::system::SystemParam, math::Vec3Swizzles, prelude::*};
+
+use kloonorio_core::{
+    construction_queue::{ConstructionGhost, ConstructionQueue, CONSTRUCTION_REACH},
+    deconstruction::mark_for_deconstruction,
+    discrete_rotation::DiscreteRotation,
+    inventory::Inventory,
+    player::LocalPlayer,
+    player_command::{
+        CommandSource, InventoryLocations, LocalCommands, PlayerCommand, ScheduledCommands,
+    },
+    simulation::{SimulationSet, SimulationTick},
+    structure::Structures,
+    types::{AppState, Building},
+};
+
+use crate::builder::placeable::{
+    create_structure_texture_atlas, place_structures, planned_ghost_sprite, structure_rect,
+    structure_transform, PlacementError, PlacementRules,
+};
+
+/// Seconds between the local player's attempts to build a ghost, a placement takes a few ticks
+/// to go through
+const BUILD_INTERVAL: f32 = 0.25;
+
+pub struct GhostPlugin;
+
+impl Plugin for GhostPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(GhostBuildTimer(Timer::from_seconds(
+            BUILD_INTERVAL,
+            TimerMode::Repeating,
+        )))
+        .add_systems(
+            FixedUpdate,
+            (remove_built_over_ghosts, place_ghosts)
+                .chain()
+                .after(place_structures)
+                .after(mark_for_deconstruction)
+                .in_set(SimulationSet::Commands),
+        )
+        .add_systems(
+            Update,
+            (
+                add_ghost_sprites,
+                build_ghosts.run_if(resource_equals(CommandSource::Input)),
+            )
+                .run_if(in_state(AppState::Running)),
+        );
+    }
+}
+
+#[derive(Resource, Debug)]
+struct GhostBuildTimer(Timer);
+
+/// Where structures can be placed, and the tiles covered by ghosts
+#[derive(SystemParam)]
+struct Footprints<'w, 's> {
+    rules: PlacementRules<'w, 's>,
+    ghost_query: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static ConstructionGhost,
+            &'static Transform,
+            &'static DiscreteRotation,
+        ),
+    >,
+}
+
+impl Footprints<'_, '_> {
+    fn ghost_rects(&self) -> impl Iterator<Item = (Entity, Rect)> + '_ {
+        self.ghost_query
+            .iter()
+            .filter_map(|(entity, ghost, transform, rotation)| {
+                self.rules
+                    .structures
+                    .get(&ghost.structure)
+                    .map(|structure| {
+                        (
+                            entity,
+                            structure_rect(structure, transform.translation.xy(), rotation),
+                        )
+                    })
+            })
+    }
+}
+
+/// Place the ghosts players planned on this tick wherever their structure could be placed and
+/// there is no other ghost. Players don't stand in the way, a ghost is built once its player
+/// moved off it. The ghosts are queued to be built in the order they were placed.
+fn place_ghosts(
+    mut commands: Commands,
+    tick: Res<SimulationTick>,
+    scheduled_commands: Res<ScheduledCommands>,
+    inventory_locations: InventoryLocations,
+    footprints: Footprints,
+    mut construction_queue: ResMut<ConstructionQueue>,
+) {
+    // Ghosts placed on this tick aren't spawned until the commands are applied
+    let mut ghost_rects: Vec<Rect> = footprints.ghost_rects().map(|(_, rect)| rect).collect();
+    for (player, command) in scheduled_commands.at(tick.0) {
+        let PlayerCommand::PlaceGhost {
+            structure,
+            position,
+            rotation,
+        } = command
+        else {
+            continue;
+        };
+        let Some(player) = inventory_locations.player(*player) else {
+            continue;
+        };
+        let Some(structure) = footprints.rules.structures.get(structure) else {
+            warn!(structure, "Unknown structure");
+            continue;
+        };
+        match footprints
+            .rules
+            .check(structure, *position, rotation, &ghost_rects)
+        {
+            Ok(()) | Err(PlacementError::Player) => {}
+            Err(error) => {
+                debug!(?structure, ?position, ?error, "Can't plan structure");
+                continue;
+            }
+        }
+
+        let ghost = commands
+            .spawn((
+                Name::new(structure.name.to_string() + " (planned)"),
+                *rotation,
+                structure_transform(*position, *rotation),
+                ConstructionGhost {
+                    player,
+                    structure: structure.name.clone(),
+                },
+            ))
+            .id();
+        construction_queue.push(ghost);
+        ghost_rects.push(structure_rect(structure, *position, rotation));
+    }
+}
+
+/// Ghosts are spawned by the simulation without a sprite, it's added once they are
+fn add_ghost_sprites(
+    mut commands: Commands,
+    ghost_query: Query<(Entity, &ConstructionGhost, &Transform), Added<ConstructionGhost>>,
+    structures: Res<Structures>,
+    asset_server: Res<AssetServer>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    for (entity, ghost, transform) in &ghost_query {
+        let Some(structure) = structures.get(&ghost.structure) else {
+            continue;
+        };
+        let texture_atlas_handle =
+            create_structure_texture_atlas(&asset_server, structure, &mut texture_atlases);
+        commands.entity(entity).insert(planned_ghost_sprite(
+            *transform,
+            texture_atlas_handle,
+            structure,
+        ));
+    }
+}
+
+/// A ghost is done once a structure is built over it, by its player or anyone else
+fn remove_built_over_ghosts(
+    mut commands: Commands,
+    new_building_query: Query<(&Name, &Transform, &DiscreteRotation), Added<Building>>,
+    footprints: Footprints,
+    mut construction_queue: ResMut<ConstructionQueue>,
+) {
+    for (name, transform, rotation) in &new_building_query {
+        let Some(structure) = footprints.rules.structures.get(name.as_str()) else {
+            continue;
+        };
+        let rect = structure_rect(structure, transform.translation.xy(), rotation);
+        for (ghost, _) in footprints
+            .ghost_rects()
+            .filter(|(_, other)| !rect.intersect(*other).is_empty())
+        {
+            commands.entity(ghost).despawn_recursive();
+            construction_queue.remove(ghost);
+        }
+    }
+}
+
+/// Have the local player build the first of their ghosts that is in reach and that they have the
+/// item for. The structure is placed like any other, taking the item from the inventory.
+fn build_ghosts(
+    time: Res<Time>,
+    mut timer: ResMut<GhostBuildTimer>,
+    player_query: Query<(Entity, &GlobalTransform, &Inventory), With<LocalPlayer>>,
+    ghost_query: Query<(&ConstructionGhost, &Transform, &DiscreteRotation)>,
+    construction_queue: Res<ConstructionQueue>,
+    mut local_commands: ResMut<LocalCommands>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+    let Ok((player, player_transform, inventory)) = player_query.get_single() else {
+        return;
+    };
+    let next = construction_queue
+        .iter()
+        .filter_map(|ghost| ghost_query.get(ghost).ok())
+        .find(|(ghost, transform, _)| {
+            ghost.player == player
+                && transform
+                    .translation
+                    .xy()
+                    .distance(player_transform.translation().xy())
+                    <= CONSTRUCTION_REACH
+                && inventory.find_item(&ghost.structure).is_some()
+        });
+    if let Some((ghost, transform, rotation)) = next {
+        local_commands.push(PlayerCommand::PlaceStructure {
+            structure: ghost.structure.clone(),
+            position: transform.translation.xy(),
+            rotation: *rotation,
+        });
+    }
+}
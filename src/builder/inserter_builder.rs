@@ -7,7 +7,7 @@ use bevy::{
         system::{Commands, Query, Res},
     },
     hierarchy::BuildChildren,
-    math::{Vec2, Vec3},
+    math::{Vec2, Vec3, Vec3Swizzles},
     prelude::default,
     transform::components::{GlobalTransform, Transform},
 };
@@ -15,7 +15,7 @@ use bevy::{
 use kloonorio_core::{
     discrete_rotation::{DiscreteRotation, SideCount},
     structure_components::inserter::{
-        inserter_dropoff_location, inserter_pickup_location, Inserter, InserterHand,
+        inserter_dropoff_location, inserter_pickup_location, Inserter, InserterFilter, InserterHand,
     },
     types::AppState,
 };
@@ -38,10 +38,28 @@ impl Plugin for InserterBuilderPlugin {
 pub struct InserterBuilder {
     speed: f32,
     capacity: u32,
+    reach: u32,
+    filter: bool,
 }
 impl InserterBuilder {
-    pub fn new(speed: f32, capacity: u32) -> Self {
-        InserterBuilder { speed, capacity }
+    pub fn new(speed: f32, capacity: u32, reach: u32) -> Self {
+        InserterBuilder {
+            speed,
+            capacity,
+            reach,
+            filter: false,
+        }
+    }
+
+    /// A filter inserter starts out with an empty whitelist, so it doesn't move anything until
+    /// the player sets a filter
+    pub fn new_filter(speed: f32, capacity: u32, reach: u32) -> Self {
+        InserterBuilder {
+            speed,
+            capacity,
+            reach,
+            filter: true,
+        }
     }
 }
 
@@ -55,8 +73,8 @@ fn build_inserter(
         let span = info_span!("Build inserter", inserter = ?inserter_entity);
         let _enter = span.enter();
 
-        let pickup_tile_location = inserter_pickup_location(transform);
-        let dropoff_tile_location = inserter_dropoff_location(transform);
+        let pickup_tile_location = inserter_pickup_location(transform, inserter_builder.reach);
+        let dropoff_tile_location = inserter_dropoff_location(transform, inserter_builder.reach);
 
         let pickup_tile_entity = terrain_params
             .tile_entity_at_global_pos(pickup_tile_location)
@@ -82,12 +100,19 @@ fn build_inserter(
             ))
             .id();
 
-        let inserter = Inserter::new(
+        let dropoff_direction = dropoff_tile_location - transform.translation().xy();
+
+        let mut inserter = Inserter::new(
             inserter_builder.speed,
             inserter_builder.capacity,
             pickup_tile_entity,
             dropoff_tile_entity,
-        );
+            dropoff_direction,
+        )
+        .with_reach(inserter_builder.reach);
+        if inserter_builder.filter {
+            inserter = inserter.with_filter(InserterFilter::default());
+        }
         commands
             .entity(inserter_entity)
             .add_child(inserter_hand_entity)
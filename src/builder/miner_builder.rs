@@ -64,7 +64,14 @@ fn build_miner(
             .tile_entity_at_global_pos(dropoff_tile_location)
             .unwrap();
 
-        let miner = Miner::new(miner_builder.speed, covered_tiles, dropoff_tile_entity);
+        let dropoff_direction = dropoff_tile_location - transform.translation().xy();
+
+        let miner = Miner::new(
+            miner_builder.speed,
+            covered_tiles,
+            dropoff_tile_entity,
+            dropoff_direction,
+        );
         commands
             .entity(miner_entity)
             .insert(miner)
@@ -1,12 +1,19 @@
 use bevy::{
-    app::{App, Plugin, Update},
+    app::{App, FixedUpdate, Plugin, Update},
     ecs::schedule::{common_conditions::in_state, IntoSystemConfigs},
 };
-use kloonorio_core::types::AppState;
+use kloonorio_core::{
+    player_command::apply_player_commands, simulation::SimulationSet, types::AppState,
+};
 
+pub mod blueprint;
+pub mod deconstruction_planner;
+pub mod ghost;
 pub mod inserter_builder;
 pub mod miner_builder;
 pub mod placeable;
+pub mod rotation;
+pub mod splitter_builder;
 pub mod transport_belt_builder;
 
 pub struct BuilderPlugin;
@@ -14,14 +21,23 @@ pub struct BuilderPlugin;
 impl Plugin for BuilderPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins((
+            ghost::GhostPlugin,
             inserter_builder::InserterBuilderPlugin,
             miner_builder::MinerBuilderPlugin,
+            rotation::RotationPlugin,
+            splitter_builder::SplitterBuilderPlugin,
             transport_belt_builder::TransportBeltBuilderPlugin,
         ))
         .add_systems(
             Update,
             (placeable::placeable, placeable::placeable_rotation)
                 .run_if(in_state(AppState::Running)),
+        )
+        .add_systems(
+            FixedUpdate,
+            placeable::place_structures
+                .after(apply_player_commands)
+                .in_set(SimulationSet::Commands),
         );
     }
 }
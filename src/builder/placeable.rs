@@ -1,39 +1,225 @@
-use bevy::{ecs::system::EntityCommands, math::Vec3Swizzles, prelude::*, utils::HashSet};
+use bevy::{
+    ecs::system::{EntityCommands, SystemParam},
+    math::Vec3Swizzles,
+    prelude::*,
+    utils::HashSet,
+};
 use bevy_rapier2d::prelude::*;
 use kloonorio_core::{
-    discrete_rotation::DiscreteRotation,
+    discrete_rotation::{CompassDirection, DiscreteRotation, SideCount},
     inventory::{Fuel, Inventory, Output, Source, Storage},
     item::Item,
-    structure::{Structure, Structures},
+    player::{LocalPlayer, SimulatedPosition, PLAYER_RADIUS},
+    player_command::{LocalCommands, PlayerCommand},
+    spawn_order::{SpawnCounter, SpawnOrder},
+    structure::{PlaceStructureEvent, Structure, Structures},
     structure_components::{
-        assembler::Assembler, burner::Burner, smelter::Smelter, StructureComponent,
+        assembler::{Assembler, PendingRecipe},
+        burner::Burner,
+        electricity::{ElectricConsumer, Generator, PowerPole},
+        fluid::{Boiler, FluidBox, OffshorePump, SteamEngine},
+        lab::Lab,
+        smelter::Smelter,
+        StructureComponent,
     },
+    tile_occupants::TileOccupants,
     types::{Building, CraftingQueue, Ghost},
+    undo::{ConstructionAction, ConstructionEvent},
 };
 use kloonorio_render::isometric_sprite::{IsometricSprite, IsometricSpriteBundle};
-use kloonorio_terrain::{CursorWorldPos, TILE_SIZE};
+use kloonorio_terrain::{CursorWorldPos, HoveredTile, TerrainParams, DEEP_WATER, TILE_SIZE, WATER};
 use kloonorio_ui::{inventory_grid::Hand, picker::Pickable, HoveringUI};
 
 use crate::{
     builder::{
-        inserter_builder::InserterBuilder, miner_builder::MinerBuilder,
-        transport_belt_builder::TransportBeltBuilder,
+        blueprint::BlueprintTool, inserter_builder::InserterBuilder, miner_builder::MinerBuilder,
+        splitter_builder::SplitterBuilder, transport_belt_builder::TransportBeltBuilder,
     },
     entity_tile_tracking::TileTracked,
     ysort::YSort,
 };
 
+/// Tint of ghosts that are planned rather than built right away
+pub const PLANNED_COLOR: Color = Color::rgba(0.5, 0.7, 1.0, 0.4);
+
+/// Why a structure can't be placed somewhere
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlacementError {
+    /// Another structure takes up some of its tiles
+    Occupied,
+    /// A player stands in the way
+    Player,
+    /// It has to be placed on water
+    NotOnWater,
+}
+
+/// Decides where structures can be placed. Only the simulation is looked at, so every client
+/// comes to the same decision for a command applied on the same tick.
+#[derive(SystemParam)]
+pub struct PlacementRules<'w, 's> {
+    building_query: Query<
+        'w,
+        's,
+        (&'static Name, &'static Transform, &'static DiscreteRotation),
+        With<Building>,
+    >,
+    player_query: Query<'w, 's, &'static SimulatedPosition>,
+    terrain_params: TerrainParams<'w, 's>,
+    pub structures: Res<'w, Structures>,
+}
+
+impl PlacementRules<'_, '_> {
+    /// Check a structure against the world and against `placed`, the structures placed earlier on
+    /// the same tick that aren't spawned yet
+    pub fn check(
+        &self,
+        structure: &Structure,
+        position: Vec2,
+        rotation: &DiscreteRotation,
+        placed: &[Rect],
+    ) -> Result<(), PlacementError> {
+        let rect = structure_rect(structure, position, rotation);
+        if self
+            .building_rects()
+            .chain(placed.iter().copied())
+            .any(|other| !rect.intersect(other).is_empty())
+        {
+            return Err(PlacementError::Occupied);
+        }
+
+        let on_water = self
+            .terrain_params
+            .tile_texture_at(position)
+            .is_some_and(|texture_id| matches!(texture_id, WATER | DEEP_WATER));
+        if structure.is_placed_on_water() && !on_water {
+            return Err(PlacementError::NotOnWater);
+        }
+
+        let collider = Rect::from_center_size(position, rotated_collider_size(structure, rotation));
+        let on_player = self.player_query.iter().any(|player| {
+            player
+                .0
+                .distance(player.0.clamp(collider.min, collider.max))
+                < PLAYER_RADIUS
+        });
+        if on_player {
+            return Err(PlacementError::Player);
+        }
+        Ok(())
+    }
+
+    pub fn building_rects(&self) -> impl Iterator<Item = Rect> + '_ {
+        self.building_query
+            .iter()
+            .filter_map(|(name, transform, rotation)| {
+                self.structures.get(name.as_str()).map(|structure| {
+                    structure_rect(structure, transform.translation.xy(), rotation)
+                })
+            })
+    }
+}
+
+/// The mouse and keys placing the structure in hand, and the line being dragged out with them
+#[derive(SystemParam)]
+pub struct PlacementInput<'w, 's> {
+    mouse_input: Res<'w, Input<MouseButton>>,
+    keys: Res<'w, Input<KeyCode>>,
+    line: Local<'s, Option<PlacementLine>>,
+}
+
+/// A line of structures placed by dragging the mouse from where it was pressed
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlacementLine {
+    structure: String,
+    /// Position of the last structure in the line
+    last: Vec2,
+    /// Way the line is heading, known once the cursor is a full structure away from the start
+    direction: Option<IVec2>,
+    /// Place ghosts instead of structures
+    planning: bool,
+}
+
+impl PlacementLine {
+    fn new(structure: &Structure, start: Vec2, planning: bool) -> Self {
+        Self {
+            structure: structure.name.clone(),
+            last: start,
+            direction: None,
+            planning,
+        }
+    }
+
+    /// Step the line toward the target one structure at a time. The line starts out along the
+    /// dominant axis, keeps its direction while the target is ahead and turns a corner once the
+    /// target is only off to a side. Returns the positions stepped onto with the direction of
+    /// each step.
+    fn extend(&mut self, target: Vec2, size: Vec2) -> Vec<(Vec2, IVec2)> {
+        let mut steps = vec![];
+        loop {
+            let delta = target - self.last;
+            let ahead = |direction: &IVec2| {
+                let direction = direction.as_vec2();
+                delta.dot(direction) >= (size * direction).length()
+            };
+            let direction = match self.direction {
+                Some(direction) => {
+                    let side = IVec2::new(-direction.y, direction.x);
+                    [direction, side, -side].into_iter().find(ahead)
+                }
+                None if delta.x.abs() >= delta.y.abs() => {
+                    Some(IVec2::new(delta.x.signum() as i32, 0)).filter(ahead)
+                }
+                None => Some(IVec2::new(0, delta.y.signum() as i32)).filter(ahead),
+            };
+            let Some(direction) = direction else {
+                break;
+            };
+            self.last += direction.as_vec2() * size;
+            self.direction = Some(direction);
+            steps.push((self.last, direction));
+        }
+        steps
+    }
+
+    fn command(&self, position: Vec2, rotation: DiscreteRotation) -> PlayerCommand {
+        if self.planning {
+            PlayerCommand::PlaceGhost {
+                structure: self.structure.clone(),
+                position,
+                rotation,
+            }
+        } else {
+            PlayerCommand::PlaceStructure {
+                structure: self.structure.clone(),
+                position,
+                rotation,
+            }
+        }
+    }
+}
+
+/// Rotation of a belt heading in a direction of a line, north being +Y
+fn belt_rotation(direction: IVec2) -> DiscreteRotation {
+    let mut rotation = DiscreteRotation::new(SideCount::Four);
+    rotation.set(match (direction.x, direction.y) {
+        (1, _) => CompassDirection::East,
+        (-1, _) => CompassDirection::West,
+        (_, -1) => CompassDirection::South,
+        _ => CompassDirection::North,
+    });
+    rotation
+}
+
 pub fn placeable(
     mut commands: Commands,
-    mut placeable_query: Query<(Entity, &mut Hand), Without<HoveringUI>>,
+    mut placeable_query: Query<(&mut Hand, &Inventory), (With<LocalPlayer>, Without<HoveringUI>)>,
     cursor_pos: Res<CursorWorldPos>,
-    mouse_input: Res<Input<MouseButton>>,
+    mut input: PlacementInput,
     ghosts: Query<Entity, With<Ghost>>,
     asset_server: Res<AssetServer>,
-    rapier_context: Res<RapierContext>,
     mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-    structures: Res<Structures>,
-    mut inventories_query: Query<&mut Inventory>,
+    rules: PlacementRules,
+    mut local_commands: ResMut<LocalCommands>,
 ) {
     let span = info_span!("Placeable");
     let _enter = span.enter();
@@ -42,92 +228,257 @@ pub fn placeable(
         commands.entity(ghost).despawn_recursive();
     }
 
-    for (hand_entity, mut hand) in &mut placeable_query {
-        let mut inventory = inventories_query.get_mut(hand_entity).unwrap();
-        if let Some(Some(stack)) = hand.get_item().map(|ih| inventory.slots[ih.slot].clone()) {
-            let structure_name = &*stack.item;
-            let Some(structure) = structures.get(structure_name) else {
-                continue;
-            };
-            let texture_atlas_handle =
-                create_structure_texture_atlas(&asset_server, structure, &mut texture_atlases);
+    for (mut hand, inventory) in &mut placeable_query {
+        let held = hand
+            .get_item()
+            .and_then(|ih| inventory.slots[ih.slot].as_ref())
+            .map(|stack| stack.item.to_string());
+        // Holding shift plans a ghost instead, without an item in hand that's all there is to do
+        let (structure_name, planning) = match held {
+            Some(item) => (
+                item,
+                input
+                    .keys
+                    .any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]),
+            ),
+            None => match hand.ghost.clone() {
+                Some(ghost) => (ghost, true),
+                None => continue,
+            },
+        };
+        let Some(structure) = rules.structures.get(&structure_name) else {
+            continue;
+        };
+        let texture_atlas_handle =
+            create_structure_texture_atlas(&asset_server, structure, &mut texture_atlases);
 
-            let translation = cursor_to_structure_position(&cursor_pos, structure);
-
-            let rotation = *hand
-                .rotation
-                .get_or_insert_with(|| DiscreteRotation::new(structure.sides.try_into().unwrap()));
-
-            if rapier_context
-                .intersection_with_shape(
-                    translation,
-                    0.,
-                    &structure_collider(structure),
-                    QueryFilter::new().exclude_sensors(),
-                )
-                .is_some()
-            {
-                spawn_structure_ghost(
-                    &mut commands,
-                    translation,
-                    rotation,
-                    texture_atlas_handle,
-                    Color::rgba(1.0, 0.3, 0.3, 0.5),
-                    structure,
-                );
-            } else if mouse_input.just_pressed(MouseButton::Left) {
-                if inventory.remove_items(&[(Item::new(structure.name.clone()), 1)]) {
-                    debug!("Placing {:?}", structure);
-                    place_structure(
-                        &mut commands,
-                        texture_atlas_handle.clone(),
-                        translation,
-                        rotation,
-                        structure,
-                    );
-                    if !inventory.has_items(&[(Item::new(structure.name.clone()), 1)]) {
-                        hand.clear();
-                    }
+        let rotation = *hand
+            .rotation
+            .get_or_insert_with(|| DiscreteRotation::new(structure.sides.try_into().unwrap()));
+
+        let translation = cursor_to_structure_position(&cursor_pos, structure, &rotation);
+
+        // The same rules the placement is checked against once it's applied
+        let blocked = |position: Vec2, rotation: DiscreteRotation| {
+            match rules.check(structure, position, &rotation, &[]) {
+                Ok(()) => false,
+                // Ghosts can be planned under a player, they are built once the player moves
+                Err(PlacementError::Player) => !planning,
+                Err(_) => true,
+            }
+        };
+        let mut place = |line: &PlacementLine, position: Vec2, rotation: DiscreteRotation| {
+            if !blocked(position, rotation) {
+                local_commands.push(line.command(position, rotation));
+            }
+        };
+
+        // Belts follow the line, each one is placed once the direction of the next is known
+        let is_belt = structure
+            .components
+            .iter()
+            .any(|component| matches!(component, StructureComponent::TransportBelt));
+        let line = &mut *input.line;
+        if line
+            .as_ref()
+            .is_some_and(|line| line.structure != structure.name)
+        {
+            *line = None;
+        }
+        if input.mouse_input.just_pressed(MouseButton::Left) {
+            let new_line = PlacementLine::new(structure, translation, planning);
+            if !is_belt {
+                place(&new_line, translation, rotation);
+            }
+            *line = Some(new_line);
+        }
+        let released = !input.mouse_input.pressed(MouseButton::Left);
+        if let Some(current) = line.as_mut() {
+            if released {
+                // The last belt keeps the direction of the line
+                if is_belt {
+                    place(current, current.last, rotation);
                 }
             } else {
-                spawn_structure_ghost(
-                    &mut commands,
-                    translation,
-                    rotation,
-                    texture_atlas_handle,
-                    Color::rgba(1.0, 1.0, 1.0, 0.5),
-                    structure,
-                );
+                let size = rotated_structure_size(structure, &rotation).as_vec2();
+                let mut previous = current.last;
+                for (position, direction) in current.extend(translation, size) {
+                    if is_belt {
+                        let rotation = belt_rotation(direction);
+                        place(current, previous, rotation);
+                        hand.rotation = Some(rotation);
+                    } else {
+                        place(current, position, rotation);
+                    }
+                    previous = position;
+                }
             }
         }
+        if released {
+            *line = None;
+        }
+
+        let color = if blocked(translation, rotation) {
+            Color::rgba(1.0, 0.3, 0.3, 0.5)
+        } else if planning {
+            PLANNED_COLOR
+        } else {
+            Color::rgba(1.0, 1.0, 1.0, 0.5)
+        };
+        spawn_structure_ghost(
+            &mut commands,
+            translation,
+            hand.rotation.unwrap_or(rotation),
+            texture_atlas_handle,
+            color,
+            structure,
+        );
     }
 }
 
+/// Rotate the structure in hand, or the hovered structure when there is none
 pub fn placeable_rotation(
     keys: Res<Input<KeyCode>>,
-    mut placeable_query: Query<(Entity, &mut Hand), Without<HoveringUI>>,
+    mut placeable_query: Query<
+        (&mut Hand, Option<&HoveredTile>),
+        (With<LocalPlayer>, Without<HoveringUI>),
+    >,
+    tile_occupants_query: Query<&TileOccupants>,
+    building_query: Query<&Transform, With<Building>>,
+    blueprint_tool: Option<Res<BlueprintTool>>,
+    mut local_commands: ResMut<LocalCommands>,
 ) {
-    if keys.just_pressed(KeyCode::R) {
-        if let Ok((_hand_entity, mut hand)) = placeable_query.get_single_mut() {
-            if let Some(rotation) = hand.rotation.as_mut() {
-                rotation.rotate();
-                debug!("Rotated to {:?}", hand.rotation);
+    if !keys.just_pressed(KeyCode::R) {
+        return;
+    }
+    let Ok((mut hand, hovered_tile)) = placeable_query.get_single_mut() else {
+        return;
+    };
+    if hand.get_item().is_some() || hand.ghost.is_some() {
+        if let Some(rotation) = hand.rotation.as_mut() {
+            rotation.rotate();
+            debug!("Rotated to {:?}", hand.rotation);
+        }
+        return;
+    }
+    // The blueprint being pasted is turned instead
+    if blueprint_tool.is_some_and(|tool| tool.is_pasting()) {
+        return;
+    }
+    let hovered_structure = hovered_tile
+        .and_then(|hovered_tile| tile_occupants_query.get(hovered_tile.entity).ok())
+        .and_then(|tile_occupants| {
+            tile_occupants
+                .iter()
+                .find_map(|occupant| building_query.get(*occupant).ok())
+        });
+    if let Some(transform) = hovered_structure {
+        local_commands.push(PlayerCommand::RotateStructure {
+            position: transform.translation.xy(),
+        });
+    }
+}
+
+/// Place the structures players asked for on this tick, each taken from the player's inventory.
+/// Whether a structure fits is checked by the placement rules rather than the colliders, which
+/// every client has at the same place on the same tick.
+pub fn place_structures(
+    mut commands: Commands,
+    mut place_structure_events: EventReader<PlaceStructureEvent>,
+    mut construction_events: EventWriter<ConstructionEvent>,
+    mut inventory_query: Query<&mut Inventory>,
+    rules: PlacementRules,
+    asset_server: Res<AssetServer>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut spawn_counter: ResMut<SpawnCounter>,
+) {
+    // Structures placed on this tick aren't spawned until the commands are applied
+    let mut placed: Vec<Rect> = vec![];
+    for PlaceStructureEvent { cause, placement } in place_structure_events.read() {
+        let (position, rotation) = (placement.position, placement.rotation);
+        let Some(structure) = rules.structures.get(&placement.structure) else {
+            warn!(structure = placement.structure, "Unknown structure");
+            continue;
+        };
+        if let Err(error) = rules.check(structure, position, &rotation, &placed) {
+            debug!(?structure, ?position, ?error, "Can't place structure");
+            continue;
+        }
+
+        let Ok(mut inventory) = inventory_query.get_mut(cause.player) else {
+            continue;
+        };
+        if inventory.remove_items(&[(Item::new(structure.name.clone()), 1)]) {
+            debug!("Placing {:?}", structure);
+            let texture_atlas_handle =
+                create_structure_texture_atlas(&asset_server, structure, &mut texture_atlases);
+            let entity = place_structure(
+                &mut commands,
+                texture_atlas_handle,
+                position,
+                rotation,
+                structure,
+                spawn_counter.next_order(),
+            );
+            if let Some(recipe) = &placement.recipe {
+                commands
+                    .entity(entity)
+                    .insert(PendingRecipe(recipe.clone()));
             }
+            construction_events.send(ConstructionEvent {
+                cause: *cause,
+                action: ConstructionAction::Place(placement.clone()),
+            });
+            placed.push(structure_rect(structure, position, &rotation));
         }
     }
 }
 
-fn cursor_to_structure_position(cursor_pos: &CursorWorldPos, structure: &Structure) -> Vec2 {
-    let min_corner: Vec2 = cursor_pos.0.xy() - (structure.size.as_vec2() / 2.0);
+fn cursor_to_structure_position(
+    cursor_pos: &CursorWorldPos,
+    structure: &Structure,
+    rotation: &DiscreteRotation,
+) -> Vec2 {
+    let size = rotated_structure_size(structure, rotation).as_vec2();
+    let min_corner: Vec2 = cursor_pos.0.xy() - (size / 2.0);
     let grid_fitted_min_corner = min_corner.ceil();
-    let structure_rect = Rect::from_corners(
-        grid_fitted_min_corner,
-        grid_fitted_min_corner + structure.size.as_vec2(),
-    );
+    let structure_rect = Rect::from_corners(grid_fitted_min_corner, grid_fitted_min_corner + size);
 
     structure_rect.center() - Vec2::splat(0.5)
 }
 
+/// The tiles a structure covers
+pub(crate) fn structure_rect(
+    structure: &Structure,
+    position: Vec2,
+    rotation: &DiscreteRotation,
+) -> Rect {
+    Rect::from_center_size(
+        position,
+        rotated_structure_size(structure, rotation).as_vec2(),
+    )
+}
+
+/// The size of a structure in tiles once rotated, structures facing east or west have their
+/// width and height swapped
+pub(crate) fn rotated_structure_size(structure: &Structure, rotation: &DiscreteRotation) -> IVec2 {
+    match rotation.compass_direction() {
+        CompassDirection::East | CompassDirection::West => {
+            IVec2::new(structure.size.y, structure.size.x)
+        }
+        _ => structure.size,
+    }
+}
+
+fn rotated_collider_size(structure: &Structure, rotation: &DiscreteRotation) -> Vec2 {
+    match rotation.compass_direction() {
+        CompassDirection::East | CompassDirection::West => {
+            Vec2::new(structure.collider.y, structure.collider.x)
+        }
+        _ => structure.collider,
+    }
+}
+
 pub fn create_structure_texture_atlas(
     asset_server: &Res<AssetServer>,
     structure: &Structure,
@@ -155,6 +506,10 @@ fn structure_texture_size(structure: &Structure) -> Vec2 {
     if structure.animated {
         // For animated structures, add 2 tiles to each dimension for the border
         (structure.size.as_vec2() + Vec2::new(2., 2.)) * Vec2::new(TILE_SIZE.x, TILE_SIZE.y)
+    } else if structure.sides > 1 {
+        // Sprites aren't rotated, so rotatable structures get square frames that fit every
+        // orientation
+        Vec2::splat(structure.size.max_element() as f32) * Vec2::new(TILE_SIZE.x, TILE_SIZE.y)
     } else {
         // For non-animated structures, just multiply the structure size by the tile size
         structure.size.as_vec2() * Vec2::new(TILE_SIZE.x, TILE_SIZE.y)
@@ -167,7 +522,8 @@ pub fn place_structure(
     translation: Vec2,
     rotation: DiscreteRotation,
     structure: &Structure,
-) {
+    spawn_order: SpawnOrder,
+) -> Entity {
     let mut structure_entity = spawn_structure_base(
         commands,
         structure.name.to_string(),
@@ -179,17 +535,25 @@ pub fn place_structure(
     );
     structure_entity.insert((
         Building,
+        spawn_order,
         Pickable,
         TileTracked,
         structure_collider(structure),
     ));
 
-    spawn_structure_components(&mut structure_entity, structure);
+    spawn_structure_components(
+        &mut structure_entity,
+        structure,
+        GlobalTransform::from(structure_transform(translation, rotation)),
+    );
+    structure_entity.id()
 }
 
 fn structure_sprite_size(structure: &Structure) -> Vec2 {
     if structure.animated {
         structure.size.as_vec2() + 2.
+    } else if structure.sides > 1 {
+        Vec2::splat(structure.size.max_element() as f32)
     } else {
         structure.size.as_vec2()
     }
@@ -219,6 +583,16 @@ pub fn spawn_structure_ghost(
     .insert(Ghost);
 }
 
+/// The sprite of a ghost that stays until its structure is built, unlike the preview under the
+/// cursor. The ghost itself is spawned by the simulation.
+pub fn planned_ghost_sprite(
+    transform: Transform,
+    texture_atlas_handle: Handle<TextureAtlas>,
+    structure: &Structure,
+) -> impl Bundle {
+    structure_sprite(transform, structure, texture_atlas_handle, PLANNED_COLOR)
+}
+
 fn spawn_structure_base<'w, 's, 'a>(
     commands: &'a mut Commands<'w, 's>,
     name: String,
@@ -228,15 +602,28 @@ fn spawn_structure_base<'w, 's, 'a>(
     texture_atlas_handle: Handle<TextureAtlas>,
     color: Color,
 ) -> EntityCommands<'w, 's, 'a> {
-    let structure_entity = commands.spawn((
+    let transform = structure_transform(translation, rotation);
+    commands.spawn((
         Name::new(name),
         rotation,
+        structure_sprite(transform, structure, texture_atlas_handle, color),
+    ))
+}
+
+fn structure_sprite(
+    transform: Transform,
+    structure: &Structure,
+    texture_atlas_handle: Handle<TextureAtlas>,
+    color: Color,
+) -> (YSort, IsometricSpriteBundle) {
+    (
         YSort { base_layer: 1. },
         IsometricSpriteBundle {
             texture_atlas: texture_atlas_handle,
-            transform: Transform::from_translation(translation.extend(1.))
-                .with_rotation(Quat::from_rotation_z(-rotation.radians())),
-
+            transform,
+            // Builders look up the tiles around new structures before the transforms are
+            // propagated
+            global_transform: GlobalTransform::from(transform),
             sprite: IsometricSprite {
                 color,
                 sides: structure.sides,
@@ -245,23 +632,34 @@ fn spawn_structure_base<'w, 's, 'a>(
             },
             ..default()
         },
-    ));
+    )
+}
 
-    structure_entity
+pub(crate) fn structure_transform(translation: Vec2, rotation: DiscreteRotation) -> Transform {
+    Transform::from_translation(translation.extend(1.))
+        .with_rotation(Quat::from_rotation_z(-rotation.radians()))
 }
 
-pub fn spawn_structure_components(entity_commands: &mut EntityCommands, structure: &Structure) {
+pub fn spawn_structure_components(
+    entity_commands: &mut EntityCommands,
+    structure: &Structure,
+    global_transform: GlobalTransform,
+) {
     let span = info_span!("spawn_components", structure = ?structure.name);
     let _enter = span.enter();
+    let child_transform = TransformBundle {
+        global: global_transform,
+        ..default()
+    };
     for component in &structure.components {
         match component {
-            StructureComponent::Smelter => {
+            StructureComponent::Smelter(categories) => {
                 debug!("Spawning smelter");
-                entity_commands.insert(Smelter);
+                entity_commands.insert(Smelter::new(categories.clone()));
             }
-            StructureComponent::Burner => {
+            StructureComponent::Burner(consumption) => {
                 debug!("Spawning burner");
-                entity_commands.insert(Burner::default());
+                entity_commands.insert(Burner::new(*consumption));
             }
             StructureComponent::CraftingQueue => {
                 debug!("Spawning crafting queue");
@@ -273,7 +671,7 @@ pub fn spawn_structure_components(entity_commands: &mut EntityCommands, structur
                     p.spawn((
                         Storage,
                         Inventory::new(*slots),
-                        TransformBundle::default(),
+                        child_transform,
                         Sensor,
                         structure_collider(structure),
                         TileTracked,
@@ -289,7 +687,7 @@ pub fn spawn_structure_components(entity_commands: &mut EntityCommands, structur
                             *slots,
                             filter.iter().map(|s| Item::new(s.clone())).collect(),
                         ),
-                        TransformBundle::default(),
+                        child_transform,
                         Sensor,
                         structure_collider(structure),
                         TileTracked,
@@ -302,7 +700,7 @@ pub fn spawn_structure_components(entity_commands: &mut EntityCommands, structur
                     p.spawn((
                         Output,
                         Inventory::new(*slots),
-                        TransformBundle::default(),
+                        child_transform,
                         Sensor,
                         structure_collider(structure),
                         TileTracked,
@@ -314,8 +712,9 @@ pub fn spawn_structure_components(entity_commands: &mut EntityCommands, structur
                 entity_commands.with_children(|p| {
                     p.spawn((
                         Fuel,
-                        Inventory::new_with_filter(*slots, HashSet::from_iter([Item::new("Coal")])),
-                        TransformBundle::default(),
+                        // The burner allows the fuel items once it's spawned
+                        Inventory::new_with_filter(*slots, HashSet::new()),
+                        child_transform,
                         Sensor,
                         structure_collider(structure),
                         TileTracked,
@@ -326,17 +725,74 @@ pub fn spawn_structure_components(entity_commands: &mut EntityCommands, structur
                 debug!("Spawning miner");
                 entity_commands.insert(MinerBuilder::new(*speed));
             }
-            StructureComponent::Inserter(speed, capacity) => {
+            StructureComponent::Inserter(speed, capacity, reach) => {
                 debug!("Spawning inserter");
-                entity_commands.insert(InserterBuilder::new(*speed, *capacity));
+                entity_commands.insert(InserterBuilder::new(*speed, *capacity, *reach));
+            }
+            StructureComponent::FilterInserter(speed, capacity, reach) => {
+                debug!("Spawning filter inserter");
+                entity_commands.insert(InserterBuilder::new_filter(*speed, *capacity, *reach));
             }
             StructureComponent::TransportBelt => {
                 debug!("Spawning transport belt");
-                entity_commands.insert(TransportBeltBuilder);
+                entity_commands.insert(TransportBeltBuilder::Belt);
             }
-            StructureComponent::Assembler => {
+            StructureComponent::UndergroundBelt(max_distance) => {
+                debug!("Spawning underground belt");
+                entity_commands.insert(TransportBeltBuilder::Underground {
+                    max_distance: *max_distance,
+                });
+            }
+            StructureComponent::Splitter => {
+                debug!("Spawning splitter");
+                entity_commands.insert(SplitterBuilder);
+            }
+            StructureComponent::Assembler(categories) => {
                 debug!("Spawning assembler");
-                entity_commands.insert(Assembler::default());
+                entity_commands.insert(Assembler::new(categories.clone()));
+            }
+            StructureComponent::PowerPole(wire_reach, supply_range) => {
+                debug!("Spawning power pole");
+                entity_commands.insert(PowerPole::new(*wire_reach, *supply_range));
+            }
+            StructureComponent::Generator(production) => {
+                debug!("Spawning generator");
+                entity_commands.insert(Generator::new(*production));
+            }
+            StructureComponent::ElectricConsumer(consumption) => {
+                debug!("Spawning electric consumer");
+                entity_commands.insert(ElectricConsumer::new(*consumption));
+            }
+            StructureComponent::FluidBox(capacity, filter, pipe_connections) => {
+                debug!("Spawning fluid box");
+                entity_commands.with_children(|p| {
+                    p.spawn((
+                        FluidBox::new(*capacity, filter.clone(), pipe_connections.clone()),
+                        child_transform,
+                    ));
+                });
+            }
+            StructureComponent::OffshorePump(pumping_speed) => {
+                debug!("Spawning offshore pump");
+                entity_commands.insert(OffshorePump {
+                    pumping_speed: *pumping_speed,
+                });
+            }
+            StructureComponent::Boiler(steam_per_second) => {
+                debug!("Spawning boiler");
+                entity_commands.insert(Boiler {
+                    steam_per_second: *steam_per_second,
+                });
+            }
+            StructureComponent::SteamEngine(steam_per_second) => {
+                debug!("Spawning steam engine");
+                entity_commands.insert(SteamEngine {
+                    steam_per_second: *steam_per_second,
+                });
+            }
+            StructureComponent::Lab(speed) => {
+                debug!("Spawning lab");
+                entity_commands.insert(Lab::new(*speed));
             }
         }
     }
@@ -345,6 +801,27 @@ pub fn spawn_structure_components(entity_commands: &mut EntityCommands, structur
 #[cfg(test)]
 mod test {
 
+    use bevy::ecs::system::SystemState;
+    use kloonorio_core::{
+        deconstruction::RemoveStructureEvent,
+        discrete_rotation::SideCount,
+        player::{Player, PlayerId},
+        player_command::{apply_player_commands, CommandSource, ScheduledCommands},
+        recipe::Recipes,
+        research::{QueueResearchEvent, ResearchQueue, Technologies},
+        simulation::SimulationTick,
+        structure::RotateStructureEvent,
+        structure_components::{
+            assembler::ChangeAssemblerRecipeEvent,
+            inserter::{inserter_pickup_location, ChangeInserterFilterEvent},
+            splitter::ChangeSplitterSettingsEvent,
+        },
+    };
+    use kloonorio_terrain::{
+        terrain_generator::{FlatChunkGenerator, TerrainGenerator},
+        Terrain, TerrainBundle, GROUND,
+    };
+
     use super::*;
 
     #[test]
@@ -359,11 +836,114 @@ mod test {
             animated: false,
         };
 
-        let result = cursor_to_structure_position(&cursor_pos, &structure);
+        let result = cursor_to_structure_position(
+            &cursor_pos,
+            &structure,
+            &DiscreteRotation::new(SideCount::One),
+        );
 
         assert_eq!(result, Vec2::ZERO);
     }
 
+    #[test]
+    fn cursor_to_structure_position_rotated_2x1() {
+        let cursor_pos = CursorWorldPos(Vec3::ZERO);
+        let structure = Structure {
+            name: "test".into(),
+            size: IVec2::new(2, 1),
+            sides: 4,
+            collider: Vec2::new(1.8, 0.9),
+            components: vec![],
+            animated: false,
+        };
+        let mut rotation = DiscreteRotation::new(SideCount::Four);
+
+        let result = cursor_to_structure_position(&cursor_pos, &structure, &rotation);
+        assert_eq!(result, Vec2::new(-0.5, 0.));
+
+        rotation.rotate();
+        let result = cursor_to_structure_position(&cursor_pos, &structure, &rotation);
+        assert_eq!(result, Vec2::new(0., -0.5));
+    }
+
+    #[test]
+    fn structure_rects_of_neighbours_dont_overlap() {
+        let structure = Structure {
+            name: "test".into(),
+            size: IVec2::new(2, 1),
+            sides: 4,
+            collider: Vec2::new(1.8, 0.9),
+            components: vec![],
+            animated: false,
+        };
+        let mut rotation = DiscreteRotation::new(SideCount::Four);
+        let rect = structure_rect(&structure, Vec2::new(-0.5, 0.), &rotation);
+        let neighbour = structure_rect(&structure, Vec2::new(1.5, 0.), &rotation);
+        assert!(rect.intersect(neighbour).is_empty());
+
+        rotation.rotate();
+        let rotated = structure_rect(&structure, Vec2::new(0., -0.5), &rotation);
+        assert!(!rect.intersect(rotated).is_empty());
+    }
+
+    #[test]
+    fn placement_line_follows_the_drag_around_corners() {
+        let structure = Structure {
+            name: "test".into(),
+            size: IVec2::new(1, 1),
+            sides: 4,
+            collider: Vec2::new(0.8, 0.9),
+            components: vec![],
+            animated: false,
+        };
+        let mut line = PlacementLine::new(&structure, Vec2::ZERO, false);
+
+        // Less than a structure away along the dominant axis doesn't step yet
+        assert!(line.extend(Vec2::new(0., 0.), Vec2::ONE).is_empty());
+        let steps = line.extend(Vec2::new(3., 1.), Vec2::ONE);
+        assert_eq!(
+            steps,
+            vec![
+                (Vec2::new(1., 0.), IVec2::X),
+                (Vec2::new(2., 0.), IVec2::X),
+                (Vec2::new(3., 0.), IVec2::X),
+                (Vec2::new(3., 1.), IVec2::Y),
+            ]
+        );
+        // The line doesn't double back on itself
+        assert!(line.extend(Vec2::new(3., -2.), Vec2::ONE).is_empty());
+        assert_eq!(
+            belt_rotation(IVec2::Y).compass_direction(),
+            CompassDirection::North
+        );
+        assert_eq!(
+            belt_rotation(-IVec2::X).compass_direction(),
+            CompassDirection::West
+        );
+    }
+
+    #[test]
+    fn placement_line_is_spaced_by_structure_size() {
+        let structure = Structure {
+            name: "test".into(),
+            size: IVec2::new(2, 2),
+            sides: 1,
+            collider: Vec2::new(1.8, 1.8),
+            components: vec![],
+            animated: false,
+        };
+        let mut line = PlacementLine::new(&structure, Vec2::splat(0.5), false);
+
+        let steps = line.extend(Vec2::new(0.5, -4.5), Vec2::splat(2.));
+        assert_eq!(
+            steps,
+            vec![
+                (Vec2::new(0.5, -1.5), -IVec2::Y),
+                (Vec2::new(0.5, -3.5), -IVec2::Y),
+            ]
+        );
+    }
+
     #[test]
     fn structure_texture_size_1x1() {
         let structure = Structure {
@@ -412,6 +992,22 @@ mod test {
         assert_eq!(result, Vec2::new(80., 80.));
     }
 
+    #[test]
+    fn structure_texture_size_rotatable_2x1() {
+        let structure = Structure {
+            name: "test".into(),
+            size: IVec2::new(2, 1),
+            sides: 4,
+            collider: Vec2::new(1.8, 0.9),
+            components: vec![],
+            animated: false,
+        };
+
+        let result = structure_texture_size(&structure);
+
+        assert_eq!(result, Vec2::new(32., 32.));
+    }
+
     #[test]
     fn structure_sprite_size_1x1() {
         let structure = Structure {
@@ -443,4 +1039,126 @@ mod test {
 
         assert_eq!(result, Vec2::new(5., 5.));
     }
+
+    #[test]
+    fn placed_inserter_picks_up_behind_itself() {
+        let mut app = App::new();
+        app.add_plugins((TaskPoolPlugin::default(), AssetPlugin::default()))
+            .init_asset::<Image>()
+            .init_asset::<TextureAtlas>()
+            .init_resource::<SimulationTick>()
+            .init_resource::<CommandSource>()
+            .init_resource::<ScheduledCommands>()
+            .init_resource::<Recipes>()
+            .init_resource::<ResearchQueue>()
+            .init_resource::<Technologies>()
+            .init_resource::<Structures>()
+            .init_resource::<SpawnCounter>()
+            .add_event::<ChangeAssemblerRecipeEvent>()
+            .add_event::<ChangeSplitterSettingsEvent>()
+            .add_event::<ChangeInserterFilterEvent>()
+            .add_event::<QueueResearchEvent>()
+            .add_event::<PlaceStructureEvent>()
+            .add_event::<RotateStructureEvent>()
+            .add_event::<RemoveStructureEvent>()
+            .add_event::<ConstructionEvent>()
+            .add_systems(Update, (apply_player_commands, place_structures).chain());
+        app.world.resource_mut::<Structures>().insert(
+            "Inserter".into(),
+            Structure {
+                name: "Inserter".into(),
+                size: IVec2::new(1, 1),
+                sides: 4,
+                collider: Vec2::new(0.9, 0.9),
+                components: vec![StructureComponent::Inserter(1., 1, 1)],
+                animated: false,
+            },
+        );
+        let mut inventory = Inventory::new(1);
+        inventory.add_item(&Item::new("Inserter"), 1);
+        app.world.spawn((Player, PlayerId(0), inventory));
+        let mut rotation = DiscreteRotation::new(SideCount::Four);
+        rotation.set(CompassDirection::East);
+        app.world.resource_mut::<ScheduledCommands>().schedule(
+            0,
+            [(
+                PlayerId(0),
+                PlayerCommand::PlaceStructure {
+                    structure: "Inserter".into(),
+                    position: Vec2::new(3., 4.),
+                    rotation,
+                },
+            )],
+        );
+        app.update();
+
+        // Transforms aren't propagated, the builder has to work with the ones set at spawn
+        let (_, transform) = app
+            .world
+            .query::<(&InserterBuilder, &GlobalTransform)>()
+            .single(&app.world);
+        let pickup = inserter_pickup_location(transform, 1);
+        assert!(pickup.abs_diff_eq(Vec2::new(3., 5.), 1e-5), "{pickup}");
+    }
+
+    #[test]
+    fn placement_rules_check_players_and_water() {
+        let mut app = App::new();
+        app.init_resource::<Structures>();
+        app.world.spawn(TerrainBundle {
+            generator: TerrainGenerator::new(Box::new(FlatChunkGenerator::new(GROUND))),
+            ..default()
+        });
+        app.world.spawn(SimulatedPosition(Vec2::new(5., 0.2)));
+        let chest = Structure {
+            name: "Wooden chest".into(),
+            size: IVec2::new(1, 1),
+            sides: 1,
+            collider: Vec2::new(0.9, 0.9),
+            components: vec![],
+            animated: false,
+        };
+        let pump = Structure {
+            name: "Offshore pump".into(),
+            components: vec![StructureComponent::OffshorePump(1.)],
+            ..chest.clone()
+        };
+        let rotation = DiscreteRotation::new(SideCount::One);
+        let mut system_state = SystemState::<PlacementRules>::new(&mut app.world);
+
+        let rules = system_state.get_mut(&mut app.world);
+        assert_eq!(rules.check(&chest, Vec2::ZERO, &rotation, &[]), Ok(()));
+        assert_eq!(
+            rules.check(
+                &chest,
+                Vec2::ZERO,
+                &rotation,
+                &[Rect::from_center_size(Vec2::ZERO, Vec2::ONE)]
+            ),
+            Err(PlacementError::Occupied)
+        );
+        assert_eq!(
+            rules.check(&chest, Vec2::new(5., 0.), &rotation, &[]),
+            Err(PlacementError::Player)
+        );
+        assert_eq!(
+            rules.check(&chest, Vec2::new(6., 0.), &rotation, &[]),
+            Ok(())
+        );
+        assert_eq!(
+            rules.check(&pump, Vec2::new(0., 3.), &rotation, &[]),
+            Err(PlacementError::NotOnWater)
+        );
+
+        app.world
+            .query::<&mut Terrain>()
+            .single_mut(&mut app.world)
+            .modified_tiles
+            .insert(IVec2::new(0, 3), WATER);
+        let rules = system_state.get_mut(&mut app.world);
+        assert_eq!(
+            rules.check(&pump, Vec2::new(0., 3.), &rotation, &[]),
+            Ok(())
+        );
+    }
 }
@@ -0,0 +1,123 @@
+use bevy::{math::Vec3Swizzles, prelude::*};
+use kloonorio_core::{
+    deconstruction::BeltLinks,
+    discrete_rotation::DiscreteRotation,
+    player_command::apply_player_commands,
+    simulation::SimulationSet,
+    structure::RotateStructureEvent,
+    structure_components::{
+        inserter::{inserter_dropoff_location, inserter_pickup_location, Inserter},
+        transport_belt::{TransportBelt, UndergroundBelt},
+    },
+    undo::{ConstructionAction, ConstructionEvent},
+};
+use kloonorio_terrain::TerrainParams;
+
+use crate::builder::{
+    inserter_builder::InserterBuilder, placeable::structure_transform,
+    transport_belt_builder::TransportBeltBuilder,
+};
+
+pub struct RotationPlugin;
+
+impl Plugin for RotationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            rotate_structures
+                .after(apply_player_commands)
+                .in_set(SimulationSet::Commands),
+        );
+    }
+}
+
+/// A structure along with the parts of it that depend on the way it faces
+type RotatedStructureQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut Transform,
+        &'static mut DiscreteRotation,
+        Option<&'static mut Inserter>,
+        Has<InserterBuilder>,
+        Has<TransportBelt>,
+        Has<UndergroundBelt>,
+        Option<&'static TransportBeltBuilder>,
+    ),
+>;
+
+/// Rotate the structures players asked for on this tick. Only inserters and belts can be rotated,
+/// other structures only work out what they are connected to when they are built.
+fn rotate_structures(
+    mut commands: Commands,
+    mut rotate_structure_events: EventReader<RotateStructureEvent>,
+    mut construction_events: EventWriter<ConstructionEvent>,
+    mut structure_query: RotatedStructureQuery,
+    mut belt_links: BeltLinks,
+    terrain_params: TerrainParams,
+) {
+    for RotateStructureEvent {
+        cause,
+        structure,
+        rotation,
+    } in rotate_structure_events.read()
+    {
+        let Ok((
+            mut transform,
+            mut current_rotation,
+            inserter,
+            is_inserter_builder,
+            is_belt,
+            is_underground_belt,
+            belt_builder,
+        )) = structure_query.get_mut(*structure)
+        else {
+            continue;
+        };
+        let is_plain_belt = (is_belt && !is_underground_belt)
+            || matches!(belt_builder, Some(TransportBeltBuilder::Belt));
+        if inserter.is_none() && !is_inserter_builder && !is_plain_belt {
+            debug!(?structure, "Structure can't be rotated");
+            continue;
+        }
+        if *current_rotation == *rotation {
+            continue;
+        }
+
+        let position = transform.translation.xy();
+        let rotated_transform = structure_transform(position, *rotation);
+        let global_transform = GlobalTransform::from(rotated_transform);
+        if let Some(mut inserter) = inserter {
+            let pickup_location = inserter_pickup_location(&global_transform, inserter.reach());
+            let dropoff_location = inserter_dropoff_location(&global_transform, inserter.reach());
+            let (Some(pickup_tile), Some(dropoff_tile)) = (
+                terrain_params.tile_entity_at_global_pos(pickup_location),
+                terrain_params.tile_entity_at_global_pos(dropoff_location),
+            ) else {
+                debug!(?structure, "Tiles around the inserter aren't spawned");
+                continue;
+            };
+            inserter.retarget(pickup_tile, dropoff_tile, dropoff_location - position);
+        }
+        if is_belt && !is_underground_belt {
+            // The belt is linked again by its builder, like a belt that was just placed
+            belt_links.unlink(*structure);
+            commands
+                .entity(*structure)
+                .insert(TransportBeltBuilder::Belt);
+        }
+
+        construction_events.send(ConstructionEvent {
+            cause: *cause,
+            action: ConstructionAction::Rotate {
+                position,
+                from: *current_rotation,
+                to: *rotation,
+            },
+        });
+        *current_rotation = *rotation;
+        *transform = rotated_transform;
+        // Builders look up the tiles around the structure before the transforms are propagated
+        commands.entity(*structure).insert(global_transform);
+    }
+}
@@ -0,0 +1,117 @@
+use bevy::{
+    app::{App, Plugin, Update},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        query::With,
+        schedule::{common_conditions::in_state, IntoSystemConfigs},
+        system::{Commands, Query},
+    },
+    math::{Vec2, Vec3Swizzles},
+    reflect::Reflect,
+    transform::components::GlobalTransform,
+};
+use kloonorio_core::{
+    discrete_rotation::DiscreteRotation,
+    entity_set::EntitySet,
+    structure_components::{
+        splitter::{splitter_half_position, Splitter, SplitterSide},
+        transport_belt::{
+            NextBelt, PreviousBelts, TransportBelt, UndergroundBelt, UndergroundBeltKind,
+        },
+    },
+    tile_occupants::TileOccupants,
+    types::AppState,
+};
+use kloonorio_terrain::TerrainParams;
+
+pub struct SplitterBuilderPlugin;
+
+impl Plugin for SplitterBuilderPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<SplitterBuilder>()
+            .add_systems(Update, build_splitter.run_if(in_state(AppState::Running)));
+    }
+}
+
+#[derive(Component, Debug, Reflect)]
+pub struct SplitterBuilder;
+
+fn build_splitter(
+    mut commands: Commands,
+    splitter_builder_query: Query<
+        (Entity, &GlobalTransform, &DiscreteRotation),
+        With<SplitterBuilder>,
+    >,
+    transport_belt_query: Query<(&DiscreteRotation, Option<&UndergroundBelt>), With<TransportBelt>>,
+    tile_occupants_query: Query<&TileOccupants>,
+    terrain_params: TerrainParams,
+    mut previous_belts_query: Query<&mut PreviousBelts>,
+) {
+    for (splitter_entity, transform, rotation) in &splitter_builder_query {
+        let forward = rotation.forward();
+        let mut splitter = Splitter::default();
+        let mut input_belts = EntitySet::new();
+
+        let belt_at = |tile_pos: Vec2| {
+            terrain_params
+                .tile_entity_at_global_pos(tile_pos)
+                .and_then(|tile| tile_occupants_query.get(tile).ok())
+                .and_then(|tile_occupants| {
+                    tile_occupants.iter().find_map(|occupant| {
+                        transport_belt_query.get(*occupant).ok().map(
+                            |(belt_rotation, underground_belt)| {
+                                (
+                                    *occupant,
+                                    belt_rotation.forward(),
+                                    underground_belt.map(|u| u.kind),
+                                )
+                            },
+                        )
+                    })
+                })
+        };
+
+        for side in SplitterSide::ALL {
+            let half_pos = splitter_half_position(transform.translation().xy(), rotation, side);
+
+            // Belts behind this half facing the same way feed into the splitter, unless they
+            // are underground belt entrances
+            if let Some((input_belt, _, _)) =
+                belt_at(half_pos - forward).filter(|(_, belt_forward, underground_kind)| {
+                    belt_forward.dot(forward) > 0.5
+                        && *underground_kind != Some(UndergroundBeltKind::Entrance)
+                })
+            {
+                commands
+                    .entity(input_belt)
+                    .insert(NextBelt(splitter_entity));
+                input_belts.insert(input_belt);
+            }
+
+            // Belts in front of this half that don't face back into the splitter take its
+            // output, except for underground belt exits which only take items from their
+            // entrance
+            if let Some((output_belt, _, _)) =
+                belt_at(half_pos + forward).filter(|(_, belt_forward, underground_kind)| {
+                    belt_forward.dot(forward) > -0.5
+                        && *underground_kind != Some(UndergroundBeltKind::Exit)
+                })
+            {
+                splitter.set_output(side, Some(output_belt));
+                previous_belts_query
+                    .get_mut(output_belt)
+                    .unwrap_or_else(|_| {
+                        panic!("Expected {:?} to have PreviousBelts component", output_belt)
+                    })
+                    .belts
+                    .insert(splitter_entity);
+            }
+        }
+
+        commands
+            .entity(splitter_entity)
+            .insert((splitter, PreviousBelts { belts: input_belts }))
+            .remove::<SplitterBuilder>();
+    }
+}
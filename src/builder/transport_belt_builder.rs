@@ -12,7 +12,12 @@ use bevy::{
 };
 use kloonorio_core::{
     discrete_rotation::{CompassDirection, DiscreteRotation},
-    structure_components::transport_belt::{NextBelt, PreviousBelts, TransportBelt},
+    structure_components::{
+        splitter::{splitter_half_position, Splitter, SplitterSide},
+        transport_belt::{
+            NextBelt, PreviousBelts, TransportBelt, UndergroundBelt, UndergroundBeltKind,
+        },
+    },
     tile_occupants::TileOccupants,
     types::AppState,
 };
@@ -30,7 +35,14 @@ impl Plugin for TransportBeltBuilderPlugin {
 }
 
 #[derive(Component, Debug, Reflect)]
-pub struct TransportBeltBuilder;
+pub enum TransportBeltBuilder {
+    Belt,
+    /// Becomes the exit of an unpaired entrance behind it within `max_distance` tiles, or an
+    /// entrance if there is none
+    Underground {
+        max_distance: u32,
+    },
+}
 
 fn build_transport_belt(
     mut commands: Commands,
@@ -40,10 +52,61 @@ fn build_transport_belt(
     discrete_rotation_query: Query<&DiscreteRotation>,
     terrain_params: TerrainParams,
     mut previous_belts_query: Query<&mut PreviousBelts>,
+    mut splitter_query: Query<(&mut Splitter, &GlobalTransform, &DiscreteRotation)>,
+    mut underground_belt_query: Query<(&mut UndergroundBelt, &DiscreteRotation)>,
 ) {
-    for (transport_belt_entity, _transport_belt_builder, transform) in &transport_belt_builder_query
+    for (transport_belt_entity, transport_belt_builder, transform) in &transport_belt_builder_query
     {
-        // Find the next belt in the direction this belt is facing
+        let forward = discrete_rotation_query
+            .get(transport_belt_entity)
+            .unwrap()
+            .forward();
+
+        let mut previous_belts = Vec::new();
+
+        let underground_belt = match transport_belt_builder {
+            TransportBeltBuilder::Belt => None,
+            TransportBeltBuilder::Underground { max_distance } => {
+                let entrance = find_underground_entrance(
+                    transform.translation().xy(),
+                    forward,
+                    *max_distance,
+                    &terrain_params,
+                    &tile_occupants_query,
+                    &underground_belt_query,
+                );
+                if let Some(entrance) = entrance {
+                    // Pair up with the entrance, items skip the tiles in between
+                    underground_belt_query.get_mut(entrance).unwrap().0.partner =
+                        Some(transport_belt_entity);
+                    commands
+                        .entity(entrance)
+                        .insert(NextBelt(transport_belt_entity));
+                    previous_belts.push(entrance);
+                    Some(UndergroundBelt {
+                        kind: UndergroundBeltKind::Exit,
+                        max_distance: *max_distance,
+                        partner: Some(entrance),
+                    })
+                } else {
+                    Some(UndergroundBelt {
+                        kind: UndergroundBeltKind::Entrance,
+                        max_distance: *max_distance,
+                        partner: None,
+                    })
+                }
+            }
+        };
+        let is_entrance = underground_belt
+            .as_ref()
+            .map_or(false, |underground_belt| underground_belt.is_entrance());
+        let is_exit = underground_belt
+            .as_ref()
+            .map_or(false, |underground_belt| underground_belt.is_exit());
+
+        // Find the next belt in the direction this belt is facing, or a splitter facing the same
+        // way that this belt feeds into. Entrances send their items to their exit instead, and
+        // exits only take items from their entrance.
         let next_tile_pos = transform.transform_point(Vec3::new(0., 1., 0.));
         let next_belt_entity = terrain_params
             .tile_entity_at_global_pos(next_tile_pos.xy())
@@ -51,9 +114,22 @@ fn build_transport_belt(
             .and_then(|tile_occupants| {
                 tile_occupants
                     .iter()
-                    .find(|occupant| transport_belt_query.contains(**occupant))
+                    .find(|occupant| {
+                        let is_belt = transport_belt_query.contains(**occupant)
+                            && !underground_belt_query
+                                .get(**occupant)
+                                .map_or(false, |(underground_belt, _)| underground_belt.is_exit());
+                        let is_splitter = splitter_query.get(**occupant).map_or(
+                            false,
+                            |(_, _, splitter_rotation)| {
+                                splitter_rotation.forward().dot(forward) > 0.5
+                            },
+                        );
+                        is_belt || is_splitter
+                    })
                     .copied()
-            });
+            })
+            .filter(|_| !is_entrance);
 
         if let Some(next_belt_entity) = next_belt_entity {
             // Add the next belt component
@@ -74,8 +150,6 @@ fn build_transport_belt(
         }
 
         // Find belts to the north, east, south, and west of this belt
-        let mut previous_belts = Vec::new();
-
         let north_tile_pos = transform.translation().xy() + Vec2::new(0., 1.);
         let dir_from_north = CompassDirection::South;
         let east_tile_pos = transform.translation().xy() + Vec2::new(1., 0.);
@@ -84,12 +158,18 @@ fn build_transport_belt(
         let dir_from_south = CompassDirection::North;
         let west_tile_pos = transform.translation().xy() + Vec2::new(-1., 0.);
         let dir_from_west = CompassDirection::East;
-        for (tile_pos, dir_from) in &[
-            (north_tile_pos, dir_from_north),
-            (east_tile_pos, dir_from_east),
-            (south_tile_pos, dir_from_south),
-            (west_tile_pos, dir_from_west),
-        ] {
+        let neighbours = if is_exit {
+            // Exits only take items from their entrance
+            vec![]
+        } else {
+            vec![
+                (north_tile_pos, dir_from_north),
+                (east_tile_pos, dir_from_east),
+                (south_tile_pos, dir_from_south),
+                (west_tile_pos, dir_from_west),
+            ]
+        };
+        for (tile_pos, dir_from) in &neighbours {
             if let Some(other_belt_entity) = terrain_params
                 .tile_entity_at_global_pos(*tile_pos)
                 .and_then(|tile| tile_occupants_query.get(tile).ok())
@@ -99,6 +179,14 @@ fn build_transport_belt(
                         .find(|occupant| transport_belt_query.contains(**occupant))
                         .copied()
                 })
+                .filter(|other_belt_entity| {
+                    // Entrances send their items underground
+                    !underground_belt_query
+                        .get(*other_belt_entity)
+                        .map_or(false, |(underground_belt, _)| {
+                            underground_belt.is_entrance()
+                        })
+                })
             {
                 let other_belt_dir = discrete_rotation_query
                     .get(other_belt_entity)
@@ -119,15 +207,91 @@ fn build_transport_belt(
             }
         }
 
-        let belt = TransportBelt::default();
-        commands
-            .entity(transport_belt_entity)
-            .insert((
-                belt,
-                PreviousBelts {
-                    belts: previous_belts.iter().copied().collect(),
-                },
-            ))
+        // Splitters behind this belt put their output on it
+        let belt_pos = transform.translation().xy();
+        for (tile_pos, _) in &neighbours {
+            let Some(splitter_entity) = terrain_params
+                .tile_entity_at_global_pos(*tile_pos)
+                .and_then(|tile| tile_occupants_query.get(tile).ok())
+                .and_then(|tile_occupants| {
+                    tile_occupants
+                        .iter()
+                        .find(|occupant| splitter_query.contains(**occupant))
+                        .copied()
+                })
+            else {
+                continue;
+            };
+            let (mut splitter, splitter_transform, splitter_rotation) =
+                splitter_query.get_mut(splitter_entity).unwrap();
+            let splitter_forward = splitter_rotation.forward();
+            if splitter_forward.dot(forward) < -0.5 {
+                // Belt faces back into the splitter
+                continue;
+            }
+            let output_side = SplitterSide::ALL.into_iter().find(|side| {
+                let half_pos = splitter_half_position(
+                    splitter_transform.translation().xy(),
+                    splitter_rotation,
+                    *side,
+                );
+                (half_pos + splitter_forward).distance(belt_pos) < 0.5
+            });
+            if let Some(output_side) = output_side {
+                splitter.set_output(output_side, Some(transport_belt_entity));
+                previous_belts.push(splitter_entity);
+            }
+        }
+
+        let mut belt_commands = commands.entity(transport_belt_entity);
+        belt_commands
+            .insert(PreviousBelts {
+                belts: previous_belts.iter().copied().collect(),
+            })
             .remove::<TransportBeltBuilder>();
+        // A rotated belt is built again, it stays in its segment until the segment is rebuilt
+        if !transport_belt_query.contains(transport_belt_entity) {
+            belt_commands.insert(TransportBelt::default());
+        }
+        if let Some(underground_belt) = underground_belt {
+            belt_commands.insert(underground_belt);
+        }
     }
 }
+
+/// Find an unpaired underground belt entrance behind `position` that faces the same way. Only the
+/// closest underground belt facing that way is considered, so pairs can't cross each other.
+fn find_underground_entrance(
+    position: Vec2,
+    forward: Vec2,
+    max_distance: u32,
+    terrain_params: &TerrainParams,
+    tile_occupants_query: &Query<&TileOccupants>,
+    underground_belt_query: &Query<(&mut UndergroundBelt, &DiscreteRotation)>,
+) -> Option<Entity> {
+    (1..=max_distance)
+        .find_map(|distance| {
+            let tile_pos = position - forward * distance as f32;
+            terrain_params
+                .tile_entity_at_global_pos(tile_pos)
+                .and_then(|tile| tile_occupants_query.get(tile).ok())
+                .and_then(|tile_occupants| {
+                    tile_occupants
+                        .iter()
+                        .find(|occupant| {
+                            underground_belt_query
+                                .get(**occupant)
+                                .map_or(false, |(_, rotation)| {
+                                    rotation.forward().dot(forward) > 0.5
+                                })
+                        })
+                        .copied()
+                })
+        })
+        .filter(|underground_belt_entity| {
+            let (underground_belt, _) = underground_belt_query
+                .get(*underground_belt_entity)
+                .unwrap();
+            underground_belt.is_entrance() && underground_belt.partner.is_none()
+        })
+}
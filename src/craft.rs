@@ -1,23 +1,24 @@
 use bevy::{
-    app::{App, Plugin, Update},
+    app::{App, FixedUpdate, Plugin},
     ecs::{
         query::With,
-        schedule::{common_conditions::in_state, IntoSystemConfigs},
+        schedule::IntoSystemConfigs,
         system::{Query, Res},
     },
     time::Time,
 };
 use kloonorio_core::{
-    inventory::Inventory,
-    player::Player,
-    types::{AppState, CraftingQueue},
+    inventory::Inventory, player::Player, simulation::SimulationSet, types::CraftingQueue,
 };
 
 pub struct CraftPlugin;
 
 impl Plugin for CraftPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, craft_ticker.run_if(in_state(AppState::Running)));
+        app.add_systems(
+            FixedUpdate,
+            craft_ticker.in_set(SimulationSet::PlayerCrafting),
+        );
     }
 }
 
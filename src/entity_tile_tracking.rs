@@ -1,20 +1,24 @@
+use std::collections::BTreeMap;
+
 use bevy::{
     app::{App, Plugin, Update},
     ecs::{
-        component::Component,
         entity::Entity,
         query::{Added, Changed, Or, With},
         removal_detection::RemovedComponents,
         schedule::{IntoSystemConfigs, SystemSet},
         system::{Commands, Query},
     },
-    math::{Vec2, Vec3Swizzles},
+    math::{Quat, Vec2, Vec3, Vec3Swizzles},
     transform::components::GlobalTransform,
-    utils::{HashMap, HashSet},
 };
 
 use bevy_rapier2d::geometry::Collider;
-use kloonorio_core::tile_occupants::{EntityOnTiles, TileOccupants};
+pub use kloonorio_core::tile_occupants::TileTracked;
+use kloonorio_core::{
+    entity_set::EntitySet,
+    tile_occupants::{EntityOnTiles, TileOccupants},
+};
 use kloonorio_terrain::TerrainParams;
 
 pub struct EntityTileTrackingPlugin;
@@ -31,9 +35,6 @@ impl Plugin for EntityTileTrackingPlugin {
 #[derive(SystemSet, Default, Hash, PartialEq, Eq, Debug, Clone)]
 pub struct EntityTileTrackingSet;
 
-#[derive(Component)]
-pub struct TileTracked;
-
 fn update_entity_on_tile_system(
     mut commands: Commands,
     mut query: Query<
@@ -51,16 +52,18 @@ fn update_entity_on_tile_system(
     tile_occupants_query: Query<(Entity, &TileOccupants)>,
     terrain_params: TerrainParams,
 ) {
-    let mut tile_occupants: HashMap<Entity, HashSet<Entity>> = tile_occupants_query
+    let mut tile_occupants: BTreeMap<Entity, EntitySet> = tile_occupants_query
         .iter()
         .map(|(tile_entity, occupants)| (tile_entity, occupants.iter().cloned().collect()))
         .collect();
     for (mut entity_on_tile, global_transform, entity, opt_collider) in query.iter_mut() {
         // Find the new tiles the entity is on
-        let entity_position = global_transform.translation().xy();
+        let (_, entity_rotation, entity_translation) =
+            global_transform.to_scale_rotation_translation();
+        let entity_position = entity_translation.xy();
         let entity_tile_positions = {
             if let Some(collider) = opt_collider {
-                get_covered_tiles_for_collider(collider, entity_position)
+                get_covered_tiles_for_collider(collider, entity_position, entity_rotation)
             } else {
                 vec![entity_position]
             }
@@ -102,12 +105,25 @@ fn update_entity_on_tile_system(
     }
 }
 
-fn get_covered_tiles_for_collider(collider: &Collider, entity_position: Vec2) -> Vec<Vec2> {
-    let aabb = collider.raw.compute_aabb(&entity_position.into());
-    let min_x = aabb.mins.x.round() as i32;
-    let min_y = aabb.mins.y.round() as i32;
-    let max_x = aabb.maxs.x.round() as i32;
-    let max_y = aabb.maxs.y.round() as i32;
+fn get_covered_tiles_for_collider(
+    collider: &Collider,
+    entity_position: Vec2,
+    entity_rotation: Quat,
+) -> Vec<Vec2> {
+    // Rotate the corners of the local bounding box, so rotated structures that aren't square
+    // cover the right tiles
+    let (mins, maxs) = collider.raw.compute_local_aabb().vertices().iter().fold(
+        (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+        |(mins, maxs), vertex| {
+            let corner =
+                entity_position + (entity_rotation * Vec3::new(vertex.x, vertex.y, 0.)).xy();
+            (mins.min(corner), maxs.max(corner))
+        },
+    );
+    let min_x = mins.x.round() as i32;
+    let min_y = mins.y.round() as i32;
+    let max_x = maxs.x.round() as i32;
+    let max_y = maxs.y.round() as i32;
     let mut tiles = Vec::new();
     for x in min_x..=max_x {
         for y in min_y..=max_y {
@@ -119,7 +135,7 @@ fn get_covered_tiles_for_collider(collider: &Collider, entity_position: Vec2) ->
 }
 
 fn add_to_tile_occupants(
-    tile_occupants: &mut HashMap<Entity, HashSet<Entity>>,
+    tile_occupants: &mut BTreeMap<Entity, EntitySet>,
     tile_entity: Entity,
     entity: Entity,
 ) {
@@ -130,7 +146,7 @@ fn add_to_tile_occupants(
 }
 
 fn remove_from_tile_occupants(
-    tile_occupants: &mut HashMap<Entity, HashSet<Entity>>,
+    tile_occupants: &mut BTreeMap<Entity, EntitySet>,
     tile_entity: Entity,
     entity: Entity,
 ) {
@@ -153,7 +169,10 @@ fn tile_tracker_removed(
                 }
             }
         }
-        commands.entity(entity).remove::<EntityOnTiles>();
+        // Despawned entities are taken off their tiles by whatever despawned them
+        if let Some(mut entity_commands) = commands.get_entity(entity) {
+            entity_commands.remove::<EntityOnTiles>();
+        }
     }
 }
 
@@ -164,7 +183,7 @@ mod test {
     use super::*;
     use bevy::{
         app::Update,
-        ecs::system::SystemState,
+        ecs::{component::Component, system::SystemState},
         math::IVec2,
         transform::{components::Transform, TransformPlugin},
     };
@@ -272,7 +291,7 @@ mod test {
         ) {
             let collider = Collider::cuboid(0.4, 0.4);
             for pos in collider_positions {
-                let covered_tiles = get_covered_tiles_for_collider(&collider, pos.as_vec2());
+                let covered_tiles = get_covered_tiles_for_collider(&collider, pos.as_vec2(), Quat::IDENTITY);
                 assert_eq!(covered_tiles.len(), 1, "Collider should cover one tile");
                 assert_eq!(covered_tiles[0], pos.as_vec2(), "Collider should cover the tile it is in");
             }
@@ -290,7 +309,7 @@ mod test {
             let collider = Collider::cuboid(0.9, 0.9);
             for pos in collider_positions {
                 let collider_pos = pos.as_vec2() - Vec2::new(0.5, 0.5);
-                let covered_tiles = get_covered_tiles_for_collider(&collider, collider_pos);
+                let covered_tiles = get_covered_tiles_for_collider(&collider, collider_pos, Quat::IDENTITY);
                 assert_eq!(covered_tiles.len(), 4, "Collider should cover 4 tiles");
                 let min_x = collider_pos.x.floor() as i32;
                 let min_y = collider_pos.y.floor() as i32;
@@ -316,7 +335,7 @@ mod test {
         ) {
             let collider = Collider::cuboid(1.35, 1.35);
             for pos in collider_positions {
-                let covered_tiles = get_covered_tiles_for_collider(&collider, pos.as_vec2());
+                let covered_tiles = get_covered_tiles_for_collider(&collider, pos.as_vec2(), Quat::IDENTITY);
                 assert_eq!(covered_tiles.len(), 9, "Collider should cover 9 tiles");
                 for x in -1..=1 {
                     for y in -1..=1 {
@@ -328,6 +347,21 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_get_covered_tiles_for_rotated_collider_2x1() {
+        let collider = Collider::cuboid(0.9, 0.45);
+
+        let covered_tiles = get_covered_tiles_for_collider(
+            &collider,
+            Vec2::new(0., 0.5),
+            Quat::from_rotation_z(-std::f32::consts::FRAC_PI_2),
+        );
+
+        assert_eq!(covered_tiles.len(), 2, "Collider should cover 2 tiles");
+        assert!(covered_tiles.contains(&Vec2::new(0., 0.)));
+        assert!(covered_tiles.contains(&Vec2::new(0., 1.)));
+    }
+
     proptest! {
         #[test]
         fn test_update_entity_on_tile_system_collider(
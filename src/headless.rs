@@ -0,0 +1,245 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    app::{AppExit, ScheduleRunnerPlugin},
+    hierarchy::HierarchyPlugin,
+    input::InputPlugin,
+    log::LogPlugin,
+    prelude::*,
+    time::TimeUpdateStrategy,
+    transform::TransformPlugin,
+};
+use bevy_rapier2d::prelude::*;
+
+use kloonorio_core::{
+    save_game::SaveParams, simulation::SimulationSet, types::AppState, KloonorioCorePlugins,
+};
+use kloonorio_terrain::{KloonorioTerrainPlugin, Terrain, TerrainSettings};
+
+use crate::{
+    builder::BuilderPlugin,
+    craft::CraftPlugin,
+    entity_tile_tracking::EntityTileTrackingPlugin,
+    item_loader::ItemLoaderPlugin,
+    loading::LoadingPlugin,
+    player::PlayerPlugin,
+    recipe_loader::RecipeLoaderPlugin,
+    replay::{start_playback, ReplayPlayback, ReplayPlugin, ReplaySettings},
+    save_file::{saved_terrain, write_save_file, PendingLoad, SaveFilePlugin, SaveFileSettings},
+    scene_setup::SceneSetupPlugin,
+    structure_loader::StructureLoaderPlugin,
+    technology_loader::TechnologyLoaderPlugin,
+};
+
+pub const USAGE: &str = "Usage: kloonorio --headless [--ticks <ticks>] [--load <save file>] \
+[--replay <replay file>] [--out <save file>]
+
+Simulates the factory without a window as fast as possible. Scenarios are save files written by
+hand, structures in them only need a name, position and rotation. A replay is simulated until its
+end instead of for a number of ticks, the exit code is 1 when it diverged from the recording.";
+
+/// Command line options of a headless run
+#[derive(Debug, PartialEq)]
+pub struct HeadlessArgs {
+    /// Fixed ticks to simulate before exiting
+    pub ticks: u32,
+    /// Save file or scenario to start from, a new game is started without one
+    pub load: Option<PathBuf>,
+    /// Replay to check against the recording, instead of a save file to start from
+    pub replay: Option<PathBuf>,
+    /// Where to save the game once all ticks are simulated
+    pub out: Option<PathBuf>,
+}
+
+impl HeadlessArgs {
+    /// Returns `None` when the game should start with a window
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Option<Result<Self, String>> {
+        let mut args = args.into_iter().peekable();
+        if args.peek().map(String::as_str) != Some("--headless") {
+            return None;
+        }
+        args.next();
+
+        let mut headless_args = HeadlessArgs {
+            ticks: 3600,
+            load: None,
+            replay: None,
+            out: None,
+        };
+        while let Some(arg) = args.next() {
+            let Some(value) = args.next() else {
+                return Some(Err(format!("Missing value for {}", arg)));
+            };
+            match arg.as_str() {
+                "--ticks" => match value.parse() {
+                    Ok(ticks) => headless_args.ticks = ticks,
+                    Err(_) => return Some(Err(format!("Invalid number of ticks {}", value))),
+                },
+                "--load" => headless_args.load = Some(PathBuf::from(value)),
+                "--replay" => headless_args.replay = Some(PathBuf::from(value)),
+                "--out" => headless_args.out = Some(PathBuf::from(value)),
+                _ => return Some(Err(format!("Unknown argument {}", arg))),
+            }
+        }
+        if headless_args.load.is_some() && headless_args.replay.is_some() {
+            return Some(Err(
+                "A replay starts from its own game, it can't be loaded".to_string()
+            ));
+        }
+        Some(Ok(headless_args))
+    }
+}
+
+#[derive(Resource)]
+struct HeadlessRun {
+    ticks: u32,
+    ticks_done: u32,
+    started: Option<Instant>,
+    out: Option<PathBuf>,
+}
+
+/// Run the simulation on `MinimalPlugins`, without rendering, input handling or UI
+pub fn run(args: HeadlessArgs) {
+    let timestep = Time::<Fixed>::default().timestep();
+    let mut app = App::new();
+    app.add_state::<AppState>()
+        .add_plugins((
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::ZERO)),
+            LogPlugin::default(),
+            AssetPlugin::default(),
+            ImagePlugin::default_nearest(),
+            TransformPlugin,
+            HierarchyPlugin,
+            InputPlugin,
+            RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(16.),
+        ))
+        .init_asset::<TextureAtlas>()
+        // Every update simulates a single fixed tick, however long it took
+        .insert_resource(TimeUpdateStrategy::ManualDuration(timestep))
+        .insert_resource(RapierConfiguration {
+            gravity: Vec2::new(0.0, 8.0),
+            ..default()
+        })
+        .insert_resource(SaveFileSettings {
+            path: args.load.clone().unwrap_or_default(),
+            load_on_start: args.load.is_some(),
+        })
+        .insert_resource(ReplaySettings { record: None })
+        .insert_resource(HeadlessRun {
+            ticks: args.ticks,
+            ticks_done: 0,
+            started: None,
+            out: args.out,
+        })
+        .add_plugins((KloonorioCorePlugins, KloonorioTerrainPlugin))
+        .add_plugins((
+            RecipeLoaderPlugin,
+            StructureLoaderPlugin,
+            ItemLoaderPlugin,
+            TechnologyLoaderPlugin,
+            LoadingPlugin { headless: true },
+            CraftPlugin,
+            BuilderPlugin,
+            PlayerPlugin,
+            SceneSetupPlugin,
+            EntityTileTrackingPlugin,
+            SaveFilePlugin,
+            ReplayPlugin,
+        ))
+        .add_systems(
+            FixedUpdate,
+            count_ticks
+                .after(SimulationSet::Inserters)
+                .run_if(in_state(AppState::Running))
+                .run_if(not(resource_exists::<PendingLoad>())),
+        );
+    if let Some(replay) = &args.replay {
+        if let Err(err) = start_playback(&mut app, replay) {
+            error!("Could not play replay: {}", err);
+            std::process::exit(1);
+        }
+    }
+    app.run();
+}
+
+fn count_ticks(
+    mut headless_run: ResMut<HeadlessRun>,
+    mut app_exit_events: EventWriter<AppExit>,
+    save_params: SaveParams,
+    terrain_query: Query<&Terrain>,
+    terrain_settings: Res<TerrainSettings>,
+    replay_playback: Option<Res<ReplayPlayback>>,
+) {
+    let started = *headless_run.started.get_or_insert_with(Instant::now);
+    headless_run.ticks_done += 1;
+    let done = match &replay_playback {
+        Some(replay_playback) => replay_playback.finished,
+        None => headless_run.ticks_done >= headless_run.ticks,
+    };
+    if !done {
+        return;
+    }
+
+    let elapsed = started.elapsed();
+    info!(
+        "Simulated {} ticks in {:.2?}, {:.0} ticks per second",
+        headless_run.ticks_done,
+        elapsed,
+        headless_run.ticks_done as f64 / elapsed.as_secs_f64()
+    );
+    if let Some(out) = &headless_run.out {
+        let terrain = saved_terrain(&terrain_settings, terrain_query.single());
+        match write_save_file(out, &save_params.save_game(terrain)) {
+            Ok(()) => info!("Saved game to {}", out.display()),
+            Err(err) => error!("Could not save game: {}", err),
+        }
+    }
+    if replay_playback.is_some_and(|replay_playback| replay_playback.diverged.is_some()) {
+        std::process::exit(1);
+    }
+    app_exit_events.send(AppExit);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Option<Result<HeadlessArgs, String>> {
+        HeadlessArgs::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn windowed_without_headless_flag() {
+        assert_eq!(parse(&[]), None);
+        assert_eq!(parse(&["--ticks", "10"]), None);
+    }
+
+    #[test]
+    fn parse_headless_args() {
+        assert_eq!(
+            parse(&["--headless", "--ticks", "10", "--load", "saves/factory.ron"]),
+            Some(Ok(HeadlessArgs {
+                ticks: 10,
+                load: Some(PathBuf::from("saves/factory.ron")),
+                replay: None,
+                out: None,
+            }))
+        );
+        assert!(matches!(parse(&["--headless", "--ticks"]), Some(Err(_))));
+        assert!(matches!(
+            parse(&["--headless", "--ticks", "many"]),
+            Some(Err(_))
+        ));
+        assert!(matches!(
+            parse(&["--headless", "--speed", "2"]),
+            Some(Err(_))
+        ));
+        assert!(matches!(
+            parse(&["--headless", "--load", "a.ron", "--replay", "b.ron"]),
+            Some(Err(_))
+        ));
+    }
+}
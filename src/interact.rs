@@ -1,31 +1,54 @@
 use bevy::{
-    app::{App, Plugin, Update},
+    app::{App, FixedUpdate, Plugin, Update},
     ecs::{
-        entity::Entity,
-        query::{With, Without},
-        system::{Commands, Query, Res, Resource},
+        query::With,
+        schedule::IntoSystemConfigs,
+        system::{Local, Query, Res, ResMut, Resource},
     },
     input::{mouse::MouseButton, Input},
-    math::Vec3Swizzles,
-    time::{Time, Timer, TimerMode},
-    transform::components::GlobalTransform,
+    math::{Vec2, Vec3Swizzles},
+    time::Time,
+    transform::components::{GlobalTransform, Transform},
 };
 use bevy_ecs_tilemap::tiles::TileTextureIndex;
 
-use kloonorio_core::{inventory::Inventory, item::Item, player::Player, types::MineCountdown};
-use kloonorio_terrain::{HoveredTile, COAL, IRON, STONE, TREE};
+use kloonorio_core::{
+    deconstruction::mine_structures,
+    inventory::Inventory,
+    item::Item,
+    player::LocalPlayer,
+    player_command::{LocalCommands, PlayerCommand},
+    simulation::SimulationSet,
+    tile_occupants::TileOccupants,
+    types::{Building, MineCountdown},
+};
+use kloonorio_terrain::{HoveredTile, COAL, COPPER, IRON, STONE, TREE};
 
 pub struct InteractPlugin;
 
 impl Plugin for InteractPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<InteractionSettings>()
-            .add_systems(Update, (interact, interact_cancel, interact_completion));
+            .add_systems(Update, interact)
+            .add_systems(
+                FixedUpdate,
+                interact_completion
+                    .before(mine_structures)
+                    .in_set(SimulationSet::PlayerMining),
+            );
     }
 }
 
-pub fn is_minable(tile: u32) -> bool {
-    matches!(tile, COAL | IRON | STONE | TREE)
+/// The item a tile gives when it's mined by hand
+pub fn mined_product(tile: u32) -> Option<Item> {
+    match tile {
+        COAL => Some(Item::new("Coal")),
+        IRON => Some(Item::new("Iron ore")),
+        COPPER => Some(Item::new("Copper ore")),
+        STONE => Some(Item::new("Stone")),
+        TREE => Some(Item::new("Wood")),
+        _ => None,
+    }
 }
 
 #[derive(Resource)]
@@ -41,70 +64,68 @@ impl Default for InteractionSettings {
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+enum MiningTarget {
+    Resource(Item),
+    /// The position of the structure
+    Structure(Vec2),
+}
+
+/// Mine the hovered structure, or the hovered tile if there is none, while the right mouse button
+/// is held
 fn interact(
-    mut commands: Commands,
-    tile_query: Query<&TileTextureIndex>,
+    tile_query: Query<(&TileTextureIndex, Option<&TileOccupants>)>,
+    building_query: Query<&Transform, With<Building>>,
     mouse_button_input: Res<Input<MouseButton>>,
-    player_query: Query<
-        (Entity, &GlobalTransform, &HoveredTile),
-        (With<Player>, Without<MineCountdown>),
-    >,
+    player_query: Query<(&GlobalTransform, Option<&HoveredTile>), With<LocalPlayer>>,
     player_settings: Res<InteractionSettings>,
+    mut local_commands: ResMut<LocalCommands>,
+    mut mining: Local<Option<MiningTarget>>,
 ) {
-    if player_query.is_empty() {
-        return;
-    }
-    let (player_entity, player_transform, hovered_tile) = player_query.single();
-
-    if !mouse_button_input.pressed(MouseButton::Right) {
+    let Ok((player_transform, hovered_tile)) = player_query.get_single() else {
         return;
     };
 
-    if let Ok(tile_texture) = tile_query.get(hovered_tile.entity) {
-        let tile_distance = player_transform
-            .translation()
-            .xy()
-            .distance(hovered_tile.tile_center);
-        if is_minable(tile_texture.0) && tile_distance < player_settings.max_mining_distance {
-            commands.entity(player_entity).insert(MineCountdown {
-                timer: Timer::from_seconds(1.0, TimerMode::Repeating),
-                target: hovered_tile.entity,
-            });
-        }
-    }
-}
+    let target = hovered_tile
+        .filter(|_| mouse_button_input.pressed(MouseButton::Right))
+        .filter(|hovered_tile| {
+            player_transform
+                .translation()
+                .xy()
+                .distance(hovered_tile.tile_center)
+                < player_settings.max_mining_distance
+        })
+        .and_then(|hovered_tile| tile_query.get(hovered_tile.entity).ok())
+        .and_then(|(tile_texture, tile_occupants)| {
+            let structure = tile_occupants
+                .into_iter()
+                .flat_map(TileOccupants::iter)
+                .find_map(|occupant| building_query.get(*occupant).ok());
+            match structure {
+                Some(transform) => Some(MiningTarget::Structure(transform.translation.xy())),
+                None => mined_product(tile_texture.0).map(MiningTarget::Resource),
+            }
+        });
 
-fn interact_cancel(
-    mut commands: Commands,
-    player_query: Query<Entity, With<MineCountdown>>,
-    mouse_button_input: Res<Input<MouseButton>>,
-) {
-    if mouse_button_input.just_released(MouseButton::Right) {
-        for player_entity in &player_query {
-            commands.entity(player_entity).remove::<MineCountdown>();
-        }
+    // Commands are only sent when the player starts mining something else
+    if target != *mining {
+        local_commands.push(match &target {
+            Some(MiningTarget::Resource(product)) => PlayerCommand::StartMining {
+                product: product.clone(),
+            },
+            Some(MiningTarget::Structure(position)) => PlayerCommand::StartMiningStructure {
+                position: *position,
+            },
+            None => PlayerCommand::StopMining,
+        });
+        *mining = target;
     }
 }
 
-fn interact_completion(
-    mut commands: Commands,
-    time: Res<Time>,
-    mut query: Query<(Entity, &mut Inventory, &mut MineCountdown)>,
-    tile_query: Query<&TileTextureIndex>,
-) {
-    for (entity, mut inventory, mut interaction) in &mut query {
-        if interaction.timer.tick(time.delta()).just_finished() {
-            commands.entity(entity).remove::<MineCountdown>();
-            let tile_entity = interaction.target;
-            if let Ok(tile_texture) = tile_query.get(tile_entity) {
-                match tile_texture.0 {
-                    COAL => inventory.add_item(&Item::new("Coal"), 1),
-                    IRON => inventory.add_item(&Item::new("Iron ore"), 1),
-                    STONE => inventory.add_item(&Item::new("Stone"), 1),
-                    TREE => inventory.add_item(&Item::new("Wood"), 1),
-                    _ => 0,
-                };
-            }
+fn interact_completion(time: Res<Time>, mut query: Query<(&mut Inventory, &mut MineCountdown)>) {
+    for (mut inventory, mut mine_countdown) in &mut query {
+        if mine_countdown.timer.tick(time.delta()).just_finished() {
+            inventory.add_item(&mine_countdown.product, 1);
         }
     }
 }
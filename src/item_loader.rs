@@ -6,13 +6,13 @@ use bevy::{
 };
 use serde::Deserialize;
 
-use kloonorio_core::item::Item;
+use kloonorio_core::item::ItemDefinition;
 
 #[derive(Default)]
 pub struct ItemAssetLoader;
 #[derive(Asset, Clone, Debug, Deserialize, TypeUuid, Reflect)]
 #[uuid = "09483f6e-220b-486c-aaf2-857b4c9cab23"]
-pub struct ItemAsset(pub Vec<Item>);
+pub struct ItemAsset(pub Vec<ItemDefinition>);
 
 #[non_exhaustive]
 #[derive(Debug, thiserror::Error)]
@@ -4,12 +4,15 @@ use bevy::{
     utils::HashMap,
 };
 use bevy_egui::EguiContexts;
-use kloonorio_core::{item::Items, recipe::Recipes, structure::Structures, types::AppState};
+use kloonorio_core::{
+    item::Items, recipe::Recipes, research::Technologies, structure::Structures, types::AppState,
+};
 use kloonorio_render::item_textures::ItemTextures;
 use kloonorio_ui::icon::Icons;
 
 use crate::{
     item_loader::ItemAsset, recipe_loader::RecipesAsset, structure_loader::StructuresAsset,
+    technology_loader::TechnologiesAsset,
 };
 
 #[derive(Default, Resource, Reflect)]
@@ -25,13 +28,19 @@ pub struct LoadState {
     pub items_loaded: bool,
     pub items_handle: Handle<ItemAsset>,
     pub item_textures_loaded: bool,
+    pub technologies_handle: Handle<TechnologiesAsset>,
+    pub technologies_loaded: bool,
 }
 
 fn start_loading(asset_server: Res<AssetServer>, mut loadstate: ResMut<LoadState>) {
     loadstate.recipes_handle = asset_server.load("data/base.recipes.ron");
     loadstate.structures_handle = asset_server.load("data/base.structures.ron");
-    loadstate.icons_handle = asset_server.load_folder("textures/icons");
     loadstate.items_handle = asset_server.load("data/base.items.ron");
+    loadstate.technologies_handle = asset_server.load("data/base.technologies.ron");
+}
+
+fn start_loading_icons(asset_server: Res<AssetServer>, mut loadstate: ResMut<LoadState>) {
+    loadstate.icons_handle = asset_server.load_folder("textures/icons");
 }
 
 fn load_items(
@@ -45,7 +54,11 @@ fn load_items(
     }
 
     if let Some(ItemAsset(loaded_item)) = item_asset {
-        items.extend(loaded_item.iter().map(|r| (r.to_string(), r.clone())));
+        items.extend(
+            loaded_item
+                .iter()
+                .map(|definition| (definition.name.to_string(), definition.clone())),
+        );
         loadstate.items_loaded = true;
     }
 }
@@ -173,40 +186,83 @@ fn load_recipes(
     }
 }
 
+fn load_technologies(
+    mut loadstate: ResMut<LoadState>,
+    technologies_assets: Res<Assets<TechnologiesAsset>>,
+    mut technologies: ResMut<Technologies>,
+) {
+    let technologies_asset = technologies_assets.get(&loadstate.technologies_handle);
+    if loadstate.technologies_loaded || technologies_asset.is_none() {
+        return;
+    }
+
+    if let Some(TechnologiesAsset(loaded_technologies)) = technologies_asset {
+        technologies.extend(
+            loaded_technologies
+                .iter()
+                .map(|t| (t.name.clone(), t.clone())),
+        );
+        loadstate.technologies_loaded = true;
+    }
+}
+
 fn check_loading(loadstate: Res<LoadState>, mut next_state: ResMut<NextState<AppState>>) {
     if loadstate.recipes_loaded
         && loadstate.structures_loaded
         && loadstate.icons_loaded
         && loadstate.items_loaded
         && loadstate.item_textures_loaded
+        && loadstate.technologies_loaded
     {
         next_state.set(AppState::Running);
     }
 }
 
-pub struct LoadingPlugin;
+#[derive(Default)]
+pub struct LoadingPlugin {
+    /// Only load the game data, the icons and item textures are only needed when rendering
+    pub headless: bool,
+}
 
 impl Plugin for LoadingPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<Structures>()
             .register_type::<Recipes>()
             .register_type::<Items>()
+            .register_type::<Technologies>()
             .init_resource::<Structures>()
             .init_resource::<Recipes>()
-            .init_resource::<Icons>()
             .init_resource::<Items>()
+            .init_resource::<Technologies>()
             .add_systems(OnEnter(AppState::Loading), start_loading)
             .add_systems(
                 Update,
                 (
                     load_recipes,
                     load_structures,
-                    load_item_icons,
                     load_items,
-                    load_item_textures,
+                    load_technologies,
                     check_loading,
                 )
                     .run_if(in_state(AppState::Loading)),
             );
+
+        if self.headless {
+            app.insert_resource(LoadState {
+                icons_loaded: true,
+                item_textures_loaded: true,
+                ..default()
+            });
+        } else {
+            app.init_resource::<LoadState>()
+                .init_resource::<Icons>()
+                .add_systems(OnEnter(AppState::Loading), start_loading_icons)
+                .add_systems(
+                    Update,
+                    (load_item_icons, load_item_textures)
+                        .before(check_loading)
+                        .run_if(in_state(AppState::Loading)),
+                );
+        }
     }
 }
@@ -2,45 +2,80 @@ use bevy::{
     diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
     prelude::*,
 };
+use bevy_ecs_tilemap::TilemapPlugin;
 use bevy_rapier2d::prelude::*;
 use biter::BiterPlugin;
-use builder::BuilderPlugin;
+use builder::{
+    blueprint::BlueprintPlugin, deconstruction_planner::DeconstructionPlannerPlugin, BuilderPlugin,
+};
 use entity_tile_tracking::EntityTileTrackingPlugin;
 use kloonorio_core::{types::AppState, KloonorioCorePlugins};
 use kloonorio_render::KloonorioRenderPlugins;
 use kloonorio_terrain::KloonorioTerrainPlugin;
 use kloonorio_ui::KloonorioUiPlugin;
-use loading::LoadState;
 use scene_setup::SceneSetupPlugin;
 use shoot::ShootPlugin;
 
+mod autosave;
 pub mod biter;
 mod builder;
 mod camera;
 mod craft;
 mod entity_tile_tracking;
+mod headless;
 mod interact;
 mod item_loader;
 mod loading;
+mod multiplayer;
 mod player;
 mod player_control;
 mod recipe_loader;
+mod replay;
+mod save_file;
 mod scene_setup;
 mod shoot;
 mod structure_loader;
+mod technology_loader;
 mod ysort;
 
 use crate::{
-    camera::PanZoomCameraPlugin, craft::CraftPlugin, interact::InteractPlugin,
-    item_loader::ItemLoaderPlugin, loading::LoadingPlugin, player::PlayerPlugin,
-    player_control::PlayerControlPlugin, recipe_loader::RecipeLoaderPlugin,
-    structure_loader::StructureLoaderPlugin, ysort::YSortPlugin,
+    autosave::AutosavePlugin, camera::PanZoomCameraPlugin, craft::CraftPlugin,
+    interact::InteractPlugin, item_loader::ItemLoaderPlugin, loading::LoadingPlugin,
+    player::PlayerPlugin, player_control::PlayerControlPlugin, recipe_loader::RecipeLoaderPlugin,
+    replay::ReplayPlugin, save_file::SaveFilePlugin, structure_loader::StructureLoaderPlugin,
+    technology_loader::TechnologyLoaderPlugin, ysort::YSortPlugin,
 };
 
 fn main() {
+    if let Some(headless_args) = headless::HeadlessArgs::parse(std::env::args().skip(1)) {
+        match headless_args {
+            Ok(headless_args) => headless::run(headless_args),
+            Err(err) => {
+                eprintln!("{}\n{}", err, headless::USAGE);
+                std::process::exit(2);
+            }
+        }
+        return;
+    }
+    let multiplayer_args = match multiplayer::MultiplayerArgs::parse(std::env::args().skip(1)) {
+        Some(Ok(multiplayer_args)) => Some(multiplayer_args),
+        Some(Err(err)) => {
+            eprintln!("{}\n{}", err, multiplayer::USAGE);
+            std::process::exit(2);
+        }
+        None => None,
+    };
+    let replay_args = match replay::ReplayArgs::parse(std::env::args().skip(1)) {
+        Some(Ok(replay_args)) => Some(replay_args),
+        Some(Err(err)) => {
+            eprintln!("{}\n{}", err, replay::USAGE);
+            std::process::exit(2);
+        }
+        None => None,
+    };
+
     let mut app = App::new();
-    app.init_resource::<LoadState>()
-        .add_state::<AppState>()
+    app.add_state::<AppState>()
         .add_plugins((
             FrameTimeDiagnosticsPlugin,
             LogDiagnosticsPlugin::default(),
@@ -48,6 +83,7 @@ fn main() {
                 .set(AssetPlugin { ..default() })
                 .set(ImagePlugin::default_nearest()),
             RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(16.),
+            TilemapPlugin,
         ))
         .insert_resource(RapierConfiguration {
             gravity: Vec2::new(0.0, 8.0),
@@ -65,7 +101,8 @@ fn main() {
             RecipeLoaderPlugin,
             StructureLoaderPlugin,
             ItemLoaderPlugin,
-            LoadingPlugin,
+            TechnologyLoaderPlugin,
+            LoadingPlugin::default(),
             InteractPlugin,
             CraftPlugin,
             BuilderPlugin,
@@ -77,6 +114,23 @@ fn main() {
             PanZoomCameraPlugin,
             SceneSetupPlugin,
             EntityTileTrackingPlugin,
-        ))
-        .run();
+            SaveFilePlugin,
+            AutosavePlugin,
+            ReplayPlugin,
+            BlueprintPlugin,
+            DeconstructionPlannerPlugin,
+        ));
+    if let Some(multiplayer_args) = multiplayer_args {
+        if let Err(err) = multiplayer::start(&mut app, multiplayer_args) {
+            error!("Could not start multiplayer game: {}", err);
+            std::process::exit(1);
+        }
+    }
+    if let Some(replay_args) = replay_args {
+        if let Err(err) = replay::start_playback(&mut app, &replay_args.replay) {
+            error!("Could not play replay: {}", err);
+            std::process::exit(1);
+        }
+    }
+    app.run();
 }
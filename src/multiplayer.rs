@@ -0,0 +1,757 @@
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    thread,
+    time::Duration,
+};
+
+use bevy::{
+    prelude::*,
+    time::{TimeSystem, TimeUpdateStrategy},
+    utils::synccell::SyncCell,
+};
+use serde::{Deserialize, Serialize};
+
+use kloonorio_core::{
+    checksum::ChecksumParams,
+    player::PlayerId,
+    player_command::{schedule_local_commands, LocalCommands, PlayerCommand, ScheduledCommands},
+    simulation::{advance_simulation_tick, SimulationSet, SimulationTick},
+};
+use kloonorio_terrain::TerrainSettings;
+
+use crate::{player::Players, save_file::SaveFileSettings};
+
+pub const USAGE: &str = "Usage: kloonorio --host <port> [--players <players>]
+       kloonorio --join <host address>
+
+The host waits for the other players to join before the game starts. Every client simulates the
+same world from the players' commands, a desync is logged when their checksums differ.";
+
+/// Ticks between a command being given and it being applied, which gives the commands time to
+/// reach every client
+const INPUT_DELAY: u64 = 4;
+/// Ticks between comparing the checksums of the clients' simulations
+const CHECKSUM_INTERVAL: u64 = 60;
+/// How long to wait for another player before giving up on the game
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Command line options of a multiplayer game
+#[derive(Debug, PartialEq)]
+pub enum MultiplayerArgs {
+    /// Wait for the other players on a port
+    Host { port: u16, players: u32 },
+    /// Join the game of a host
+    Join { address: String },
+}
+
+impl MultiplayerArgs {
+    /// Returns `None` when the game is single player
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Option<Result<Self, String>> {
+        let mut args = args.into_iter();
+        let mut multiplayer_args = match args.next().as_deref() {
+            Some("--host") => match args.next().map(|port| port.parse()) {
+                Some(Ok(port)) => MultiplayerArgs::Host { port, players: 2 },
+                Some(Err(_)) => return Some(Err("Invalid port".to_string())),
+                None => return Some(Err("Missing value for --host".to_string())),
+            },
+            Some("--join") => match args.next() {
+                Some(address) => MultiplayerArgs::Join { address },
+                None => return Some(Err("Missing value for --join".to_string())),
+            },
+            _ => return None,
+        };
+        while let Some(arg) = args.next() {
+            let Some(value) = args.next() else {
+                return Some(Err(format!("Missing value for {}", arg)));
+            };
+            match (arg.as_str(), &mut multiplayer_args) {
+                ("--players", MultiplayerArgs::Host { players, .. }) => {
+                    match value.parse::<u32>() {
+                        Ok(count @ 1..) => *players = count,
+                        _ => return Some(Err(format!("Invalid number of players {}", value))),
+                    }
+                }
+                _ => return Some(Err(format!("Unknown argument {}", arg))),
+            }
+        }
+        Some(Ok(multiplayer_args))
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum MultiplayerError {
+    /// An [IO](std::io) Error.
+    #[error("Could not reach other player: {0}")]
+    Io(#[from] std::io::Error),
+    /// A [RON](ron) Error.
+    #[error(transparent)]
+    Ron(#[from] ron::Error),
+    /// A [RON](ron) Spanned Error.
+    #[error(transparent)]
+    RonSpannedError(#[from] ron::error::SpannedError),
+    /// The other player closed the connection
+    #[error("Other player disconnected")]
+    Disconnected,
+    /// The other player sent a message out of turn
+    #[error("Unexpected message {0}")]
+    UnexpectedMessage(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum Message {
+    /// Sent by the host to every client once all players have joined
+    Start {
+        player: PlayerId,
+        players: u32,
+        seed: u32,
+    },
+    /// A client's commands, to be applied on the tick
+    Commands {
+        tick: u64,
+        commands: Vec<PlayerCommand>,
+    },
+    /// Sent by the host, the commands of every player for the tick in player order
+    Tick {
+        tick: u64,
+        commands: Vec<(PlayerId, PlayerCommand)>,
+    },
+    /// A client's checksum after simulating the tick
+    Checksum { tick: u64, checksum: u64 },
+    /// Sent by the host when a client's checksum differed from its own
+    Desync { tick: u64 },
+}
+
+/// Messages are sent as RON, one per line
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Result<Self, MultiplayerError> {
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        })
+    }
+
+    fn set_timeout(&self, timeout: Option<Duration>) -> Result<(), MultiplayerError> {
+        self.writer.set_read_timeout(timeout)?;
+        Ok(())
+    }
+
+    fn send(&mut self, message: &Message) -> Result<(), MultiplayerError> {
+        let mut line = ron::to_string(message)?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Message, MultiplayerError> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(MultiplayerError::Disconnected);
+        }
+        Ok(ron::from_str(&line)?)
+    }
+}
+
+enum Role {
+    Host {
+        clients: Vec<(PlayerId, Connection)>,
+        /// The host's own commands by the tick they are applied on
+        pending: BTreeMap<u64, Vec<PlayerCommand>>,
+        /// The host's checksums by tick, until every client has sent theirs
+        checksums: BTreeMap<u64, u64>,
+    },
+    Client {
+        host: Connection,
+    },
+}
+
+/// The connections of a game in deterministic lockstep. Every tick the host collects the
+/// commands of all players and sends them to every client, nobody simulates a tick before it has
+/// them. Waiting for the other players blocks, so this runs on the network thread.
+struct Lockstep {
+    local: PlayerId,
+    players: u32,
+    seed: u32,
+    /// The first tick a client's simulation differed from the host's
+    desync: Option<u64>,
+    role: Role,
+}
+
+impl Lockstep {
+    /// Exchanges the local commands for the commands of every player on this tick. Local
+    /// commands are applied `INPUT_DELAY` ticks later.
+    fn exchange(
+        &mut self,
+        tick: u64,
+        local_commands: Vec<PlayerCommand>,
+    ) -> Result<Vec<(PlayerId, PlayerCommand)>, MultiplayerError> {
+        match &mut self.role {
+            Role::Host {
+                clients,
+                pending,
+                checksums,
+            } => {
+                pending.insert(tick + INPUT_DELAY, local_commands);
+                let mut found_desync = None;
+                let mut commands: Vec<(PlayerId, PlayerCommand)> = pending
+                    .remove(&tick)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|command| (self.local, command))
+                    .collect();
+                // Nobody has given commands for the first ticks
+                if tick >= INPUT_DELAY {
+                    for (player, client) in clients.iter_mut() {
+                        loop {
+                            match client.receive()? {
+                                Message::Commands {
+                                    tick: commands_tick,
+                                    commands: client_commands,
+                                } if commands_tick == tick => {
+                                    commands.extend(
+                                        client_commands
+                                            .into_iter()
+                                            .map(|command| (*player, command)),
+                                    );
+                                    break;
+                                }
+                                Message::Checksum {
+                                    tick: checksum_tick,
+                                    checksum,
+                                } => {
+                                    if checksums.get(&checksum_tick) != Some(&checksum)
+                                        && self.desync.is_none()
+                                    {
+                                        error!(
+                                            "Player {} desynced on tick {}",
+                                            player.0 + 1,
+                                            checksum_tick
+                                        );
+                                        self.desync = Some(checksum_tick);
+                                        found_desync = self.desync;
+                                    }
+                                }
+                                message => {
+                                    return Err(MultiplayerError::UnexpectedMessage(format!(
+                                        "{:?}",
+                                        message
+                                    )))
+                                }
+                            }
+                        }
+                    }
+                }
+                // Clients ask for the commands of the next tick before sending their checksum of
+                // a tick, so it comes before their commands for the tick `INPUT_DELAY + 2` later
+                *checksums = checksums.split_off(&tick.saturating_sub(INPUT_DELAY + 2));
+
+                let mut messages = vec![Message::Tick {
+                    tick,
+                    commands: commands.clone(),
+                }];
+                if let Some(desync) = found_desync {
+                    messages.push(Message::Desync { tick: desync });
+                }
+                for (_, client) in clients.iter_mut() {
+                    for message in &messages {
+                        client.send(message)?;
+                    }
+                }
+                Ok(commands)
+            }
+            Role::Client { host } => {
+                host.send(&Message::Commands {
+                    tick: tick + INPUT_DELAY,
+                    commands: local_commands,
+                })?;
+                loop {
+                    match host.receive()? {
+                        Message::Tick {
+                            tick: commands_tick,
+                            commands,
+                        } if commands_tick == tick => return Ok(commands),
+                        Message::Desync { tick } => {
+                            if self.desync.is_none() {
+                                error!("Desynced from the host on tick {}", tick);
+                                self.desync = Some(tick);
+                            }
+                        }
+                        message => {
+                            return Err(MultiplayerError::UnexpectedMessage(format!(
+                                "{:?}",
+                                message
+                            )))
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The host keeps its checksum of a simulated tick to compare with the clients'
+    fn share_checksum(&mut self, tick: u64, checksum: u64) -> Result<(), MultiplayerError> {
+        match &mut self.role {
+            Role::Host { checksums, .. } => {
+                checksums.insert(tick, checksum);
+                Ok(())
+            }
+            Role::Client { host } => host.send(&Message::Checksum { tick, checksum }),
+        }
+    }
+}
+
+/// What the game asks of the network thread
+enum Request {
+    /// Send the local commands and receive the commands of every player for the tick
+    Exchange {
+        tick: u64,
+        commands: Vec<PlayerCommand>,
+    },
+    /// Compare the checksum of a simulated tick with the other players'
+    Checksum { tick: u64, checksum: u64 },
+}
+
+/// What the network thread tells the game
+enum Response {
+    Tick {
+        tick: u64,
+        commands: Vec<(PlayerId, PlayerCommand)>,
+    },
+    Desync {
+        tick: u64,
+    },
+    /// The connection failed, the thread stops
+    Left(MultiplayerError),
+}
+
+/// Handle the game's requests in order until the game is gone or the connection fails
+fn run_lockstep(mut lockstep: Lockstep, requests: Receiver<Request>, responses: Sender<Response>) {
+    for request in requests {
+        let desync = lockstep.desync;
+        let result = match request {
+            Request::Exchange { tick, commands } => lockstep
+                .exchange(tick, commands)
+                .map(|commands| Some(Response::Tick { tick, commands })),
+            Request::Checksum { tick, checksum } => {
+                lockstep.share_checksum(tick, checksum).map(|()| None)
+            }
+        };
+        if lockstep.desync != desync {
+            if let Some(tick) = lockstep.desync {
+                let _ = responses.send(Response::Desync { tick });
+            }
+        }
+        let response = match result {
+            Ok(Some(response)) => response,
+            Ok(None) => continue,
+            Err(err) => Response::Left(err),
+        };
+        let left = matches!(response, Response::Left(_));
+        if responses.send(response).is_err() || left {
+            return;
+        }
+    }
+}
+
+/// A multiplayer game. The network thread exchanges commands with the other players, while the
+/// simulation waits on a tick until it has every player's commands for it.
+#[derive(Resource)]
+pub struct Session {
+    pub local: PlayerId,
+    pub players: u32,
+    pub seed: u32,
+    /// The first tick a client's simulation differed from the host's
+    pub desync: Option<u64>,
+    requests: Sender<Request>,
+    responses: SyncCell<Receiver<Response>>,
+    /// Every player's commands by the tick they are applied on
+    received: BTreeMap<u64, Vec<(PlayerId, PlayerCommand)>>,
+    /// Local commands that go out with the next exchange
+    outgoing: Vec<PlayerCommand>,
+    /// The last tick the commands were asked for
+    requested: Option<u64>,
+}
+
+impl Session {
+    fn new(lockstep: Lockstep) -> Self {
+        let (requests, request_receiver) = mpsc::channel();
+        let (response_sender, responses) = mpsc::channel();
+        let session = Session {
+            local: lockstep.local,
+            players: lockstep.players,
+            seed: lockstep.seed,
+            desync: None,
+            requests,
+            responses: SyncCell::new(responses),
+            received: BTreeMap::new(),
+            outgoing: Vec::new(),
+            requested: None,
+        };
+        thread::spawn(move || run_lockstep(lockstep, request_receiver, response_sender));
+        session
+    }
+
+    /// Take what the network thread has received without waiting for it, and ask for the
+    /// commands of the following tick once those of `tick` are in. Returns whether every
+    /// player's commands for `tick` are in.
+    fn poll(
+        &mut self,
+        tick: u64,
+        mut local_commands: Vec<PlayerCommand>,
+    ) -> Result<bool, MultiplayerError> {
+        self.outgoing.append(&mut local_commands);
+        loop {
+            match self.responses.get().try_recv() {
+                Ok(Response::Tick { tick, commands }) => {
+                    self.received.insert(tick, commands);
+                }
+                Ok(Response::Desync { tick }) => {
+                    self.desync.get_or_insert(tick);
+                }
+                Ok(Response::Left(err)) => return Err(err),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Err(MultiplayerError::Disconnected),
+            }
+        }
+
+        let ready = self.received.contains_key(&tick);
+        // The next tick is asked for while this one is simulated, so the answer can be in by
+        // the next frame
+        let next = if ready { tick + 1 } else { tick };
+        if self.requested.map_or(true, |requested| requested < next) {
+            self.requests
+                .send(Request::Exchange {
+                    tick: next,
+                    commands: std::mem::take(&mut self.outgoing),
+                })
+                .map_err(|_| MultiplayerError::Disconnected)?;
+            self.requested = Some(next);
+        }
+        Ok(ready)
+    }
+
+    /// A failed connection is noticed on the next poll
+    fn share_checksum(&mut self, tick: u64, checksum: u64) {
+        let _ = self.requests.send(Request::Checksum { tick, checksum });
+    }
+}
+
+/// Connect to the other players and set the game up for this client's player
+pub fn start(app: &mut App, args: MultiplayerArgs) -> Result<(), MultiplayerError> {
+    let session = match args {
+        MultiplayerArgs::Host { port, players } => host(port, players)?,
+        MultiplayerArgs::Join { address } => join(address)?,
+    };
+    app.world.resource_mut::<TerrainSettings>().seed = session.seed;
+    // A save only exists on one client
+    app.world.resource_mut::<SaveFileSettings>().load_on_start = false;
+    app.insert_resource(Players {
+        count: session.players,
+        local: session.local,
+    })
+    .insert_resource(session)
+    .add_plugins(MultiplayerPlugin);
+    Ok(())
+}
+
+/// Wait for the other players to join, then start the game for everyone
+pub fn host(port: u16, players: u32) -> Result<Session, MultiplayerError> {
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    info!("Waiting for {} players on port {}", players - 1, port);
+    let lockstep = accept_players(listener, players, rand::random())?;
+    Ok(Session::new(lockstep))
+}
+
+fn accept_players(
+    listener: TcpListener,
+    players: u32,
+    seed: u32,
+) -> Result<Lockstep, MultiplayerError> {
+    let mut clients = vec![];
+    for player in (1..players).map(PlayerId) {
+        let (stream, address) = listener.accept()?;
+        info!("Player {} joined from {}", player.0 + 1, address);
+        let connection = Connection::new(stream)?;
+        connection.set_timeout(Some(TIMEOUT))?;
+        clients.push((player, connection));
+    }
+    for (player, client) in &mut clients {
+        client.send(&Message::Start {
+            player: *player,
+            players,
+            seed,
+        })?;
+    }
+    Ok(Lockstep {
+        local: PlayerId(0),
+        players,
+        seed,
+        desync: None,
+        role: Role::Host {
+            clients,
+            pending: BTreeMap::new(),
+            checksums: BTreeMap::new(),
+        },
+    })
+}
+
+/// Join a host and wait for the game to start
+pub fn join(address: impl ToSocketAddrs) -> Result<Session, MultiplayerError> {
+    Ok(Session::new(connect(address)?))
+}
+
+fn connect(address: impl ToSocketAddrs) -> Result<Lockstep, MultiplayerError> {
+    let mut host = Connection::new(TcpStream::connect(address)?)?;
+    // The host starts the game once everyone has joined, which takes as long as it takes
+    let Message::Start {
+        player,
+        players,
+        seed,
+    } = host.receive()?
+    else {
+        return Err(MultiplayerError::UnexpectedMessage(
+            "before the game started".to_string(),
+        ));
+    };
+    host.set_timeout(Some(TIMEOUT))?;
+    info!("Joined as player {} of {}", player.0 + 1, players);
+    Ok(Lockstep {
+        local: player,
+        players,
+        seed,
+        desync: None,
+        role: Role::Client { host },
+    })
+}
+
+/// Keeps the simulation of every player in lockstep while there is a `Session`
+pub struct MultiplayerPlugin;
+
+impl Plugin for MultiplayerPlugin {
+    fn build(&self, app: &mut App) {
+        let timestep = Time::<Fixed>::default().timestep();
+        // A single tick per frame, so structures placed on a tick are built before the next one
+        // on every client
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(timestep))
+            .add_systems(
+                First,
+                exchange_commands
+                    .before(TimeSystem)
+                    .run_if(resource_exists::<Session>()),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    schedule_received_commands
+                        .in_set(SimulationSet::Commands)
+                        .before(schedule_local_commands),
+                    share_checksum
+                        .after(SimulationSet::Inserters)
+                        .before(advance_simulation_tick),
+                )
+                    .run_if(resource_exists::<Session>()),
+            );
+    }
+}
+
+/// Hand the local commands to the network thread and pick up what it received. The frame only
+/// simulates a tick once every player's commands for it are in, until then the fixed time stands
+/// still while the frame is drawn as usual.
+fn exchange_commands(
+    mut commands: Commands,
+    mut session: ResMut<Session>,
+    tick: Res<SimulationTick>,
+    mut local_commands: ResMut<LocalCommands>,
+    mut time_update_strategy: ResMut<TimeUpdateStrategy>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    let timestep = fixed_time.timestep();
+    match session.poll(tick.0, std::mem::take(&mut local_commands.0)) {
+        Ok(ready) => {
+            let delta = if ready { timestep } else { Duration::ZERO };
+            *time_update_strategy = TimeUpdateStrategy::ManualDuration(delta);
+        }
+        Err(err) => {
+            error!("Left the multiplayer game: {}", err);
+            commands.remove_resource::<Session>();
+            *time_update_strategy = TimeUpdateStrategy::ManualDuration(timestep);
+        }
+    }
+}
+
+fn schedule_received_commands(
+    mut session: ResMut<Session>,
+    tick: Res<SimulationTick>,
+    mut local_commands: ResMut<LocalCommands>,
+    mut scheduled_commands: ResMut<ScheduledCommands>,
+) {
+    // Commands given since the last exchange go out with the next one
+    session.outgoing.append(&mut local_commands.0);
+    if let Some(tick_commands) = session.received.remove(&tick.0) {
+        scheduled_commands.schedule(tick.0, tick_commands);
+    }
+}
+
+fn share_checksum(
+    mut session: ResMut<Session>,
+    tick: Res<SimulationTick>,
+    checksum_params: ChecksumParams,
+) {
+    if tick.0 % CHECKSUM_INTERVAL != 0 {
+        return;
+    }
+    session.share_checksum(tick.0, checksum_params.checksum());
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> Option<Result<MultiplayerArgs, String>> {
+        MultiplayerArgs::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_multiplayer_args() {
+        assert_eq!(parse(&[]), None);
+        assert_eq!(parse(&["--headless"]), None);
+        assert_eq!(
+            parse(&["--host", "7000", "--players", "3"]),
+            Some(Ok(MultiplayerArgs::Host {
+                port: 7000,
+                players: 3
+            }))
+        );
+        assert_eq!(
+            parse(&["--join", "localhost:7000"]),
+            Some(Ok(MultiplayerArgs::Join {
+                address: "localhost:7000".to_string()
+            }))
+        );
+        assert!(matches!(parse(&["--host"]), Some(Err(_))));
+        assert!(matches!(
+            parse(&["--host", "7000", "--players", "0"]),
+            Some(Err(_))
+        ));
+        assert!(matches!(
+            parse(&["--join", "localhost:7000", "--players", "3"]),
+            Some(Err(_))
+        ));
+    }
+
+    type Ticks = Vec<Vec<(PlayerId, PlayerCommand)>>;
+
+    /// Runs a host and a client over loopback, each giving a command on one tick and sharing
+    /// their checksums
+    fn lockstep(host_checksum: u64, client_checksum: u64) -> (Lockstep, Ticks, Lockstep, Ticks) {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut session = connect(address).unwrap();
+            let ticks: Vec<_> = (0..8)
+                .map(|tick| {
+                    let commands = match tick {
+                        0 => vec![PlayerCommand::StopMining],
+                        _ => vec![],
+                    };
+                    let tick_commands = session.exchange(tick, commands).unwrap();
+                    session.share_checksum(tick, client_checksum).unwrap();
+                    tick_commands
+                })
+                .collect();
+            (session, ticks)
+        });
+
+        let mut session = accept_players(listener, 2, 7).unwrap();
+        let ticks: Vec<_> = (0..8)
+            .map(|tick| {
+                let commands = match tick {
+                    1 => vec![PlayerCommand::MoveTo(Vec2::new(1., 2.))],
+                    _ => vec![],
+                };
+                let tick_commands = session.exchange(tick, commands).unwrap();
+                session.share_checksum(tick, host_checksum).unwrap();
+                tick_commands
+            })
+            .collect();
+        let (client_session, client_ticks) = client.join().unwrap();
+        (session, ticks, client_session, client_ticks)
+    }
+
+    #[test]
+    fn commands_are_applied_on_the_same_tick_everywhere() {
+        let (host, host_ticks, client, client_ticks) = lockstep(42, 42);
+        assert_eq!(client.local, PlayerId(1));
+        assert_eq!(client.seed, 7);
+        assert_eq!(host_ticks, client_ticks);
+
+        let delay = INPUT_DELAY as usize;
+        assert_eq!(
+            host_ticks[delay],
+            vec![(PlayerId(1), PlayerCommand::StopMining)]
+        );
+        assert_eq!(
+            host_ticks[delay + 1],
+            vec![(PlayerId(0), PlayerCommand::MoveTo(Vec2::new(1., 2.)))]
+        );
+        assert_eq!(host.desync, None);
+        assert_eq!(client.desync, None);
+    }
+
+    #[test]
+    fn different_checksums_are_a_desync() {
+        let (host, _, client, _) = lockstep(42, 43);
+        assert_eq!(host.desync, Some(0));
+        assert_eq!(client.desync, Some(0));
+    }
+
+    /// Poll until every player's commands for the tick are in
+    fn wait_for_tick(
+        session: &mut Session,
+        tick: u64,
+        mut commands: Vec<PlayerCommand>,
+    ) -> Vec<(PlayerId, PlayerCommand)> {
+        let deadline = Instant::now() + TIMEOUT;
+        while !session.poll(tick, std::mem::take(&mut commands)).unwrap() {
+            assert!(Instant::now() < deadline, "Tick {} never came in", tick);
+            thread::sleep(Duration::from_millis(1));
+        }
+        session.received.remove(&tick).unwrap()
+    }
+
+    #[test]
+    fn sessions_dont_wait_for_other_players() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        let host = thread::spawn(move || accept_players(listener, 2, 7).unwrap());
+        let mut client = join(address).unwrap();
+        let host = host.join().unwrap();
+
+        // The host hasn't started exchanging commands
+        assert!(!client.poll(0, vec![PlayerCommand::StopMining]).unwrap());
+
+        let mut host = Session::new(host);
+        let mut host_ticks = vec![];
+        let mut client_ticks = vec![];
+        for tick in 0..=INPUT_DELAY {
+            host_ticks.push(wait_for_tick(&mut host, tick, vec![]));
+            client_ticks.push(wait_for_tick(&mut client, tick, vec![]));
+        }
+        assert_eq!(host_ticks, client_ticks);
+        assert_eq!(
+            host_ticks[INPUT_DELAY as usize],
+            vec![(PlayerId(1), PlayerCommand::StopMining)]
+        );
+    }
+}
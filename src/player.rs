@@ -5,7 +5,7 @@ use bevy::{
     core_pipeline::core_2d::Camera2dBundle,
     ecs::{
         schedule::OnEnter,
-        system::{Commands, Res},
+        system::{Commands, Res, Resource},
     },
     hierarchy::BuildChildren,
     math::Vec2,
@@ -15,7 +15,12 @@ use bevy::{
     transform::{components::Transform, TransformBundle},
 };
 use bevy_rapier2d::{control::KinematicCharacterController, geometry::Collider};
-use kloonorio_core::{health::Health, item::Item, player::Player};
+use kloonorio_core::{
+    deconstruction::DECONSTRUCTION_PLANNER,
+    health::Health,
+    item::Item,
+    player::{LocalPlayer, Player, PlayerId, SimulatedPosition, PLAYER_RADIUS},
+};
 
 use crate::{shoot::Gun, ysort::YSort};
 use kloonorio_core::{
@@ -28,26 +33,111 @@ pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(AppState::Running), spawn_player);
+        app.init_resource::<Players>()
+            .add_systems(OnEnter(AppState::Running), spawn_players);
+    }
+}
+
+/// The players in the game and the one controlled on this client
+#[derive(Resource, Debug)]
+pub struct Players {
+    pub count: u32,
+    pub local: PlayerId,
+}
+
+impl Default for Players {
+    fn default() -> Self {
+        Self {
+            count: 1,
+            local: PlayerId(0),
+        }
     }
 }
 
-fn spawn_player(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn starting_inventory() -> Inventory {
     let mut inventory = Inventory::new(100);
     inventory.add_item(&Item::new("Wooden chest"), 100);
     inventory.add_item(&Item::new("Burner mining drill"), 100);
     inventory.add_item(&Item::new("Stone furnace"), 100);
     inventory.add_item(&Item::new("Burner inserter"), 100);
+    inventory.add_item(&Item::new("Filter inserter"), 50);
+    inventory.add_item(&Item::new("Long-handed inserter"), 50);
+    inventory.add_item(&Item::new("Stack inserter"), 50);
     inventory.add_item(&Item::new("Coal"), 200);
     inventory.add_item(&Item::new("Iron plate"), 200);
     inventory.add_item(&Item::new("Transport belt"), 200);
+    inventory.add_item(&Item::new("Underground belt"), 50);
+    inventory.add_item(&Item::new("Splitter"), 50);
     inventory.add_item(&Item::new("Burner assembling machine"), 100);
+    inventory.add_item(&Item::new("Small electric pole"), 100);
+    inventory.add_item(&Item::new("Burner generator"), 10);
+    inventory.add_item(&Item::new("Electric mining drill"), 50);
+    inventory.add_item(&Item::new("Inserter"), 100);
+    inventory.add_item(&Item::new("Assembling machine"), 50);
+    inventory.add_item(&Item::new("Pipe"), 200);
+    inventory.add_item(&Item::new("Underground pipe"), 50);
+    inventory.add_item(&Item::new("Offshore pump"), 10);
+    inventory.add_item(&Item::new("Boiler"), 10);
+    inventory.add_item(&Item::new("Steam engine"), 20);
+    inventory.add_item(&Item::new("Lab"), 10);
+    inventory.add_item(&Item::new(DECONSTRUCTION_PLANNER), 1);
+    inventory
+}
+
+fn spawn_players(mut commands: Commands, players: Res<Players>, asset_server: Res<AssetServer>) {
+    for id in (0..players.count).map(PlayerId) {
+        if id == players.local {
+            spawn_local_player(&mut commands, id, &asset_server);
+        } else {
+            spawn_remote_player(&mut commands, id, &asset_server);
+        }
+    }
+}
+
+fn player_sprite(asset_server: &AssetServer) -> (Name, SpriteBundle) {
+    (
+        Name::new("Player sprite"),
+        SpriteBundle {
+            texture: asset_server.load("textures/character.png"),
+            transform: Transform::from_xyz(0.0, 0.4, 0.0),
+            sprite: Sprite {
+                custom_size: Some(Vec2::new(2., 2.)),
+                ..default()
+            },
+            ..default()
+        },
+    )
+}
+
+/// Players controlled by other clients only follow their commands
+fn spawn_remote_player(commands: &mut Commands, id: PlayerId, asset_server: &AssetServer) {
+    commands
+        .spawn((
+            Name::new(format!("Player {}", id.0 + 1)),
+            YSort { base_layer: 1.0 },
+            TransformBundle::from_transform(Transform::from_xyz(0.0, 0.0, 1.0)),
+            SimulatedPosition::default(),
+            Player,
+            id,
+            Health::new(100),
+            starting_inventory(),
+            CraftingQueue::default(),
+        ))
+        .with_children(|parent| {
+            parent.spawn(player_sprite(asset_server));
+        });
+}
+
+fn spawn_local_player(commands: &mut Commands, id: PlayerId, asset_server: &AssetServer) {
     commands
         .spawn((
             Name::new("Player"),
             YSort { base_layer: 1.0 },
             TransformBundle::from_transform(Transform::from_xyz(0.0, 0.0, 1.0)),
+            SimulatedPosition::default(),
             Player,
+            id,
+            LocalPlayer,
             Health::new(100),
             Hand::default(),
             Gun {
@@ -55,10 +145,10 @@ fn spawn_player(mut commands: Commands, asset_server: Res<AssetServer>) {
                 damage: 10,
                 cooldown: 1.,
             },
-            inventory,
+            starting_inventory(),
             CraftingQueue::default(),
             KinematicCharacterController { ..default() },
-            Collider::ball(0.3),
+            Collider::ball(PLAYER_RADIUS),
             Hotbar::new(5),
         ))
         .with_children(|parent| {
@@ -73,17 +163,6 @@ fn spawn_player(mut commands: Commands, asset_server: Res<AssetServer>) {
                     ..default()
                 },
             ));
-            parent.spawn((
-                Name::new("Player sprite"),
-                SpriteBundle {
-                    texture: asset_server.load("textures/character.png"),
-                    transform: Transform::from_xyz(0.0, 0.4, 0.0),
-                    sprite: Sprite {
-                        custom_size: Some(Vec2::new(2., 2.)),
-                        ..default()
-                    },
-                    ..default()
-                },
-            ));
+            parent.spawn(player_sprite(asset_server));
         });
 }
@@ -1,7 +1,13 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::HashSet};
 
 use bevy_rapier2d::control::KinematicCharacterController;
-use kloonorio_core::{player::Player, types::AppState};
+use kloonorio_core::{
+    ground_item::{GroundItem, KEY_PICKUP_RANGE, WALK_PICKUP_RANGE},
+    inventory::Inventory,
+    player::LocalPlayer,
+    player_command::{CommandSource, LocalCommands, PlayerCommand},
+    types::AppState,
+};
 use kloonorio_terrain::CursorWorldPos;
 
 use crate::{
@@ -15,14 +21,21 @@ impl Plugin for PlayerControlPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
             Update,
-            (keyboard_movement_system, keyboard_shoot_system).run_if(in_state(AppState::Running)),
+            (
+                keyboard_movement_system.run_if(resource_equals(CommandSource::Input)),
+                undo_keys.run_if(resource_equals(CommandSource::Input)),
+                keyboard_shoot_system,
+                // Pickups are applied after the move to where the player asked for them
+                (send_player_position, pick_up_ground_items).chain(),
+            )
+                .run_if(in_state(AppState::Running)),
         );
     }
 }
 
 fn keyboard_movement_system(
     keyboard_input: Res<Input<KeyCode>>,
-    mut player_query: Query<&mut KinematicCharacterController, With<Player>>,
+    mut player_query: Query<&mut KinematicCharacterController, With<LocalPlayer>>,
     timer: Res<Time>,
 ) {
     let mut direction = Vec3::new(0.0, 0.0, 0.0);
@@ -53,9 +66,70 @@ fn keyboard_movement_system(
     }
 }
 
+/// Ctrl+Z reverts the last construction action, Ctrl+Y carries it out again
+fn undo_keys(keyboard_input: Res<Input<KeyCode>>, mut local_commands: ResMut<LocalCommands>) {
+    if !keyboard_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::Z) {
+        local_commands.push(PlayerCommand::Undo);
+    } else if keyboard_input.just_pressed(KeyCode::Y) {
+        local_commands.push(PlayerCommand::Redo);
+    }
+}
+
+/// The other players see the local player where it was on the tick the command is applied
+fn send_player_position(
+    player_query: Query<&GlobalTransform, With<LocalPlayer>>,
+    mut local_commands: ResMut<LocalCommands>,
+    mut sent_position: Local<Option<Vec2>>,
+) {
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+    let position = player_transform.translation().xy();
+    if *sent_position != Some(position) {
+        local_commands.push(PlayerCommand::MoveTo(position));
+        *sent_position = Some(position);
+    }
+}
+
+/// Ask to pick up ground items once they come in range, by walking up to them or pressing the
+/// pickup key. Items that are left on the ground aren't asked for again until they do.
+fn pick_up_ground_items(
+    keyboard_input: Res<Input<KeyCode>>,
+    player_query: Query<(&GlobalTransform, &Inventory), With<LocalPlayer>>,
+    ground_item_query: Query<(Entity, &GlobalTransform, &GroundItem)>,
+    mut local_commands: ResMut<LocalCommands>,
+    mut previously_in_range: Local<HashSet<Entity>>,
+) {
+    let range = if keyboard_input.pressed(KeyCode::F) {
+        KEY_PICKUP_RANGE
+    } else {
+        WALK_PICKUP_RANGE
+    };
+    let Ok((player_transform, inventory)) = player_query.get_single() else {
+        return;
+    };
+    let position = player_transform.translation().xy();
+    let in_range: HashSet<Entity> = ground_item_query
+        .iter()
+        .filter(|(_, ground_item_transform, ground_item)| {
+            !ground_item.is_empty()
+                && inventory.can_add_item(&ground_item.stack.item)
+                && position.distance(ground_item_transform.translation().xy()) <= range
+        })
+        .map(|(entity, _, _)| entity)
+        .collect();
+    if !in_range.is_subset(&previously_in_range) {
+        local_commands.push(PlayerCommand::PickUp { range });
+    }
+    *previously_in_range = in_range;
+}
+
 fn keyboard_shoot_system(
     keyboard_input: Res<Input<KeyCode>>,
-    player_query: Query<Entity, (With<Player>, With<Gun>)>,
+    player_query: Query<Entity, (With<LocalPlayer>, With<Gun>)>,
     biter_query: Query<(Entity, &GlobalTransform), With<Biter>>,
     cursor_pos: Res<CursorWorldPos>,
     mut commands: Commands,
@@ -0,0 +1,423 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use kloonorio_core::{
+    checksum::ChecksumParams,
+    player::PlayerId,
+    player_command::{
+        apply_player_commands, schedule_local_commands, CommandSource, LocalCommands,
+        PlayerCommand, ScheduledCommands,
+    },
+    save_game::{SaveGame, SaveParams},
+    simulation::{advance_simulation_tick, SimulationSet, SimulationTick},
+    types::AppState,
+};
+use kloonorio_terrain::{Terrain, TerrainSettings};
+
+use crate::{
+    multiplayer::Session,
+    save_file::{is_loading, saved_terrain, LoadSaveGame, SaveFileSettings},
+};
+
+pub const USAGE: &str = "Usage: kloonorio --replay <replay file>
+
+Plays back a recorded game, the player follows the recorded commands instead of the input.
+Every game is recorded to replays/last_game.ron, which is overwritten when the next game starts.";
+
+/// Ticks between the checksums a replay is checked against
+const CHECKSUM_INTERVAL: u64 = 60;
+
+/// Command line options of watching a replay
+#[derive(Debug, PartialEq)]
+pub struct ReplayArgs {
+    pub replay: PathBuf,
+}
+
+impl ReplayArgs {
+    /// Returns `None` when there is no replay to watch
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Option<Result<Self, String>> {
+        let mut args = args.into_iter();
+        if args.next().as_deref() != Some("--replay") {
+            return None;
+        }
+        let Some(replay) = args.next() else {
+            return Some(Err("Missing value for --replay".to_string()));
+        };
+        if let Some(arg) = args.next() {
+            return Some(Err(format!("Unknown argument {}", arg)));
+        }
+        Some(Ok(ReplayArgs {
+            replay: PathBuf::from(replay),
+        }))
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayFileError {
+    /// An [IO](std::io) Error.
+    #[error("Could not access replay file: {0}")]
+    Io(#[from] std::io::Error),
+    /// A [Ron](ron) Error while writing.
+    #[error("Could not write RON: {0}")]
+    Ron(#[from] ron::Error),
+    /// A [Ron](ron) Error while reading.
+    #[error("Could not parse RON: {0}")]
+    RonSpannedError(#[from] ron::error::SpannedError),
+    /// The replay doesn't begin with the game it starts from
+    #[error("Replay file doesn't start with a game")]
+    MissingStart,
+}
+
+/// Replays are written as RON, one entry per line, so a crash loses no more than the last line
+#[derive(Debug, Serialize, Deserialize)]
+enum ReplayEntry {
+    /// The game the replay starts from, always the first entry
+    Start(SaveGame),
+    /// Every player's commands on a tick, counted from the start of the replay
+    Commands {
+        tick: u64,
+        commands: Vec<(PlayerId, PlayerCommand)>,
+    },
+    /// The checksum of the simulation after a tick
+    Checksum { tick: u64, checksum: u64 },
+}
+
+fn write_entry(file: &mut File, entry: &ReplayEntry) -> Result<(), ReplayFileError> {
+    let mut line = ron::to_string(entry)?;
+    line.push('\n');
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Start a new replay file, replacing the previous one
+fn create_replay_file(path: &Path, start: &ReplayEntry) -> Result<File, ReplayFileError> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+    let mut file = File::create(path)?;
+    write_entry(&mut file, start)?;
+    Ok(file)
+}
+
+pub fn read_replay_file(path: &Path) -> Result<(SaveGame, ReplayPlayback), ReplayFileError> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let first = lines.next().ok_or(ReplayFileError::MissingStart)??;
+    let ReplayEntry::Start(start) = ron::from_str(&first)? else {
+        return Err(ReplayFileError::MissingStart);
+    };
+
+    let mut playback = ReplayPlayback::default();
+    for line in lines {
+        match ron::from_str(&line?)? {
+            ReplayEntry::Start(_) => return Err(ReplayFileError::MissingStart),
+            ReplayEntry::Commands { tick, commands } => {
+                playback.end = playback.end.max(tick);
+                playback.commands.insert(tick, commands);
+            }
+            ReplayEntry::Checksum { tick, checksum } => {
+                playback.end = playback.end.max(tick);
+                playback.checksums.insert(tick, checksum);
+            }
+        }
+    }
+    Ok((start, playback))
+}
+
+#[derive(Resource, Debug, Reflect)]
+pub struct ReplaySettings {
+    /// The file every game is recorded to, nothing is recorded without one
+    pub record: Option<PathBuf>,
+}
+
+impl Default for ReplaySettings {
+    fn default() -> Self {
+        Self {
+            record: Some(PathBuf::from("replays/last_game.ron")),
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+enum ReplayRecorder {
+    /// Waiting for the game to be spawned
+    #[default]
+    Waiting,
+    Recording {
+        file: File,
+        /// The simulation tick the replay starts on
+        start_tick: u64,
+    },
+    /// Writing the replay failed, until the next game starts
+    Stopped,
+}
+
+/// A replay being played back. Its commands take the place of the local player's.
+#[derive(Resource, Default, Debug)]
+pub struct ReplayPlayback {
+    commands: BTreeMap<u64, Vec<(PlayerId, PlayerCommand)>>,
+    checksums: BTreeMap<u64, u64>,
+    /// The last tick of the replay
+    end: u64,
+    /// The simulation tick the replay started on, once the game it starts from is spawned
+    start_tick: Option<u64>,
+    /// The first tick the simulation differed from the recording
+    pub diverged: Option<u64>,
+    pub finished: bool,
+}
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<ReplaySettings>()
+            .init_resource::<ReplaySettings>()
+            .init_resource::<ReplayRecorder>()
+            .add_systems(
+                FixedUpdate,
+                (
+                    restart_recording.run_if(is_loading),
+                    record_commands
+                        .after(schedule_local_commands)
+                        .before(apply_player_commands)
+                        .in_set(SimulationSet::Commands)
+                        .run_if(not(is_loading)),
+                    record_checksum
+                        .after(SimulationSet::Inserters)
+                        .before(advance_simulation_tick),
+                )
+                    .run_if(in_state(AppState::Running))
+                    .run_if(not(resource_exists::<ReplayPlayback>()))
+                    // Only the local player's part of a multiplayer game could be saved
+                    .run_if(not(resource_exists::<Session>())),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    (ignore_input, play_commands.run_if(not(is_loading)))
+                        .before(schedule_local_commands)
+                        .in_set(SimulationSet::Commands),
+                    check_replay
+                        .after(SimulationSet::Inserters)
+                        .before(advance_simulation_tick),
+                )
+                    .run_if(in_state(AppState::Running))
+                    .run_if(resource_exists::<ReplayPlayback>()),
+            );
+    }
+}
+
+/// Play back a replay instead of starting a new game
+pub fn start_playback(app: &mut App, path: &Path) -> Result<(), ReplayFileError> {
+    let (start, playback) = read_replay_file(path)?;
+    app.world.resource_mut::<SaveFileSettings>().load_on_start = false;
+    app.world.resource_mut::<ReplaySettings>().record = None;
+    *app.world.resource_mut::<CommandSource>() = CommandSource::Replay;
+    app.insert_resource(LoadSaveGame(start))
+        .insert_resource(playback);
+    Ok(())
+}
+
+/// A loaded game starts a new replay
+fn restart_recording(mut recorder: ResMut<ReplayRecorder>) {
+    *recorder = ReplayRecorder::Waiting;
+}
+
+/// Start recording from the current game once it's spawned, then write down the commands of
+/// every tick
+fn record_commands(
+    mut recorder: ResMut<ReplayRecorder>,
+    tick: Res<SimulationTick>,
+    scheduled_commands: Res<ScheduledCommands>,
+    save_params: SaveParams,
+    terrain_query: Query<&Terrain>,
+    terrain_settings: Res<TerrainSettings>,
+    replay_settings: Res<ReplaySettings>,
+) {
+    let Some(path) = &replay_settings.record else {
+        return;
+    };
+    if matches!(*recorder, ReplayRecorder::Waiting) {
+        let Ok(terrain) = terrain_query.get_single() else {
+            return;
+        };
+        let start =
+            ReplayEntry::Start(save_params.save_game(saved_terrain(&terrain_settings, terrain)));
+        *recorder = match create_replay_file(path, &start) {
+            Ok(file) => ReplayRecorder::Recording {
+                file,
+                start_tick: tick.0,
+            },
+            Err(err) => {
+                error!("Could not record replay: {}", err);
+                ReplayRecorder::Stopped
+            }
+        };
+    }
+
+    let ReplayRecorder::Recording { file, start_tick } = &mut *recorder else {
+        return;
+    };
+    let commands = scheduled_commands.at(tick.0);
+    if commands.is_empty() {
+        return;
+    }
+    let entry = ReplayEntry::Commands {
+        tick: tick.0 - *start_tick,
+        commands: commands.to_vec(),
+    };
+    if let Err(err) = write_entry(file, &entry) {
+        error!("Could not record replay: {}", err);
+        *recorder = ReplayRecorder::Stopped;
+    }
+}
+
+fn record_checksum(
+    mut recorder: ResMut<ReplayRecorder>,
+    tick: Res<SimulationTick>,
+    checksum_params: ChecksumParams,
+) {
+    let ReplayRecorder::Recording { file, start_tick } = &mut *recorder else {
+        return;
+    };
+    let replay_tick = tick.0 - *start_tick;
+    if !replay_tick.is_multiple_of(CHECKSUM_INTERVAL) {
+        return;
+    }
+    let entry = ReplayEntry::Checksum {
+        tick: replay_tick,
+        checksum: checksum_params.checksum(),
+    };
+    if let Err(err) = write_entry(file, &entry) {
+        error!("Could not record replay: {}", err);
+        *recorder = ReplayRecorder::Stopped;
+    }
+}
+
+/// The input of whoever watches the replay is ignored until it's done
+fn ignore_input(playback: Res<ReplayPlayback>, mut local_commands: ResMut<LocalCommands>) {
+    if !playback.finished {
+        local_commands.0.clear();
+    }
+}
+
+fn play_commands(
+    mut playback: ResMut<ReplayPlayback>,
+    tick: Res<SimulationTick>,
+    mut scheduled_commands: ResMut<ScheduledCommands>,
+) {
+    if playback.finished {
+        return;
+    }
+    let start_tick = *playback.start_tick.get_or_insert(tick.0);
+    if let Some(commands) = playback.commands.remove(&(tick.0 - start_tick)) {
+        scheduled_commands.schedule(tick.0, commands);
+    }
+}
+
+/// Compare the simulation with the recording, the player takes over once the replay is done
+fn check_replay(
+    mut playback: ResMut<ReplayPlayback>,
+    mut command_source: ResMut<CommandSource>,
+    tick: Res<SimulationTick>,
+    checksum_params: ChecksumParams,
+) {
+    let Some(start_tick) = playback.start_tick else {
+        return;
+    };
+    if playback.finished {
+        return;
+    }
+    let replay_tick = tick.0 - start_tick;
+    if let Some(checksum) = playback.checksums.get(&replay_tick) {
+        if *checksum != checksum_params.checksum() && playback.diverged.is_none() {
+            error!("Replay diverged from the recording on tick {}", replay_tick);
+            playback.diverged = Some(replay_tick);
+        }
+    }
+    if replay_tick >= playback.end {
+        info!("Replay finished after {} ticks", replay_tick);
+        playback.finished = true;
+        *command_source = CommandSource::Input;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use kloonorio_core::{
+        inventory::Inventory,
+        save_game::{SavedPlayer, SavedTerrain},
+    };
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> Option<Result<ReplayArgs, String>> {
+        ReplayArgs::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_replay_args() {
+        assert_eq!(parse(&[]), None);
+        assert_eq!(parse(&["--headless", "--replay", "bug.ron"]), None);
+        assert_eq!(
+            parse(&["--replay", "replays/bug.ron"]),
+            Some(Ok(ReplayArgs {
+                replay: PathBuf::from("replays/bug.ron")
+            }))
+        );
+        assert!(matches!(parse(&["--replay"]), Some(Err(_))));
+        assert!(matches!(
+            parse(&["--replay", "bug.ron", "--ticks"]),
+            Some(Err(_))
+        ));
+    }
+
+    #[test]
+    fn replay_file_round_trip() {
+        let path = std::env::temp_dir().join("kloonorio_replay_round_trip.ron");
+        let start = ReplayEntry::Start(SaveGame {
+            terrain: SavedTerrain {
+                seed: 7,
+                modified_tiles: vec![],
+            },
+            player: SavedPlayer {
+                position: Vec2::new(1., 2.),
+                inventory: Inventory::new(10),
+                crafting_queue: vec![],
+            },
+            structures: vec![],
+            research: default(),
+            ground_items: vec![],
+        });
+        let mut file = create_replay_file(&path, &start).unwrap();
+        let command = PlayerCommand::Craft {
+            recipe: "Wooden chest\nwith a newline".to_string(),
+        };
+        for entry in [
+            ReplayEntry::Checksum {
+                tick: 0,
+                checksum: 42,
+            },
+            ReplayEntry::Commands {
+                tick: 3,
+                commands: vec![(PlayerId(0), command.clone())],
+            },
+        ] {
+            write_entry(&mut file, &entry).unwrap();
+        }
+
+        let (start, playback) = read_replay_file(&path).unwrap();
+        assert_eq!(start.terrain.seed, 7);
+        assert_eq!(playback.commands[&3], vec![(PlayerId(0), command)]);
+        assert_eq!(playback.checksums[&0], 42);
+        assert_eq!(playback.end, 3);
+        std::fs::remove_file(path).unwrap();
+    }
+}
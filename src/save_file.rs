@@ -0,0 +1,302 @@
+use std::path::{Path, PathBuf};
+
+use bevy::{prelude::*, utils::HashSet};
+use ron::ser::PrettyConfig;
+
+use kloonorio_core::{
+    ground_item::GroundItem,
+    inventory::Inventory,
+    player::{LocalPlayer, SimulatedPosition},
+    research::ResearchQueue,
+    save_game::{
+        link_loaded_structure, restored_crafting_queue, spawn_saved_ground_items, LoadGameEvent,
+        SaveGame, SaveGameEvent, SaveParams, SavedTerrain,
+    },
+    spawn_order::SpawnCounter,
+    structure::Structures,
+    types::{AppState, Building, CraftingQueue},
+};
+use kloonorio_terrain::{
+    terrain_pos_to_chunk_id, Terrain, TerrainParams, TerrainSettings, CHUNK_SIZE,
+};
+
+use crate::{
+    builder::{
+        placeable::{create_structure_texture_atlas, place_structure},
+        splitter_builder::SplitterBuilder,
+        transport_belt_builder::TransportBeltBuilder,
+    },
+    scene_setup::spawn_terrain,
+};
+
+pub struct SaveFilePlugin;
+
+impl Plugin for SaveFilePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<SaveFileSettings>()
+            .init_resource::<SaveFileSettings>()
+            .add_systems(OnEnter(AppState::Running), load_on_start)
+            .add_systems(
+                Update,
+                (
+                    save_game,
+                    load_game,
+                    spawn_loaded_game.run_if(resource_exists::<PendingLoad>()),
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Running)),
+            );
+    }
+}
+
+#[derive(Resource, Debug, Reflect)]
+pub struct SaveFileSettings {
+    /// The file the save and load menu actions use
+    pub path: PathBuf,
+    /// Load the save file when the game starts, if there is one
+    pub load_on_start: bool,
+}
+
+impl Default for SaveFileSettings {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("saves/save.ron"),
+            load_on_start: true,
+        }
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum SaveFileError {
+    /// An [IO](std::io) Error.
+    #[error("Could not access save file: {0}")]
+    Io(#[from] std::io::Error),
+    /// A [Ron](ron) Error while writing.
+    #[error("Could not write RON: {0}")]
+    Ron(#[from] ron::Error),
+    /// A [Ron](ron) Error while reading.
+    #[error("Could not parse RON: {0}")]
+    RonSpannedError(#[from] ron::error::SpannedError),
+}
+
+/// Write the save game next to the save file first, so a crash while writing doesn't leave a
+/// broken save file behind
+pub fn write_save_file(path: &Path, save_game: &SaveGame) -> Result<(), SaveFileError> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+    let ron = ron::ser::to_string_pretty(save_game, PrettyConfig::default())?;
+    let temporary_path = path.with_extension("ron.tmp");
+    std::fs::write(&temporary_path, ron)?;
+    std::fs::rename(temporary_path, path)?;
+    Ok(())
+}
+
+pub fn read_save_file(path: &Path) -> Result<SaveGame, SaveFileError> {
+    let ron = std::fs::read_to_string(path)?;
+    Ok(ron::from_str(&ron)?)
+}
+
+/// A save game that is spawned once the chunks under its structures are ready
+#[derive(Resource)]
+pub(crate) struct PendingLoad(SaveGame);
+
+/// A save game to load that isn't in the save file, like the start of a replay
+#[derive(Resource)]
+pub(crate) struct LoadSaveGame(pub SaveGame);
+
+/// A save game is being loaded and isn't spawned yet
+pub(crate) fn is_loading(
+    pending_load: Option<Res<PendingLoad>>,
+    load_save_game: Option<Res<LoadSaveGame>>,
+) -> bool {
+    pending_load.is_some() || load_save_game.is_some()
+}
+
+pub fn saved_terrain(terrain_settings: &TerrainSettings, terrain: &Terrain) -> SavedTerrain {
+    SavedTerrain {
+        seed: terrain_settings.seed,
+        modified_tiles: terrain
+            .modified_tiles
+            .iter()
+            .map(|(position, texture)| (*position, *texture))
+            .collect(),
+    }
+}
+
+fn load_on_start(
+    mut load_game_events: EventWriter<LoadGameEvent>,
+    save_file_settings: Res<SaveFileSettings>,
+) {
+    if save_file_settings.load_on_start && save_file_settings.path.exists() {
+        load_game_events.send(LoadGameEvent);
+    }
+}
+
+fn save_game(
+    mut save_game_events: EventReader<SaveGameEvent>,
+    save_params: SaveParams,
+    terrain_query: Query<&Terrain>,
+    terrain_settings: Res<TerrainSettings>,
+    save_file_settings: Res<SaveFileSettings>,
+) {
+    if save_game_events.read().count() == 0 {
+        return;
+    }
+
+    let terrain = saved_terrain(&terrain_settings, terrain_query.single());
+    let path = &save_file_settings.path;
+    match write_save_file(path, &save_params.save_game(terrain)) {
+        Ok(()) => info!("Saved game to {}", path.display()),
+        Err(err) => error!("Could not save game: {}", err),
+    }
+}
+
+/// Clear the current game and regenerate the terrain of the save game, the structures are
+/// spawned by `spawn_loaded_game` once the terrain is ready
+fn load_game(
+    mut commands: Commands,
+    mut load_game_events: EventReader<LoadGameEvent>,
+    load_save_game: Option<Res<LoadSaveGame>>,
+    building_query: Query<Entity, Or<(With<Building>, With<GroundItem>)>>,
+    terrain_query: Query<Entity, With<Terrain>>,
+    mut terrain_settings: ResMut<TerrainSettings>,
+    structures: Res<Structures>,
+    asset_server: Res<AssetServer>,
+    save_file_settings: Res<SaveFileSettings>,
+) {
+    let save_game = if let Some(load_save_game) = load_save_game {
+        commands.remove_resource::<LoadSaveGame>();
+        load_save_game.0.clone()
+    } else if load_game_events.read().count() > 0 {
+        match read_save_file(&save_file_settings.path) {
+            Ok(save_game) => save_game,
+            Err(err) => {
+                error!("Could not load game: {}", err);
+                return;
+            }
+        }
+    } else {
+        return;
+    };
+    if let Some(unknown) = save_game
+        .structures
+        .iter()
+        .find(|saved| !structures.contains_key(&saved.name))
+    {
+        error!("Could not load game: unknown structure {}", unknown.name);
+        return;
+    }
+
+    for entity in building_query.iter().chain(terrain_query.iter()) {
+        commands.entity(entity).despawn_recursive();
+    }
+    terrain_settings.seed = save_game.terrain.seed;
+    spawn_terrain(
+        &mut commands,
+        &asset_server,
+        save_game.terrain.seed,
+        save_game.terrain.modified_tiles.iter().copied().collect(),
+    );
+    commands.insert_resource(PendingLoad(save_game));
+}
+
+/// Spawn the structures of a save game and restore the player and research. Structures are
+/// only spawned once all chunks around them are, as their builders look up neighbouring tiles.
+fn spawn_loaded_game(
+    mut commands: Commands,
+    mut pending_load: ResMut<PendingLoad>,
+    mut terrain_params: TerrainParams,
+    terrain_query: Query<Entity, With<Terrain>>,
+    structures: Res<Structures>,
+    asset_server: Res<AssetServer>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut player_query: Query<
+        (
+            &mut Transform,
+            &mut SimulatedPosition,
+            &mut Inventory,
+            &mut CraftingQueue,
+        ),
+        With<LocalPlayer>,
+    >,
+    mut research_queue: ResMut<ResearchQueue>,
+) {
+    let save_game = &mut pending_load.0;
+    let Ok(terrain_entity) = terrain_query.get_single() else {
+        return;
+    };
+
+    let chunk_positions: HashSet<IVec2> = save_game
+        .structures
+        .iter()
+        .map(|saved| terrain_pos_to_chunk_id(saved.position))
+        .flat_map(|chunk_position| {
+            (-1..=1).flat_map(move |x| (-1..=1).map(move |y| chunk_position + IVec2::new(x, y)))
+        })
+        .collect();
+    let mut chunks_ready = true;
+    for chunk_position in chunk_positions {
+        let chunk_center = (chunk_position * CHUNK_SIZE.as_ivec2()).as_vec2();
+        if terrain_params
+            .tile_entity_at_global_pos(chunk_center)
+            .is_none()
+        {
+            terrain_params.spawn_chunk(terrain_entity, chunk_position);
+            chunks_ready = false;
+        }
+    }
+    if !chunks_ready {
+        return;
+    }
+
+    // Structures are saved in the order they were spawned, so they keep their order
+    let mut spawn_counter = SpawnCounter::default();
+    let entities: Vec<Entity> = save_game
+        .structures
+        .iter()
+        .map(|saved| {
+            let structure = &structures[&saved.name];
+            let texture_atlas_handle =
+                create_structure_texture_atlas(&asset_server, structure, &mut texture_atlases);
+            place_structure(
+                &mut commands,
+                texture_atlas_handle,
+                saved.position,
+                saved.rotation,
+                structure,
+                spawn_counter.next_order(),
+            )
+        })
+        .collect();
+    commands.insert_resource(spawn_counter);
+    for (entity, saved) in entities.iter().zip(&save_game.structures) {
+        // Saved links replace the ones the builders would look for
+        if saved.belt.is_some() {
+            commands.entity(*entity).remove::<TransportBeltBuilder>();
+        }
+        if saved.splitter.is_some() {
+            commands.entity(*entity).remove::<SplitterBuilder>();
+        }
+        link_loaded_structure(&mut commands, *entity, saved, &entities);
+    }
+    spawn_saved_ground_items(&mut commands, &save_game.ground_items);
+
+    if let Ok((mut transform, mut simulated_position, mut inventory, mut crafting_queue)) =
+        player_query.get_single_mut()
+    {
+        transform.translation = save_game.player.position.extend(transform.translation.z);
+        simulated_position.0 = save_game.player.position;
+        *inventory = save_game.player.inventory.clone();
+        *crafting_queue = restored_crafting_queue(&save_game.player.crafting_queue);
+    }
+    *research_queue = std::mem::take(&mut save_game.research);
+
+    info!(
+        "Loaded {} structures and {} ground items",
+        entities.len(),
+        save_game.ground_items.len()
+    );
+    commands.remove_resource::<PendingLoad>();
+}
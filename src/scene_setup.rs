@@ -2,12 +2,14 @@ use bevy::{
     app::{App, Plugin, Startup},
     asset::AssetServer,
     ecs::system::{Commands, Res},
+    math::IVec2,
     prelude::default,
+    utils::HashMap,
 };
 
 use kloonorio_terrain::{
     terrain_generator::{NoiseChunkGenerator, TerrainGenerator},
-    Terrain, TerrainBundle,
+    Terrain, TerrainBundle, TerrainSettings,
 };
 
 pub struct SceneSetupPlugin;
@@ -18,13 +20,34 @@ impl Plugin for SceneSetupPlugin {
     }
 }
 
-fn setup_terrain(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let chunk_generator = NoiseChunkGenerator::new(1234567);
+fn setup_terrain(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    terrain_settings: Res<TerrainSettings>,
+) {
+    spawn_terrain(
+        &mut commands,
+        &asset_server,
+        terrain_settings.seed,
+        HashMap::new(),
+    );
+}
+
+pub fn spawn_terrain(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    seed: u32,
+    modified_tiles: HashMap<IVec2, u32>,
+) {
+    let chunk_generator = NoiseChunkGenerator::new(seed);
     let terrain_generator = TerrainGenerator::new(Box::new(chunk_generator));
     let terrain_texture = asset_server.load("textures/terrain.png");
 
     commands.spawn(TerrainBundle {
-        terrain: Terrain::new(terrain_texture),
+        terrain: Terrain {
+            modified_tiles,
+            ..Terrain::new(terrain_texture)
+        },
         generator: terrain_generator,
         ..default()
     });
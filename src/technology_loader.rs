@@ -0,0 +1,65 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+use kloonorio_core::research::Technology;
+
+#[derive(Default)]
+pub struct TechnologiesAssetLoader;
+
+#[derive(Asset, Clone, Debug, Deserialize, TypeUuid, Reflect)]
+#[uuid = "d3a6f1c2-7b4e-4f0a-9c85-2e61b7a4d9f3"]
+pub struct TechnologiesAsset(pub Vec<Technology>);
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum TechnologyAssetLoaderError {
+    /// An [IO](std::io) Error.
+    #[error("Could not load asset: {0}")]
+    Io(#[from] std::io::Error),
+    /// A [Ron](ron) Error.
+    #[error("Could not parse RON: {0}")]
+    RonSpannedError(#[from] ron::error::SpannedError),
+}
+
+impl AssetLoader for TechnologiesAssetLoader {
+    type Asset = TechnologiesAsset;
+    type Error = TechnologyAssetLoaderError;
+    type Settings = ();
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<TechnologiesAsset, Self::Error>> {
+        let _ = settings;
+        Box::pin(async move {
+            let path = load_context.path().display().to_string();
+            let _span = info_span!("Loading technologies asset", path = path);
+            let _enter = _span.enter();
+            let mut buf = Vec::new();
+            reader.read_to_end(&mut buf).await?;
+            let technologies_asset = ron::de::from_bytes(&buf)?;
+            debug!("Finished loading");
+            Ok(TechnologiesAsset(technologies_asset))
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["technologies.ron"]
+    }
+}
+
+pub struct TechnologyLoaderPlugin;
+
+impl Plugin for TechnologyLoaderPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<TechnologiesAsset>()
+            .init_asset::<TechnologiesAsset>()
+            .init_asset_loader::<TechnologiesAssetLoader>();
+    }
+}